@@ -0,0 +1,38 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use pahkat_client::{
+    manifest::{Lockfile, Manifest},
+    PackageStore,
+};
+
+use crate::transaction::Outcome;
+
+pub(crate) async fn apply(
+    store: Arc<dyn PackageStore>,
+    manifest_path: &Path,
+    prune: bool,
+    locked: bool,
+    options: crate::transaction::Options,
+) -> Result<(), anyhow::Error> {
+    let lockfile_path = manifest_path.with_extension("lock");
+
+    let (actions, lockfile) = if locked {
+        let lockfile = Lockfile::load(&lockfile_path)?;
+        (lockfile.actions(&*store, prune)?, None)
+    } else {
+        let manifest = Manifest::load(manifest_path)?;
+        let actions = manifest.actions(&*store, prune)?;
+        (actions, Some(Lockfile::resolve(&*store, &manifest)?))
+    };
+
+    let outcome = crate::transaction::process(store, actions, options).await?;
+
+    // Only record the lockfile once the store actually matches it
+    if let (Some(lockfile), Outcome::Applied) = (lockfile, outcome) {
+        lockfile.save(&lockfile_path)?;
+        println!("Wrote lockfile to {}", lockfile_path.display());
+    }
+
+    Ok(())
+}
@@ -35,6 +35,12 @@ pub(crate) enum Args {
     #[structopt(template(SUB_TEMPLATE))]
     Uninstall(command::Uninstall),
     #[structopt(template(SUB_TEMPLATE))]
+    Upgrade(command::Upgrade),
+    #[structopt(template(SUB_TEMPLATE))]
+    Apply(command::Apply),
+    #[structopt(template(SUB_TEMPLATE))]
+    Export(command::Export),
+    #[structopt(template(SUB_TEMPLATE))]
     Status(command::Status),
     #[structopt(template(SUBC_TEMPLATE))]
     Config(command::Config),
@@ -48,6 +54,9 @@ impl ConfigPath for Args {
             Args::Download(x) => x.config_path(),
             Args::Install(x) => x.config_path(),
             Args::Uninstall(x) => x.config_path(),
+            Args::Upgrade(x) => x.config_path(),
+            Args::Apply(x) => x.config_path(),
+            Args::Export(x) => x.config_path(),
             Args::Config(x) => x.config_path(),
             Args::Status(x) => x.config_path(),
         }
@@ -62,6 +71,9 @@ impl Platform for Args {
             Args::Download(x) => x.platform(),
             Args::Install(x) => x.platform(),
             Args::Uninstall(x) => x.platform(),
+            Args::Upgrade(x) => x.platform(),
+            Args::Apply(x) => x.platform(),
+            Args::Export(x) => x.platform(),
             Args::Status(x) => x.platform(),
             Args::Config(x) => None,
         }
@@ -26,8 +26,20 @@ pub struct Download {
 #[derive(Debug, StructOpt)]
 #[structopt(about = "Install packages from configured repositories")]
 pub struct Install {
-    #[structopt(required = true, help = "Packages to install")]
+    #[structopt(
+        required = true,
+        help = "Packages to install, optionally with a version (foo@1.2.3, foo@^1.2)"
+    )]
     pub packages: Vec<String>,
+    #[structopt(
+        long,
+        help = "Allow installing an older version than the one installed"
+    )]
+    pub allow_downgrade: bool,
+    #[structopt(long, help = "Show what would be done, without doing it")]
+    pub dry_run: bool,
+    #[structopt(short = "y", long = "yes", help = "Do not ask for confirmation")]
+    pub assume_yes: bool,
     #[structopt(flatten)]
     global_opts: super::GlobalOpts,
 }
@@ -37,6 +49,59 @@ pub struct Install {
 pub struct Uninstall {
     #[structopt(required = true, help = "Packages to uninstall")]
     pub packages: Vec<String>,
+    #[structopt(long, help = "Show what would be done, without doing it")]
+    pub dry_run: bool,
+    #[structopt(short = "y", long = "yes", help = "Do not ask for confirmation")]
+    pub assume_yes: bool,
+    #[structopt(flatten)]
+    global_opts: super::GlobalOpts,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Upgrade installed packages with available updates")]
+pub struct Upgrade {
+    #[structopt(help = "Packages to upgrade [default: all with available updates]")]
+    pub packages: Vec<String>,
+    #[structopt(long, help = "Show what would be done, without doing it")]
+    pub dry_run: bool,
+    #[structopt(short = "y", long = "yes", help = "Do not ask for confirmation")]
+    pub assume_yes: bool,
+    #[structopt(flatten)]
+    global_opts: super::GlobalOpts,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Install, upgrade and remove packages to match a manifest")]
+pub struct Apply {
+    #[structopt(help = "Path to manifest", parse(from_os_str))]
+    pub manifest_path: PathBuf,
+    #[structopt(long, help = "Uninstall installed packages not listed in the manifest")]
+    pub prune: bool,
+    #[structopt(long, help = "Apply the exact versions recorded in the manifest's lockfile")]
+    pub locked: bool,
+    #[structopt(
+        long,
+        help = "Allow installing an older version than the one installed"
+    )]
+    pub allow_downgrade: bool,
+    #[structopt(long, help = "Show what would be done, without doing it")]
+    pub dry_run: bool,
+    #[structopt(short = "y", long = "yes", help = "Do not ask for confirmation")]
+    pub assume_yes: bool,
+    #[structopt(flatten)]
+    global_opts: super::GlobalOpts,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Export a manifest of installed packages")]
+pub struct Export {
+    #[structopt(
+        short,
+        long = "output",
+        help = "Output path [default: stdout]",
+        parse(from_os_str)
+    )]
+    pub output_path: Option<PathBuf>,
     #[structopt(flatten)]
     global_opts: super::GlobalOpts,
 }
@@ -66,6 +131,46 @@ pub struct Status {
 
 use crate::{ConfigPath, Platform};
 
+impl Install {
+    pub fn options(&self) -> crate::transaction::Options {
+        crate::transaction::Options {
+            dry_run: self.dry_run,
+            assume_yes: self.assume_yes,
+            allow_downgrade: self.allow_downgrade,
+        }
+    }
+}
+
+impl Uninstall {
+    pub fn options(&self) -> crate::transaction::Options {
+        crate::transaction::Options {
+            dry_run: self.dry_run,
+            assume_yes: self.assume_yes,
+            allow_downgrade: false,
+        }
+    }
+}
+
+impl Upgrade {
+    pub fn options(&self) -> crate::transaction::Options {
+        crate::transaction::Options {
+            dry_run: self.dry_run,
+            assume_yes: self.assume_yes,
+            allow_downgrade: false,
+        }
+    }
+}
+
+impl Apply {
+    pub fn options(&self) -> crate::transaction::Options {
+        crate::transaction::Options {
+            dry_run: self.dry_run,
+            assume_yes: self.assume_yes,
+            allow_downgrade: self.allow_downgrade,
+        }
+    }
+}
+
 impl ConfigPath for Download {
     #[inline]
     fn config_path(&self) -> Option<&Path> {
@@ -108,6 +213,48 @@ impl Platform for Uninstall {
     }
 }
 
+impl ConfigPath for Upgrade {
+    #[inline]
+    fn config_path(&self) -> Option<&Path> {
+        self.global_opts.config_path.as_ref().map(PathBuf::as_path)
+    }
+}
+
+impl Platform for Upgrade {
+    #[inline]
+    fn platform(&self) -> Option<&str> {
+        self.global_opts.platform.as_ref().map(|x| &**x)
+    }
+}
+
+impl ConfigPath for Apply {
+    #[inline]
+    fn config_path(&self) -> Option<&Path> {
+        self.global_opts.config_path.as_ref().map(PathBuf::as_path)
+    }
+}
+
+impl Platform for Apply {
+    #[inline]
+    fn platform(&self) -> Option<&str> {
+        self.global_opts.platform.as_ref().map(|x| &**x)
+    }
+}
+
+impl ConfigPath for Export {
+    #[inline]
+    fn config_path(&self) -> Option<&Path> {
+        self.global_opts.config_path.as_ref().map(PathBuf::as_path)
+    }
+}
+
+impl Platform for Export {
+    #[inline]
+    fn platform(&self) -> Option<&str> {
+        self.global_opts.platform.as_ref().map(|x| &**x)
+    }
+}
+
 impl ConfigPath for Status {
     #[inline]
     fn config_path(&self) -> Option<&Path> {
@@ -0,0 +1,21 @@
+use std::path::Path;
+
+use pahkat_client::{manifest::Manifest, package_store::InstallTarget, PackageStore};
+
+pub(crate) fn export(
+    store: &dyn PackageStore,
+    output_path: Option<&Path>,
+    target: InstallTarget,
+) -> Result<(), anyhow::Error> {
+    let manifest = Manifest::from_store(store, target);
+
+    match output_path {
+        Some(path) => {
+            manifest.save(path)?;
+            println!("Wrote manifest to {}", path.display());
+        }
+        None => print!("{}", manifest.to_toml()?),
+    }
+
+    Ok(())
+}
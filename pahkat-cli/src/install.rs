@@ -1,24 +1,21 @@
-use std::path::Path;
 use std::sync::Arc;
 
-use futures::stream::StreamExt;
-
 use crate::Platform;
 use pahkat_client::{
-    package_store::InstallTarget,
-    transaction::{PackageAction, PackageTransaction},
-    DownloadEvent, PackageKey, PackageStore,
+    package_store::InstallTarget, transaction::PackageAction, PackageKey, PackageStore,
 };
 
 pub(crate) async fn install<'a>(
     store: Arc<dyn PackageStore>,
     packages: &'a Vec<String>,
     target: InstallTarget,
+    options: crate::transaction::Options,
     args: &'a crate::Args,
 ) -> Result<(), anyhow::Error> {
     let keys: Vec<PackageKey> = packages
         .iter()
-        .map(|id| {
+        .map(|spec| {
+            let (id, version) = parse_spec(spec);
             let mut key: PackageKey = store
                 .find_package_by_id(id)
                 .map(|x| x.0)
@@ -28,95 +25,27 @@ pub(crate) async fn install<'a>(
                 key.query.platform = Some(platform.to_string());
             }
 
+            if let Some(version) = version {
+                key.query.version = Some(version.to_string());
+            }
+
             Ok(key)
         })
         .collect::<anyhow::Result<Vec<_>>>()?;
 
-    // for key in keys.iter() {
-    //     // let pb = indicatif::ProgressBar::new(0);
-    //     // pb.set_style(indicatif::ProgressStyle::default_bar()
-    //     //     .template("{spinner:.green} {prefix} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-    //     //     .progress_chars("=>-"));
-    //     // pb.set_prefix(&key.id);
-
-    //     let progress = Box::new(move |cur, max| {
-    //         // pb.set_length(max);
-    //         // pb.set_position(cur);
-
-    //         // if cur >= max {
-    //         //     pb.finish_and_clear();
-    //         // }
-    //         true
-    //     });
-
-    //     let _ = store.download(&key, progress)?;
-    // }
+    let actions = keys
+        .into_iter()
+        .map(|x| PackageAction::install(x, target))
+        .collect();
 
-    let transaction = PackageTransaction::new(
-        Arc::clone(&store),
-        keys.iter()
-            .map(|x| PackageAction::install(x.clone(), target.clone()))
-            .collect(),
-    )?;
-
-    for record in transaction.actions().iter() {
-        let id = record.action.id.clone();
-        let mut download = store.download(&record.action.id);
-
-        // TODO: handle cancel here
-
-        println!("Downloading {}", id);
-
-        while let Some(event) = download.next().await {
-            match event {
-                DownloadEvent::Error(e) => {
-                    println!("Error: {}", e);
-                    return Ok(());
-                }
-                DownloadEvent::Progress((current, total)) => {
-                    println!("Progress: {}/{}", current, total);
-                }
-                DownloadEvent::Complete(_) => {
-                    println!("Complete");
-                }
-            }
-        }
-    }
-
-    let (canceler, mut tx) = transaction.process();
-
-    while let Some(event) = tx.next().await {
-        let mut is_completed = false;
-        use pahkat_client::transaction::TransactionEvent;
-
-        // TODO: handle cancel here
+    crate::transaction::process(store, actions, options).await?;
+    Ok(())
+}
 
-        match event {
-            TransactionEvent::Installing(id) => {
-                println!("Installing: {}", id);
-            }
-            TransactionEvent::Uninstalling(id) => {
-                println!("Uninstalling: {}", id);
-            }
-            TransactionEvent::Progress(id, msg) => {
-                println!("Progress: {} {}", id, msg);
-            }
-            TransactionEvent::Error(id, err) => {
-                println!("Error: {} {}", id, err);
-                return Ok(());
-            }
-            TransactionEvent::Complete => {
-                println!("Complete!");
-                is_completed = true;
-            }
-        }
+/// Splits `foo@1.2.3` or `foo@^1.2` into its package identifier and version.
+fn parse_spec(spec: &str) -> (&str, Option<&str>) {
+    match spec.rfind('@') {
+        Some(i) if !spec[i + 1..].contains('/') => (&spec[..i], Some(&spec[i + 1..])),
+        _ => (spec, None),
     }
-    // transaction
-    //     .process(|key, event| {
-    //         println!("{:?}: {:?}", &key, &event);
-    //         true
-    //     })
-    //     .join()
-    //     .unwrap()?;
-    Ok(())
 }
@@ -1,9 +1,13 @@
+mod apply;
 mod cli;
 mod config;
 mod download;
+mod export;
 mod install;
 mod status;
+mod transaction;
 mod uninstall;
+mod upgrade;
 
 use anyhow::{Context, Result};
 use cli::{Args, ConfigPath, Platform};
@@ -23,7 +27,6 @@ fn config_path(holder: &dyn ConfigPath) -> Result<PathBuf> {
         .with_context(|| "No default config path could be found")
 }
 
-
 #[inline(always)]
 #[cfg(feature = "windows")]
 async fn store(config_path: Option<&Path>) -> anyhow::Result<Arc<dyn PackageStore>> {
@@ -116,11 +119,27 @@ async fn main() -> anyhow::Result<()> {
         }
         cli::Args::Uninstall(a) => {
             let store = store(args.config_path()).await?;
-            uninstall::uninstall(&*store, &a.packages, Default::default())?
+            uninstall::uninstall(store, &a.packages, Default::default(), a.options()).await?
         }
         cli::Args::Install(a) => {
             let store = store(args.config_path()).await?;
-            install::install(store, &a.packages, Default::default(), &args).await?
+            install::install(store, &a.packages, Default::default(), a.options(), &args).await?
+        }
+        cli::Args::Upgrade(a) => {
+            let store = store(args.config_path()).await?;
+            upgrade::upgrade(store, &a.packages, Default::default(), a.options(), &args).await?
+        }
+        cli::Args::Apply(a) => {
+            let store = store(args.config_path()).await?;
+            apply::apply(store, &a.manifest_path, a.prune, a.locked, a.options()).await?
+        }
+        cli::Args::Export(a) => {
+            let store = store(args.config_path()).await?;
+            export::export(
+                &*store,
+                a.output_path.as_ref().map(|x| &**x),
+                Default::default(),
+            )?
         }
         cli::Args::Config(a) => {
             let store = store(args.config_path()).await?;
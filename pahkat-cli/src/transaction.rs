@@ -0,0 +1,363 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use futures::stream::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use pahkat_client::{
+    repo::PackageCandidateError,
+    transaction::{
+        install::ProcessError, PackageAction, PackageTransaction, TransactionError,
+        TransactionEvent, TransactionOptions, TransactionPlan,
+    },
+    DownloadError, DownloadEvent, PackageActionType, PackageKey, PackageStatus, PackageStore,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Options {
+    pub dry_run: bool,
+    pub assume_yes: bool,
+    pub allow_downgrade: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    /// The store now matches the requested actions
+    Applied,
+    /// Nothing was changed, because of a dry run or a declined confirmation
+    Skipped,
+}
+
+pub(crate) async fn process(
+    store: Arc<dyn PackageStore>,
+    actions: Vec<PackageAction>,
+    options: Options,
+) -> Result<Outcome, anyhow::Error> {
+    let transaction_options = TransactionOptions {
+        allow_downgrade: options.allow_downgrade,
+    };
+    let transaction =
+        match PackageTransaction::with_options(Arc::clone(&store), actions, transaction_options) {
+            Ok(v) => v,
+            Err(PackageCandidateError::Downgrade(key)) => {
+                anyhow::bail!("Refusing to downgrade {} without --allow-downgrade", key.id)
+            }
+            Err(e) => return Err(e.into()),
+        };
+    let plan = transaction.plan();
+
+    print_plan(&plan);
+
+    if options.dry_run {
+        return Ok(Outcome::Skipped);
+    }
+
+    if plan.actions.is_empty() {
+        return Ok(Outcome::Applied);
+    }
+
+    if !options.assume_yes {
+        let is_confirmed = dialoguer::Confirm::new()
+            .with_prompt("Do you want to continue?")
+            .default(true)
+            .interact()?;
+
+        if !is_confirmed {
+            println!("Cancelled.");
+            return Ok(Outcome::Skipped);
+        }
+    }
+
+    let multi = MultiProgress::new();
+    let bars = transaction
+        .actions()
+        .iter()
+        .map(|record| {
+            let pb = multi.add(ProgressBar::new(record.target.payload.size()));
+            pb.set_style(status_style());
+            pb.set_prefix(&record.action.id.id);
+            pb.set_message("waiting");
+            (record.action.id.clone(), pb)
+        })
+        .collect::<Vec<_>>();
+    let drawer = tokio::task::spawn_blocking(move || multi.join());
+
+    let mut completed = vec![];
+    let result = run(&*store, &transaction, &bars, &mut completed).await;
+
+    // Every bar must be finished for the drawer to return.
+    for (_, pb) in bars.iter() {
+        if !pb.is_finished() {
+            pb.abandon_with_message("not processed");
+        }
+    }
+    let _ = drawer.await;
+
+    match result {
+        Ok(()) => {
+            println!("Complete!");
+            Ok(Outcome::Applied)
+        }
+        Err(failure) => {
+            print_failure(&failure, &transaction, &completed);
+            Err(anyhow::anyhow!("Transaction failed"))
+        }
+    }
+}
+
+enum Failure {
+    Download(PackageKey, DownloadError),
+    Transaction(PackageKey, TransactionError),
+    Cancelled,
+}
+
+async fn run(
+    store: &dyn PackageStore,
+    transaction: &PackageTransaction,
+    bars: &[(PackageKey, ProgressBar)],
+    completed: &mut Vec<PackageKey>,
+) -> Result<(), Failure> {
+    let bar = |key: &PackageKey| bars.iter().find(|x| &x.0 == key).map(|x| &x.1);
+
+    // Ctrl-C stops the transaction between steps; an install already
+    // running is left to finish so nothing is left half installed.
+    let mut ctrl_c = Box::pin(tokio::signal::ctrl_c());
+
+    for record in transaction.actions().iter() {
+        if record.action.action != PackageActionType::Install {
+            continue;
+        }
+
+        let id = record.action.id.clone();
+        let pb = bar(&id).unwrap();
+        pb.set_style(download_style());
+
+        let mut download = store.download(&id);
+
+        loop {
+            let event = tokio::select! {
+                event = download.next() => match event {
+                    Some(event) => event,
+                    None => break,
+                },
+                _ = &mut ctrl_c => {
+                    pb.set_style(status_style());
+                    pb.abandon_with_message("cancelled");
+                    return Err(Failure::Cancelled);
+                }
+            };
+
+            match event {
+                DownloadEvent::Error(e) => {
+                    pb.set_style(status_style());
+                    pb.abandon_with_message("download failed");
+                    return Err(Failure::Download(id, e));
+                }
+                DownloadEvent::Progress((current, total)) => {
+                    pb.set_length(total);
+                    pb.set_position(current);
+                }
+                DownloadEvent::Complete(_) => {
+                    pb.set_style(status_style());
+                    pb.set_message("downloaded");
+                }
+            }
+        }
+    }
+
+    let (canceler, mut tx) = transaction.process();
+    let mut current: Option<(PackageKey, &'static str)> = None;
+
+    loop {
+        let event = tokio::select! {
+            event = tx.next() => match event {
+                Some(event) => event,
+                None => break,
+            },
+            _ = &mut ctrl_c => {
+                canceler.cancel();
+                if let Some((key, _)) = current.take() {
+                    bar(&key).unwrap().abandon_with_message("cancelled");
+                }
+                return Err(Failure::Cancelled);
+            }
+        };
+
+        match event {
+            TransactionEvent::Installing(id) | TransactionEvent::Uninstalling(id)
+                if bar(&id).is_some() =>
+            {
+                if let Some((key, done)) = current.take() {
+                    bar(&key).unwrap().finish_with_message(done);
+                    completed.push(key);
+                }
+
+                let pb = bar(&id).unwrap();
+                let is_install = transaction
+                    .actions()
+                    .iter()
+                    .any(|x| x.action.id == id && x.action.is_install());
+                if is_install {
+                    pb.set_message("installing…");
+                    current = Some((id, "installed"));
+                } else {
+                    pb.set_message("uninstalling…");
+                    current = Some((id, "uninstalled"));
+                }
+            }
+            TransactionEvent::Installing(_) | TransactionEvent::Uninstalling(_) => {}
+            TransactionEvent::Progress(id, msg) => {
+                if let Some(pb) = bar(&id) {
+                    pb.set_message(&msg);
+                }
+            }
+            TransactionEvent::Error(id, err) => {
+                if let Some(pb) = bar(&id) {
+                    pb.abandon_with_message("failed");
+                }
+                return Err(Failure::Transaction(id, err));
+            }
+            TransactionEvent::Complete => {
+                if let Some((key, done)) = current.take() {
+                    bar(&key).unwrap().finish_with_message(done);
+                    completed.push(key);
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    // The stream ended without completing, so it was cancelled.
+    Err(Failure::Cancelled)
+}
+
+fn download_style() -> ProgressStyle {
+    ProgressStyle::default_bar()
+        .template("{prefix:.bold} [{bar:30.cyan/blue}] {bytes}/{total_bytes} ({eta})")
+        .progress_chars("=>-")
+}
+
+fn status_style() -> ProgressStyle {
+    ProgressStyle::default_bar().template("{prefix:.bold} {wide_msg}")
+}
+
+fn print_failure(failure: &Failure, transaction: &PackageTransaction, completed: &[PackageKey]) {
+    println!();
+    println!("The transaction did not complete.");
+
+    match failure {
+        Failure::Download(key, e) => {
+            println!("  Failed to download: {}", &key.id);
+            print_error(e);
+        }
+        Failure::Transaction(key, e) => {
+            match e {
+                TransactionError::Install(e) => {
+                    println!("  Failed to install: {}", &key.id);
+                    print_error(e);
+                }
+                TransactionError::Uninstall(e) => {
+                    println!("  Failed to uninstall: {}", &key.id);
+                    print_error(e);
+                }
+                e => println!("  Failed to process {}: {}", &key.id, e),
+            };
+        }
+        Failure::Cancelled => {
+            println!("  The transaction was cancelled.");
+        }
+    }
+
+    if !completed.is_empty() {
+        println!("  Completed:");
+        for key in completed.iter() {
+            println!("    {}", &key.id);
+        }
+    }
+
+    let failed = match failure {
+        Failure::Download(key, _) | Failure::Transaction(key, _) => Some(key),
+        Failure::Cancelled => None,
+    };
+    let skipped = transaction
+        .actions()
+        .iter()
+        .map(|x| &x.action.id)
+        .filter(|key| !completed.contains(*key) && Some(*key) != failed)
+        .map(|key| key.id.clone())
+        .collect::<Vec<_>>();
+
+    if !skipped.is_empty() {
+        println!("  Not processed:");
+        for id in skipped {
+            println!("    {}", id);
+        }
+    }
+}
+
+fn print_error(error: &(dyn Error + 'static)) {
+    println!("  Reason: {}", error);
+
+    let mut source = error.source();
+    while let Some(e) = source {
+        println!("    caused by: {}", e);
+
+        if let Some(ProcessError::Unknown(output)) = e.downcast_ref::<ProcessError>() {
+            println!("    exit status: {}", output.status);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            for line in stderr.trim().lines() {
+                println!("    | {}", line);
+            }
+        }
+
+        source = e.source();
+    }
+}
+
+pub(crate) fn print_plan(plan: &TransactionPlan) {
+    if plan.actions.is_empty() {
+        println!("Nothing to do.");
+        return;
+    }
+
+    println!("The following actions will be performed:");
+    for planned in plan.actions.iter() {
+        let verb = match (planned.action.action, planned.status) {
+            (PackageActionType::Install, PackageStatus::RequiresUpdate) => "upgrade",
+            (PackageActionType::Install, PackageStatus::RequiresDowngrade) => "downgrade",
+            (PackageActionType::Install, _) => "install",
+            (PackageActionType::Uninstall, _) => "uninstall",
+        };
+
+        println!(
+            "  {:<10} {} {}{}",
+            verb,
+            &planned.action.id.id,
+            &planned.version,
+            if planned.is_dependency {
+                " (dependency)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    println!();
+    println!(
+        "Download size: {}",
+        indicatif::HumanBytes(plan.download_size)
+    );
+    println!(
+        "Installed size change: {}{}",
+        if plan.installed_size_delta < 0 {
+            "-"
+        } else {
+            "+"
+        },
+        indicatif::HumanBytes(plan.installed_size_delta.abs() as u64)
+    );
+
+    if plan.is_reboot_required {
+        println!("A reboot will be required.");
+    }
+}
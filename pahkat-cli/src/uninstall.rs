@@ -1,20 +1,30 @@
-use std::path::Path;
+use std::sync::Arc;
 
-use pahkat_client::{package_store::InstallTarget, PackageStore};
+use pahkat_client::{
+    package_store::InstallTarget, transaction::PackageAction, PackageKey, PackageStore,
+};
 
-pub fn uninstall(
-    store: &dyn PackageStore,
+pub(crate) async fn uninstall(
+    store: Arc<dyn PackageStore>,
     packages: &Vec<String>,
     target: InstallTarget,
+    options: crate::transaction::Options,
 ) -> Result<(), anyhow::Error> {
-    for id in packages {
-        let pkg_key = store
-            .find_package_by_id(id)
-            .map(|x| x.0)
-            .ok_or_else(|| anyhow::anyhow!("Could not find package for: `{}`", id))?;
-        println!("Uninstalling {}", &pkg_key);
-        let status = store.uninstall(&pkg_key, target)?;
-        println!("{:?}", status);
-    }
+    let keys: Vec<PackageKey> = packages
+        .iter()
+        .map(|id| {
+            store
+                .find_package_by_id(id)
+                .map(|x| x.0)
+                .ok_or_else(|| anyhow::anyhow!("Could not find package for: `{}`", id))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let actions = keys
+        .into_iter()
+        .map(|x| PackageAction::uninstall(x, target))
+        .collect();
+
+    crate::transaction::process(store, actions, options).await?;
     Ok(())
 }
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use crate::Platform;
+use pahkat_client::{
+    package_store::InstallTarget, transaction::PackageAction, PackageKey, PackageStatus,
+    PackageStore,
+};
+
+pub(crate) async fn upgrade<'a>(
+    store: Arc<dyn PackageStore>,
+    packages: &'a Vec<String>,
+    target: InstallTarget,
+    options: crate::transaction::Options,
+    args: &'a crate::Args,
+) -> Result<(), anyhow::Error> {
+    let mut keys: Vec<PackageKey> = if packages.is_empty() {
+        // Upgrade everything that has an update available
+        let urls = {
+            let repos = store.repos();
+            let repos = repos.read().unwrap();
+            repos.keys().cloned().collect::<Vec<_>>()
+        };
+
+        let mut keys = vec![];
+        for url in urls {
+            for (id, status) in store.all_statuses(&url, target).into_iter() {
+                if let Ok(PackageStatus::RequiresUpdate) = status {
+                    keys.push(PackageKey {
+                        repository_url: url.clone(),
+                        id,
+                        query: Default::default(),
+                    });
+                }
+            }
+        }
+        keys
+    } else {
+        packages
+            .iter()
+            .map(|id| {
+                store
+                    .find_package_by_id(id)
+                    .map(|x| x.0)
+                    .ok_or_else(|| anyhow::anyhow!("Could not find package for: `{}`", id))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|key| match store.status(key, target) {
+                Ok(PackageStatus::RequiresUpdate) => true,
+                _ => {
+                    println!("{}: no update available", &key.id);
+                    false
+                }
+            })
+            .collect()
+    };
+
+    if let Some(platform) = args.platform() {
+        for key in keys.iter_mut() {
+            key.query.platform = Some(platform.to_string());
+        }
+    }
+
+    let actions = keys
+        .into_iter()
+        .map(|x| PackageAction::install(x, target))
+        .collect();
+
+    crate::transaction::process(store, actions, options).await?;
+    Ok(())
+}
@@ -13,6 +13,8 @@ pub(crate) fn cmp(
 
     if candidate_version > &installed_version {
         Ok(PackageStatus::RequiresUpdate)
+    } else if candidate_version < &installed_version {
+        Ok(PackageStatus::RequiresDowngrade)
     } else {
         Ok(PackageStatus::UpToDate)
     }
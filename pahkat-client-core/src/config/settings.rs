@@ -29,6 +29,9 @@ pub struct SettingsData {
     pub max_concurrent_downloads: u8,
     #[serde(default)]
     pub skip_admin_verification: bool,
+    /// Report successful installs to the repository they came from
+    #[serde(default)]
+    pub send_install_stats: bool,
 }
 
 impl Default for SettingsData {
@@ -38,6 +41,7 @@ impl Default for SettingsData {
             tmp_dir: tmp_dir_default(),
             max_concurrent_downloads: 0,
             skip_admin_verification: false,
+            send_install_stats: false,
         }
     }
 }
@@ -182,6 +186,10 @@ impl Settings {
         self.data.skip_admin_verification
     }
 
+    pub fn send_install_stats(&self) -> bool {
+        self.data.send_install_stats
+    }
+
     pub fn set_cache_dir(&mut self, cache_dir: ConfigPath) -> Result<(), FileError> {
         self.data.cache_dir = cache_dir;
 
@@ -211,4 +219,14 @@ impl Settings {
 
         Ok(())
     }
+
+    pub fn set_send_install_stats(&mut self, value: bool) -> Result<(), FileError> {
+        self.data.send_install_stats = value;
+
+        if self.permission == Permission::ReadWrite {
+            return self.data.save(&self.path);
+        }
+
+        Ok(())
+    }
 }
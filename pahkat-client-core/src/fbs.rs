@@ -14,6 +14,8 @@ pub(crate) trait TargetExt {
 
 pub(crate) trait PackagesExt<B: AsRef<[u8]>> {
     fn packages(&self) -> Option<Map<'_, &'_ str, pahkat_fbs::Descriptor<&'_ [u8]>>>;
+    fn synthetics(&self) -> Option<Map<'_, &'_ str, pahkat_fbs::Synthetic<&'_ [u8]>>>;
+    fn redirects(&self) -> Option<Map<'_, &'_ str, pahkat_fbs::Redirect<&'_ [u8]>>>;
 }
 
 impl PackagesExt<&'_ [u8]> for pahkat_fbs::Packages<&'_ [u8]> {
@@ -22,6 +24,18 @@ impl PackagesExt<&'_ [u8]> for pahkat_fbs::Packages<&'_ [u8]> {
         let values = self.packages_values().ok()??;
         Some(Map::new(keys, values))
     }
+
+    fn synthetics(&self) -> Option<Map<'_, &'_ str, pahkat_fbs::Synthetic<&'_ [u8]>>> {
+        let keys = self.synthetics_keys().ok()??;
+        let values = self.synthetics_values().ok()??;
+        Some(Map::new(keys, values))
+    }
+
+    fn redirects(&self) -> Option<Map<'_, &'_ str, pahkat_fbs::Redirect<&'_ [u8]>>> {
+        let keys = self.redirects_keys().ok()??;
+        let values = self.redirects_values().ok()??;
+        Some(Map::new(keys, values))
+    }
 }
 
 impl<B: AsRef<[u8]>> DescriptorExt for pahkat_fbs::Descriptor<B> {
@@ -38,6 +52,20 @@ impl<B: AsRef<[u8]>> DescriptorExt for pahkat_fbs::Descriptor<B> {
     }
 }
 
+impl<B: AsRef<[u8]>> DescriptorExt for pahkat_fbs::Synthetic<B> {
+    fn name(&self) -> Option<Map<'_, &'_ str, &'_ str>> {
+        let keys = self.name_keys().ok()??;
+        let values = self.name_values().ok()??;
+        Some(Map::new(keys, values))
+    }
+
+    fn description(&self) -> Option<Map<'_, &'_ str, &'_ str>> {
+        let keys = self.description_keys().ok()??;
+        let values = self.description_values().ok()??;
+        Some(Map::new(keys, values))
+    }
+}
+
 impl<B: AsRef<[u8]>> TargetExt for pahkat_fbs::Target<B> {
     fn dependencies(&self) -> Option<Map<'_, &'_ str, &'_ str>> {
         let keys = self.dependencies_keys().ok()??;
@@ -46,13 +74,16 @@ impl<B: AsRef<[u8]>> TargetExt for pahkat_fbs::Target<B> {
     }
 }
 
-fn build_target<B: AsRef<[u8]>>(
-    t: &pahkat_fbs::Target<B>,
-) -> Result<pahkat_types::payload::Target, fbs::Error> {
-    let platform = t.platform()?.to_string();
-    let arch = t.arch()?.map(str::to_string);
-    let dependencies = t
-        .dependencies()
+impl<B: AsRef<[u8]>> TargetExt for pahkat_fbs::SyntheticTarget<B> {
+    fn dependencies(&self) -> Option<Map<'_, &'_ str, &'_ str>> {
+        let keys = self.dependencies_keys().ok()??;
+        let values = self.dependencies_values().ok()??;
+        Some(Map::new(keys, values))
+    }
+}
+
+fn build_dependencies<T: TargetExt>(t: &T) -> pahkat_types::DependencyMap {
+    t.dependencies()
         .map(|x| {
             let mut out = std::collections::BTreeMap::new();
             for (k, v) in x.iter() {
@@ -60,7 +91,26 @@ fn build_target<B: AsRef<[u8]>>(
             }
             out
         })
-        .unwrap_or_else(|| Default::default());
+        .unwrap_or_else(|| Default::default())
+}
+
+fn build_lang_map(map: Option<Map<'_, &'_ str, &'_ str>>) -> pahkat_types::LangTagMap<String> {
+    map.map(|x| {
+        let mut out = std::collections::BTreeMap::new();
+        for (k, v) in x.iter() {
+            out.insert(k.to_string(), v.to_string());
+        }
+        out
+    })
+    .unwrap_or_else(|| Default::default())
+}
+
+fn build_target<B: AsRef<[u8]>>(
+    t: &pahkat_fbs::Target<B>,
+) -> Result<pahkat_types::payload::Target, fbs::Error> {
+    let platform = t.platform()?.to_string();
+    let arch = t.arch()?.map(str::to_string);
+    let dependencies = build_dependencies(t);
     let payload = match t.payload()? {
         pahkat_fbs::Payload::WindowsExecutable(x) => {
             pahkat_types::payload::Payload::WindowsExecutable(
@@ -163,6 +213,7 @@ impl<'a> TryFrom<&'a pahkat_fbs::Descriptor<&'a [u8]>> for pahkat_types::package
                                     .map(|t| build_target(&t))
                                     .collect::<Result<Vec<_>, _>>()?,
                             )
+                            .yanked(x.yanked()?.unwrap_or(false))
                             .build();
                         Ok(release)
                     })
@@ -174,6 +225,133 @@ impl<'a> TryFrom<&'a pahkat_fbs::Descriptor<&'a [u8]>> for pahkat_types::package
     }
 }
 
+fn build_verifier<B: AsRef<[u8]>>(
+    t: &pahkat_fbs::SyntheticTarget<B>,
+) -> Result<pahkat_types::synth::Verifier, fbs::Error> {
+    use pahkat_types::synth::{macos, windows, Verifier};
+
+    let verifier = match t.verifier()? {
+        pahkat_fbs::Verifier::WindowsRegistryKey(x) => Verifier::WindowsRegistryKey(
+            windows::RegistryKey::builder()
+                .path(x.path()?.to_string())
+                .name(x.name()?.to_string())
+                .build(),
+        ),
+        pahkat_fbs::Verifier::MacOSPackageRef(x) => Verifier::MacOSPackageRef(
+            macos::PackageRef::builder()
+                .pkg_id(x.pkg_id()?.to_string())
+                .min_version(x.min_version()?.map(str::to_string))
+                .max_version(x.max_version()?.map(str::to_string))
+                .min_build(x.min_build()?.map(str::to_string))
+                .max_build(x.max_build()?.map(str::to_string))
+                .build(),
+        ),
+        pahkat_fbs::Verifier::MacOSPathRef(x) => Verifier::MacOSPathRef(
+            macos::PathRef::builder()
+                .app_paths(
+                    x.app_paths()?
+                        .map(|x| {
+                            x.iter()
+                                .filter_map(Result::ok)
+                                .map(str::to_string)
+                                .collect()
+                        })
+                        .unwrap_or(vec![]),
+                )
+                .min_version(x.min_version()?.map(str::to_string))
+                .max_version(x.max_version()?.map(str::to_string))
+                .min_build(x.min_build()?.map(str::to_string))
+                .max_build(x.max_build()?.map(str::to_string))
+                .build(),
+        ),
+    };
+
+    Ok(verifier)
+}
+
+impl<'a> TryFrom<&'a pahkat_fbs::Synthetic<&'a [u8]>> for pahkat_types::synth::Descriptor {
+    type Error = fbs::Error;
+
+    fn try_from(pkg: &'a pahkat_fbs::Synthetic<&'a [u8]>) -> Result<Self, Self::Error> {
+        let releases = match pkg.release()? {
+            Some(releases) => releases
+                .iter()
+                .filter_map(Result::ok)
+                .map(|x| {
+                    let targets = match x.target()? {
+                        Some(targets) => targets
+                            .iter()
+                            .filter_map(Result::ok)
+                            .map(|t| {
+                                Ok(pahkat_types::synth::Target::builder()
+                                    .platform(t.platform()?.to_string())
+                                    .arch(t.arch()?.map(str::to_string))
+                                    .dependencies(build_dependencies(&t))
+                                    .verifier(build_verifier(&t)?)
+                                    .build())
+                            })
+                            .collect::<Result<Vec<_>, fbs::Error>>()?,
+                        None => vec![],
+                    };
+
+                    Ok(pahkat_types::synth::Release::builder()
+                        .version(x.version()?.to_string())
+                        .channel(x.channel()?.to_string())
+                        .targets(targets)
+                        .build())
+                })
+                .collect::<Result<Vec<_>, fbs::Error>>()?,
+            None => vec![],
+        };
+
+        let descriptor = pahkat_types::synth::Descriptor::builder()
+            .synthetic(
+                pahkat_types::synth::SyntheticData::builder()
+                    .id(pkg.id()?.into())
+                    .tags(
+                        pkg.tags()?
+                            .map(|tags| tags.iter().map(|x| x.unwrap_or("").to_string()).collect())
+                            .unwrap_or(vec![]),
+                    )
+                    .build(),
+            )
+            .name(build_lang_map(pkg.name()))
+            .description(build_lang_map(pkg.description()))
+            .releases(releases)
+            .build();
+
+        Ok(descriptor)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RedirectError {
+    #[error("Invalid index data")]
+    Fbs(#[from] fbs::Error),
+
+    #[error("Invalid redirect URL `{0}`")]
+    Url(String, #[source] url::ParseError),
+}
+
+pub(crate) fn build_redirect<B: AsRef<[u8]>>(
+    id: &str,
+    redirect: &pahkat_fbs::Redirect<B>,
+) -> Result<pahkat_types::package::Redirect, RedirectError> {
+    let url = redirect.url()?;
+    let url = url
+        .parse::<url::Url>()
+        .map_err(|e| RedirectError::Url(url.to_string(), e))?;
+
+    Ok(pahkat_types::package::Redirect::builder()
+        .redirect(
+            pahkat_types::package::RedirectData::builder()
+                .id(id.to_string())
+                .url(url)
+                .build(),
+        )
+        .build())
+}
+
 pub struct Map<'a, K, V> {
     keys: fbs::Vector<'a, fbs::ForwardsUOffset<K>>,
     values: fbs::Vector<'a, fbs::ForwardsUOffset<V>>,
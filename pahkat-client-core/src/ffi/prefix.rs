@@ -215,6 +215,13 @@ pub extern "C" fn pahkat_prefix_transaction_actions(
     handle.actions().to_vec()
 }
 
+#[cffi::marshal(return_marshaler = "JsonMarshaler")]
+pub extern "C" fn pahkat_prefix_transaction_plan(
+    #[marshal(cffi::BoxRefMarshaler::<PackageTransaction>)] handle: &PackageTransaction,
+) -> crate::transaction::TransactionPlan {
+    handle.plan()
+}
+
 #[cffi::marshal(return_marshaler = "cffi::UnitMarshaler")]
 pub extern "C" fn pahkat_prefix_transaction_process(
     #[marshal(cffi::BoxRefMarshaler::<PackageTransaction>)] handle: &PackageTransaction,
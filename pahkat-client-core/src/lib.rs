@@ -9,6 +9,7 @@ pub mod ffi;
 
 pub mod config;
 pub mod defaults;
+pub mod manifest;
 pub mod package_store;
 pub mod repo;
 pub mod transaction;
@@ -17,12 +18,16 @@ mod cmp;
 mod download;
 mod ext;
 mod fbs;
+mod stats;
 
 pub use self::config::{Config, Permission};
-pub use self::download::Download;
+pub use self::download::{Download, DownloadError};
 pub use self::package_store::{DownloadEvent, InstallTarget, PackageStore};
 pub use self::repo::{LoadedRepository, PackageKey};
-pub use self::transaction::{PackageAction, PackageActionType, PackageStatus, PackageTransaction};
+pub use self::transaction::{
+    PackageAction, PackageActionType, PackageStatus, PackageTransaction, TransactionOptions,
+    TransactionPlan,
+};
 
 #[cfg(all(target_os = "macos", feature = "macos"))]
 pub use package_store::macos::MacOSPackageStore;
@@ -0,0 +1,839 @@
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::config::FileError;
+use crate::package_store::{InstallTarget, PackageStore};
+use crate::repo::{PayloadError, ReleaseQuery};
+use crate::transaction::{PackageAction, PackageStatus, PackageStatusError};
+use pahkat_types::package::{Package, Release, Version};
+use pahkat_types::payload::Target;
+use pahkat_types::repo::RepoUrl;
+use pahkat_types::{DependencyKey, PackageKey};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("Could not determine status for package: `{0}`")]
+    Status(PackageKey, #[source] PackageStatusError),
+
+    #[error("Could not resolve payload for package: `{0}`")]
+    Payload(PackageKey, #[source] PayloadError),
+
+    #[error("Locked URL for package `{0}` no longer matches the repository: {1}")]
+    UrlMismatch(PackageKey, Url),
+}
+
+/// A list of packages a store is expected to have installed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default, rename = "package", skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<ManifestPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestPackage {
+    pub key: PackageKey,
+
+    /// A version requirement, such as `1.2.3` or `^1.2`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+
+    #[serde(default)]
+    pub target: InstallTarget,
+}
+
+impl ManifestPackage {
+    /// The key to resolve this entry with, including its version requirement and channel.
+    pub fn package_key(&self) -> PackageKey {
+        let mut key = self.key.clone();
+
+        if let Some(version) = self.version.as_ref() {
+            key.query.version = Some(version.clone());
+        }
+
+        if let Some(channel) = self.channel.as_ref() {
+            key.query.channel = Some(channel.clone());
+        }
+
+        key
+    }
+}
+
+/// The exact releases a manifest resolved to, for reproducible re-application.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default, rename = "package", skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub key: PackageKey,
+    pub version: Version,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+
+    pub url: Url,
+
+    #[serde(default)]
+    pub target: InstallTarget,
+}
+
+impl LockedPackage {
+    /// The key to resolve this entry with, pinned to the exact locked version.
+    pub fn package_key(&self) -> PackageKey {
+        let mut key = self.key.clone();
+        key.query.version = Some(self.version.to_string());
+        key.query.channel = self.channel.clone();
+        key
+    }
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Manifest, FileError> {
+        load(path.as_ref())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FileError> {
+        save(path.as_ref(), self)
+    }
+
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string_pretty(self)
+    }
+
+    /// Creates a manifest of every package currently installed in the store,
+    /// pinned to the installed version and its channel where they can be found.
+    pub fn from_store(store: &dyn PackageStore, target: InstallTarget) -> Manifest {
+        let packages = installed_keys(store, target)
+            .into_iter()
+            .map(|key| {
+                let (version, channel) = match installed_release(store, &key, target) {
+                    Some((version, channel)) => (Some(version.to_string()), channel),
+                    None => (None, None),
+                };
+
+                ManifestPackage {
+                    key,
+                    version,
+                    channel,
+                    target,
+                }
+            })
+            .collect();
+
+        Manifest { packages }
+    }
+
+    /// Returns the actions required to make the store match this manifest.
+    ///
+    /// If `prune` is true, installed packages not listed in the manifest are uninstalled.
+    pub fn actions(
+        &self,
+        store: &dyn PackageStore,
+        prune: bool,
+    ) -> Result<Vec<PackageAction>, ManifestError> {
+        let entries = self
+            .packages
+            .iter()
+            .map(|x| (x.package_key(), x.target))
+            .collect::<Vec<_>>();
+        actions(store, &entries, prune)
+    }
+}
+
+impl Lockfile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Lockfile, FileError> {
+        load(path.as_ref())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), FileError> {
+        save(path.as_ref(), self)
+    }
+
+    /// Resolves every package in the manifest, and everything they depend on,
+    /// to the release it would install.
+    pub fn resolve(
+        store: &dyn PackageStore,
+        manifest: &Manifest,
+    ) -> Result<Lockfile, ManifestError> {
+        let mut packages = vec![];
+        let mut locked = HashSet::new();
+
+        // Listed packages come first, so a dependency that is also listed is
+        // locked with the listed version requirement and target
+        let mut pending = manifest
+            .packages
+            .iter()
+            .map(|x| (x.package_key(), x.target))
+            .collect::<VecDeque<_>>();
+
+        while let Some((key, target)) = pending.pop_front() {
+            if !locked.insert((key.repository_url.clone(), key.id.clone())) {
+                continue;
+            }
+
+            let (payload, release) =
+                resolve_release(store, &key).map_err(|e| ManifestError::Payload(key.clone(), e))?;
+            let dependencies = payload.dependencies.keys().cloned().collect();
+            pending.extend(
+                dependency_keys(store, dependencies)
+                    .into_iter()
+                    .map(|x| (x, target)),
+            );
+
+            packages.push(LockedPackage {
+                key: key.without_query_params(),
+                version: release.version,
+                channel: release.channel,
+                url: payload.payload.url().clone(),
+                target,
+            });
+        }
+
+        Ok(Lockfile { packages })
+    }
+
+    /// Returns the actions required to make the store match this lockfile.
+    ///
+    /// Fails if any locked release now resolves to a different URL than the one recorded.
+    pub fn actions(
+        &self,
+        store: &dyn PackageStore,
+        prune: bool,
+    ) -> Result<Vec<PackageAction>, ManifestError> {
+        for package in self.packages.iter() {
+            let key = package.package_key();
+            let (target, _) =
+                resolve_release(store, &key).map_err(|e| ManifestError::Payload(key.clone(), e))?;
+
+            if target.payload.url() != &package.url {
+                return Err(ManifestError::UrlMismatch(
+                    key,
+                    target.payload.url().clone(),
+                ));
+            }
+        }
+
+        let entries = self
+            .packages
+            .iter()
+            .map(|x| (x.package_key(), x.target))
+            .collect::<Vec<_>>();
+        actions(store, &entries, prune)
+    }
+}
+
+fn load<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, FileError> {
+    let file = std::fs::read_to_string(path).map_err(|e| FileError::Read(e, path.to_path_buf()))?;
+    toml::from_str(&file).map_err(|e| FileError::FromToml(e, path.to_path_buf()))
+}
+
+fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), FileError> {
+    let data =
+        toml::to_string_pretty(value).map_err(|e| FileError::ToToml(e, path.to_path_buf()))?;
+    std::fs::write(path, data).map_err(|e| FileError::Write(e, path.to_path_buf()))
+}
+
+fn installed_keys(store: &dyn PackageStore, target: InstallTarget) -> Vec<PackageKey> {
+    let urls = {
+        let repos = store.repos();
+        let repos = repos.read().unwrap();
+        repos.keys().cloned().collect::<Vec<_>>()
+    };
+
+    urls.into_iter()
+        .flat_map(|url| {
+            store.all_statuses(&url, target).into_iter().filter_map(
+                move |(id, status)| match status {
+                    Ok(PackageStatus::NotInstalled) | Err(_) => None,
+                    Ok(_) => Some(PackageKey::new_unchecked(url.clone(), id, None)),
+                },
+            )
+        })
+        .collect()
+}
+
+/// The target and release the key resolves to in the store.
+fn resolve_release(
+    store: &dyn PackageStore,
+    key: &PackageKey,
+) -> Result<(Target, Release), PayloadError> {
+    let descriptor = match store.find_package_by_key(key) {
+        Some(Package::Concrete(v)) => v,
+        Some(_) => return Err(PayloadError::NoConcretePackage),
+        None => return Err(PayloadError::NoPackage),
+    };
+
+    let repos = store.repos();
+    let repos = repos.read().unwrap();
+    let query = ReleaseQuery::new(key, &*repos);
+    let result = query
+        .iter(&descriptor)
+        .next()
+        .map(|x| (x.target.clone(), x.release.clone()))
+        .ok_or(PayloadError::NoPayloadFound);
+    result
+}
+
+/// The version and channel of the release that is installed, found by asking
+/// the store which of the package's releases it considers up to date.
+fn installed_release(
+    store: &dyn PackageStore,
+    key: &PackageKey,
+    target: InstallTarget,
+) -> Option<(Version, Option<String>)> {
+    let descriptor = match store.find_package_by_key(key) {
+        Some(Package::Concrete(v)) => v,
+        _ => return None,
+    };
+
+    let candidates = {
+        let repos = store.repos();
+        let repos = repos.read().unwrap();
+        let mut query = ReleaseQuery::new(key, &*repos).and_yanked();
+        // The installed release may be from any channel, or yanked since
+        query.channels = descriptor
+            .release
+            .iter()
+            .filter_map(|x| x.channel.as_deref())
+            .collect();
+        let result = query
+            .iter(&descriptor)
+            .map(|x| (x.release.version.clone(), x.release.channel.clone()))
+            .collect::<Vec<_>>();
+        result
+    };
+
+    candidates.into_iter().find(|(version, channel)| {
+        let mut key = key.clone();
+        key.query.version = Some(version.to_string());
+        key.query.channel = channel.clone();
+        match store.status(&key, target) {
+            Ok(PackageStatus::UpToDate) => true,
+            _ => false,
+        }
+    })
+}
+
+/// The packages the entries resolve to and everything they depend on, so
+/// pruning keeps dependencies that were installed along with them.
+fn with_dependencies(
+    store: &dyn PackageStore,
+    entries: &[(PackageKey, InstallTarget)],
+) -> HashSet<(RepoUrl, String)> {
+    let mut listed = HashSet::new();
+    let mut pending = entries.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+
+    while let Some(key) = pending.pop() {
+        if listed.insert((key.repository_url.clone(), key.id.clone())) {
+            pending.extend(dependencies(store, &key));
+        }
+    }
+
+    listed
+}
+
+/// The dependencies of the release the key resolves to.
+fn dependencies(store: &dyn PackageStore, key: &PackageKey) -> Vec<PackageKey> {
+    let descriptor = match store.find_package_by_key(key) {
+        Some(Package::Concrete(v)) => v,
+        _ => return vec![],
+    };
+
+    let dependencies = {
+        let repos = store.repos();
+        let repos = repos.read().unwrap();
+        let query = ReleaseQuery::new(key, &*repos);
+        let result = match query.iter(&descriptor).next() {
+            Some(v) => v.target.dependencies.keys().cloned().collect::<Vec<_>>(),
+            None => vec![],
+        };
+        result
+    };
+
+    dependency_keys(store, dependencies)
+}
+
+fn dependency_keys(store: &dyn PackageStore, dependencies: Vec<DependencyKey>) -> Vec<PackageKey> {
+    dependencies
+        .into_iter()
+        .filter_map(|dependency| match dependency {
+            DependencyKey::Remote(url) => PackageKey::try_from(url).ok(),
+            DependencyKey::Local(id) => store.find_package_by_id(&id).map(|x| x.0),
+        })
+        .collect()
+}
+
+fn actions(
+    store: &dyn PackageStore,
+    entries: &[(PackageKey, InstallTarget)],
+    prune: bool,
+) -> Result<Vec<PackageAction>, ManifestError> {
+    let mut actions = vec![];
+
+    for (key, target) in entries.iter() {
+        let status = store
+            .status(key, *target)
+            .map_err(|e| ManifestError::Status(key.clone(), e))?;
+
+        match status {
+            PackageStatus::NotInstalled | PackageStatus::RequiresUpdate => {
+                actions.push(PackageAction::install(key.clone(), *target));
+            }
+            PackageStatus::RequiresDowngrade if key.query.version.is_some() => {
+                actions.push(PackageAction::install(key.clone(), *target));
+            }
+            PackageStatus::UpToDate | PackageStatus::RequiresDowngrade => {}
+        }
+    }
+
+    if prune {
+        let targets = entries
+            .iter()
+            .map(|x| x.1)
+            .chain(std::iter::once(InstallTarget::System))
+            .collect::<HashSet<_>>();
+        let listed = with_dependencies(store, entries);
+
+        for target in targets {
+            for key in installed_keys(store, target) {
+                if !listed.contains(&(key.repository_url.clone(), key.id.clone())) {
+                    actions.push(PackageAction::uninstall(key, target));
+                }
+            }
+        }
+    }
+
+    Ok(actions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, RwLock};
+
+    use hashbrown::HashMap;
+    use pahkat_types::package::Descriptor;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::package_store::{
+        DownloadEvent, ImportError, LocalizedStrings, ProgressEvent, SharedRepoErrors, SharedRepos,
+        SharedStoreConfig, Stream,
+    };
+    use crate::repo::{PackageQuery, RepoDownloadError};
+    use crate::transaction::{
+        install::InstallError, uninstall::UninstallError, PackageActionType,
+        PackageDependencyStatusError, ResolvedPackageQuery,
+    };
+    use crate::LoadedRepository;
+
+    const REPO_URL: &str = "https://example.com/repo/";
+
+    /// A store over one repository that installs a release by recording its
+    /// version, without downloading or unpacking anything.
+    struct TestStore {
+        repo_url: RepoUrl,
+        repos: SharedRepos,
+        errors: SharedRepoErrors,
+        config: SharedStoreConfig,
+        packages: Vec<Descriptor>,
+        installed: RwLock<HashMap<String, Version>>,
+    }
+
+    impl TestStore {
+        fn new(packages: Vec<Descriptor>) -> TestStore {
+            let repo_url: RepoUrl = REPO_URL.parse().unwrap();
+            let info = toml::from_str(&format!(
+                "[repository]\nurl = \"{}\"\n\n[agent]\nname = \"test\"\nversion = \"0.0.0\"\n",
+                REPO_URL
+            ))
+            .unwrap();
+            let repo = LoadedRepository {
+                info,
+                packages: vec![].into_boxed_slice(),
+                meta: serde_json::from_str("{\"channel\": null}").unwrap(),
+            };
+
+            let mut repos = HashMap::new();
+            repos.insert(repo_url.clone(), repo);
+
+            TestStore {
+                repo_url,
+                repos: Arc::new(RwLock::new(repos)),
+                errors: Default::default(),
+                config: Arc::new(RwLock::new(Config::read_only())),
+                packages,
+                installed: Default::default(),
+            }
+        }
+
+        /// Installs the releases each key resolves to.
+        fn with_installed(self, keys: &[PackageKey]) -> TestStore {
+            for key in keys {
+                self.install(key, InstallTarget::System).unwrap();
+            }
+            self
+        }
+
+        fn installed_version(&self, id: &str) -> Option<String> {
+            let installed = self.installed.read().unwrap();
+            installed.get(id).map(|x| x.to_string())
+        }
+    }
+
+    impl PackageStore for TestStore {
+        fn repos(&self) -> SharedRepos {
+            Arc::clone(&self.repos)
+        }
+
+        fn errors(&self) -> SharedRepoErrors {
+            Arc::clone(&self.errors)
+        }
+
+        fn config(&self) -> SharedStoreConfig {
+            Arc::clone(&self.config)
+        }
+
+        fn download(&self, key: &PackageKey) -> Stream<DownloadEvent> {
+            let event = match resolve_release(self, key) {
+                Ok((target, _)) => {
+                    ProgressEvent::Complete(PathBuf::from(target.payload.url().path()))
+                }
+                Err(_) => ProgressEvent::Complete(PathBuf::new()),
+            };
+            Box::pin(futures::stream::iter(vec![event]))
+        }
+
+        fn import(&self, _key: &PackageKey, path: &Path) -> Result<PathBuf, ImportError> {
+            Ok(path.to_path_buf())
+        }
+
+        fn install(
+            &self,
+            key: &PackageKey,
+            _target: InstallTarget,
+        ) -> Result<PackageStatus, InstallError> {
+            let (_, release) = resolve_release(self, key)?;
+            let mut installed = self.installed.write().unwrap();
+            installed.insert(key.id.clone(), release.version);
+            Ok(PackageStatus::UpToDate)
+        }
+
+        fn uninstall(
+            &self,
+            key: &PackageKey,
+            _target: InstallTarget,
+        ) -> Result<PackageStatus, UninstallError> {
+            let mut installed = self.installed.write().unwrap();
+            match installed.remove(&key.id) {
+                Some(_) => Ok(PackageStatus::NotInstalled),
+                None => Err(UninstallError::NotInstalled),
+            }
+        }
+
+        fn status(
+            &self,
+            key: &PackageKey,
+            _target: InstallTarget,
+        ) -> Result<PackageStatus, PackageStatusError> {
+            let installed = match self.installed_version(&key.id) {
+                Some(v) => v,
+                None => return Ok(PackageStatus::NotInstalled),
+            };
+
+            let (_, release) = resolve_release(self, key).map_err(PackageStatusError::Payload)?;
+            crate::cmp::cmp(&installed, &release.version)
+        }
+
+        fn dependency_status(
+            &self,
+            key: &PackageKey,
+            target: InstallTarget,
+        ) -> Result<Vec<(PackageKey, PackageStatus)>, PackageDependencyStatusError> {
+            let (payload, _) = resolve_release(self, key)
+                .map_err(|e| PackageDependencyStatusError::Payload(key.clone(), e))?;
+
+            let dependencies = payload.dependencies.keys().cloned().collect();
+            dependency_keys(self, dependencies)
+                .into_iter()
+                .map(|key| match self.status(&key, target) {
+                    Ok(status) => Ok((key, status)),
+                    Err(PackageStatusError::Payload(e)) => {
+                        Err(PackageDependencyStatusError::Payload(key, e))
+                    }
+                    Err(_) => Err(PackageDependencyStatusError::ParsingVersion(key)),
+                })
+                .collect()
+        }
+
+        fn all_statuses(
+            &self,
+            repo_url: &RepoUrl,
+            target: InstallTarget,
+        ) -> BTreeMap<String, Result<PackageStatus, PackageStatusError>> {
+            if repo_url != &self.repo_url {
+                return BTreeMap::new();
+            }
+
+            self.packages
+                .iter()
+                .map(|x| {
+                    let id = x.package.id.clone();
+                    let status = self.status(&key(&id), target);
+                    (id, status)
+                })
+                .collect()
+        }
+
+        fn find_package_by_id(&self, package_id: &str) -> Option<(PackageKey, Package)> {
+            let key = key(package_id);
+            self.find_package_by_key(&key).map(|x| (key, x))
+        }
+
+        fn find_package_by_key(&self, key: &PackageKey) -> Option<Package> {
+            if key.repository_url != self.repo_url {
+                return None;
+            }
+
+            self.packages
+                .iter()
+                .find(|x| x.package.id == key.id)
+                .map(|x| Package::Concrete(x.clone()))
+        }
+
+        fn refresh_repos(
+            &self,
+        ) -> crate::package_store::Future<Result<(), HashMap<RepoUrl, RepoDownloadError>>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn clear_cache(&self) {}
+
+        fn strings(
+            &self,
+            _language: String,
+        ) -> crate::package_store::Future<HashMap<RepoUrl, LocalizedStrings>> {
+            Box::pin(async { HashMap::new() })
+        }
+
+        fn resolve_package_query(
+            &self,
+            query: PackageQuery,
+            install_target: &[InstallTarget],
+        ) -> ResolvedPackageQuery {
+            let repos = self.repos();
+            let repos = repos.read().unwrap();
+            crate::repo::resolve_package_query(self, &query, install_target, &*repos)
+        }
+    }
+
+    /// A package with a release per `(version, channel)`, each depending on
+    /// `dependencies`.
+    fn descriptor(
+        id: &str,
+        releases: &[(&str, Option<&str>)],
+        dependencies: &[&str],
+    ) -> Descriptor {
+        let dependencies = dependencies
+            .iter()
+            .map(|x| format!("\"{}\" = \"*\"\n", x))
+            .collect::<String>();
+
+        let releases = releases
+            .iter()
+            .map(|(version, channel)| {
+                format!(
+                    r#"
+[[release]]
+version = "{version}"
+{channel}
+
+[[release.target]]
+platform = "{platform}"
+
+[release.target.dependencies]
+{dependencies}
+[release.target.payload]
+type = "TarballPackage"
+url = "https://example.com/{id}-{version}.txz"
+size = 1
+installed_size = 1
+"#,
+                    id = id,
+                    version = version,
+                    channel = channel
+                        .map(|x| format!("channel = \"{}\"", x))
+                        .unwrap_or_default(),
+                    platform = crate::defaults::platform(),
+                    dependencies = dependencies,
+                )
+            })
+            .collect::<String>();
+
+        toml::from_str(&format!("[package]\nid = \"{}\"\n{}", id, releases)).unwrap()
+    }
+
+    fn key(id: &str) -> PackageKey {
+        PackageKey::new_unchecked(REPO_URL.parse().unwrap(), id.to_string(), None)
+    }
+
+    fn manifest_package(id: &str, version: Option<&str>) -> ManifestPackage {
+        ManifestPackage {
+            key: key(id),
+            version: version.map(str::to_string),
+            channel: None,
+            target: InstallTarget::System,
+        }
+    }
+
+    #[test]
+    fn prune_keeps_dependencies_of_listed_packages() {
+        let store = TestStore::new(vec![
+            descriptor("app", &[("1.0.0", None)], &["lib"]),
+            descriptor("lib", &[("1.0.0", None)], &["base"]),
+            descriptor("base", &[("1.0.0", None)], &[]),
+            descriptor("stray", &[("1.0.0", None)], &[]),
+        ])
+        .with_installed(&[key("app"), key("lib"), key("base"), key("stray")]);
+
+        let manifest = Manifest {
+            packages: vec![manifest_package("app", None)],
+        };
+
+        let actions = manifest.actions(&store, true).unwrap();
+
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].id.id, "stray");
+        assert_eq!(actions[0].action, PackageActionType::Uninstall);
+    }
+
+    #[test]
+    fn lockfile_locks_dependencies() {
+        let store = TestStore::new(vec![
+            descriptor("app", &[("1.0.0", None), ("2.0.0", None)], &["lib"]),
+            descriptor("lib", &[("1.0.0", None), ("1.1.0", None)], &["base"]),
+            descriptor("base", &[("3.0.0", None)], &[]),
+            descriptor("stray", &[("1.0.0", None)], &[]),
+        ]);
+
+        let manifest = Manifest {
+            packages: vec![manifest_package("app", Some("1.0.0"))],
+        };
+        let lockfile = Lockfile::resolve(&store, &manifest).unwrap();
+
+        let locked = lockfile
+            .packages
+            .iter()
+            .map(|x| format!("{}@{} {}", x.key.id, x.version, x.url))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            locked,
+            vec![
+                "app@1.0.0 https://example.com/app-1.0.0.txz",
+                "lib@1.1.0 https://example.com/lib-1.1.0.txz",
+                "base@3.0.0 https://example.com/base-3.0.0.txz",
+            ]
+        );
+    }
+
+    #[test]
+    fn lockfile_pins_dependencies_when_applied() {
+        let store = TestStore::new(vec![
+            descriptor("app", &[("1.0.0", None)], &["lib"]),
+            descriptor("lib", &[("1.0.0", None)], &[]),
+        ]);
+
+        let manifest = Manifest {
+            packages: vec![manifest_package("app", None)],
+        };
+        let lockfile = Lockfile::resolve(&store, &manifest).unwrap();
+
+        // A newer dependency is published after locking
+        let mut store = store;
+        store.packages[1] = descriptor("lib", &[("1.0.0", None), ("1.1.0", None)], &[]);
+
+        let actions = lockfile.actions(&store, false).unwrap();
+        let keys = actions
+            .iter()
+            .map(|x| (x.id.id.as_str(), x.id.query.version.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![("app", Some("1.0.0")), ("lib", Some("1.0.0"))]);
+    }
+
+    #[test]
+    fn lockfile_listed_dependency_keeps_its_requirement() {
+        let store = TestStore::new(vec![
+            descriptor("app", &[("1.0.0", None)], &["lib"]),
+            descriptor("lib", &[("1.0.0", None), ("1.1.0", None)], &[]),
+        ]);
+
+        let manifest = Manifest {
+            packages: vec![
+                manifest_package("app", None),
+                manifest_package("lib", Some("1.0.0")),
+            ],
+        };
+        let lockfile = Lockfile::resolve(&store, &manifest).unwrap();
+
+        assert_eq!(lockfile.packages.len(), 2);
+        assert_eq!(lockfile.packages[1].key.id, "lib");
+        assert_eq!(lockfile.packages[1].version.to_string(), "1.0.0");
+    }
+
+    #[test]
+    fn from_store_records_installed_release() {
+        let store = TestStore::new(vec![
+            descriptor(
+                "app",
+                &[
+                    ("1.0.0", None),
+                    ("2.0.0", None),
+                    ("2.1.0-beta", Some("beta")),
+                ],
+                &[],
+            ),
+            descriptor("lib", &[("1.0.0", None), ("1.1.0", None)], &[]),
+            descriptor("stray", &[("1.0.0", None)], &[]),
+        ]);
+
+        let mut beta = key("app");
+        beta.query.channel = Some("beta".into());
+        let mut old = key("lib");
+        old.query.version = Some("1.0.0".into());
+        let store = store.with_installed(&[beta, old]);
+
+        let manifest = Manifest::from_store(&store, InstallTarget::System);
+
+        let packages = manifest
+            .packages
+            .iter()
+            .map(|x| {
+                (
+                    x.key.id.as_str(),
+                    x.version.as_deref(),
+                    x.channel.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            packages,
+            vec![
+                ("app", Some("2.1.0-beta"), Some("beta")),
+                ("lib", Some("1.0.0"), None),
+            ]
+        );
+
+        // Applying the export to the same store changes nothing
+        assert!(manifest.actions(&store, true).unwrap().is_empty());
+    }
+}
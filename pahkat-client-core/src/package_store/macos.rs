@@ -10,6 +10,7 @@ use std::sync::{Arc, RwLock};
 use hashbrown::HashMap;
 use pahkat_types::package::Package;
 use pahkat_types::repo::RepoUrl;
+use pahkat_types::synth::Verifier;
 use serde::de::{self, Deserializer};
 use serde::Deserialize;
 use url::Url;
@@ -106,7 +107,8 @@ impl PackageStore for MacOSPackageStore {
         install_target: InstallTarget,
     ) -> Result<PackageStatus, UninstallError> {
         let repos = self.repos.read().unwrap();
-        let query = crate::repo::ReleaseQuery::new(key, &*repos);
+        // An installed release can still be uninstalled after it is yanked
+        let query = crate::repo::ReleaseQuery::new(key, &*repos).and_yanked();
 
         let (target, release, descriptor) =
             crate::repo::resolve_payload(key, &query, &*repos).map_err(UninstallError::Payload)?;
@@ -153,6 +155,12 @@ impl PackageStore for MacOSPackageStore {
         let repos = self.repos.read().unwrap();
         let query = crate::repo::ReleaseQuery::new(key, &*repos);
 
+        if let Some((target, version)) = crate::repo::resolve_synthetic(key, &query, &*repos)
+            .map_err(PackageStatusError::Payload)?
+        {
+            return synthetic_status(&target.verifier, &version, install_target);
+        }
+
         let (target, release, descriptor) = crate::repo::resolve_payload(key, &query, &*repos)
             .map_err(PackageStatusError::Payload)?;
         let installer = match target.payload {
@@ -177,7 +185,8 @@ impl PackageStore for MacOSPackageStore {
 
                 PackageCandidateError::Payload(p, e) => PackageDependencyStatusError::Payload(p, e),
                 PackageCandidateError::UnresolvedId(id) => PackageDependencyStatusError::PackageNotFound(id),
-                PackageCandidateError::UninstallConflict(_) => unreachable!()
+                PackageCandidateError::UninstallConflict(_) => unreachable!(),
+                PackageCandidateError::Downgrade(_) => unreachable!()
             })
     }
 
@@ -380,6 +389,108 @@ fn get_package_info(
     return Ok(plist);
 }
 
+fn synthetic_status(
+    verifier: &Verifier,
+    version: &pahkat_types::package::Version,
+    target: InstallTarget,
+) -> Result<PackageStatus, PackageStatusError> {
+    let (installed_version, installed_build, min_version, max_version, min_build, max_build) =
+        match verifier {
+            Verifier::MacOSPackageRef(v) => {
+                let pkg_info = match get_package_info(&v.pkg_id, target) {
+                    Ok(v) => v,
+                    Err(ProcessError::NotFound) => return Ok(PackageStatus::NotInstalled),
+                    Err(e) => {
+                        log::error!("{:?}", e);
+                        return Ok(PackageStatus::NotInstalled);
+                    }
+                };
+
+                (
+                    pkg_info.pkg_version,
+                    None,
+                    &v.min_version,
+                    &v.max_version,
+                    &v.min_build,
+                    &v.max_build,
+                )
+            }
+            Verifier::MacOSPathRef(v) => {
+                let bundle = match v.app_paths.iter().find_map(|x| bundle_info(x)) {
+                    Some(v) => v,
+                    None => return Ok(PackageStatus::NotInstalled),
+                };
+
+                (
+                    bundle.0,
+                    bundle.1,
+                    &v.min_version,
+                    &v.max_version,
+                    &v.min_build,
+                    &v.max_build,
+                )
+            }
+            _ => return Err(PackageStatusError::WrongPayloadType),
+        };
+
+    // Installed software outside of the verifier's bounds is not this package.
+    let installed = pahkat_types::package::Version::new(&installed_version)
+        .map_err(|_| PackageStatusError::ParsingVersion)?;
+    let is_version_in_bounds = min_version
+        .as_ref()
+        .and_then(|x| pahkat_types::package::Version::new(x).ok())
+        .map(|min| installed >= min)
+        .unwrap_or(true)
+        && max_version
+            .as_ref()
+            .and_then(|x| pahkat_types::package::Version::new(x).ok())
+            .map(|max| installed <= max)
+            .unwrap_or(true);
+    let build = installed_build.and_then(|x| x.parse::<u64>().ok());
+    let is_build_in_bounds = match build {
+        Some(build) => {
+            min_build
+                .as_ref()
+                .and_then(|x| x.parse::<u64>().ok())
+                .map(|min| build >= min)
+                .unwrap_or(true)
+                && max_build
+                    .as_ref()
+                    .and_then(|x| x.parse::<u64>().ok())
+                    .map(|max| build <= max)
+                    .unwrap_or(true)
+        }
+        None => true,
+    };
+
+    if !is_version_in_bounds || !is_build_in_bounds {
+        return Ok(PackageStatus::NotInstalled);
+    }
+
+    cmp::cmp(&installed_version, version)
+}
+
+/// Reads the version and build number from an application bundle's `Info.plist`.
+fn bundle_info(app_path: &str) -> Option<(String, Option<String>)> {
+    let path = match app_path.strip_prefix("~/") {
+        Some(rest) => pathos::user::home_dir().ok()?.join(rest),
+        None => PathBuf::from(app_path),
+    };
+
+    let plist = plist::Value::from_file(path.join("Contents").join("Info.plist")).ok()?;
+    let dict = plist.as_dictionary()?;
+    let version = dict
+        .get("CFBundleShortVersionString")
+        .and_then(plist::Value::as_string)?
+        .to_string();
+    let build = dict
+        .get("CFBundleVersion")
+        .and_then(plist::Value::as_string)
+        .map(str::to_string);
+
+    Some((version, build))
+}
+
 fn install_macos_package(pkg_path: &Path, target: InstallTarget) -> Result<(), ProcessError> {
     let target_str = match target {
         InstallTarget::User => "CurrentUserHomeDirectory",
@@ -348,7 +348,10 @@ impl PackageStore for PrefixPackageStore {
             crate::repo::ReleaseQuery::new(key, &*repos).and_payloads(vec!["TarballPackage"]);
         log::debug!("query: {:?}", &query);
 
+        // The installed release may have been yanked since, so only fall back
+        // to yanked releases when nothing else matches
         let (target, release, package) = crate::repo::resolve_payload(key, &query, &*repos)
+            .or_else(|_| crate::repo::resolve_payload(key, &query.clone().and_yanked(), &*repos))
             .map_err(PackageStatusError::Payload)?;
         let _installer = match target.payload {
             pahkat_types::payload::Payload::TarballPackage(v) => v,
@@ -376,7 +379,8 @@ impl PackageStore for PrefixPackageStore {
 
                 PackageCandidateError::Payload(p, e) => PackageDependencyStatusError::Payload(p, e),
                 PackageCandidateError::UnresolvedId(id) => PackageDependencyStatusError::PackageNotFound(id),
-                PackageCandidateError::UninstallConflict(_) => unreachable!()
+                PackageCandidateError::UninstallConflict(_) => unreachable!(),
+                PackageCandidateError::Downgrade(_) => unreachable!()
             })
     }
 
@@ -23,6 +23,7 @@ use pahkat_types::{
     package::{Descriptor, Package},
     payload::windows,
     repo::RepoUrl,
+    synth::Verifier,
 };
 
 const UNINSTALL_PATH: &'static str = r"Software\Microsoft\Windows\CurrentVersion\Uninstall";
@@ -158,7 +159,8 @@ impl PackageStore for WindowsPackageStore {
         install_target: InstallTarget,
     ) -> Result<PackageStatus, UninstallError> {
         let repos = self.repos.read().unwrap();
-        let query = crate::repo::ReleaseQuery::new(key, &*repos);
+        // An installed release can still be uninstalled after it is yanked
+        let query = crate::repo::ReleaseQuery::new(key, &*repos).and_yanked();
 
         let (target, release, descriptor) =
             crate::repo::resolve_payload(key, &query, &*repos).map_err(UninstallError::Payload)?;
@@ -247,6 +249,12 @@ impl PackageStore for WindowsPackageStore {
         let repos = self.repos.read().unwrap();
         let query = crate::repo::ReleaseQuery::new(key, &*repos);
 
+        if let Some((target, version)) = crate::repo::resolve_synthetic(key, &query, &*repos)
+            .map_err(PackageStatusError::Payload)?
+        {
+            return synthetic_status(&target.verifier, &version);
+        }
+
         let (target, release, descriptor) = crate::repo::resolve_payload(key, &query, &*repos)
             .map_err(PackageStatusError::Payload)?;
         let installer = match target.payload {
@@ -271,7 +279,8 @@ impl PackageStore for WindowsPackageStore {
 
                 PackageCandidateError::Payload(p, e) => PackageDependencyStatusError::Payload(p, e),
                 PackageCandidateError::UnresolvedId(id) => PackageDependencyStatusError::PackageNotFound(id),
-                PackageCandidateError::UninstallConflict(_) => unreachable!()
+                PackageCandidateError::UninstallConflict(_) => unreachable!(),
+                PackageCandidateError::Downgrade(_) => unreachable!()
             })
     }
 
@@ -367,6 +376,8 @@ impl WindowsPackageStore {
     ) -> Result<PackageStatus, PackageStatusError> {
         let repos = self.repos.read().unwrap();
         let mut query = crate::repo::ReleaseQuery::new(key, &*repos);
+        // The installed release may have been yanked since
+        query.include_yanked = true;
 
         let (response, inst_key) = match query
             .iter(package)
@@ -394,6 +405,48 @@ impl WindowsPackageStore {
     }
 }
 
+fn synthetic_status(
+    verifier: &Verifier,
+    version: &pahkat_types::package::Version,
+) -> Result<PackageStatus, PackageStatusError> {
+    let verifier = match verifier {
+        Verifier::WindowsRegistryKey(v) => v,
+        _ => return Err(PackageStatusError::WrongPayloadType),
+    };
+
+    let regkey = match open_regkey(&verifier.path) {
+        Some(v) => v,
+        None => return Ok(PackageStatus::NotInstalled),
+    };
+
+    let installed_version: String = match regkey.value(&*verifier.name) {
+        Ok(Data::String(v)) => v.to_string_lossy(),
+        Ok(_) => return Err(PackageStatusError::ParsingVersion),
+        Err(_) => return Ok(PackageStatus::NotInstalled),
+    };
+
+    log::trace!("Registry version: {}", &installed_version);
+    crate::cmp::cmp(&installed_version, version)
+}
+
+/// Opens a registry key by path, such as `HKEY_CURRENT_USER\Software\Foo`.
+///
+/// Paths without a recognised hive prefix are opened under `HKEY_LOCAL_MACHINE`.
+fn open_regkey(path: &str) -> Option<RegKey> {
+    let (hive, path) = match path.find('\\') {
+        Some(i) => match &path[..i] {
+            "HKEY_LOCAL_MACHINE" | "HKLM" => (Hive::LocalMachine, &path[i + 1..]),
+            "HKEY_CURRENT_USER" | "HKCU" => (Hive::CurrentUser, &path[i + 1..]),
+            _ => (Hive::LocalMachine, path),
+        },
+        None => (Hive::LocalMachine, path),
+    };
+
+    hive.open(path.to_string(), Security::Read | Security::Wow6464Key)
+        .or_else(|_| hive.open(path.to_string(), Security::Read | Security::Wow6432Key))
+        .ok()
+}
+
 #[inline(always)]
 fn uninstall_regkey(installer: &windows::Executable) -> Option<RegKey> {
     Hive::LocalMachine
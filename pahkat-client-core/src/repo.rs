@@ -47,6 +47,9 @@ pub struct ReleaseQuery<'a> {
     pub channels: Vec<&'a str>,
     pub versions: Vec<VersionQuery<'a>>,
     pub payloads: Vec<&'a str>,
+    /// Whether yanked releases may match, such as when finding what is
+    /// already installed
+    pub include_yanked: bool,
 }
 
 impl<'a> ReleaseQuery<'a> {
@@ -54,6 +57,11 @@ impl<'a> ReleaseQuery<'a> {
         self.payloads = payloads;
         self
     }
+
+    pub(crate) fn and_yanked(mut self) -> Self {
+        self.include_yanked = true;
+        self
+    }
 }
 
 impl<'a> Default for ReleaseQuery<'a> {
@@ -64,6 +72,7 @@ impl<'a> Default for ReleaseQuery<'a> {
             channels: vec![],
             versions: vec![],
             payloads: defaults::payloads().to_vec(),
+            include_yanked: false,
         }
     }
 }
@@ -76,12 +85,29 @@ pub enum VersionQuery<'a> {
 }
 
 impl<'a> VersionQuery<'a> {
+    /// Parses a version query. A plain version such as `1.2.3` matches only that
+    /// version; anything else is treated as a semver requirement such as `^1.2`.
+    pub fn new(input: &'a str) -> Self {
+        if Version::new(input).is_ok() {
+            return VersionQuery::Match(input);
+        }
+
+        match semver::VersionReq::parse(input) {
+            Ok(req) => VersionQuery::Semantic(req),
+            Err(_) => VersionQuery::Match(input),
+        }
+    }
+
     fn any_semantic() -> Self {
         VersionQuery::Semantic(semver::VersionReq::parse("*").unwrap())
     }
 
     fn matches(&self, version: &Version) -> bool {
         match (self, version) {
+            (VersionQuery::Match(input), version) => match Version::new(input) {
+                Ok(v) => &v == version,
+                Err(_) => false,
+            },
             (VersionQuery::Semantic(mask), Version::Semantic(v)) => mask.matches(v),
             _ => false,
         }
@@ -96,6 +122,8 @@ fn empty_payloads() -> &'static [&'static str] {
 pub(crate) struct ReleaseQueryIter<'a> {
     query: &'a ReleaseQuery<'a>,
     descriptor: &'a pahkat_types::package::Descriptor,
+    /// Indices into the descriptor's releases, greatest version first.
+    order: Vec<usize>,
     next_release: usize,
 }
 
@@ -110,13 +138,23 @@ impl<'a> ReleaseQueryIter<'a> {
     fn next_release(&mut self) -> Option<ReleaseQueryResponse<'a>> {
         log::trace!("Beginning release query iter: {:?}", &self.query);
 
-        while let Some(release) = self.descriptor.release.get(self.next_release) {
+        while let Some(release) = self
+            .order
+            .get(self.next_release)
+            .and_then(|i| self.descriptor.release.get(*i))
+        {
             log::trace!(
                 "Candidate release: version:{:?}, channel:{:?}",
                 &release.version.to_string(),
                 &release.channel
             );
 
+            if release.yanked && !self.query.include_yanked {
+                log::trace!("Skipping (yanked)");
+                self.next_release += 1;
+                continue;
+            }
+
             // If query is empty, it means search only for the main empty channel
             if let Some(channel) = release.channel.as_ref().map(|x| x.as_str()) {
                 if !self.query.channels.contains(&channel) {
@@ -130,6 +168,13 @@ impl<'a> ReleaseQueryIter<'a> {
                 continue;
             }
 
+            let versions = &self.query.versions;
+            if !versions.is_empty() && !versions.iter().any(|v| v.matches(&release.version)) {
+                log::trace!("Skipping (version does not match)");
+                self.next_release += 1;
+                continue;
+            }
+
             if let Some(payload) = self.next_payload(release) {
                 log::trace!("Target resolved: {:?}", &payload.target);
                 self.next_release += 1;
@@ -189,9 +234,19 @@ impl<'a> ReleaseQuery<'a> {
         &'a self,
         descriptor: &'a pahkat_types::package::Descriptor,
     ) -> ReleaseQueryIter<'a> {
+        // Stable sort, so equal versions keep the order they were listed in.
+        let mut order = (0..descriptor.release.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| {
+            descriptor.release[*b]
+                .version
+                .partial_cmp(&descriptor.release[*a].version)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
         ReleaseQueryIter {
             query: self,
             descriptor,
+            order,
             next_release: 0,
         }
     }
@@ -235,9 +290,10 @@ impl<'a> ReleaseQuery<'a> {
                 .query
                 .version
                 .as_ref()
-                .map(|v| vec![VersionQuery::Match(&*v)])
+                .map(|v| vec![VersionQuery::new(&*v)])
                 .unwrap_or_else(|| vec![]),
             payloads: defaults::payloads().to_vec(),
+            include_yanked: false,
         }
     }
 }
@@ -330,11 +386,17 @@ pub(crate) fn resolve_package_query<'a>(
         let status = descriptors
             .iter()
             .fold(PackageStatus::UpToDate, |acc, cur| {
-                match (acc, cur.status) {
+                // A newer version than offered being installed is as good as up to date here
+                let cur_status = match cur.status {
+                    PackageStatus::RequiresDowngrade => PackageStatus::UpToDate,
+                    v => v,
+                };
+
+                match (acc, cur_status) {
                     // If currently requires update, nothing trumps this state
                     (PackageStatus::RequiresUpdate, _) => acc,
                     // Only requires update trumps NotInstalled
-                    (PackageStatus::NotInstalled, PackageStatus::RequiresUpdate) => cur.status,
+                    (PackageStatus::NotInstalled, PackageStatus::RequiresUpdate) => cur_status,
                     (PackageStatus::NotInstalled, PackageStatus::UpToDate) => {
                         PackageStatus::RequiresUpdate
                     }
@@ -343,7 +405,7 @@ pub(crate) fn resolve_package_query<'a>(
                     }
                     // Everything trumps UpToDate
                     (PackageStatus::UpToDate, v) => v,
-                    _ => cur.status,
+                    _ => cur_status,
                 }
             });
         let size = descriptors
@@ -519,7 +581,11 @@ pub(crate) fn all_statuses<'a>(
             }
         };
 
-        for id in packages.keys() {
+        let synthetics = repo.packages();
+        let synthetics = synthetics.synthetics();
+        let synthetic_ids = synthetics.iter().flat_map(|x| x.keys());
+
+        for id in packages.keys().chain(synthetic_ids) {
             let key =
                 PackageKey::new_unchecked(repo.info().repository.url.clone(), id.to_string(), None);
             let status = store.status(&key, target);
@@ -551,15 +617,12 @@ pub(crate) async fn strings<'p>(
         .map(|(url, strings_url)| async move {
             let (tx, rx) = tokio::sync::oneshot::channel();
             tokio::spawn(async move {
-                let response = match reqwest::get(strings_url).await {
-                    Ok(v) => match v.text().await {
-                        Ok(v) => match toml::from_str(&v) {
-                            Ok(v) => Some(v),
-                            Err(_) => None,
-                        },
-                        Err(_) => None,
-                    },
-                    Err(_) => None,
+                let response = match fetch_strings(&strings_url).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log::error!("Failed to load strings from {}: {:?}", &strings_url, e);
+                        None
+                    }
                 };
                 tx.send(response).unwrap();
             });
@@ -576,6 +639,139 @@ pub(crate) async fn strings<'p>(
         .collect::<HashMap<_, _>>()
 }
 
+#[derive(Debug, thiserror::Error)]
+enum StringsError {
+    #[error("Request failed")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Invalid strings file")]
+    Toml(#[from] toml::de::Error),
+}
+
+/// Fetches a strings file, where a missing file simply means the repository
+/// has no strings for that language.
+async fn fetch_strings(
+    url: &Url,
+) -> Result<Option<crate::package_store::LocalizedStrings>, StringsError> {
+    let response = reqwest::get(url.clone()).await?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        log::debug!("No strings found at {}", url);
+        return Ok(None);
+    }
+
+    let text = response.error_for_status()?.text().await?;
+    let strings: pahkat_types::repo::Localisation = toml::from_str(&text)?;
+
+    Ok(Some(crate::package_store::LocalizedStrings {
+        tags: strings.tags.into_iter().collect(),
+        channels: strings.channels.into_iter().collect(),
+    }))
+}
+
+/// The maximum number of package redirects followed before giving up, to avoid cycles.
+const MAX_REDIRECTS: usize = 8;
+
+fn find_package<'p>(
+    package_key: &PackageKey,
+    repos: &'p HashMap<RepoUrl, LoadedRepository>,
+) -> Option<Package> {
+    let repo = repos.get(&package_key.repository_url)?;
+    log::trace!("Got repo: {}", &repo.info.repository.url);
+
+    // TODO: need to check that any release supports the requested channel
+    let packages = repo.packages();
+
+    match packages.packages() {
+        Some(packages) => {
+            if let Some(pkg) = packages.get(&package_key.id) {
+                log::trace!("Found pkg: {}", &package_key);
+                return (&pkg).try_into().map(Package::Concrete).ok();
+            }
+        }
+        None => {
+            log::error!(
+                "No packages map in fbs for {:?}!",
+                &package_key.repository_url
+            );
+        }
+    }
+
+    if let Some(pkg) = packages.synthetics().and_then(|x| x.get(&package_key.id)) {
+        log::trace!("Found synthetic pkg: {}", &package_key);
+        return (&pkg).try_into().map(Package::Synthetic).ok();
+    }
+
+    if let Some(pkg) = packages.redirects().and_then(|x| x.get(&package_key.id)) {
+        log::trace!("Found redirect: {}", &package_key);
+        return match crate::fbs::build_redirect(&package_key.id, &pkg) {
+            Ok(v) => Some(Package::Redirect(v)),
+            Err(e) => {
+                log::warn!("Skipping redirect {}: {}", &package_key, e);
+                None
+            }
+        };
+    }
+
+    None
+}
+
+/// Follows any package redirects for the given key, returning the key of the package
+/// that is ultimately pointed to.
+///
+/// Redirects are only followed within the same repository, or into repositories listed
+/// in the redirecting repository's `accepted_redirections`.
+pub(crate) fn resolve_redirects(
+    package_key: &PackageKey,
+    repos: &HashMap<RepoUrl, LoadedRepository>,
+) -> Option<PackageKey> {
+    let mut key = package_key.clone();
+
+    for _ in 0..=MAX_REDIRECTS {
+        let redirect = match find_package(&key, repos)? {
+            Package::Redirect(v) => v,
+            _ => return Some(key),
+        };
+
+        let mut next = match PackageKey::try_from(&redirect.redirect.url) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Invalid redirect for {}: {}", &key, e);
+                return None;
+            }
+        };
+
+        let is_accepted = next.repository_url == key.repository_url
+            || repos
+                .get(&key.repository_url)
+                .map(|repo| {
+                    repo.info()
+                        .repository
+                        .accepted_redirections
+                        .contains(&next.repository_url)
+                })
+                .unwrap_or(false);
+
+        if !is_accepted {
+            log::warn!(
+                "Not following redirect from {} to {}: repository not accepted",
+                &key,
+                &next
+            );
+            return None;
+        }
+
+        log::trace!("Following redirect: {} -> {}", &key, &next);
+
+        // Keep the original query, so version and channel pins survive the redirect
+        next.query = key.query.clone();
+        key = next;
+    }
+
+    log::error!("Too many redirects resolving {}", &package_key);
+    None
+}
+
 pub(crate) fn find_package_by_key<'p>(
     package_key: &PackageKey,
     repos: &'p HashMap<RepoUrl, LoadedRepository>,
@@ -586,29 +782,8 @@ pub(crate) fn find_package_by_key<'p>(
         repos.iter().map(|(x, _)| x).collect::<Vec<_>>()
     );
 
-    repos.get(&package_key.repository_url).and_then(|r| {
-        log::trace!("Got repo: {}", &r.info.repository.url);
-        // TODO: need to check that any release supports the requested channel
-        let packages = r.packages();
-        let packages = match packages.packages() {
-            Some(v) => v,
-            None => {
-                log::error!(
-                    "No packages map in fbs for {:?}!",
-                    &package_key.repository_url
-                );
-                return None;
-            }
-        };
-
-        let pkg = match packages.get(&package_key.id) {
-            Some(x) => x,
-            None => return None,
-        };
-        log::trace!("Found pkg: {}", &package_key);
-
-        (&pkg).try_into().map(Package::Concrete).ok()
-    })
+    let key = resolve_redirects(package_key, repos)?;
+    find_package(&key, repos)
 }
 
 pub(crate) fn find_package_by_id(
@@ -621,26 +796,66 @@ pub(crate) fn find_package_by_id(
         Err(_) => {}
     };
 
-    repos.iter().find_map(|(key, repo)| {
-        let packages = repo.packages();
-        let packages = match packages.packages() {
-            Some(v) => v,
-            None => {
-                log::error!("No packages map in fbs for {:?}!", &key);
-                return None;
+    repos.values().find_map(|repo| {
+        let key = PackageKey::new_unchecked(
+            repo.info().repository.url.clone(),
+            package_id.to_string(),
+            None,
+        );
+
+        find_package_by_key(&key, repos).map(|pkg| (key, pkg))
+    })
+}
+
+/// Resolves the verifier target and version of a synthetic package for the given query.
+///
+/// Returns `Ok(None)` if the key does not refer to a synthetic package.
+pub(crate) fn resolve_synthetic<'a>(
+    package_key: &PackageKey,
+    query: &ReleaseQuery<'a>,
+    repos: &'a HashMap<RepoUrl, LoadedRepository>,
+) -> Result<Option<(pahkat_types::synth::Target, Version)>, PayloadError> {
+    let descriptor = match find_package_by_key(package_key, repos) {
+        Some(Package::Synthetic(v)) => v,
+        Some(_) => return Ok(None),
+        None => return Err(PayloadError::NoPackage),
+    };
+
+    let mut releases = descriptor
+        .releases
+        .iter()
+        .filter_map(|x| Version::new(&x.version).ok().map(|v| (v, x)))
+        .collect::<Vec<_>>();
+    releases.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    for (version, release) in releases {
+        // An empty channel is the main channel, as with concrete releases
+        if !release.channel.is_empty() && !query.channels.contains(&&*release.channel) {
+            continue;
+        }
+
+        if !query.versions.is_empty() && !query.versions.iter().any(|v| v.matches(&version)) {
+            continue;
+        }
+
+        let target = release.targets.iter().find(|target| {
+            if target.platform != query.platform {
+                return false;
             }
-        };
 
-        packages.get(package_id).map(|x| {
-            let key = PackageKey::new_unchecked(
-                repo.info().repository.url.clone(),
-                package_id.to_string(),
-                None,
-            );
+            match (query.arch, target.arch.as_ref()) {
+                (Some(arch), Some(target_arch)) => target_arch == arch,
+                (None, Some(_)) => false,
+                _ => true,
+            }
+        });
 
-            (&x).try_into().map(|p| (key, Package::Concrete(p))).ok()
-        })?
-    })
+        if let Some(target) = target {
+            return Ok(Some((target.clone(), version)));
+        }
+    }
+
+    Err(PayloadError::NoPayloadFound)
 }
 
 #[must_use]
@@ -762,6 +977,9 @@ pub enum PackageCandidateError {
 
     #[error("Attempting to uninstall package required by installation set: `{0}`")]
     UninstallConflict(PackageKey),
+
+    #[error("Refusing to downgrade package without downgrades being allowed: `{0}`")]
+    Downgrade(PackageKey),
 }
 
 use crate::{ext::DependencyKeyExt, package_store::InstallTarget, PackageActionType};
@@ -774,10 +992,10 @@ fn resolve_package_candidate(
     repos: &HashMap<RepoUrl, LoadedRepository>,
 ) -> Result<PackageCandidate, PackageCandidateError> {
     let package_key = &candidate.1;
-    let query = crate::repo::ReleaseQuery::new(package_key, &repos);
 
     match candidate.0 {
         PackageActionType::Install => {
+            let query = crate::repo::ReleaseQuery::new(package_key, &repos);
             let status = install_target
                 .iter()
                 .fold(None, |acc, cur| match acc {
@@ -805,7 +1023,7 @@ fn resolve_package_candidate(
                         PackageStatus::NotInstalled => {
                             pkg.requires_reboot.contains(&RebootSpec::Install)
                         }
-                        PackageStatus::RequiresUpdate => {
+                        PackageStatus::RequiresUpdate | PackageStatus::RequiresDowngrade => {
                             pkg.requires_reboot.contains(&RebootSpec::Update)
                         }
                         _ => false,
@@ -817,7 +1035,7 @@ fn resolve_package_candidate(
                         PackageStatus::NotInstalled => {
                             pkg.requires_reboot.contains(&RebootSpec::Install)
                         }
-                        PackageStatus::RequiresUpdate => {
+                        PackageStatus::RequiresUpdate | PackageStatus::RequiresDowngrade => {
                             pkg.requires_reboot.contains(&RebootSpec::Update)
                         }
                         _ => false,
@@ -837,6 +1055,8 @@ fn resolve_package_candidate(
             })
         }
         PackageActionType::Uninstall => {
+            // An installed release can still be uninstalled after it is yanked
+            let query = crate::repo::ReleaseQuery::new(package_key, &repos).and_yanked();
             let status = install_target
                 .iter()
                 .fold(None, |acc, cur| match acc {
@@ -971,6 +1191,12 @@ pub(crate) fn resolve_package_set(
                 && candidate.status == PackageStatus::UpToDate
             {
                 None
+            } else if candidate.action == PackageActionType::Install
+                && candidate.status == PackageStatus::RequiresDowngrade
+                && key.query.version.is_none()
+            {
+                // Only downgrade when a version was explicitly asked for
+                None
             } else if candidate.action == PackageActionType::Uninstall
                 && candidate.status == PackageStatus::NotInstalled
             {
@@ -1000,3 +1226,431 @@ pub(crate) fn resolve_package_set(
 
     Ok(output_mutation_set)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(releases: &[(&str, Option<&str>)]) -> Descriptor {
+        let releases = releases
+            .iter()
+            .map(|(version, channel)| {
+                format!(
+                    r#"
+[[release]]
+version = "{version}"
+{channel}
+
+[[release.target]]
+platform = "linux"
+
+[release.target.payload]
+type = "TarballPackage"
+url = "https://example.com/test-{version}.txz"
+size = 1
+installed_size = 1
+"#,
+                    version = version,
+                    channel = channel
+                        .map(|x| format!("channel = \"{}\"", x))
+                        .unwrap_or_default(),
+                )
+            })
+            .collect::<String>();
+
+        toml::from_str(&format!("[package]\nid = \"test\"\n{}", releases)).unwrap()
+    }
+
+    fn query<'a>(channels: Vec<&'a str>, versions: Vec<VersionQuery<'a>>) -> ReleaseQuery<'a> {
+        ReleaseQuery {
+            platform: "linux",
+            arch: None,
+            channels,
+            versions,
+            payloads: vec![],
+            include_yanked: false,
+        }
+    }
+
+    fn versions(query: &ReleaseQuery<'_>, descriptor: &Descriptor) -> Vec<String> {
+        query
+            .iter(descriptor)
+            .map(|x| x.release.version.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn version_query_exact() {
+        let query = VersionQuery::new("1.2.3");
+
+        assert!(matches!(query, VersionQuery::Match("1.2.3")));
+        assert!(query.matches(&Version::new("1.2.3").unwrap()));
+        assert!(!query.matches(&Version::new("1.2.4").unwrap()));
+    }
+
+    #[test]
+    fn version_query_requirement() {
+        let query = VersionQuery::new("^1.2");
+
+        assert!(matches!(query, VersionQuery::Semantic(_)));
+        assert!(query.matches(&Version::new("1.2.0").unwrap()));
+        assert!(query.matches(&Version::new("1.9.1").unwrap()));
+        assert!(!query.matches(&Version::new("2.0.0").unwrap()));
+        assert!(!query.matches(&Version::new("1.1.9").unwrap()));
+    }
+
+    #[test]
+    fn version_query_unparseable() {
+        let query = VersionQuery::new("not a version");
+
+        assert!(matches!(query, VersionQuery::Match(_)));
+        assert!(!query.matches(&Version::new("1.0.0").unwrap()));
+    }
+
+    #[test]
+    fn releases_resolve_greatest_version_first() {
+        let descriptor = descriptor(&[("1.0.0", None), ("2.0.0", None), ("1.5.0", None)]);
+
+        assert_eq!(
+            versions(&query(vec![], vec![]), &descriptor),
+            vec!["2.0.0", "1.5.0", "1.0.0"]
+        );
+    }
+
+    #[test]
+    fn releases_resolve_greatest_matching_version() {
+        let descriptor = descriptor(&[("1.0.0", None), ("2.0.0", None), ("1.5.0", None)]);
+        let query = query(vec![], vec![VersionQuery::new("^1")]);
+
+        assert_eq!(
+            query.iter(&descriptor).next().unwrap().release.version,
+            Version::new("1.5.0").unwrap()
+        );
+    }
+
+    #[test]
+    fn releases_resolve_only_requested_channels() {
+        let descriptor = descriptor(&[
+            ("1.0.0", None),
+            ("1.1.0-nightly.20200101T000000Z", Some("nightly")),
+            ("0.9.0", None),
+        ]);
+
+        assert_eq!(
+            versions(&query(vec![], vec![]), &descriptor),
+            vec!["1.0.0", "0.9.0"]
+        );
+        assert_eq!(
+            versions(&query(vec!["nightly"], vec![]), &descriptor),
+            vec!["1.1.0-nightly.20200101T000000Z", "1.0.0", "0.9.0"]
+        );
+    }
+
+    #[test]
+    fn releases_with_equal_versions_keep_listed_order() {
+        let mut descriptor = descriptor(&[("1.0.0", None), ("1.0.0", None)]);
+        descriptor.release[1].authors = vec!["second".into()];
+
+        let query = query(vec![], vec![]);
+        let first = query.iter(&descriptor).next().unwrap();
+
+        assert!(first.release.authors.is_empty());
+    }
+
+    #[test]
+    fn releases_skip_yanked_unless_requested() {
+        let mut descriptor = descriptor(&[("1.0.0", None), ("2.0.0", None), ("1.5.0", None)]);
+        descriptor.release[1].yanked = true;
+
+        assert_eq!(
+            versions(&query(vec![], vec![]), &descriptor),
+            vec!["1.5.0", "1.0.0"]
+        );
+        assert_eq!(
+            versions(&query(vec![], vec![]).and_yanked(), &descriptor),
+            vec!["2.0.0", "1.5.0", "1.0.0"]
+        );
+
+        let query = query(vec![], vec![VersionQuery::new("2.0.0")]);
+        assert!(query.iter(&descriptor).next().is_none());
+        assert_eq!(versions(&query.and_yanked(), &descriptor), vec!["2.0.0"]);
+    }
+
+    const REPO: &str = "https://example.com/repo/";
+    const OTHER: &str = "https://example.com/other/";
+
+    fn index(url: &str, accepted: &[&str]) -> String {
+        format!(
+            "[repository]\nurl = \"{}\"\nchannels = [\"beta\"]\naccepted_redirections = [{}]\n\n\
+             [agent]\nname = \"test\"\nversion = \"0.0.0\"\n",
+            url,
+            accepted
+                .iter()
+                .map(|x| format!("\"{}\"", x))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+
+    fn concrete(id: &str) -> String {
+        format!(
+            "[package]\nid = \"{id}\"\n\n[[release]]\nversion = \"1.0.0\"\n\n\
+             [[release.target]]\nplatform = \"linux\"\n\n[release.target.payload]\n\
+             type = \"TarballPackage\"\nurl = \"https://example.com/{id}.txz\"\n\
+             size = 1\ninstalled_size = 1\n",
+            id = id
+        )
+    }
+
+    fn redirect(id: &str, to: &str) -> String {
+        format!("[redirect]\nid = \"{}\"\nurl = \"{}\"\n", id, to)
+    }
+
+    /// Indexes the packages with the repository manager, and loads the result
+    /// as if it had been downloaded.
+    fn repository(index: &str, packages: &[(&str, String)]) -> LoadedRepository {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.toml"), index).unwrap();
+        for (id, descriptor) in packages {
+            let path = dir.path().join("packages").join(id);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("index.toml"), descriptor).unwrap();
+        }
+
+        pahkat_repomgr::repo::indexing::index(
+            pahkat_repomgr::repo::indexing::Request::builder()
+                .path(std::borrow::Cow::Borrowed(dir.path()))
+                .build(),
+        )
+        .unwrap();
+
+        LoadedRepository {
+            info: toml::from_str(index).unwrap(),
+            packages: std::fs::read(dir.path().join("packages/index.bin"))
+                .unwrap()
+                .into_boxed_slice(),
+            meta: repository::LoadedRepositoryMeta { channel: None },
+        }
+    }
+
+    fn repos(repos: Vec<(&str, LoadedRepository)>) -> HashMap<RepoUrl, LoadedRepository> {
+        repos
+            .into_iter()
+            .map(|(url, repo)| (url.parse().unwrap(), repo))
+            .collect()
+    }
+
+    fn key(repo: &str, id: &str) -> PackageKey {
+        PackageKey::new_unchecked(repo.parse().unwrap(), id.to_string(), None)
+    }
+
+    /// The repository and id a key resolves to after following redirects.
+    fn redirected(
+        repos: &HashMap<RepoUrl, LoadedRepository>,
+        key: &PackageKey,
+    ) -> Option<(String, String)> {
+        resolve_redirects(key, repos).map(|x| (x.repository_url.to_string(), x.id))
+    }
+
+    #[test]
+    fn redirects_within_repository() {
+        let repos = repos(vec![(
+            REPO,
+            repository(
+                &index(REPO, &[]),
+                &[
+                    ("app", concrete("app")),
+                    (
+                        "old",
+                        redirect("old", "https://example.com/repo/packages/app"),
+                    ),
+                ],
+            ),
+        )]);
+
+        assert_eq!(
+            redirected(&repos, &key(REPO, "old")),
+            Some((REPO.to_string(), "app".to_string()))
+        );
+        assert_eq!(
+            redirected(&repos, &key(REPO, "app")),
+            Some((REPO.to_string(), "app".to_string()))
+        );
+        assert_eq!(redirected(&repos, &key(REPO, "missing")), None);
+    }
+
+    #[test]
+    fn redirects_keep_query() {
+        let repos = repos(vec![(
+            REPO,
+            repository(
+                &index(REPO, &[]),
+                &[
+                    ("app", concrete("app")),
+                    (
+                        "old",
+                        redirect("old", "https://example.com/repo/packages/app"),
+                    ),
+                ],
+            ),
+        )]);
+
+        let mut old = key(REPO, "old");
+        old.query.version = Some("1.0.0".into());
+        old.query.channel = Some("beta".into());
+
+        let key = resolve_redirects(&old, &repos).unwrap();
+        assert_eq!(key.id, "app");
+        assert_eq!(key.query.version.as_deref(), Some("1.0.0"));
+        assert_eq!(key.query.channel.as_deref(), Some("beta"));
+    }
+
+    #[test]
+    fn redirects_only_into_accepted_repositories() {
+        let other = || repository(&index(OTHER, &[]), &[("app", concrete("app"))]);
+        let packages = [(
+            "old",
+            redirect("old", "https://example.com/other/packages/app"),
+        )];
+
+        let repos_unaccepted = repos(vec![
+            (REPO, repository(&index(REPO, &[]), &packages)),
+            (OTHER, other()),
+        ]);
+        assert_eq!(redirected(&repos_unaccepted, &key(REPO, "old")), None);
+
+        let repos_accepted = repos(vec![
+            (REPO, repository(&index(REPO, &[OTHER]), &packages)),
+            (OTHER, other()),
+        ]);
+        assert_eq!(
+            redirected(&repos_accepted, &key(REPO, "old")),
+            Some((OTHER.to_string(), "app".to_string()))
+        );
+    }
+
+    #[test]
+    fn redirect_cycles_are_not_followed() {
+        let repos = repos(vec![(
+            REPO,
+            repository(
+                &index(REPO, &[]),
+                &[
+                    ("a", redirect("a", "https://example.com/repo/packages/b")),
+                    ("b", redirect("b", "https://example.com/repo/packages/a")),
+                ],
+            ),
+        )]);
+
+        assert_eq!(redirected(&repos, &key(REPO, "a")), None);
+    }
+
+    #[test]
+    fn redirects_are_followed_up_to_the_limit() {
+        // `r0` redirects through `r1`, `r2`, ... until `r{len}`, which is concrete
+        let chain = |len: usize| {
+            let mut packages = (0..len)
+                .map(|i| {
+                    let id = format!("r{}", i);
+                    let to = format!("https://example.com/repo/packages/r{}", i + 1);
+                    let descriptor = redirect(&id, &to);
+                    (id, descriptor)
+                })
+                .collect::<Vec<_>>();
+            let last = format!("r{}", len);
+            packages.push((last.clone(), concrete(&last)));
+
+            let packages = packages
+                .iter()
+                .map(|(id, descriptor)| (id.as_str(), descriptor.clone()))
+                .collect::<Vec<_>>();
+            repos(vec![(REPO, repository(&index(REPO, &[]), &packages))])
+        };
+
+        let repos = chain(MAX_REDIRECTS);
+        assert_eq!(
+            redirected(&repos, &key(REPO, "r0")),
+            Some((REPO.to_string(), format!("r{}", MAX_REDIRECTS)))
+        );
+
+        let repos = chain(MAX_REDIRECTS + 1);
+        assert_eq!(redirected(&repos, &key(REPO, "r0")), None);
+    }
+
+    fn synthetic(id: &str, releases: &[(&str, &str, &str)]) -> String {
+        let mut out = format!("[synthetic]\nid = \"{}\"\n", id);
+        for (version, channel, platform) in releases {
+            out.push_str(&format!(
+                "\n[[releases]]\nversion = \"{}\"\nchannel = \"{}\"\n\n\
+                 [[releases.targets]]\nplatform = \"{}\"\n\n[releases.targets.verifier]\n\
+                 type = \"WindowsRegistryKey\"\npath = \"HKLM\\\\Software\\\\{}\"\nname = \"Version\"\n",
+                version, channel, platform, id
+            ));
+        }
+        out
+    }
+
+    fn synthetic_repos() -> HashMap<RepoUrl, LoadedRepository> {
+        repos(vec![(
+            REPO,
+            repository(
+                &index(REPO, &[]),
+                &[
+                    ("app", concrete("app")),
+                    (
+                        "sys",
+                        synthetic(
+                            "sys",
+                            &[
+                                ("1.0.0", "beta", "linux"),
+                                ("2.0.0", "beta", "linux"),
+                                ("3.0.0", "beta", "windows"),
+                            ],
+                        ),
+                    ),
+                ],
+            ),
+        )])
+    }
+
+    #[test]
+    fn synthetic_resolves_greatest_matching_release() {
+        let repos = synthetic_repos();
+        let key = key(REPO, "sys");
+
+        let (target, version) = resolve_synthetic(&key, &query(vec!["beta"], vec![]), &repos)
+            .unwrap()
+            .unwrap();
+        assert_eq!(version, Version::new("2.0.0").unwrap());
+        assert_eq!(target.platform, "linux");
+
+        let query = query(vec!["beta"], vec![VersionQuery::new("1.0.0")]);
+        let (_, version) = resolve_synthetic(&key, &query, &repos).unwrap().unwrap();
+        assert_eq!(version, Version::new("1.0.0").unwrap());
+    }
+
+    #[test]
+    fn synthetic_skips_other_channels() {
+        let repos = synthetic_repos();
+
+        assert!(matches!(
+            resolve_synthetic(&key(REPO, "sys"), &query(vec![], vec![]), &repos),
+            Err(PayloadError::NoPayloadFound)
+        ));
+    }
+
+    #[test]
+    fn synthetic_ignores_other_packages() {
+        let repos = synthetic_repos();
+        let query = query(vec!["beta"], vec![]);
+
+        assert!(matches!(
+            resolve_synthetic(&key(REPO, "app"), &query, &repos),
+            Ok(None)
+        ));
+        assert!(matches!(
+            resolve_synthetic(&key(REPO, "missing"), &query, &repos),
+            Err(PayloadError::NoPackage)
+        ));
+    }
+}
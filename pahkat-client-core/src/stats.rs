@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+use pahkat_types::package::Release;
+use pahkat_types::payload::Target;
+use pahkat_types::PackageKey;
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct InstallPing {
+    version: String,
+    platform: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arch: Option<String>,
+}
+
+/// Reports a successful install to the repository it came from, for its
+/// download statistics. This is best effort: it runs on its own thread and
+/// failures are only logged.
+pub(crate) fn send_install_ping(key: &PackageKey, release: &Release, target: &Target) {
+    let url = match key
+        .repository_url
+        .join(&format!("packages/{}/downloads", key.id))
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("Could not build install ping URL for {}: {}", key, e);
+            return;
+        }
+    };
+
+    let body = match serde_json::to_vec(&InstallPing {
+        version: release.version.to_string(),
+        platform: target.platform.clone(),
+        arch: target.arch.clone(),
+    }) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+
+    std::thread::spawn(move || {
+        let client = match reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+        {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("Could not send install ping: {}", e);
+                return;
+            }
+        };
+
+        let result = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .and_then(|x| x.error_for_status());
+
+        match result {
+            Ok(_) => log::debug!("Sent install ping to {}", url),
+            Err(e) => log::warn!("Install ping to {} failed: {}", url, e),
+        }
+    });
+}
@@ -16,15 +16,22 @@ pub enum PackageStatus {
     NotInstalled,
     UpToDate,
     RequiresUpdate,
+    /// The installed version is newer than the candidate release.
+    RequiresDowngrade,
 }
 
 use crate::repo::PayloadError;
 
+/// The status as reported over FFI and by the RPC `Status` call.
+///
+/// `RequiresDowngrade` is reported as up to date (`1`), as it was before the
+/// two were told apart, so existing consumers keep treating a newer installed
+/// version as nothing to do. Only the transaction plan reports it as `3`.
 pub fn status_to_i8(result: Result<PackageStatus, PackageStatusError>) -> i8 {
     match result {
         Ok(status) => match status {
             PackageStatus::NotInstalled => 0,
-            PackageStatus::UpToDate => 1,
+            PackageStatus::UpToDate | PackageStatus::RequiresDowngrade => 1,
             PackageStatus::RequiresUpdate => 2,
         },
         Err(error) => match error {
@@ -48,6 +55,7 @@ impl fmt::Display for PackageStatus {
                 PackageStatus::NotInstalled => "Not installed",
                 PackageStatus::UpToDate => "Up to date",
                 PackageStatus::RequiresUpdate => "Requires update",
+                PackageStatus::RequiresDowngrade => "Requires downgrade",
             }
         )
     }
@@ -252,9 +260,56 @@ impl std::fmt::Display for ResolvedAction {
     }
 }
 
+/// A single step of a transaction plan, as shown to the user before processing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedAction {
+    pub action: PackageAction,
+    pub version: pahkat_types::package::Version,
+    pub status: PackageStatus,
+    /// True if this action was pulled in to satisfy a dependency, rather than requested.
+    pub is_dependency: bool,
+    pub download_size: u64,
+    /// Change in installed size, in bytes. Negative for uninstalls.
+    pub installed_size: i64,
+}
+
+/// The resolved outcome of a transaction, without processing any of its actions.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionPlan {
+    pub actions: Vec<PlannedAction>,
+    pub download_size: u64,
+    pub installed_size_delta: i64,
+    pub is_reboot_required: bool,
+}
+
+impl TransactionPlan {
+    fn new(actions: Vec<PlannedAction>, is_reboot_required: bool) -> TransactionPlan {
+        let download_size = actions.iter().map(|x| x.download_size).sum();
+        let installed_size_delta = actions.iter().map(|x| x.installed_size).sum();
+
+        TransactionPlan {
+            actions,
+            download_size,
+            installed_size_delta,
+            is_reboot_required,
+        }
+    }
+}
+
+/// Options for resolving a transaction.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransactionOptions {
+    /// Allow installing an older release than the one installed, which only
+    /// happens for actions that pin a version
+    pub allow_downgrade: bool,
+}
+
 pub struct PackageTransaction {
     store: Arc<dyn PackageStore>,
     actions: Arc<Vec<ResolvedAction>>,
+    plan: TransactionPlan,
     is_reboot_required: bool,
 }
 
@@ -313,9 +368,18 @@ pub struct ResolvedPackageQuery {
 }
 
 impl PackageTransaction {
+    /// Resolves a transaction that refuses to downgrade any package.
     pub fn new(
         store: Arc<dyn PackageStore>,
         actions: Vec<PackageAction>,
+    ) -> Result<PackageTransaction, PackageCandidateError> {
+        Self::with_options(store, actions, TransactionOptions::default())
+    }
+
+    pub fn with_options(
+        store: Arc<dyn PackageStore>,
+        actions: Vec<PackageAction>,
+        options: TransactionOptions,
     ) -> Result<PackageTransaction, PackageCandidateError> {
         log::debug!("New transaction with actions: {:#?}", &actions);
 
@@ -336,31 +400,60 @@ impl PackageTransaction {
         let mutation_set =
             crate::repo::resolve_package_set(&*store, &*candidate_keys, &*install_target)?;
 
+        if !options.allow_downgrade {
+            let downgrade = mutation_set.iter().find(|x| {
+                x.action == PackageActionType::Install
+                    && x.status == PackageStatus::RequiresDowngrade
+            });
+            if let Some(candidate) = downgrade {
+                return Err(PackageCandidateError::Downgrade(
+                    candidate.package_key.clone(),
+                ));
+            }
+        }
+
         let is_reboot_required = mutation_set.iter().any(|x| x.is_reboot_required);
 
         // Create a list of resolved actions to be processed.
-        let new_actions = mutation_set
+        let (new_actions, planned_actions): (Vec<_>, Vec<_>) = mutation_set
             .into_iter()
             .map(|candidate| {
                 let key = candidate.package_key;
                 let action = candidate.action;
+                let requested = actions.iter().find(|x| &x.id == &key).cloned();
+                let is_dependency = requested.is_none();
 
-                ResolvedAction {
+                let resolved = ResolvedAction {
                     descriptor: candidate.descriptor,
                     release: candidate.release,
                     target: candidate.target,
-                    action: actions
-                        .iter()
-                        .find(|x| &x.id == &key)
-                        .cloned()
-                        .unwrap_or_else(|| PackageAction {
-                            id: key,
-                            action,
-                            target: InstallTarget::System,
-                        }),
-                }
+                    action: requested.unwrap_or_else(|| PackageAction {
+                        id: key,
+                        action,
+                        target: InstallTarget::System,
+                    }),
+                };
+
+                // Uninstalls download nothing; the installed size of the already
+                // installed version is not known, so the candidate's is used.
+                let payload = &resolved.target.payload;
+                let (download_size, installed_size) = match action {
+                    PackageActionType::Install => (payload.size(), payload.installed_size() as i64),
+                    PackageActionType::Uninstall => (0, -(payload.installed_size() as i64)),
+                };
+
+                let planned = PlannedAction {
+                    action: resolved.action.clone(),
+                    version: resolved.release.version.clone(),
+                    status: candidate.status,
+                    is_dependency,
+                    download_size,
+                    installed_size,
+                };
+
+                (resolved, planned)
             })
-            .collect::<Vec<_>>();
+            .unzip();
 
         // Check for uninstall actions that contradict this set
         // for action in actions
@@ -383,6 +476,7 @@ impl PackageTransaction {
         Ok(PackageTransaction {
             store,
             actions: Arc::new(new_actions),
+            plan: TransactionPlan::new(planned_actions, is_reboot_required),
             is_reboot_required,
         })
     }
@@ -391,6 +485,11 @@ impl PackageTransaction {
         Arc::clone(&self.actions)
     }
 
+    /// Describes what processing this transaction would do, without doing it.
+    pub fn plan(&self) -> TransactionPlan {
+        self.plan.clone()
+    }
+
     pub fn is_reboot_required(&self) -> bool {
         self.is_reboot_required
     }
@@ -423,6 +522,16 @@ impl PackageTransaction {
                         match store.install(&action.id, action.target) {
                             Ok(_) => {
                                 log::trace!("We came out the other side.");
+
+                                let config = store.config();
+                                let send_stats = config.read().unwrap().settings().send_install_stats();
+                                if send_stats {
+                                    crate::stats::send_install_ping(
+                                        &action.id,
+                                        &record.release,
+                                        &record.target,
+                                    );
+                                }
                             }
                             Err(e) => {
                                 log::error!("{:?}", &e);
@@ -1,14 +1,24 @@
+use std::borrow::Cow;
 use std::path::PathBuf;
 
 use structopt::StructOpt;
 use url::Url;
 
-use pahkat_repomgr::{nuke, package, repo, Request};
+use pahkat_repomgr::input::Input;
+use pahkat_repomgr::{nuke, package, release, repo, stats, strings, Request};
 use pahkat_types::package::Version;
 
 #[derive(Debug, StructOpt)]
 #[structopt()]
 struct Args {
+    /// Fail on missing inputs instead of prompting; implied when stdin is not a terminal
+    #[structopt(long, global = true)]
+    non_interactive: bool,
+
+    /// JSON or TOML file of request fields, used for inputs not given as arguments
+    #[structopt(long, global = true, parse(from_os_str))]
+    request_file: Option<PathBuf>,
+
     #[structopt(subcommand)]
     command: Command,
 }
@@ -43,12 +53,91 @@ impl RepoInitCommand {
 struct RepoIndexCommand {
     #[structopt(parse(from_os_str))]
     repo_path: Option<PathBuf>,
+
+    /// Skip rebuilding when the inputs match packages/index.manifest.toml, and keep it updated
+    #[structopt(long)]
+    manifest: bool,
+
+    /// Fail if packages/index.bin is not up to date with the TOML sources, without writing it
+    #[structopt(long, conflicts_with = "manifest")]
+    check: bool,
 }
 
 impl RepoIndexCommand {
     fn to_partial<'a>(&'a self) -> repo::indexing::PartialRequest<'a> {
         repo::indexing::PartialRequest::builder()
             .path(self.repo_path.as_ref().map(|x| &**x))
+            .manifest(self.manifest)
+            .check(self.check)
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct RepoValidateCommand {
+    #[structopt(parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+}
+
+impl RepoValidateCommand {
+    fn to_partial<'a>(&'a self) -> repo::validate::PartialRequest<'a> {
+        repo::validate::PartialRequest::builder()
+            .path(self.repo_path.as_ref().map(|x| &**x))
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct RepoRenderCommand {
+    #[structopt(parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    /// Directory to write the site to; defaults to `site` in the repository
+    #[structopt(short, long, parse(from_os_str))]
+    output_path: Option<PathBuf>,
+
+    /// Language to render pages in
+    #[structopt(short, long)]
+    language: Option<String>,
+}
+
+impl RepoRenderCommand {
+    fn to_partial<'a>(&'a self) -> repo::render::PartialRequest<'a> {
+        repo::render::PartialRequest::builder()
+            .path(self.repo_path.as_ref().map(|x| &**x))
+            .output_path(self.output_path.as_ref().map(|x| &**x))
+            .language(self.language.as_ref().map(|x| &**x))
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct RepoDiffCommand {
+    /// Old side: a repository directory, an index.bin or a git revision
+    old: Option<String>,
+
+    /// New side, as for the old side; defaults to the working tree
+    new: Option<String>,
+
+    /// Repository to resolve git revisions and relative paths in
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    /// Changelog format: markdown or json
+    #[structopt(short, long, default_value = "markdown")]
+    format: repo::diff::Format,
+
+    /// Write the changelog to a file rather than stdout
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+impl RepoDiffCommand {
+    fn to_partial<'a>(&'a self) -> repo::diff::PartialRequest<'a> {
+        repo::diff::PartialRequest::builder()
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .old(self.old.as_ref().map(|x| &**x))
+            .new(self.new.as_ref().map(|x| &**x))
             .build()
     }
 }
@@ -89,12 +178,16 @@ struct PackageUpdateCommand {
     #[structopt(short = "-r", long, parse(from_os_str))]
     repo_path: Option<PathBuf>,
 
+    /// Target TOML file; may be given more than once to update several targets at once
     #[structopt(short = "-i", long, parse(from_os_str))]
-    payload_path: Option<PathBuf>,
+    payload_path: Vec<PathBuf>,
 
     #[structopt(short, long)]
     platform: Option<String>,
 
+    #[structopt(short, long)]
+    arch: Option<String>,
+
     #[structopt(short, long)]
     channel: Option<String>,
 
@@ -103,6 +196,10 @@ struct PackageUpdateCommand {
 
     #[structopt(short, long)]
     url: Option<url::Url>,
+
+    /// Artifact file to read size, checksum and installer metadata from
+    #[structopt(long, parse(from_os_str))]
+    artifact: Option<PathBuf>,
 }
 
 impl PackageUpdateCommand {
@@ -110,11 +207,116 @@ impl PackageUpdateCommand {
         package::update::PartialRequest::builder()
             .id(self.id.as_ref().map(|x| &**x))
             .platform(self.platform.as_ref().map(|x| &**x))
+            .arch(self.arch.as_ref().map(|x| &**x))
             .version(self.version.as_ref().map(|x| &*x))
-            .payload_path(self.payload_path.as_ref().map(|x| &**x))
+            .payload_paths(Some(&self.payload_path))
             .repo_path(self.repo_path.as_ref().map(|x| &**x))
             .channel(self.channel.as_ref().map(|x| &**x))
             .url(self.url.as_ref())
+            .artifact(self.artifact.as_ref().map(|x| &**x))
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ReleaseRemoveCommand {
+    id: Option<String>,
+
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    #[structopt(short, long)]
+    version: Option<Version>,
+
+    #[structopt(short, long)]
+    channel: Option<String>,
+
+    /// Only remove the target for this platform, rather than the whole release
+    #[structopt(short, long)]
+    platform: Option<String>,
+
+    #[structopt(short, long)]
+    arch: Option<String>,
+}
+
+impl ReleaseRemoveCommand {
+    fn to_partial<'a>(&'a self) -> release::remove::PartialRequest<'a> {
+        release::remove::PartialRequest::builder()
+            .id(self.id.as_ref().map(|x| &**x))
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .version(self.version.as_ref())
+            .channel(self.channel.as_ref().map(|x| &**x))
+            .platform(self.platform.as_ref().map(|x| &**x))
+            .arch(self.arch.as_ref().map(|x| &**x))
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ReleaseYankCommand {
+    id: Option<String>,
+
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    #[structopt(short, long)]
+    version: Option<Version>,
+
+    #[structopt(short, long)]
+    channel: Option<String>,
+
+    /// Make a yanked release available for new installs again
+    #[structopt(long)]
+    undo: bool,
+}
+
+impl ReleaseYankCommand {
+    fn to_partial<'a>(&'a self) -> release::yank::PartialRequest<'a> {
+        release::yank::PartialRequest::builder()
+            .id(self.id.as_ref().map(|x| &**x))
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .version(self.version.as_ref())
+            .channel(self.channel.as_ref().map(|x| &**x))
+            .yanked(!self.undo)
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ReleasePromoteCommand {
+    id: Option<String>,
+
+    version: Option<Version>,
+
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    /// Channel the release is in (`stable` for none)
+    #[structopt(long)]
+    from: Option<String>,
+
+    /// Channel to copy the release into (`stable` for none)
+    #[structopt(long)]
+    to: Option<String>,
+
+    /// Drop prerelease tags such as `-nightly.<date>` from the promoted version
+    #[structopt(long)]
+    strip_prerelease: bool,
+
+    /// Don't rebuild the index after promoting
+    #[structopt(long)]
+    no_index: bool,
+}
+
+impl ReleasePromoteCommand {
+    fn to_partial<'a>(&'a self) -> release::promote::PartialRequest<'a> {
+        release::promote::PartialRequest::builder()
+            .id(self.id.as_ref().map(|x| &**x))
+            .version(self.version.as_ref())
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .from(self.from.as_ref().map(|x| &**x))
+            .to(self.to.as_ref().map(|x| &**x))
+            .strip_prerelease(self.strip_prerelease)
             .build()
     }
 }
@@ -123,6 +325,9 @@ impl PackageUpdateCommand {
 enum RepoCommand {
     Init(RepoInitCommand),
     Index(RepoIndexCommand),
+    Validate(RepoValidateCommand),
+    Diff(RepoDiffCommand),
+    Render(RepoRenderCommand),
 }
 
 #[derive(Debug, StructOpt)]
@@ -131,6 +336,108 @@ enum PackageCommand {
     Update(PackageUpdateCommand),
 }
 
+#[derive(Debug, StructOpt)]
+enum ReleaseCommand {
+    Remove(ReleaseRemoveCommand),
+    /// Keep a release in the index but stop it being chosen for new installs
+    Yank(ReleaseYankCommand),
+    /// Copy a release and its targets into another channel, then rebuild the index
+    Promote(ReleasePromoteCommand),
+}
+
+#[derive(Debug, StructOpt)]
+struct StringsSetCommand {
+    language: Option<String>,
+
+    key: Option<String>,
+
+    value: Option<String>,
+
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    /// Treat the key as a channel rather than a tag
+    #[structopt(short, long)]
+    channel: bool,
+
+    /// Remove the translation for the key
+    #[structopt(short = "-d", long)]
+    remove: bool,
+}
+
+impl StringsSetCommand {
+    fn to_partial<'a>(&'a self) -> strings::set::PartialRequest<'a> {
+        strings::set::PartialRequest::builder()
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .language(self.language.as_ref().map(|x| &**x))
+            .section(Some(if self.channel {
+                strings::Section::Channels
+            } else {
+                strings::Section::Tags
+            }))
+            .key(self.key.as_ref().map(|x| &**x))
+            .value(self.value.as_ref().map(|x| &**x))
+            .remove(self.remove)
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct StringsExportCommand {
+    language: Option<String>,
+
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    /// Language to show alongside each key as a hint for translators
+    #[structopt(short, long)]
+    source_language: Option<String>,
+
+    /// Output file; `.toml` exports TOML, anything else gettext. Defaults to stdout
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+impl StringsExportCommand {
+    fn to_partial<'a>(&'a self) -> strings::export::PartialRequest<'a> {
+        strings::export::PartialRequest::builder()
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .language(self.language.as_ref().map(|x| &**x))
+            .source_language(self.source_language.as_ref().map(|x| &**x))
+            .format(self.output.as_ref().map(|x| strings::Format::from_path(x)))
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct StringsImportCommand {
+    language: Option<String>,
+
+    /// Input file; `.toml` imports TOML, anything else gettext
+    #[structopt(parse(from_os_str))]
+    input_path: Option<PathBuf>,
+
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+}
+
+impl StringsImportCommand {
+    fn to_partial<'a>(&'a self) -> strings::import::PartialRequest<'a> {
+        strings::import::PartialRequest::builder()
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .language(self.language.as_ref().map(|x| &**x))
+            .input_path(self.input_path.as_ref().map(|x| &**x))
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum StringsCommand {
+    Set(StringsSetCommand),
+    Export(StringsExportCommand),
+    Import(StringsImportCommand),
+}
+
 #[derive(Debug, StructOpt)]
 enum NukeCommand {
     Package(NukePackageCommand),
@@ -140,6 +447,7 @@ enum NukeCommand {
 enum NukePackageCommand {
     Releases(NukePackageReleasesCommand),
     Nightlies(NukePackageNightliesCommand),
+    Retain(NukePackageRetainCommand),
 }
 
 #[derive(Debug, StructOpt)]
@@ -173,11 +481,93 @@ impl NukePackageNightliesCommand {
     }
 }
 
+#[derive(Debug, StructOpt)]
+struct NukePackageRetainCommand {
+    #[structopt(short = "-r", long, parse(from_os_str))]
+    repo_path: Option<PathBuf>,
+
+    /// Only apply the policy to the package with this id
+    #[structopt(short, long)]
+    id: Option<String>,
+
+    /// Releases to keep in each channel
+    #[structopt(short = "-k", long)]
+    keep: Option<u32>,
+
+    /// Releases to keep in a given channel, as `<channel>=<count>`; `stable` is the default channel
+    #[structopt(short = "-K", long, parse(try_from_str = nuke::package::retain::parse_channel_keep))]
+    keep_channel: Vec<(String, u32)>,
+
+    /// Keep releases dated on or after this date (RFC 3339 or YYYY-MM-DD)
+    #[structopt(short, long, parse(try_from_str = nuke::package::retain::parse_date))]
+    newer_than: Option<chrono::DateTime<chrono::Utc>>,
+
+    /// List what would be removed without changing anything
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Local directory artifacts are served from; unreferenced artifacts in it are deleted
+    #[structopt(long, parse(from_os_str))]
+    artifacts_path: Option<PathBuf>,
+
+    /// URL the artifacts directory is served at
+    #[structopt(long)]
+    artifacts_url: Option<Url>,
+}
+
+impl NukePackageRetainCommand {
+    fn to_partial<'a>(&'a self) -> nuke::package::retain::PartialRequest<'a> {
+        nuke::package::retain::PartialRequest::builder()
+            .repo_path(self.repo_path.as_ref().map(|x| &**x))
+            .id(self.id.as_ref().map(|x| &**x))
+            .keep(self.keep)
+            .channel_keep(Some(&self.keep_channel))
+            .newer_than(self.newer_than)
+            .dry_run(self.dry_run)
+            .artifacts_path(self.artifacts_path.as_ref().map(|x| &**x))
+            .artifacts_url(self.artifacts_url.as_ref())
+            .build()
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct StatsCommand {
+    /// Repository URL on pahkat-server, such as `https://example.com/main/`
+    #[structopt(parse(try_from_str = Url::parse))]
+    url: Option<Url>,
+
+    /// Only show statistics for the package with this id
+    #[structopt(short, long)]
+    package: Option<String>,
+
+    /// Only count downloads on or after this date (YYYY-MM-DD)
+    #[structopt(long)]
+    since: Option<chrono::NaiveDate>,
+
+    /// Print statistics as JSON instead of a table
+    #[structopt(long)]
+    json: bool,
+}
+
+impl StatsCommand {
+    fn to_partial<'a>(&'a self) -> stats::PartialRequest<'a> {
+        stats::PartialRequest::builder()
+            .url(self.url.as_ref())
+            .package(self.package.as_ref().map(|x| &**x))
+            .since(self.since)
+            .build()
+    }
+}
+
 #[derive(Debug, StructOpt)]
 enum Command {
     Repo(RepoCommand),
     Package(PackageCommand),
+    Release(ReleaseCommand),
+    Strings(StringsCommand),
     Nuke(NukeCommand),
+    /// Show download and install counts from pahkat-server
+    Stats(StatsCommand),
     Payload(pahkat_types::payload::Payload),
 }
 
@@ -185,40 +575,168 @@ fn main() -> anyhow::Result<()> {
     env_logger::init();
     let args = Args::from_args();
 
+    let input = match args.request_file.as_ref() {
+        Some(path) => Input::from_request_file(path)?,
+        None => Input::default(),
+    };
+    let input = input.interactive(!args.non_interactive && atty::is(atty::Stream::Stdin));
+
     match args.command {
         Command::Repo(repo) => match repo {
             RepoCommand::Init(init) => {
-                let req = repo::init::Request::new_from_user_input(init.to_partial())?;
+                let req = repo::init::Request::new_from_user_input(init.to_partial(), &input)?;
                 repo::init::init(req)?;
             }
             RepoCommand::Index(index) => {
-                let req = repo::indexing::Request::new_from_user_input(index.to_partial())?;
-                repo::indexing::index(req)?;
+                let req = repo::indexing::Request::new_from_user_input(index.to_partial(), &input)?;
+                match repo::indexing::index(req)? {
+                    repo::indexing::Status::Stale => {
+                        anyhow::bail!("packages/index.bin is out of date; run `repomgr repo index`")
+                    }
+                    repo::indexing::Status::UpToDate => println!("Index is up to date."),
+                    repo::indexing::Status::Unchanged => println!("Index is unchanged."),
+                    repo::indexing::Status::Written => {}
+                }
+            }
+            RepoCommand::Validate(validate) => {
+                let req =
+                    repo::validate::Request::new_from_user_input(validate.to_partial(), &input)?;
+                let report = repo::validate::validate(req)?;
+
+                for issue in report.issues.iter() {
+                    eprintln!("{}", issue);
+                }
+
+                if !report.is_valid() {
+                    anyhow::bail!("Validation failed with {} issue(s)", report.issues.len());
+                }
+
+                println!("Validated {} package(s).", report.packages.len());
+            }
+            RepoCommand::Render(render) => {
+                let req = repo::render::Request::new_from_user_input(render.to_partial(), &input)?;
+                repo::render::render(req)?;
+            }
+            RepoCommand::Diff(diff) => {
+                let req = repo::diff::Request::new_from_user_input(diff.to_partial(), &input)?;
+                let changelog = repo::diff::diff(req)?.render(diff.format);
+
+                match diff.output.as_ref() {
+                    Some(path) => std::fs::write(path, changelog)?,
+                    None => print!("{}", changelog),
+                }
             }
         },
         Command::Package(package) => match package {
             PackageCommand::Init(init) => {
-                let req = package::init::Request::new_from_user_input(init.to_partial())?;
+                let req = package::init::Request::new_from_user_input(init.to_partial(), &input)?;
                 package::init::init(req)?;
             }
             PackageCommand::Update(update) => {
-                let req = package::update::Request::new_from_user_input(update.to_partial())?;
+                let req =
+                    package::update::Request::new_from_user_input(update.to_partial(), &input)?;
                 package::update::update(req)?;
             }
         },
+        Command::Release(release) => match release {
+            ReleaseCommand::Remove(remove) => {
+                let req =
+                    release::remove::Request::new_from_user_input(remove.to_partial(), &input)?;
+                release::remove::remove(req)?;
+            }
+            ReleaseCommand::Yank(yank) => {
+                let req = release::yank::Request::new_from_user_input(yank.to_partial(), &input)?;
+                release::yank::yank(req)?;
+            }
+            ReleaseCommand::Promote(promote) => {
+                let req =
+                    release::promote::Request::new_from_user_input(promote.to_partial(), &input)?;
+                let repo_path = match req.repo_path.ends_with("index.toml") {
+                    true => req.repo_path.parent().unwrap().to_path_buf(),
+                    false => req.repo_path.to_path_buf(),
+                };
+                let version = release::promote::promote(req)?;
+                println!("Promoted release as {}.", version);
+
+                if !promote.no_index {
+                    let req = repo::indexing::Request::builder()
+                        .path(Cow::Owned(repo_path))
+                        .build();
+                    repo::indexing::index(req)?;
+                }
+            }
+        },
+        Command::Strings(x) => match x {
+            StringsCommand::Set(set) => {
+                let req = strings::set::Request::new_from_user_input(set.to_partial(), &input)?;
+                strings::set::set(req)?;
+            }
+            StringsCommand::Export(export) => {
+                let req =
+                    strings::export::Request::new_from_user_input(export.to_partial(), &input)?;
+                let data = strings::export::export(req)?;
+
+                match export.output.as_ref() {
+                    Some(path) => std::fs::write(path, data)?,
+                    None => print!("{}", data),
+                }
+            }
+            StringsCommand::Import(import) => {
+                let req =
+                    strings::import::Request::new_from_user_input(import.to_partial(), &input)?;
+                strings::import::import(req)?;
+            }
+        },
         Command::Nuke(x) => match x {
             NukeCommand::Package(x) => match x {
                 NukePackageCommand::Releases(nuke) => {
-                    let req =
-                        nuke::package::releases::Request::new_from_user_input(nuke.to_partial())?;
+                    let req = nuke::package::releases::Request::new_from_user_input(
+                        nuke.to_partial(),
+                        &input,
+                    )?;
                     nuke::package::releases::nuke_releases(req)?;
                 },
                 NukePackageCommand::Nightlies(nuke) => {
-                    let req = nuke::package::nightlies::Request::new_from_user_input(nuke.to_partial())?;
+                    let req = nuke::package::nightlies::Request::new_from_user_input(
+                        nuke.to_partial(),
+                        &input,
+                    )?;
                     nuke::package::nightlies::nuke_nightlies(req)?;
                 }
+                NukePackageCommand::Retain(nuke) => {
+                    let req = nuke::package::retain::Request::new_from_user_input(
+                        nuke.to_partial(),
+                        &input,
+                    )?;
+                    let dry_run = req.dry_run;
+                    let report = nuke::package::retain::retain(req)?;
+                    let verb = if dry_run { "Would remove" } else { "Removed" };
+
+                    for release in report.releases.iter() {
+                        println!(
+                            "{} {} {} ({})",
+                            verb,
+                            release.id,
+                            release.version,
+                            release.channel.as_deref().unwrap_or("stable")
+                        );
+                    }
+                    for path in report.artifacts.iter() {
+                        println!("{} {}", verb, path.display());
+                    }
+                }
             },
         },
+        Command::Stats(x) => {
+            let req = stats::Request::new_from_user_input(x.to_partial(), &input)?;
+            let stats = stats::stats(req)?;
+
+            if x.json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                print!("{}", stats::render_table(&stats));
+            }
+        }
         Command::Payload(payload) => {
             println!("{}", toml::to_string_pretty(&payload)?);
         }
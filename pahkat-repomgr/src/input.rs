@@ -0,0 +1,385 @@
+//! Resolution of request fields that were not provided by the caller.
+//!
+//! A missing field is looked up, in order, in the request file of the [`Input`]
+//! passed to [`Request::new_from_user_input`](crate::Request::new_from_user_input),
+//! in a `PAHKAT_<FIELD>` environment variable, and finally by prompting.
+//! Prompting requires the `cli` feature and an interactive [`Input`]; otherwise
+//! a field with no default is reported as [`InputError::Missing`].
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// Prefix of the environment variables fields are read from.
+pub const ENV_PREFIX: &str = "PAHKAT_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum InputError {
+    #[error(
+        "Missing required input `{}`; pass it as an argument, set `{}` or add it to a request file",
+        .0,
+        env_name(.0)
+    )]
+    Missing(&'static str),
+
+    #[error("Invalid value for `{key}`: {reason}")]
+    Invalid { key: &'static str, reason: String },
+
+    #[error("Failed to read input")]
+    Prompt(#[source] io::Error),
+
+    #[error("Failed to determine the current directory")]
+    CurrentDir(#[source] io::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestFileError {
+    #[error("Failed to read request file `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to parse JSON request file `{0}`")]
+    Json(PathBuf, #[source] serde_json::Error),
+
+    #[error("Failed to parse TOML request file `{0}`")]
+    Toml(PathBuf, #[source] toml::de::Error),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    fn into_string(self) -> String {
+        match self {
+            Value::Bool(v) => v.to_string(),
+            Value::Integer(v) => v.to_string(),
+            Value::Float(v) => v.to_string(),
+            Value::String(v) => v,
+            Value::List(v) => v
+                .into_iter()
+                .map(Value::into_string)
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+
+    fn into_list(self) -> Vec<String> {
+        match self {
+            Value::List(v) => v.into_iter().map(Value::into_string).collect(),
+            Value::String(v) => v.split_whitespace().map(str::to_string).collect(),
+            v => vec![v.into_string()],
+        }
+    }
+}
+
+/// Where fields missing from a request are resolved from.
+///
+/// The default looks only in the environment and never prompts.
+#[derive(Debug, Clone, Default)]
+pub struct Input {
+    interactive: bool,
+    values: HashMap<String, Value>,
+}
+
+impl Input {
+    /// Loads a flat table of field values from a `.json` file, or from TOML
+    /// for any other extension. Keys are request field names such as
+    /// `repo_path`.
+    pub fn from_request_file(path: &Path) -> Result<Input, RequestFileError> {
+        let file = fs::read_to_string(path)
+            .map_err(|e| RequestFileError::ReadFailed(path.to_path_buf(), e))?;
+
+        let values = match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => serde_json::from_str(&file)
+                .map_err(|e| RequestFileError::Json(path.to_path_buf(), e))?,
+            _ => {
+                toml::from_str(&file).map_err(|e| RequestFileError::Toml(path.to_path_buf(), e))?
+            }
+        };
+
+        Ok(Input {
+            interactive: false,
+            values,
+        })
+    }
+
+    /// Enables or disables prompting for missing fields.
+    ///
+    /// Without the `cli` feature prompting is unavailable and this has no
+    /// effect.
+    pub fn interactive(mut self, value: bool) -> Self {
+        self.interactive = value;
+        self
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        cfg!(feature = "cli") && self.interactive
+    }
+
+    fn lookup_value(&self, key: &str) -> Option<Value> {
+        if let Some(value) = self.values.get(key) {
+            return Some(value.clone());
+        }
+
+        std::env::var(env_name(key)).ok().map(Value::String)
+    }
+
+    /// Looks up a field that is never prompted for.
+    pub(crate) fn lookup<T>(&self, key: &'static str) -> Result<Option<T>, InputError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.lookup_value(key) {
+            Some(value) => parse(key, &value.into_string()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Looks up a list field, given as an array in a request file or as a
+    /// space-delimited string.
+    pub(crate) fn lookup_list<T>(&self, key: &'static str) -> Result<Option<Vec<T>>, InputError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.lookup_value(key) {
+            Some(value) => value
+                .into_list()
+                .iter()
+                .map(|x| parse(key, x))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Looks up a boolean flag, which is unset if missing.
+    pub(crate) fn lookup_flag(&self, key: &'static str) -> Result<bool, InputError> {
+        self.lookup(key).map(|x| x.unwrap_or(false))
+    }
+
+    /// A field that is prompted for if it cannot be found elsewhere.
+    pub(crate) fn field<'a>(&'a self, key: &'static str, prompt: &'a str) -> Field<'a> {
+        Field {
+            input: self,
+            key,
+            prompt,
+            default: None,
+            initial_text: None,
+            allow_empty: false,
+        }
+    }
+}
+
+fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.to_uppercase())
+}
+
+/// The current directory, used as the default for repository paths.
+pub(crate) fn current_dir() -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|x| x.to_str().map(str::to_string))
+        .unwrap_or_else(|| ".".into())
+}
+
+/// The current directory, for path fields that default to it without
+/// prompting.
+pub(crate) fn current_path() -> Result<PathBuf, InputError> {
+    std::env::current_dir().map_err(InputError::CurrentDir)
+}
+
+fn parse<T>(key: &'static str, value: &str) -> Result<T, InputError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.parse().map_err(|e: T::Err| InputError::Invalid {
+        key,
+        reason: e.to_string(),
+    })
+}
+
+/// A field that is prompted for if it cannot be found elsewhere, created with
+/// [`Input::field`].
+#[cfg_attr(not(feature = "cli"), allow(dead_code))]
+pub(crate) struct Field<'a> {
+    input: &'a Input,
+    key: &'static str,
+    prompt: &'a str,
+    default: Option<String>,
+    initial_text: Option<&'a str>,
+    allow_empty: bool,
+}
+
+impl<'a> Field<'a> {
+    pub fn default<S: Into<String>>(mut self, default: S) -> Self {
+        self.default = Some(default.into());
+        self
+    }
+
+    pub fn initial_text(mut self, initial_text: &'a str) -> Self {
+        self.initial_text = Some(initial_text);
+        self
+    }
+
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.allow_empty = allow_empty;
+        self
+    }
+
+    pub fn required<T>(self) -> Result<T, InputError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let key = self.key;
+        match self.resolve()? {
+            Some(value) => parse(key, &value),
+            None => Err(InputError::Missing(key)),
+        }
+    }
+
+    /// Resolves a field that may be left empty, such as a channel where empty
+    /// means stable.
+    pub fn optional<T>(self) -> Result<Option<T>, InputError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let key = self.key;
+        match self.allow_empty(true).resolve()? {
+            Some(value) if value != "" => parse(key, &value).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    fn resolve(self) -> Result<Option<String>, InputError> {
+        if let Some(value) = self.input.lookup_value(self.key) {
+            return Ok(Some(value.into_string()));
+        }
+
+        if self.input.is_interactive() {
+            return self.prompt().map(Some);
+        }
+
+        Ok(self.default)
+    }
+
+    #[cfg(feature = "cli")]
+    fn prompt(self) -> Result<String, InputError> {
+        let mut input = dialoguer::Input::<String>::new();
+        input.with_prompt(self.prompt).allow_empty(self.allow_empty);
+
+        if let Some(default) = self.default {
+            input.default(default);
+        }
+
+        if let Some(initial_text) = self.initial_text {
+            input.with_initial_text(initial_text);
+        }
+
+        input.interact().map_err(InputError::Prompt)
+    }
+
+    #[cfg(not(feature = "cli"))]
+    fn prompt(self) -> Result<String, InputError> {
+        Err(InputError::Missing(self.key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_file(name: &str, contents: &str) -> (tempfile::TempDir, Input) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        let input = Input::from_request_file(&path).unwrap();
+        (dir, input)
+    }
+
+    #[test]
+    fn toml_request_file() {
+        let (_dir, input) = request_file(
+            "request.toml",
+            "repo_path = \"/srv/repo\"\nkeep = 3\ndry_run = true\nchannel_keep = [\"beta=2\", \"nightly=5\"]\n",
+        );
+
+        assert_eq!(
+            input.lookup::<PathBuf>("repo_path").unwrap(),
+            Some(PathBuf::from("/srv/repo"))
+        );
+        assert_eq!(input.lookup::<u32>("keep").unwrap(), Some(3));
+        assert!(input.lookup_flag("dry_run").unwrap());
+        assert_eq!(
+            input.lookup_list::<String>("channel_keep").unwrap(),
+            Some(vec!["beta=2".to_string(), "nightly=5".to_string()])
+        );
+        assert_eq!(input.lookup::<String>("pahkat_test_unset").unwrap(), None);
+        assert!(!input.lookup_flag("pahkat_test_unset").unwrap());
+    }
+
+    #[test]
+    fn json_request_file() {
+        let (_dir, input) = request_file("request.json", r#"{"id": "app", "tags": "a b"}"#);
+
+        assert_eq!(input.lookup::<String>("id").unwrap(), Some("app".into()));
+        assert_eq!(
+            input.lookup_list::<String>("tags").unwrap(),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        let (_dir, input) = request_file("request.toml", "keep = \"many\"\n");
+
+        assert!(matches!(
+            input.lookup::<u32>("keep"),
+            Err(InputError::Invalid { key: "keep", .. })
+        ));
+    }
+
+    #[test]
+    fn fields_without_prompting() {
+        let (_dir, input) = request_file("request.toml", "id = \"app\"\nchannel = \"\"\n");
+        let input = input.interactive(false);
+
+        assert_eq!(input.field("id", "Id").required::<String>().unwrap(), "app");
+        assert_eq!(
+            input
+                .field("channel", "Channel")
+                .optional::<String>()
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            input
+                .field("pahkat_test_unset", "Unset")
+                .default("x")
+                .required::<String>()
+                .unwrap(),
+            "x"
+        );
+        assert!(matches!(
+            input
+                .field("pahkat_test_unset", "Unset")
+                .required::<String>(),
+            Err(InputError::Missing("pahkat_test_unset"))
+        ));
+    }
+}
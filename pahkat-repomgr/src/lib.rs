@@ -1,6 +1,14 @@
+pub mod input;
 pub mod nuke;
 pub mod package;
+pub mod release;
 pub mod repo;
+#[cfg(feature = "cli")]
+pub mod stats;
+pub mod strings;
+
+#[cfg(test)]
+pub(crate) mod testing;
 
 pub(crate) mod fbs {
     fbs_build::include_fbs!("index");
@@ -10,7 +18,10 @@ pub trait Request {
     type Error;
     type Partial;
 
-    fn new_from_user_input(partial: Self::Partial) -> Result<Self, Self::Error>
+    fn new_from_user_input(
+        partial: Self::Partial,
+        input: &input::Input,
+    ) -> Result<Self, Self::Error>
     where
         Self: Sized;
 }
@@ -1,2 +1,3 @@
 pub mod releases;
 pub mod nightlies;
+pub mod retain;
@@ -5,6 +5,8 @@ use std::path::{Path, PathBuf};
 
 use typed_builder::TypedBuilder;
 
+use crate::input::{self, Input, InputError};
+
 #[non_exhaustive]
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct Request<'a> {
@@ -34,6 +36,9 @@ pub enum RequestError {
 
     #[error("Invalid input")]
     InvalidInput,
+
+    #[error(transparent)]
+    Input(#[from] InputError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -76,22 +81,15 @@ impl<'a> crate::Request for Request<'a> {
     type Error = RequestError;
     type Partial = PartialRequest<'a>;
 
-    fn new_from_user_input(partial: Self::Partial) -> Result<Self, Self::Error> {
-        use dialoguer::Input;
-
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
         let repo_path = match partial.repo_path {
             Some(path) => Cow::Borrowed(path),
-            None => Input::<String>::new()
-                .default(
-                    std::env::current_dir()
-                        .ok()
-                        .and_then(|x| x.to_str().map(str::to_string))
-                        .unwrap_or_else(|| ".".into()),
-                )
-                .with_prompt("Repository Path")
-                .interact()
-                .map(|p| Cow::Owned(PathBuf::from(p)))
-                .map_err(RequestError::PathError)?,
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
         };
 
         let _ = find_repo(&repo_path)?;
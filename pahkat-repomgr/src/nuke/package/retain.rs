@@ -0,0 +1,663 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use pahkat_types::package::{Package, Release, Version};
+use typed_builder::TypedBuilder;
+use url::Url;
+
+use crate::input::{self, Input, InputError};
+use crate::release::remove::find_repo;
+pub use crate::release::remove::FindRepoError;
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub repo_path: Cow<'a, Path>,
+    /// Only apply the policy to the package with this id
+    #[builder(default)]
+    pub id: Option<Cow<'a, str>>,
+    /// Releases to keep in every channel, newest first
+    #[builder(default)]
+    pub keep: Option<u32>,
+    /// Per channel overrides of `keep`, where `stable` names the default channel
+    #[builder(default)]
+    pub channel_keep: Cow<'a, [(String, u32)]>,
+    /// Keep every release dated on or after this time
+    #[builder(default)]
+    pub newer_than: Option<DateTime<Utc>>,
+    #[builder(default)]
+    pub dry_run: bool,
+    /// Local directory artifacts are served from, to delete unreferenced artifacts in
+    #[builder(default)]
+    pub artifacts_path: Option<Cow<'a, Path>>,
+    /// URL the artifacts directory is served at
+    #[builder(default)]
+    pub artifacts_url: Option<Cow<'a, Url>>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub id: Option<&'a str>,
+    #[builder(default)]
+    pub keep: Option<u32>,
+    #[builder(default)]
+    pub channel_keep: Option<&'a [(String, u32)]>,
+    #[builder(default)]
+    pub newer_than: Option<DateTime<Utc>>,
+    #[builder(default)]
+    pub dry_run: bool,
+    #[builder(default)]
+    pub artifacts_path: Option<&'a Path>,
+    #[builder(default)]
+    pub artifacts_url: Option<&'a Url>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+
+    #[error("No retention policy given; use `nuke package releases` to remove every release")]
+    NoPolicy,
+
+    #[error("An artifacts path and artifacts URL must be given together")]
+    IncompleteArtifacts,
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let _ = find_repo(&repo_path)?;
+
+        let id = match partial.id {
+            Some(id) => Some(Cow::Borrowed(id)),
+            None => input.lookup("id")?.map(Cow::Owned),
+        };
+
+        let keep = match partial.keep {
+            Some(keep) => Some(keep),
+            None => input.lookup("keep")?,
+        };
+
+        let channel_keep = match partial.channel_keep {
+            Some(channel_keep) if !channel_keep.is_empty() => Cow::Borrowed(channel_keep),
+            _ => Cow::Owned(
+                input
+                    .lookup_list::<String>("channel_keep")?
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|x| parse_channel_keep(x))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|reason| InputError::Invalid {
+                        key: "channel_keep",
+                        reason,
+                    })?,
+            ),
+        };
+
+        let newer_than = match partial.newer_than {
+            Some(newer_than) => Some(newer_than),
+            None => match input.lookup::<String>("newer_than")? {
+                Some(x) => Some(parse_date(&x).map_err(|e| InputError::Invalid {
+                    key: "newer_than",
+                    reason: e.to_string(),
+                })?),
+                None => None,
+            },
+        };
+
+        if keep.is_none() && channel_keep.is_empty() && newer_than.is_none() {
+            return Err(RequestError::NoPolicy);
+        }
+
+        let artifacts_path = match partial.artifacts_path {
+            Some(path) => Some(Cow::Borrowed(path)),
+            None => input.lookup::<PathBuf>("artifacts_path")?.map(Cow::Owned),
+        };
+
+        let artifacts_url = match partial.artifacts_url {
+            Some(url) => Some(Cow::Borrowed(url)),
+            None => input.lookup::<Url>("artifacts_url")?.map(Cow::Owned),
+        };
+
+        if artifacts_path.is_some() != artifacts_url.is_some() {
+            return Err(RequestError::IncompleteArtifacts);
+        }
+
+        Ok(Request {
+            repo_path,
+            id,
+            keep,
+            channel_keep,
+            newer_than,
+            dry_run: partial.dry_run || input.lookup_flag("dry_run")?,
+            artifacts_path,
+            artifacts_url,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read descriptor index: `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Failed to write TOML file `{0}`")]
+    WriteToml(PathBuf, #[source] io::Error),
+
+    #[error("Failed to serialize TOML for `{0}`")]
+    SerializeToml(PathBuf, #[source] toml::ser::Error),
+
+    #[error("Failed to remove artifact `{0}`")]
+    RemoveFailed(PathBuf, #[source] io::Error),
+
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+}
+
+/// A release removed, or to be removed in a dry run.
+#[derive(Debug, Clone)]
+pub struct Removal {
+    pub id: String,
+    pub version: Version,
+    pub channel: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub releases: Vec<Removal>,
+    pub artifacts: Vec<PathBuf>,
+}
+
+/// Parses a `<channel>=<count>` retention pair.
+pub fn parse_channel_keep(input: &str) -> Result<(String, u32), String> {
+    let mut chunks = input.splitn(2, '=');
+    match (chunks.next(), chunks.next()) {
+        (Some(channel), Some(keep)) if !channel.is_empty() => keep
+            .parse()
+            .map(|keep| (channel.to_string(), keep))
+            .map_err(|e: std::num::ParseIntError| e.to_string()),
+        _ => Err(format!("Expected `<channel>=<count>`, got `{}`", input)),
+    }
+}
+
+/// Parses a date for `newer_than`, as either RFC 3339 or a plain `YYYY-MM-DD`.
+pub fn parse_date(input: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    match DateTime::parse_from_rfc3339(input) {
+        Ok(v) => Ok(v.with_timezone(&Utc)),
+        Err(_) => NaiveDate::parse_from_str(input, "%Y-%m-%d")
+            .map(|x| DateTime::from_utc(x.and_hms(0, 0, 0), Utc)),
+    }
+}
+
+/// Removes releases not retained by the request's policy.
+///
+/// A release is retained if it is among the newest `keep` of its channel, if
+/// it is dated on or after `newer_than`, or if it is the latest stable release.
+/// Only versions carrying a timestamp, such as `1.0.0-nightly.20200101T000000Z`,
+/// have a date. Undated releases are kept when `newer_than` is the only policy
+/// for their channel, as their age is unknown.
+pub fn retain<'a>(request: Request<'a>) -> Result<Report, Error> {
+    log::debug!("{:?}", request);
+
+    let pkgs_dir = find_repo(&request.repo_path)?.join("packages");
+    let mut dirs = fs::read_dir(&pkgs_dir)
+        .map_err(|e| Error::ReadFailed(pkgs_dir.clone(), e))?
+        .filter_map(Result::ok)
+        .map(|x| x.path())
+        .filter(|x| x.is_dir())
+        .collect::<Vec<_>>();
+    dirs.sort();
+
+    let channel_keep = request
+        .channel_keep
+        .iter()
+        .map(|(channel, keep)| (&**channel, *keep))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut report = Report::default();
+    let mut removed_urls = vec![];
+    let mut referenced_urls = HashSet::new();
+
+    for dir in dirs {
+        let pkg_path = dir.join("index.toml");
+        let pkg_file =
+            fs::read_to_string(&pkg_path).map_err(|e| Error::ReadFailed(pkg_path.clone(), e))?;
+        let mut descriptor = match toml::from_str::<Package>(&pkg_file)
+            .map_err(|e| Error::ReadToml(pkg_path.clone(), e))?
+        {
+            Package::Concrete(v) => v,
+            _ => continue,
+        };
+
+        let is_selected = match request.id.as_deref() {
+            Some(id) => descriptor.package.id == id,
+            None => true,
+        };
+
+        if is_selected {
+            let id = descriptor.package.id.clone();
+            let retained = retained_releases(&descriptor.release, &request, &channel_keep);
+            let count = descriptor.release.len();
+
+            let mut index = 0;
+            descriptor.release.retain(|release| {
+                let keep = retained.contains(&index);
+                index += 1;
+
+                if !keep {
+                    removed_urls.extend(release.target.iter().map(|x| x.payload.url().clone()));
+                    report.releases.push(Removal {
+                        id: id.clone(),
+                        version: release.version.clone(),
+                        channel: release.channel.clone(),
+                    });
+                }
+                keep
+            });
+
+            if descriptor.release.len() != count && !request.dry_run {
+                let data = toml::to_string_pretty(&descriptor)
+                    .map_err(|e| Error::SerializeToml(pkg_path.clone(), e))?;
+                fs::write(&pkg_path, data).map_err(|e| Error::WriteToml(pkg_path.clone(), e))?;
+                log::info!("Wrote descriptor to {}", pkg_path.display());
+            }
+        }
+
+        referenced_urls.extend(
+            descriptor
+                .release
+                .iter()
+                .flat_map(|x| x.target.iter())
+                .map(|x| x.payload.url().clone()),
+        );
+    }
+
+    if let (Some(artifacts_path), Some(artifacts_url)) = (
+        request.artifacts_path.as_ref(),
+        request.artifacts_url.as_ref(),
+    ) {
+        for url in removed_urls {
+            // Another release may still point at the same file
+            if referenced_urls.contains(&url) {
+                continue;
+            }
+
+            let path = match artifact_path(artifacts_path, artifacts_url, &url) {
+                Some(v) => v,
+                None => {
+                    log::debug!("{} is not a local artifact", &url);
+                    continue;
+                }
+            };
+
+            if !path.is_file() || report.artifacts.contains(&path) {
+                continue;
+            }
+
+            if !request.dry_run {
+                fs::remove_file(&path).map_err(|e| Error::RemoveFailed(path.clone(), e))?;
+                log::info!("Removed artifact {}", path.display());
+            }
+            report.artifacts.push(path);
+        }
+    }
+
+    Ok(report)
+}
+
+/// Returns the indices of the releases to keep.
+fn retained_releases(
+    releases: &[Release],
+    request: &Request<'_>,
+    channel_keep: &BTreeMap<&str, u32>,
+) -> HashSet<usize> {
+    let mut by_channel = BTreeMap::<&str, Vec<usize>>::new();
+    for (i, release) in releases.iter().enumerate() {
+        let channel = release.channel.as_deref().unwrap_or("stable");
+        by_channel.entry(channel).or_default().push(i);
+    }
+
+    let mut retained = HashSet::new();
+
+    for (channel, mut indices) in by_channel {
+        indices.sort_by(|a, b| {
+            releases[*b]
+                .version
+                .partial_cmp(&releases[*a].version)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        if channel == "stable" {
+            if let Some(latest) = indices.first() {
+                retained.insert(*latest);
+            }
+        }
+
+        let limit = channel_keep.get(channel).copied().or(request.keep);
+        if limit.is_none() && request.newer_than.is_none() {
+            // No policy applies to this channel
+            retained.extend(indices);
+            continue;
+        }
+
+        for (rank, i) in indices.into_iter().enumerate() {
+            let within_limit = limit.map(|x| (rank as u32) < x).unwrap_or(false);
+            let is_recent = match (request.newer_than, release_date(&releases[i].version)) {
+                (Some(newer_than), Some(date)) => date >= newer_than,
+                (Some(_), None) => limit.is_none(),
+                _ => false,
+            };
+
+            if within_limit || is_recent {
+                retained.insert(i);
+            }
+        }
+    }
+
+    retained
+}
+
+/// The timestamp of a version, taken from a prerelease identifier such as
+/// `nightly.20200101T000000Z`.
+fn release_date(version: &Version) -> Option<DateTime<Utc>> {
+    let version = match version {
+        Version::Semantic(v) => v,
+        _ => return None,
+    };
+
+    version.pre.iter().find_map(|x| {
+        let x = x.to_string();
+        NaiveDateTime::parse_from_str(&x, "%Y%m%dT%H%M%SZ")
+            .ok()
+            .map(|x| DateTime::from_utc(x, Utc))
+    })
+}
+
+/// Maps an artifact URL to a file under the artifacts directory, if it is
+/// served from there.
+fn artifact_path(artifacts_path: &Path, artifacts_url: &Url, url: &Url) -> Option<PathBuf> {
+    let base = artifacts_url.as_str().trim_end_matches('/');
+    let relative = url.as_str().strip_prefix(base)?.strip_prefix('/')?;
+    let relative = percent_encoding::percent_decode_str(relative)
+        .decode_utf8()
+        .ok()?;
+    let relative = Path::new(&*relative);
+
+    if !relative
+        .components()
+        .all(|x| matches!(x, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    Some(artifacts_path.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+
+    fn release(version: &str, channel: Option<&str>) -> Release {
+        Release::builder()
+            .version(Version::new(version).unwrap())
+            .channel(channel.map(str::to_string))
+            .build()
+    }
+
+    fn request(path: &Path) -> Request<'_> {
+        Request::builder().repo_path(Cow::Borrowed(path)).build()
+    }
+
+    /// The versions of the retained releases, in listed order.
+    fn retained(releases: &[Release], request: &Request<'_>) -> Vec<String> {
+        let channel_keep = request
+            .channel_keep
+            .iter()
+            .map(|(channel, keep)| (&**channel, *keep))
+            .collect::<BTreeMap<_, _>>();
+        let retained = retained_releases(releases, request, &channel_keep);
+
+        releases
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| retained.contains(i))
+            .map(|(_, x)| x.version.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn keep_newest_per_channel() {
+        let releases = vec![
+            release("1.0.0", None),
+            release("1.2.0", None),
+            release("1.1.0", None),
+            release("1.3.0-beta.1", Some("beta")),
+            release("1.3.0-beta.2", Some("beta")),
+        ];
+        let request = Request {
+            keep: Some(2),
+            ..request(Path::new("."))
+        };
+
+        assert_eq!(
+            retained(&releases, &request),
+            vec!["1.2.0", "1.1.0", "1.3.0-beta.1", "1.3.0-beta.2"]
+        );
+    }
+
+    #[test]
+    fn channel_overrides() {
+        let releases = vec![
+            release("1.0.0", None),
+            release("1.1.0", None),
+            release("1.2.0-beta.1", Some("beta")),
+            release("1.2.0-beta.2", Some("beta")),
+            release("1.2.0-beta.3", Some("beta")),
+        ];
+        let channel_keep = vec![("beta".to_string(), 1)];
+        let request = Request {
+            channel_keep: Cow::Borrowed(&channel_keep),
+            ..request(Path::new("."))
+        };
+
+        // Channels without a policy keep everything.
+        assert_eq!(
+            retained(&releases, &request),
+            vec!["1.0.0", "1.1.0", "1.2.0-beta.3"]
+        );
+
+        let channel_keep = vec![("stable".to_string(), 1)];
+        let request = Request {
+            keep: Some(2),
+            channel_keep: Cow::Borrowed(&channel_keep),
+            ..request(Path::new("."))
+        };
+        assert_eq!(
+            retained(&releases, &request),
+            vec!["1.1.0", "1.2.0-beta.2", "1.2.0-beta.3"]
+        );
+    }
+
+    #[test]
+    fn latest_stable_is_always_kept() {
+        let releases = vec![release("1.0.0", None), release("2.0.0", None)];
+        let request = Request {
+            keep: Some(0),
+            ..request(Path::new("."))
+        };
+
+        assert_eq!(retained(&releases, &request), vec!["2.0.0"]);
+    }
+
+    #[test]
+    fn newer_than() {
+        let releases = vec![
+            release("1.0.0-nightly.20200101T000000Z", Some("nightly")),
+            release("1.0.0-nightly.20200301T000000Z", Some("nightly")),
+            release("1.0.0-nightly.20200201T120000Z", Some("nightly")),
+        ];
+        let request = Request {
+            newer_than: Some(parse_date("2020-02-01").unwrap()),
+            ..request(Path::new("."))
+        };
+
+        assert_eq!(
+            retained(&releases, &request),
+            vec![
+                "1.0.0-nightly.20200301T000000Z",
+                "1.0.0-nightly.20200201T120000Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn newer_than_keeps_undated_releases() {
+        let releases = vec![
+            release("1.0.0", None),
+            release("1.1.0", None),
+            release("1.1.0-beta.1", Some("beta")),
+            release("1.2.0-nightly.20200101T000000Z", Some("nightly")),
+        ];
+        let request = Request {
+            newer_than: Some(parse_date("2020-02-01").unwrap()),
+            ..request(Path::new("."))
+        };
+
+        assert_eq!(
+            retained(&releases, &request),
+            vec!["1.0.0", "1.1.0", "1.1.0-beta.1"]
+        );
+    }
+
+    #[test]
+    fn newer_than_or_keep() {
+        let releases = vec![
+            release("1.0.0", Some("nightly")),
+            release("1.1.0", Some("nightly")),
+            release("1.2.0-nightly.20200101T000000Z", Some("nightly")),
+            release("1.2.0-nightly.20200301T000000Z", Some("nightly")),
+        ];
+        let request = Request {
+            keep: Some(1),
+            newer_than: Some(parse_date("2020-02-01T00:00:00Z").unwrap()),
+            ..request(Path::new("."))
+        };
+
+        // With a count to go by, undated releases beyond it are removed.
+        assert_eq!(
+            retained(&releases, &request),
+            vec!["1.2.0-nightly.20200301T000000Z"]
+        );
+
+        let request = Request {
+            keep: Some(3),
+            ..request
+        };
+        assert_eq!(
+            retained(&releases, &request),
+            vec![
+                "1.1.0",
+                "1.2.0-nightly.20200101T000000Z",
+                "1.2.0-nightly.20200301T000000Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_channel_keep_pairs() {
+        assert_eq!(parse_channel_keep("beta=3"), Ok(("beta".to_string(), 3)));
+        assert!(parse_channel_keep("beta").is_err());
+        assert!(parse_channel_keep("=3").is_err());
+        assert!(parse_channel_keep("beta=many").is_err());
+    }
+
+    #[test]
+    fn artifact_paths() {
+        let base = Path::new("/srv/artifacts");
+        let url = "https://example.com/artifacts/".parse::<Url>().unwrap();
+        let path = |x: &str| artifact_path(base, &url, &x.parse().unwrap());
+
+        assert_eq!(
+            path("https://example.com/artifacts/app%201.0.txz"),
+            Some(base.join("app 1.0.txz"))
+        );
+        assert_eq!(path("https://example.com/other/app.txz"), None);
+        assert_eq!(path("https://example.com/artifacts/..%2Fsecret"), None);
+    }
+
+    #[test]
+    fn retain_removes_releases_and_artifacts() {
+        let repo = Repo::new();
+        let pkg = descriptor("app", &[("1.0.0", None), ("1.1.0", None), ("1.2.0", None)])
+            .replace("https://example.com/", "https://example.com/artifacts/");
+        repo.package("app", &pkg);
+        for version in &["1.0.0", "1.1.0", "1.2.0"] {
+            repo.write(format!("artifacts/app-{}.txz", version), "");
+        }
+
+        let artifacts_path = repo.path().join("artifacts");
+        let artifacts_url = "https://example.com/artifacts/".parse::<Url>().unwrap();
+        let request = Request {
+            keep: Some(2),
+            artifacts_path: Some(Cow::Borrowed(&artifacts_path)),
+            artifacts_url: Some(Cow::Borrowed(&artifacts_url)),
+            ..request(repo.path())
+        };
+
+        let report = retain(Request {
+            dry_run: true,
+            ..request.clone()
+        })
+        .unwrap();
+        assert_eq!(report.releases.len(), 1);
+        assert_eq!(report.artifacts, vec![artifacts_path.join("app-1.0.0.txz")]);
+        assert_eq!(repo.read("packages/app/index.toml"), pkg);
+        assert!(artifacts_path.join("app-1.0.0.txz").is_file());
+
+        let report = retain(request).unwrap();
+        assert_eq!(report.releases[0].version.to_string(), "1.0.0");
+        assert!(!artifacts_path.join("app-1.0.0.txz").exists());
+        assert!(artifacts_path.join("app-1.1.0.txz").is_file());
+
+        let descriptor: Package = toml::from_str(&repo.read("packages/app/index.toml")).unwrap();
+        match descriptor {
+            Package::Concrete(x) => assert_eq!(
+                x.release
+                    .iter()
+                    .map(|x| x.version.to_string())
+                    .collect::<Vec<_>>(),
+                vec!["1.1.0", "1.2.0"]
+            ),
+            _ => panic!("expected a concrete package"),
+        }
+    }
+}
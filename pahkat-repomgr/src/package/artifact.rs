@@ -0,0 +1,724 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use pahkat_types::payload::Payload;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read artifact `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Artifact `{0}` is not a {1}")]
+    UnexpectedFormat(PathBuf, &'static str),
+
+    #[error("Failed to read MSI database of `{0}`")]
+    Msi(PathBuf, #[source] io::Error),
+
+    #[error("MSI `{0}` has no ProductCode property")]
+    NoProductCode(PathBuf),
+
+    #[error("Failed to read xar archive `{0}`: {1}")]
+    Xar(PathBuf, String),
+
+    #[error("Installer package `{0}` has no package identifier")]
+    NoPackageId(PathBuf),
+
+    #[error("Failed to read tarball `{0}`")]
+    Tarball(PathBuf, #[source] io::Error),
+}
+
+/// Payload fields derived from an artifact file.
+///
+/// Fields that could not be determined from the artifact are `None`, and
+/// leave the existing payload value untouched when applied.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub size: u64,
+    pub sha256: String,
+    pub installed_size: Option<u64>,
+    pub product_code: Option<String>,
+    pub kind: Option<String>,
+    pub pkg_id: Option<String>,
+}
+
+impl Metadata {
+    pub fn apply(&self, payload: &mut Payload) {
+        match payload {
+            Payload::WindowsExecutable(x) => {
+                x.size = self.size;
+                x.sha256 = Some(self.sha256.clone());
+                if let Some(v) = self.installed_size {
+                    x.installed_size = v;
+                }
+                if let Some(v) = self.product_code.as_ref() {
+                    x.product_code = v.clone();
+                }
+                if let Some(v) = self.kind.as_ref() {
+                    x.kind = Some(v.clone());
+                }
+            }
+            Payload::MacOSPackage(x) => {
+                x.size = self.size;
+                x.sha256 = Some(self.sha256.clone());
+                if let Some(v) = self.installed_size {
+                    x.installed_size = v;
+                }
+                if let Some(v) = self.pkg_id.as_ref() {
+                    x.pkg_id = v.clone();
+                }
+            }
+            Payload::TarballPackage(x) => {
+                x.size = self.size;
+                x.sha256 = Some(self.sha256.clone());
+                if let Some(v) = self.installed_size {
+                    x.installed_size = v;
+                }
+            }
+        }
+    }
+}
+
+const MSI_MAGIC: &[u8] = &[0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+const EXE_MAGIC: &[u8] = b"MZ";
+const XAR_MAGIC: &[u8] = b"xar!";
+const XZ_MAGIC: &[u8] = &[0xFD, b'7', b'z', b'X', b'Z', 0x00];
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+/// Both POSIX (`ustar\0`) and GNU (`ustar `) tar headers start with this at
+/// offset 257.
+const TAR_MAGIC: &[u8] = b"ustar";
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Enough of the start of a file to identify every format above.
+const MAGIC_LEN: u64 = (TAR_MAGIC_OFFSET + TAR_MAGIC.len()) as u64;
+
+/// Inspects an artifact in light of the payload type it will be used for.
+///
+/// Size and checksum are always computed. Beyond that, MSIs provide their
+/// ProductCode and installed size, `.pkg` files their package identifier and
+/// installed size, and tarballs (xz, gzip or uncompressed) the sum of their
+/// entry sizes.
+///
+/// The artifact is streamed rather than read into memory, as installers can
+/// be large.
+pub fn inspect(path: &Path, payload: &Payload) -> Result<Metadata, Error> {
+    let read_failed = |e| Error::ReadFailed(path.to_path_buf(), e);
+
+    let mut file = File::open(path).map_err(read_failed)?;
+    let (size, sha256) = hash(&mut file).map_err(read_failed)?;
+
+    let mut metadata = Metadata {
+        size,
+        sha256,
+        ..Default::default()
+    };
+
+    let mut magic = Vec::with_capacity(MAGIC_LEN as usize);
+    file.seek(SeekFrom::Start(0)).map_err(read_failed)?;
+    (&mut file)
+        .take(MAGIC_LEN)
+        .read_to_end(&mut magic)
+        .map_err(read_failed)?;
+    file.seek(SeekFrom::Start(0)).map_err(read_failed)?;
+
+    match payload {
+        Payload::WindowsExecutable(_) => {
+            if magic.starts_with(MSI_MAGIC) {
+                let (product_code, installed_size) = read_msi(path)?;
+                metadata.kind = Some("msi".into());
+                metadata.product_code = Some(product_code);
+                metadata.installed_size = installed_size;
+            } else if magic.starts_with(EXE_MAGIC) {
+                metadata.kind = exe_kind(BufReader::new(file))
+                    .map_err(read_failed)?
+                    .map(str::to_string);
+            } else {
+                return Err(Error::UnexpectedFormat(
+                    path.to_path_buf(),
+                    "Windows executable or MSI",
+                ));
+            }
+        }
+        Payload::MacOSPackage(_) => {
+            if !magic.starts_with(XAR_MAGIC) {
+                return Err(Error::UnexpectedFormat(
+                    path.to_path_buf(),
+                    "macOS installer package",
+                ));
+            }
+            let (pkg_id, installed_size) = read_pkg(BufReader::new(file))
+                .map_err(|e| Error::Xar(path.to_path_buf(), e))?
+                .ok_or_else(|| Error::NoPackageId(path.to_path_buf()))?;
+            metadata.pkg_id = Some(pkg_id);
+            metadata.installed_size = installed_size;
+        }
+        Payload::TarballPackage(_) => {
+            let file = BufReader::new(file);
+            let size = if magic.starts_with(XZ_MAGIC) {
+                tarball_size(xz2::read::XzDecoder::new(file))
+            } else if magic.starts_with(GZIP_MAGIC) {
+                tarball_size(flate2::read::GzDecoder::new(file))
+            } else if magic.get(TAR_MAGIC_OFFSET..) == Some(TAR_MAGIC) {
+                tarball_size(file)
+            } else {
+                return Err(Error::UnexpectedFormat(path.to_path_buf(), "tarball"));
+            };
+            let size = size.map_err(|e| Error::Tarball(path.to_path_buf(), e))?;
+            metadata.installed_size = Some(size);
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// Returns the size and lowercase hex SHA-256 checksum of everything left in
+/// the reader.
+fn hash(mut reader: impl Read) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut size = 0;
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+fn read_msi(path: &Path) -> Result<(String, Option<u64>), Error> {
+    let mut package = msi::open(path).map_err(|e| Error::Msi(path.to_path_buf(), e))?;
+
+    let product_code = package
+        .select_rows(msi::Select::table("Property"))
+        .map_err(|e| Error::Msi(path.to_path_buf(), e))?
+        .find(|row| row["Property"].as_str() == Some("ProductCode"))
+        .and_then(|row| row["Value"].as_str().map(str::to_string))
+        .ok_or_else(|| Error::NoProductCode(path.to_path_buf()))?;
+
+    // Not every MSI installs files of its own (eg. merge module wrappers).
+    let installed_size = if package.has_table("File") {
+        let size = package
+            .select_rows(msi::Select::table("File"))
+            .map_err(|e| Error::Msi(path.to_path_buf(), e))?
+            .filter_map(|row| row["FileSize"].as_int())
+            .map(|x| x as u64)
+            .sum();
+        Some(size)
+    } else {
+        None
+    };
+
+    Ok((product_code, installed_size))
+}
+
+/// Detects the installer framework of an executable by the markers each
+/// leaves in its stub.
+fn exe_kind(mut reader: impl Read) -> io::Result<Option<&'static str>> {
+    const NSIS: &[u8] = b"NullsoftInst";
+    const INNO: &[u8] = b"Inno Setup Setup Data";
+
+    fn contains(data: &[u8], needle: &[u8]) -> bool {
+        data.windows(needle.len()).any(|x| x == needle)
+    }
+
+    // Keep the tail of each chunk so markers spanning two reads still match.
+    let overlap = NSIS.len().max(INNO.len()) - 1;
+    let mut buf = vec![0; 64 * 1024];
+    let mut len = 0;
+    let mut inno = false;
+
+    loop {
+        let n = match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        len += n;
+
+        if contains(&buf[..len], NSIS) {
+            return Ok(Some("nsis"));
+        }
+        inno = inno || contains(&buf[..len], INNO);
+
+        let keep = overlap.min(len);
+        buf.copy_within(len - keep..len, 0);
+        len = keep;
+    }
+
+    Ok(if inno { Some("inno") } else { None })
+}
+
+fn tarball_size(reader: impl Read) -> io::Result<u64> {
+    let mut archive = tar::Archive::new(reader);
+
+    let mut size = 0;
+    for entry in archive.entries()? {
+        size += entry?.header().size()?;
+    }
+    Ok(size)
+}
+
+/// A file stored in the heap of a xar archive.
+struct XarFile {
+    path: String,
+    offset: u64,
+    length: u64,
+    encoding: Option<String>,
+}
+
+/// Reads the package identifier and installed size from a flat `.pkg`.
+///
+/// Component packages carry a `PackageInfo` at their root; product archives
+/// carry a `Distribution` referencing their component packages instead.
+fn read_pkg<R: Read + Seek>(mut reader: R) -> Result<Option<(String, Option<u64>)>, String> {
+    let mut header = [0u8; 28];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| "truncated header")?;
+    let header_size = u16::from_be_bytes(header[4..6].try_into().unwrap());
+    let toc_compressed = u64::from_be_bytes(header[8..16].try_into().unwrap());
+
+    let toc_start = header_size as u64;
+    let heap_start = toc_start + toc_compressed;
+    reader
+        .seek(SeekFrom::Start(toc_start))
+        .map_err(|e| e.to_string())?;
+
+    let mut toc = String::new();
+    flate2::read::ZlibDecoder::new((&mut reader).take(toc_compressed))
+        .read_to_string(&mut toc)
+        .map_err(|e| format!("invalid table of contents: {}", e))?;
+
+    let toc = roxmltree::Document::parse(&toc)
+        .map_err(|e| format!("invalid table of contents: {}", e))?;
+    let root = toc
+        .descendants()
+        .find(|x| x.has_tag_name("toc"))
+        .ok_or("no table of contents")?;
+
+    let mut files = vec![];
+    collect_xar_files(root, "", &mut files);
+
+    let mut read_file = |name: &str| -> Result<Option<String>, String> {
+        let file = match files.iter().find(|x| x.path == name) {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+        read_xar_file(&mut reader, heap_start, file).map(Some)
+    };
+
+    if let Some(info) = read_file("PackageInfo")? {
+        return parse_package_info(&info);
+    }
+
+    if let Some(dist) = read_file("Distribution")? {
+        return parse_distribution(&dist);
+    }
+
+    // A product archive without a distribution; use its first component.
+    let component = files
+        .iter()
+        .find(|x| x.path.ends_with("/PackageInfo"))
+        .map(|x| x.path.clone());
+    match component {
+        Some(path) => parse_package_info(&read_file(&path)?.unwrap()),
+        None => Ok(None),
+    }
+}
+
+fn collect_xar_files(node: roxmltree::Node<'_, '_>, prefix: &str, files: &mut Vec<XarFile>) {
+    for file in node.children().filter(|x| x.has_tag_name("file")) {
+        let child_text = |parent: roxmltree::Node<'_, '_>, name: &str| {
+            parent
+                .children()
+                .find(|x| x.has_tag_name(name))
+                .and_then(|x| x.text())
+                .map(str::to_string)
+        };
+
+        let name = match child_text(file, "name") {
+            Some(v) => v,
+            None => continue,
+        };
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        if let Some(data) = file.children().find(|x| x.has_tag_name("data")) {
+            let offset = child_text(data, "offset").and_then(|x| x.parse().ok());
+            let length = child_text(data, "length").and_then(|x| x.parse().ok());
+            let encoding = data
+                .children()
+                .find(|x| x.has_tag_name("encoding"))
+                .and_then(|x| x.attribute("style"))
+                .map(str::to_string);
+
+            if let (Some(offset), Some(length)) = (offset, length) {
+                files.push(XarFile {
+                    path: path.clone(),
+                    offset,
+                    length,
+                    encoding,
+                });
+            }
+        }
+
+        collect_xar_files(file, &path, files);
+    }
+}
+
+fn read_xar_file(
+    mut reader: impl Read + Seek,
+    heap_start: u64,
+    file: &XarFile,
+) -> Result<String, String> {
+    reader
+        .seek(SeekFrom::Start(heap_start + file.offset))
+        .map_err(|e| e.to_string())?;
+    let reader = reader.take(file.length);
+
+    let mut out = String::new();
+    let result = match file.encoding.as_deref() {
+        None | Some("application/octet-stream") => {
+            let mut reader = reader;
+            reader.read_to_string(&mut out)
+        }
+        Some("application/x-gzip") => {
+            flate2::read::ZlibDecoder::new(reader).read_to_string(&mut out)
+        }
+        Some(other) => {
+            return Err(format!(
+                "unsupported encoding `{}` for {}",
+                other, file.path
+            ))
+        }
+    };
+    result.map_err(|e| format!("could not read {}: {}", file.path, e))?;
+    Ok(out)
+}
+
+fn parse_package_info(xml: &str) -> Result<Option<(String, Option<u64>)>, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| format!("invalid PackageInfo: {}", e))?;
+
+    let info = match doc.descendants().find(|x| x.has_tag_name("pkg-info")) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let id = match info.attribute("identifier") {
+        Some(v) => v.to_string(),
+        None => return Ok(None),
+    };
+    let installed_size = info
+        .children()
+        .find(|x| x.has_tag_name("payload"))
+        .and_then(|x| x.attribute("installKBytes"))
+        .and_then(|x| x.parse::<u64>().ok())
+        .map(|x| x * 1024);
+
+    Ok(Some((id, installed_size)))
+}
+
+fn parse_distribution(xml: &str) -> Result<Option<(String, Option<u64>)>, String> {
+    let doc =
+        roxmltree::Document::parse(xml).map_err(|e| format!("invalid Distribution: {}", e))?;
+
+    // Only the pkg-ref elements describing a component carry a size; others
+    // merely reference the component by id from a choice.
+    let refs = doc
+        .descendants()
+        .filter(|x| x.has_tag_name("pkg-ref"))
+        .filter_map(|x| {
+            let id = x.attribute("id")?;
+            let size = x.attribute("installKBytes")?.parse::<u64>().ok()?;
+            Some((id, size))
+        })
+        .collect::<Vec<_>>();
+
+    let id = match refs.first() {
+        Some((id, _)) => id.to_string(),
+        None => return Ok(None),
+    };
+    let installed_size = refs.iter().map(|(_, size)| size * 1024).sum();
+
+    Ok(Some((id, Some(installed_size))))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pahkat_types::payload::{macos, tarball, windows};
+
+    use super::*;
+
+    fn tarball_payload() -> Payload {
+        Payload::TarballPackage(
+            tarball::Package::builder()
+                .url("https://example.com/test.txz".parse().unwrap())
+                .size(0)
+                .installed_size(0)
+                .build(),
+        )
+    }
+
+    fn windows_payload() -> Payload {
+        Payload::WindowsExecutable(
+            windows::Executable::builder()
+                .url("https://example.com/test.exe".parse().unwrap())
+                .product_code("".into())
+                .size(0)
+                .installed_size(0)
+                .build(),
+        )
+    }
+
+    fn macos_payload() -> Payload {
+        Payload::MacOSPackage(
+            macos::Package::builder()
+                .url("https://example.com/test.pkg".parse().unwrap())
+                .pkg_id("".into())
+                .size(0)
+                .installed_size(0)
+                .build(),
+        )
+    }
+
+    fn inspect_bytes(data: &[u8], payload: &Payload) -> Result<Metadata, Error> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(data).unwrap();
+        inspect(file.path(), payload)
+    }
+
+    /// A tar holding files of 10 and 2500 bytes.
+    fn tar() -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, size) in &[("a", 10), ("dir/b", 2500)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(*size);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, &vec![0u8; *size as usize][..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn size_and_checksum() {
+        let metadata = inspect_bytes(&tar(), &tarball_payload()).unwrap();
+
+        assert_eq!(metadata.size, tar().len() as u64);
+        assert_eq!(metadata.sha256, format!("{:x}", Sha256::digest(&tar())));
+    }
+
+    #[test]
+    fn tarball_xz() {
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(&tar()).unwrap();
+        let data = encoder.finish().unwrap();
+
+        let metadata = inspect_bytes(&data, &tarball_payload()).unwrap();
+        assert_eq!(metadata.installed_size, Some(2510));
+    }
+
+    #[test]
+    fn tarball_gzip() {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&tar()).unwrap();
+        let data = encoder.finish().unwrap();
+
+        let metadata = inspect_bytes(&data, &tarball_payload()).unwrap();
+        assert_eq!(metadata.installed_size, Some(2510));
+    }
+
+    #[test]
+    fn tarball_uncompressed() {
+        let metadata = inspect_bytes(&tar(), &tarball_payload()).unwrap();
+        assert_eq!(metadata.installed_size, Some(2510));
+    }
+
+    #[test]
+    fn tarball_unknown_format() {
+        let result = inspect_bytes(b"PK\x03\x04 not a tarball", &tarball_payload());
+        assert!(matches!(result, Err(Error::UnexpectedFormat(_, _))));
+    }
+
+    #[test]
+    fn exe_kinds() {
+        let mut nsis = b"MZ".to_vec();
+        nsis.extend(vec![0u8; 100_000]);
+        nsis.extend(b"NullsoftInst");
+
+        let mut inno = b"MZ".to_vec();
+        // Straddle the read buffer boundary.
+        inno.extend(vec![0u8; 64 * 1024 - 10]);
+        inno.extend(b"Inno Setup Setup Data");
+
+        let kind = |data: &[u8]| inspect_bytes(data, &windows_payload()).unwrap().kind;
+        assert_eq!(kind(&nsis).as_deref(), Some("nsis"));
+        assert_eq!(kind(&inno).as_deref(), Some("inno"));
+        assert_eq!(kind(b"MZ plain executable"), None);
+    }
+
+    #[test]
+    fn msi() {
+        let cursor = io::Cursor::new(vec![]);
+        let mut package = msi::Package::create(msi::PackageType::Installer, cursor).unwrap();
+        package
+            .create_table(
+                "Property",
+                vec![
+                    msi::Column::build("Property").primary_key().id_string(72),
+                    msi::Column::build("Value").text_string(0),
+                ],
+            )
+            .unwrap();
+        package
+            .insert_rows(
+                msi::Insert::into("Property")
+                    .row(vec!["ProductName".into(), "Test".into()])
+                    .row(vec!["ProductCode".into(), "{TEST-CODE}".into()]),
+            )
+            .unwrap();
+        package
+            .create_table(
+                "File",
+                vec![
+                    msi::Column::build("File").primary_key().id_string(72),
+                    msi::Column::build("FileSize").int32(),
+                ],
+            )
+            .unwrap();
+        package
+            .insert_rows(
+                msi::Insert::into("File")
+                    .row(vec!["a".into(), msi::Value::Int(1000)])
+                    .row(vec!["b".into(), msi::Value::Int(24)]),
+            )
+            .unwrap();
+        let data = package.into_inner().unwrap().into_inner();
+
+        let metadata = inspect_bytes(&data, &windows_payload()).unwrap();
+        assert_eq!(metadata.kind.as_deref(), Some("msi"));
+        assert_eq!(metadata.product_code.as_deref(), Some("{TEST-CODE}"));
+        assert_eq!(metadata.installed_size, Some(1024));
+    }
+
+    /// Builds a xar archive from `(path, contents, zlib-compressed)` entries.
+    fn xar(entries: &[(&str, &str, bool)]) -> Vec<u8> {
+        let mut heap = vec![];
+        let mut toc = String::from("<?xml version=\"1.0\"?><xar><toc>");
+
+        for (path, contents, compressed) in entries {
+            let data = if *compressed {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(contents.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                contents.as_bytes().to_vec()
+            };
+            let encoding = if *compressed {
+                "application/x-gzip"
+            } else {
+                "application/octet-stream"
+            };
+
+            // Nest directories as the real format does.
+            let mut parts = path.split('/').collect::<Vec<_>>();
+            let name = parts.pop().unwrap();
+            for dir in parts.iter() {
+                toc.push_str(&format!("<file><name>{}</name>", dir));
+            }
+            toc.push_str(&format!(
+                "<file><name>{}</name><data><offset>{}</offset><length>{}</length>\
+                 <encoding style=\"{}\"/></data></file>",
+                name,
+                heap.len(),
+                data.len(),
+                encoding
+            ));
+            for _ in parts.iter() {
+                toc.push_str("</file>");
+            }
+
+            heap.extend(data);
+        }
+        toc.push_str("</toc></xar>");
+
+        let mut encoder = flate2::write::ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(toc.as_bytes()).unwrap();
+        let toc_compressed = encoder.finish().unwrap();
+
+        let mut out = b"xar!".to_vec();
+        out.extend(&28u16.to_be_bytes());
+        out.extend(&1u16.to_be_bytes());
+        out.extend(&(toc_compressed.len() as u64).to_be_bytes());
+        out.extend(&(toc.len() as u64).to_be_bytes());
+        out.extend(&0u32.to_be_bytes());
+        out.extend(toc_compressed);
+        out.extend(heap);
+        out
+    }
+
+    const PACKAGE_INFO: &str = r#"<?xml version="1.0"?>
+<pkg-info identifier="com.example.test" version="1.0">
+    <payload numberOfFiles="2" installKBytes="12"/>
+</pkg-info>"#;
+
+    #[test]
+    fn xar_component_package() {
+        let data = xar(&[("Bom", "bom", false), ("PackageInfo", PACKAGE_INFO, false)]);
+
+        let metadata = inspect_bytes(&data, &macos_payload()).unwrap();
+        assert_eq!(metadata.pkg_id.as_deref(), Some("com.example.test"));
+        assert_eq!(metadata.installed_size, Some(12 * 1024));
+    }
+
+    #[test]
+    fn xar_product_archive() {
+        let distribution = r#"<?xml version="1.0"?>
+<installer-gui-script minSpecVersion="1">
+    <choice id="default"><pkg-ref id="com.example.test"/></choice>
+    <pkg-ref id="com.example.test" installKBytes="12">#test.pkg</pkg-ref>
+    <pkg-ref id="com.example.extra" installKBytes="4">#extra.pkg</pkg-ref>
+</installer-gui-script>"#;
+        let data = xar(&[
+            ("test.pkg/PackageInfo", PACKAGE_INFO, true),
+            ("Distribution", distribution, true),
+        ]);
+
+        let metadata = inspect_bytes(&data, &macos_payload()).unwrap();
+        assert_eq!(metadata.pkg_id.as_deref(), Some("com.example.test"));
+        assert_eq!(metadata.installed_size, Some(16 * 1024));
+    }
+
+    #[test]
+    fn xar_product_archive_without_distribution() {
+        let data = xar(&[("test.pkg/PackageInfo", PACKAGE_INFO, true)]);
+
+        let metadata = inspect_bytes(&data, &macos_payload()).unwrap();
+        assert_eq!(metadata.pkg_id.as_deref(), Some("com.example.test"));
+    }
+
+    #[test]
+    fn xar_without_package_id() {
+        let data = xar(&[("Bom", "bom", false)]);
+
+        let result = inspect_bytes(&data, &macos_payload());
+        assert!(matches!(result, Err(Error::NoPackageId(_))));
+    }
+}
@@ -5,6 +5,8 @@ use std::path::{Path, PathBuf};
 
 use typed_builder::TypedBuilder;
 
+use crate::input::{self, Input, InputError};
+
 #[non_exhaustive]
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct Request<'a> {
@@ -40,6 +42,9 @@ pub enum RequestError {
 
     #[error("Invalid input")]
     InvalidInput,
+
+    #[error(transparent)]
+    Input(#[from] InputError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -82,22 +87,15 @@ impl<'a> crate::Request for Request<'a> {
     type Error = RequestError;
     type Partial = PartialRequest<'a>;
 
-    fn new_from_user_input(partial: Self::Partial) -> Result<Self, Self::Error> {
-        use dialoguer::Input;
-
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
         let repo_path = match partial.repo_path {
             Some(path) => Cow::Borrowed(path),
-            None => Input::<String>::new()
-                .default(
-                    std::env::current_dir()
-                        .ok()
-                        .and_then(|x| x.to_str().map(str::to_string))
-                        .unwrap_or_else(|| ".".into()),
-                )
-                .with_prompt("Repository Path")
-                .interact()
-                .map(|p| Cow::Owned(PathBuf::from(p)))
-                .map_err(RequestError::PathError)?,
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
         };
 
         let _ = find_repo(&repo_path)?;
@@ -105,47 +103,44 @@ impl<'a> crate::Request for Request<'a> {
         let id = match partial.id {
             Some(id) => Cow::Borrowed(id),
             None => Cow::Owned(
-                Input::<String>::new()
-                    .with_prompt("Package identifier")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?,
+                input
+                    .field("id", "Package identifier")
+                    .required::<String>()?,
             ),
         };
 
         let name = match partial.name {
             Some(name) => Cow::Borrowed(name),
             None => Cow::Owned(
-                Input::<String>::new()
-                    .with_prompt("Package name (in English)")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?,
+                input
+                    .field("name", "Package name (in English)")
+                    .required::<String>()?,
             ),
         };
 
         let description = match partial.description {
             Some(description) => Cow::Borrowed(description),
             None => Cow::Owned(
-                Input::<String>::new()
-                    .with_prompt("Package description (in English)")
+                input
+                    .field("description", "Package description (in English)")
                     .allow_empty(true)
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?,
+                    .required::<String>()?,
             ),
         };
 
         let tags = match partial.tags {
             Some(tags) if !tags.is_empty() => Cow::Borrowed(tags),
             _ => {
-                let raw_tags = Input::<String>::new()
-                    .with_prompt("Tags (optional, space-delimited)")
-                    .allow_empty(true)
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?;
-                if raw_tags.trim() != "" {
-                    Cow::Owned(raw_tags.split(" ").map(str::to_string).collect::<Vec<_>>())
-                } else {
-                    Cow::Owned(vec![])
-                }
+                let raw_tags = input
+                    .field("tags", "Tags (optional, space-delimited)")
+                    .optional::<String>()?
+                    .unwrap_or_default();
+                Cow::Owned(
+                    raw_tags
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>(),
+                )
             }
         };
 
@@ -1,2 +1,3 @@
+pub mod artifact;
 pub mod init;
 pub mod update;
@@ -6,6 +6,8 @@ use std::path::{Path, PathBuf};
 use pahkat_types::{package::Version, payload::Payload};
 use typed_builder::TypedBuilder;
 
+use crate::input::{self, Input, InputError};
+
 #[non_exhaustive]
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct Request<'a> {
@@ -13,8 +15,9 @@ pub struct Request<'a> {
     pub id: Cow<'a, str>,
     pub channel: Option<Cow<'a, str>>,
     pub version: Cow<'a, Version>,
-    pub target: Cow<'a, pahkat_types::payload::Target>,
+    pub targets: Cow<'a, [pahkat_types::payload::Target]>,
     pub url: Option<Cow<'a, url::Url>>,
+    pub artifact: Option<Cow<'a, Path>>,
 }
 
 #[non_exhaustive]
@@ -33,9 +36,11 @@ pub struct PartialRequest<'a> {
     #[builder(default)]
     pub version: Option<&'a Version>,
     #[builder(default)]
-    pub payload_path: Option<&'a Path>,
+    pub payload_paths: Option<&'a [PathBuf]>,
     #[builder(default)]
     pub url: Option<&'a url::Url>,
+    #[builder(default)]
+    pub artifact: Option<&'a Path>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -54,6 +59,12 @@ pub enum RequestError {
 
     #[error("Invalid input")]
     InvalidInput,
+
+    #[error("A URL, platform, arch or artifact can only be given when updating a single target")]
+    AmbiguousTarget,
+
+    #[error(transparent)]
+    Input(#[from] InputError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -96,22 +107,15 @@ impl<'a> crate::Request for Request<'a> {
     type Error = RequestError;
     type Partial = PartialRequest<'a>;
 
-    fn new_from_user_input(partial: Self::Partial) -> Result<Self, Self::Error> {
-        use dialoguer::Input;
-
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
         let repo_path = match partial.repo_path {
             Some(path) => Cow::Borrowed(path),
-            None => Input::<String>::new()
-                .default(
-                    std::env::current_dir()
-                        .ok()
-                        .and_then(|x| x.to_str().map(str::to_string))
-                        .unwrap_or_else(|| ".".into()),
-                )
-                .with_prompt("Repository Path")
-                .interact()
-                .map(|p| Cow::Owned(PathBuf::from(p)))
-                .map_err(RequestError::PathError)?,
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
         };
 
         let _ = find_repo(&repo_path)?;
@@ -119,26 +123,62 @@ impl<'a> crate::Request for Request<'a> {
         let id = match partial.id {
             Some(id) => Cow::Borrowed(id),
             None => Cow::Owned(
-                Input::<String>::new()
-                    .with_prompt("Package identifier")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?,
+                input
+                    .field("id", "Package identifier")
+                    .required::<String>()?,
             ),
         };
 
-        let payload_path = match partial.payload_path {
-            Some(path) => Cow::Borrowed(path),
-            None => Cow::Owned(
-                Input::<String>::new()
-                    .with_prompt("Target path (toml)")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)
-                    .map(std::path::PathBuf::from)?,
-            ),
+        let payload_paths = match partial.payload_paths {
+            Some(paths) if !paths.is_empty() => Cow::Borrowed(paths),
+            _ => match input.lookup_list::<PathBuf>("payload_paths")? {
+                Some(paths) if !paths.is_empty() => Cow::Owned(paths),
+                _ => Cow::Owned(vec![input
+                    .field("payload_paths", "Target path (toml)")
+                    .required::<PathBuf>()?]),
+            },
         };
 
-        let payload = std::fs::read_to_string(payload_path)?;
-        let target: pahkat_types::payload::Target = toml::from_str(&payload)?;
+        let platform = match partial.platform {
+            Some(platform) => Some(Cow::Borrowed(platform)),
+            None => input.lookup("platform")?.map(Cow::Owned),
+        };
+        let arch = match partial.arch {
+            Some(arch) => Some(Cow::Borrowed(arch)),
+            None => input.lookup("arch")?.map(Cow::Owned),
+        };
+
+        let url = match partial.url {
+            Some(url) => Some(Cow::Borrowed(url)),
+            None => input.lookup::<url::Url>("url")?.map(Cow::Owned),
+        };
+
+        let artifact = match partial.artifact {
+            Some(artifact) => Some(Cow::Borrowed(artifact)),
+            None => input.lookup::<PathBuf>("artifact")?.map(Cow::Owned),
+        };
+
+        let has_overrides =
+            url.is_some() || platform.is_some() || arch.is_some() || artifact.is_some();
+        if payload_paths.len() > 1 && has_overrides {
+            return Err(RequestError::AmbiguousTarget);
+        }
+
+        let mut targets = vec![];
+        for payload_path in payload_paths.iter() {
+            let payload = std::fs::read_to_string(payload_path)?;
+            let mut target: pahkat_types::payload::Target = toml::from_str(&payload)?;
+
+            if let Some(platform) = platform.as_ref() {
+                target.platform = platform.to_string();
+            }
+
+            if let Some(arch) = arch.as_ref() {
+                target.arch = Some(arch.to_string());
+            }
+
+            targets.push(target);
+        }
 
         let channel = match partial.channel {
             Some(channel) => {
@@ -148,21 +188,18 @@ impl<'a> crate::Request for Request<'a> {
                     Some(Cow::Borrowed(channel))
                 }
             }
-            None => Input::<String>::new()
-                .with_prompt("Channel (or none for stable)")
-                .allow_empty(true)
-                .interact()
-                .map_err(|_| RequestError::InvalidInput)
-                .map(|v| if v == "" { None } else { Some(Cow::Owned(v)) })?,
+            None => input
+                .field("channel", "Channel (or none for stable)")
+                .optional::<String>()?
+                .map(Cow::Owned),
         };
 
         let version = match partial.version {
             Some(tags) => Cow::Borrowed(tags),
             None => Cow::Owned(
-                Input::<Version>::new()
-                    .with_prompt("Release version")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?,
+                input
+                    .field("version", "Release version")
+                    .required::<Version>()?,
             ),
         };
 
@@ -171,8 +208,9 @@ impl<'a> crate::Request for Request<'a> {
             id,
             channel,
             version,
-            target: Cow::Owned(target),
-            url: partial.url.map(|x| Cow::Borrowed(x)),
+            targets: Cow::Owned(targets),
+            url,
+            artifact,
         })
     }
 }
@@ -196,6 +234,9 @@ pub enum Error {
 
     #[error("Could not find repository at provided path")]
     NoRepo(#[from] FindRepoError),
+
+    #[error("Failed to inspect artifact")]
+    Artifact(#[from] super::artifact::Error),
 }
 
 pub fn update<'a>(request: Request<'a>) -> Result<(), Error> {
@@ -219,7 +260,7 @@ pub fn update<'a>(request: Request<'a>) -> Result<(), Error> {
     let channel = request.channel.as_ref().map(|x| x.deref().to_string());
 
     // Check if a release exists that meets this criteria
-    let mut release = match descriptor
+    let release = match descriptor
         .release
         .iter_mut()
         .find(|x| &x.version == &*request.version && x.channel == channel)
@@ -242,34 +283,35 @@ pub fn update<'a>(request: Request<'a>) -> Result<(), Error> {
         }
     };
 
-    // Check if a target exists that meets this criteria
-    let mut target = match release
-        .target
-        .iter_mut()
-        .find(|x| x.platform == request.target.platform)
-    {
-        Some(target) => {
-            log::info!("Found target!");
-            *target = (*request.target).to_owned();
-            target
+    // Targets are keyed by platform and arch, so each arch of a platform is kept
+    for new_target in request.targets.iter() {
+        let mut new_target = new_target.clone();
+
+        if let Some(url) = request.url.as_ref() {
+            log::info!("Setting URL to {}", &url);
+            new_target.payload.set_url(url.deref().clone());
         }
-        None => {
-            log::info!("No target; creating.");
-            release.target.insert(
-                0,
-                pahkat_types::payload::Target::builder()
-                    .dependencies(request.target.dependencies.clone())
-                    .platform(request.target.platform.to_string())
-                    .payload(request.target.payload.clone())
-                    .build(),
-            );
-            release.target.first_mut().unwrap()
+
+        if let Some(artifact) = request.artifact.as_ref() {
+            log::info!("Inspecting artifact {}", artifact.display());
+            let metadata = super::artifact::inspect(artifact, &new_target.payload)?;
+            metadata.apply(&mut new_target.payload);
         }
-    };
 
-    if let Some(url) = request.url {
-        log::info!("Setting URL to {}", &url);
-        target.payload.set_url(url.into_owned());
+        match release
+            .target
+            .iter_mut()
+            .find(|x| x.platform == new_target.platform && x.arch == new_target.arch)
+        {
+            Some(target) => {
+                log::info!("Found target!");
+                *target = new_target;
+            }
+            None => {
+                log::info!("No target; creating.");
+                release.target.insert(0, new_target);
+            }
+        }
     }
 
     // Write the toml
@@ -280,3 +322,147 @@ pub fn update<'a>(request: Request<'a>) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+    use pahkat_types::package::Descriptor;
+    use pahkat_types::payload::Target;
+
+    fn target(platform: &str, arch: Option<&str>, url: &str) -> Target {
+        toml::from_str(&format!(
+            "platform = \"{}\"\n{}\n[payload]\ntype = \"TarballPackage\"\nurl = \"{}\"\nsize = 1\ninstalled_size = 1\n",
+            platform,
+            arch.map(|x| format!("arch = \"{}\"", x)).unwrap_or_default(),
+            url
+        ))
+        .unwrap()
+    }
+
+    fn update_targets(repo: &Repo, version: &str, targets: Vec<Target>) {
+        let version = Version::new(version).unwrap();
+        update(
+            Request::builder()
+                .repo_path(Cow::Borrowed(repo.path()))
+                .id(Cow::Borrowed("app"))
+                .channel(None)
+                .version(Cow::Owned(version))
+                .targets(Cow::Owned(targets))
+                .url(None)
+                .artifact(None)
+                .build(),
+        )
+        .unwrap();
+    }
+
+    /// The platform, arch and URL of each target of the release.
+    fn targets(repo: &Repo, version: &str) -> Vec<(String, Option<String>, String)> {
+        let descriptor: Descriptor = toml::from_str(&repo.read("packages/app/index.toml")).unwrap();
+        let release = descriptor
+            .release
+            .iter()
+            .find(|x| x.version.to_string() == version)
+            .unwrap();
+
+        release
+            .target
+            .iter()
+            .map(|x| {
+                (
+                    x.platform.clone(),
+                    x.arch.clone(),
+                    x.payload.url().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn targets_are_keyed_by_platform_and_arch() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", None)]));
+
+        let x86_64 = "https://example.com/app-x86_64.txz";
+        let arm64 = "https://example.com/app-arm64.txz";
+        update_targets(
+            &repo,
+            "1.0.0",
+            vec![target("linux", Some("x86_64"), x86_64)],
+        );
+        update_targets(&repo, "1.0.0", vec![target("linux", Some("arm64"), arm64)]);
+
+        assert_eq!(
+            targets(&repo, "1.0.0"),
+            vec![
+                ("linux".into(), Some("arm64".into()), arm64.into()),
+                ("linux".into(), Some("x86_64".into()), x86_64.into()),
+                (
+                    "linux".into(),
+                    None,
+                    "https://example.com/app-1.0.0.txz".into()
+                ),
+            ]
+        );
+
+        // Only the target with the same platform and arch is replaced
+        let replaced = "https://example.com/app-x86_64-2.txz";
+        update_targets(
+            &repo,
+            "1.0.0",
+            vec![target("linux", Some("x86_64"), replaced)],
+        );
+
+        assert_eq!(
+            targets(&repo, "1.0.0")
+                .into_iter()
+                .map(|x| x.2)
+                .collect::<Vec<_>>(),
+            vec![
+                arm64.to_string(),
+                replaced.to_string(),
+                "https://example.com/app-1.0.0.txz".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn several_targets_in_one_update() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", None)]));
+
+        let linux = "https://example.com/app-2.0.0.txz";
+        let macos = "https://example.com/app-2.0.0.pkg";
+        update_targets(
+            &repo,
+            "2.0.0",
+            vec![target("linux", None, linux), target("macos", None, macos)],
+        );
+
+        assert_eq!(
+            targets(&repo, "2.0.0"),
+            vec![
+                ("macos".into(), None, macos.into()),
+                ("linux".into(), None, linux.into()),
+            ]
+        );
+        assert_eq!(targets(&repo, "1.0.0").len(), 1);
+    }
+
+    #[test]
+    fn overrides_require_a_single_target() {
+        let repo = Repo::new();
+        let paths = vec![PathBuf::from("linux.toml"), PathBuf::from("macos.toml")];
+        let partial = PartialRequest::builder()
+            .repo_path(Some(repo.path()))
+            .id(Some("app"))
+            .payload_paths(Some(&paths[..]))
+            .platform(Some("linux"))
+            .build();
+
+        assert!(matches!(
+            <Request as crate::Request>::new_from_user_input(partial, &Input::default()),
+            Err(RequestError::AmbiguousTarget)
+        ));
+    }
+}
@@ -0,0 +1,3 @@
+pub mod promote;
+pub mod remove;
+pub mod yank;
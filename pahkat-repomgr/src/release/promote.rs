@@ -0,0 +1,402 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use pahkat_types::package::Version;
+use typed_builder::TypedBuilder;
+
+use super::remove::{find_repo, FindRepoError};
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub repo_path: Cow<'a, Path>,
+    pub id: Cow<'a, str>,
+    pub version: Cow<'a, Version>,
+    /// The channel the release is in; `None` for stable
+    pub from: Option<Cow<'a, str>>,
+    /// The channel to copy the release into; `None` for stable
+    pub to: Option<Cow<'a, str>>,
+    /// Whether to drop prerelease tags, such as `-nightly.<date>`, from the
+    /// promoted release's version
+    #[builder(default)]
+    pub strip_prerelease: bool,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub id: Option<&'a str>,
+    #[builder(default)]
+    pub version: Option<&'a Version>,
+    #[builder(default)]
+    pub from: Option<&'a str>,
+    #[builder(default)]
+    pub to: Option<&'a str>,
+    #[builder(default)]
+    pub strip_prerelease: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+/// Both an empty string and `stable` name the stable channel.
+fn channel(value: &str) -> Option<&str> {
+    match value {
+        "" | "stable" => None,
+        value => Some(value),
+    }
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let _ = find_repo(&repo_path)?;
+
+        let id = match partial.id {
+            Some(id) => Cow::Borrowed(id),
+            None => Cow::Owned(
+                input
+                    .field("id", "Package identifier")
+                    .required::<String>()?,
+            ),
+        };
+
+        let version = match partial.version {
+            Some(version) => Cow::Borrowed(version),
+            None => Cow::Owned(
+                input
+                    .field("version", "Release version")
+                    .required::<Version>()?,
+            ),
+        };
+
+        let from = match partial.from {
+            Some(from) => channel(from).map(Cow::Borrowed),
+            None => input
+                .field("from", "Channel to promote from (or none for stable)")
+                .optional::<String>()?
+                .filter(|x| x != "stable")
+                .map(Cow::Owned),
+        };
+
+        let to = match partial.to {
+            Some(to) => channel(to).map(Cow::Borrowed),
+            None => input
+                .field("to", "Channel to promote to (or none for stable)")
+                .optional::<String>()?
+                .filter(|x| x != "stable")
+                .map(Cow::Owned),
+        };
+
+        Ok(Request {
+            repo_path,
+            id,
+            version,
+            from,
+            to,
+            strip_prerelease: partial.strip_prerelease,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read descriptor index: `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Failed to write TOML file `{0}`")]
+    WriteToml(PathBuf, #[source] io::Error),
+
+    #[error("Failed to serialize TOML for `{0}`")]
+    SerializeToml(PathBuf, #[source] toml::ser::Error),
+
+    #[error("No matching release found to promote")]
+    NotFound,
+
+    #[error("The release would be promoted onto itself")]
+    SameRelease,
+
+    #[error("Release {0} already exists in the destination channel")]
+    AlreadyExists(Version),
+
+    #[error("Release {0} is yanked and can't be promoted")]
+    Yanked(Version),
+
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+}
+
+/// Copies a release and its targets into another channel, returning the
+/// promoted release's version.
+///
+/// The source release is left in place. The index must be rebuilt for
+/// clients to see the promoted release.
+pub fn promote<'a>(request: Request<'a>) -> Result<Version, Error> {
+    log::debug!("{:?}", request);
+
+    let pkg_path = find_repo(&request.repo_path)?
+        .join("packages")
+        .join(&*request.id)
+        .join("index.toml");
+
+    let pkg_file =
+        fs::read_to_string(&pkg_path).map_err(|e| Error::ReadFailed(pkg_path.clone(), e))?;
+    let mut descriptor: pahkat_types::package::Descriptor =
+        toml::from_str(&pkg_file).map_err(|e| Error::ReadToml(pkg_path.clone(), e))?;
+
+    let from = request.from.as_deref().and_then(channel);
+    let to = request.to.as_deref().and_then(channel);
+
+    let mut release = descriptor
+        .release
+        .iter()
+        .find(|x| &x.version == &*request.version && x.channel.as_deref() == from)
+        .cloned()
+        .ok_or(Error::NotFound)?;
+
+    if release.yanked {
+        return Err(Error::Yanked(release.version));
+    }
+
+    if request.strip_prerelease {
+        release.version = release.version.without_prerelease();
+    }
+
+    if from == to && release.version == *request.version {
+        return Err(Error::SameRelease);
+    }
+
+    if descriptor
+        .release
+        .iter()
+        .any(|x| x.version == release.version && x.channel.as_deref() == to)
+    {
+        return Err(Error::AlreadyExists(release.version));
+    }
+
+    release.channel = to.map(str::to_string);
+
+    log::info!(
+        "Promoted {} {} ({}) to {} ({}).",
+        request.id,
+        request.version,
+        from.unwrap_or("stable"),
+        release.version,
+        to.unwrap_or("stable")
+    );
+
+    let version = release.version.clone();
+    descriptor.release.insert(0, release);
+
+    // Write the toml
+    let data = toml::to_string_pretty(&descriptor)
+        .map_err(|e| Error::SerializeToml(pkg_path.clone(), e))?;
+    fs::write(&pkg_path, data).map_err(|e| Error::WriteToml(pkg_path.to_path_buf(), e))?;
+    log::info!("Wrote descriptor to {}", pkg_path.display());
+
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+    use pahkat_types::package::Descriptor;
+
+    const NIGHTLY: &str = "1.1.0-nightly.20200101T000000Z";
+
+    fn request<'a>(
+        repo: &'a Repo,
+        version: &'a Version,
+        from: Option<&'a str>,
+        to: Option<&'a str>,
+    ) -> Request<'a> {
+        Request::builder()
+            .repo_path(Cow::Borrowed(repo.path()))
+            .id(Cow::Borrowed("app"))
+            .version(Cow::Borrowed(version))
+            .from(from.map(Cow::Borrowed))
+            .to(to.map(Cow::Borrowed))
+            .build()
+    }
+
+    fn read(repo: &Repo) -> Descriptor {
+        toml::from_str(&repo.read("packages/app/index.toml")).unwrap()
+    }
+
+    /// Each release as its version and channel, in the order they are listed.
+    fn releases(repo: &Repo) -> Vec<(String, Option<String>)> {
+        read(repo)
+            .release
+            .iter()
+            .map(|x| (x.version.to_string(), x.channel.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn copies_into_destination() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[(NIGHTLY, Some("nightly")), ("1.0.0", None)]),
+        );
+
+        let version = Version::new(NIGHTLY).unwrap();
+        let promoted = promote(request(&repo, &version, Some("nightly"), Some("beta"))).unwrap();
+        assert_eq!(promoted, version);
+
+        assert_eq!(
+            releases(&repo),
+            vec![
+                (NIGHTLY.to_string(), Some("beta".to_string())),
+                (NIGHTLY.to_string(), Some("nightly".to_string())),
+                ("1.0.0".to_string(), None),
+            ]
+        );
+
+        let descriptor = read(&repo);
+        assert_eq!(descriptor.release[0].target, descriptor.release[1].target);
+    }
+
+    #[test]
+    fn strips_prerelease() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[(NIGHTLY, Some("nightly"))]));
+
+        let version = Version::new(NIGHTLY).unwrap();
+        let promoted = promote(Request {
+            strip_prerelease: true,
+            ..request(&repo, &version, Some("nightly"), None)
+        })
+        .unwrap();
+        assert_eq!(promoted, Version::new("1.1.0").unwrap());
+
+        assert_eq!(
+            releases(&repo),
+            vec![
+                ("1.1.0".to_string(), None),
+                (NIGHTLY.to_string(), Some("nightly".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn stable_names_releases_without_a_channel() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", Some("beta"))]));
+
+        let version = Version::new("1.0.0").unwrap();
+        promote(request(&repo, &version, Some("beta"), Some("stable"))).unwrap();
+        assert_eq!(
+            releases(&repo),
+            vec![
+                ("1.0.0".to_string(), None),
+                ("1.0.0".to_string(), Some("beta".to_string())),
+            ]
+        );
+
+        let result = promote(request(&repo, &version, Some(""), Some("beta")));
+        assert!(matches!(result, Err(Error::AlreadyExists(_))));
+    }
+
+    #[test]
+    fn same_release_is_refused() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[(NIGHTLY, Some("nightly"))]));
+
+        let version = Version::new(NIGHTLY).unwrap();
+        let result = promote(request(&repo, &version, Some("nightly"), Some("nightly")));
+        assert!(matches!(result, Err(Error::SameRelease)));
+
+        // Stripping the prerelease makes it a different release
+        promote(Request {
+            strip_prerelease: true,
+            ..request(&repo, &version, Some("nightly"), Some("nightly"))
+        })
+        .unwrap();
+        assert_eq!(
+            releases(&repo),
+            vec![
+                ("1.1.0".to_string(), Some("nightly".to_string())),
+                (NIGHTLY.to_string(), Some("nightly".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_release_is_not_replaced() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", Some("beta")), ("1.0.0", None)]),
+        );
+        let before = repo.read("packages/app/index.toml");
+
+        let version = Version::new("1.0.0").unwrap();
+        let result = promote(request(&repo, &version, Some("beta"), None));
+        match result {
+            Err(Error::AlreadyExists(v)) => assert_eq!(v, version),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(repo.read("packages/app/index.toml"), before);
+    }
+
+    #[test]
+    fn missing_release_is_not_found() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", Some("beta"))]));
+
+        let version = Version::new("1.0.0").unwrap();
+        let result = promote(request(&repo, &version, Some("nightly"), None));
+        assert!(matches!(result, Err(Error::NotFound)));
+
+        let missing = Version::new("2.0.0").unwrap();
+        let result = promote(request(&repo, &missing, Some("beta"), None));
+        assert!(matches!(result, Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn yanked_release_is_refused() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", Some("beta"))]).replace(
+                "version = \"1.0.0\"\n",
+                "version = \"1.0.0\"\nyanked = true\n",
+            ),
+        );
+        let before = repo.read("packages/app/index.toml");
+
+        let version = Version::new("1.0.0").unwrap();
+        let result = promote(request(&repo, &version, Some("beta"), None));
+        assert!(matches!(result, Err(Error::Yanked(_))));
+        assert_eq!(repo.read("packages/app/index.toml"), before);
+    }
+}
@@ -0,0 +1,403 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use pahkat_types::package::Version;
+use typed_builder::TypedBuilder;
+
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub repo_path: Cow<'a, Path>,
+    pub id: Cow<'a, str>,
+    pub version: Cow<'a, Version>,
+    pub channel: Option<Cow<'a, str>>,
+    pub platform: Option<Cow<'a, str>>,
+    pub arch: Option<Cow<'a, str>>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub id: Option<&'a str>,
+    #[builder(default)]
+    pub version: Option<&'a Version>,
+    #[builder(default)]
+    pub channel: Option<&'a str>,
+    #[builder(default)]
+    pub platform: Option<&'a str>,
+    #[builder(default)]
+    pub arch: Option<&'a str>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+
+    #[error("An arch can only be given along with a platform")]
+    ArchWithoutPlatform,
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FindRepoError {
+    #[error("IO error")]
+    Io(#[from] io::Error),
+
+    #[error("No repository found for given path")]
+    NotFound,
+}
+
+fn open_repo(path: &Path) -> Option<pahkat_types::repo::Repository> {
+    let file = fs::read_to_string(path.join("index.toml")).ok()?;
+    let repo: pahkat_types::repo::Repository = toml::from_str(&file).ok()?;
+    Some(repo)
+}
+
+pub(crate) fn find_repo(path: &Path) -> Result<&Path, FindRepoError> {
+    let mut path = path;
+
+    if path.ends_with("index.toml") {
+        path = path.parent().unwrap();
+    }
+
+    if let Some(_) = open_repo(path) {
+        return Ok(path);
+    }
+
+    while let Some(parent) = path.parent() {
+        path = parent;
+        if let Some(_) = open_repo(path) {
+            return Ok(path);
+        }
+    }
+
+    Err(FindRepoError::NotFound)
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let _ = find_repo(&repo_path)?;
+
+        let id = match partial.id {
+            Some(id) => Cow::Borrowed(id),
+            None => Cow::Owned(
+                input
+                    .field("id", "Package identifier")
+                    .required::<String>()?,
+            ),
+        };
+
+        let version = match partial.version {
+            Some(version) => Cow::Borrowed(version),
+            None => Cow::Owned(
+                input
+                    .field("version", "Release version")
+                    .required::<Version>()?,
+            ),
+        };
+
+        let channel = match partial.channel {
+            Some(channel) => {
+                if channel == "" {
+                    None
+                } else {
+                    Some(Cow::Borrowed(channel))
+                }
+            }
+            None => input
+                .field("channel", "Channel (or none for stable)")
+                .optional::<String>()?
+                .map(Cow::Owned),
+        };
+
+        let platform = match partial.platform {
+            Some(platform) => Some(Cow::Borrowed(platform)),
+            None => input.lookup("platform")?.map(Cow::Owned),
+        };
+        let arch = match partial.arch {
+            Some(arch) => Some(Cow::Borrowed(arch)),
+            None => input.lookup("arch")?.map(Cow::Owned),
+        };
+
+        if platform.is_none() && arch.is_some() {
+            return Err(RequestError::ArchWithoutPlatform);
+        }
+
+        Ok(Request {
+            repo_path,
+            id,
+            version,
+            channel,
+            platform,
+            arch,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read descriptor index: `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Failed to write TOML file `{0}`")]
+    WriteToml(PathBuf, #[source] io::Error),
+
+    #[error("Failed to serialize TOML for `{0}`")]
+    SerializeToml(PathBuf, #[source] toml::ser::Error),
+
+    #[error("No matching release or target found to remove")]
+    NotFound,
+
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+}
+
+/// Removes a release, or only its targets matching the given platform and arch.
+///
+/// A release left without any targets is removed entirely.
+pub fn remove<'a>(request: Request<'a>) -> Result<(), Error> {
+    log::debug!("{:?}", request);
+
+    let pkg_path = find_repo(&request.repo_path)?
+        .join("packages")
+        .join(&*request.id)
+        .join("index.toml");
+
+    let pkg_file =
+        fs::read_to_string(&pkg_path).map_err(|e| Error::ReadFailed(pkg_path.clone(), e))?;
+    let mut descriptor: pahkat_types::package::Descriptor =
+        toml::from_str(&pkg_file).map_err(|e| Error::ReadToml(pkg_path.clone(), e))?;
+
+    let channel = request.channel.as_deref();
+    let release_index = descriptor
+        .release
+        .iter()
+        .position(|x| &x.version == &*request.version && x.channel.as_deref() == channel)
+        .ok_or(Error::NotFound)?;
+
+    match request.platform.as_deref() {
+        Some(platform) => {
+            let release = &mut descriptor.release[release_index];
+            let arch = request.arch.as_deref();
+            let count = release.target.len();
+
+            release.target.retain(|x| {
+                x.platform != platform || (arch.is_some() && x.arch.as_deref() != arch)
+            });
+
+            if release.target.len() == count {
+                return Err(Error::NotFound);
+            }
+
+            log::info!("Removed {} target(s)", count - release.target.len());
+
+            if release.target.is_empty() {
+                log::info!("No targets left; removing release.");
+                descriptor.release.remove(release_index);
+            }
+        }
+        None => {
+            log::info!("Removing release.");
+            descriptor.release.remove(release_index);
+        }
+    }
+
+    // Write the toml
+    let data = toml::to_string_pretty(&descriptor)
+        .map_err(|e| Error::SerializeToml(pkg_path.clone(), e))?;
+    fs::write(&pkg_path, data).map_err(|e| Error::WriteToml(pkg_path.to_path_buf(), e))?;
+    log::info!("Wrote descriptor to {}", pkg_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+    use pahkat_types::package::Descriptor;
+
+    /// A descriptor for `app` with one release per version, each with a Linux
+    /// target for every arch.
+    fn with_arches(versions: &[&str], arches: &[&str]) -> String {
+        let mut out = "[package]\nid = \"app\"\n".to_string();
+        for version in versions {
+            out.push_str(&format!("\n[[release]]\nversion = \"{}\"\n", version));
+            for arch in arches {
+                out.push_str(&format!(
+                    "\n[[release.target]]\nplatform = \"linux\"\narch = \"{arch}\"\n\n\
+                     [release.target.payload]\ntype = \"TarballPackage\"\n\
+                     url = \"https://example.com/app-{version}-{arch}.txz\"\n\
+                     size = 1\ninstalled_size = 1\n",
+                    version = version,
+                    arch = arch
+                ));
+            }
+        }
+        out
+    }
+
+    fn request<'a>(repo: &'a Repo, version: &'a Version) -> Request<'a> {
+        Request::builder()
+            .repo_path(Cow::Borrowed(repo.path()))
+            .id(Cow::Borrowed("app"))
+            .version(Cow::Borrowed(version))
+            .channel(None)
+            .platform(None)
+            .arch(None)
+            .build()
+    }
+
+    /// Each remaining release as its version and the arches of its targets.
+    fn releases(repo: &Repo) -> Vec<(String, Vec<String>)> {
+        let descriptor: Descriptor = toml::from_str(&repo.read("packages/app/index.toml")).unwrap();
+        descriptor
+            .release
+            .iter()
+            .map(|release| {
+                let arches = release
+                    .target
+                    .iter()
+                    .map(|x| x.arch.clone().unwrap_or_default())
+                    .collect();
+                (release.version.to_string(), arches)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn removes_release() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None), ("2.0.0", None)]),
+        );
+
+        let version = Version::new("1.0.0").unwrap();
+        remove(request(&repo, &version)).unwrap();
+
+        assert_eq!(
+            releases(&repo),
+            vec![("2.0.0".to_string(), vec![String::new()])]
+        );
+    }
+
+    #[test]
+    fn removes_single_target() {
+        let repo = Repo::new();
+        repo.package("app", &with_arches(&["1.0.0"], &["x86_64", "arm64"]));
+
+        let version = Version::new("1.0.0").unwrap();
+        remove(Request {
+            platform: Some("linux".into()),
+            arch: Some("arm64".into()),
+            ..request(&repo, &version)
+        })
+        .unwrap();
+
+        assert_eq!(
+            releases(&repo),
+            vec![("1.0.0".to_string(), vec!["x86_64".to_string()])]
+        );
+    }
+
+    #[test]
+    fn removes_release_with_its_last_target() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &with_arches(&["1.0.0", "2.0.0"], &["x86_64", "arm64"]),
+        );
+
+        // A platform without an arch matches every arch of that platform
+        let version = Version::new("1.0.0").unwrap();
+        remove(Request {
+            platform: Some("linux".into()),
+            ..request(&repo, &version)
+        })
+        .unwrap();
+
+        assert_eq!(
+            releases(&repo),
+            vec![(
+                "2.0.0".to_string(),
+                vec!["x86_64".to_string(), "arm64".to_string()]
+            )]
+        );
+    }
+
+    #[test]
+    fn missing_release_or_target() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", None)]));
+        let before = repo.read("packages/app/index.toml");
+
+        let version = Version::new("1.0.0").unwrap();
+        assert!(matches!(
+            remove(Request {
+                channel: Some("beta".into()),
+                ..request(&repo, &version)
+            }),
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            remove(Request {
+                platform: Some("windows".into()),
+                ..request(&repo, &version)
+            }),
+            Err(Error::NotFound)
+        ));
+
+        let missing = Version::new("3.0.0").unwrap();
+        assert!(matches!(
+            remove(request(&repo, &missing)),
+            Err(Error::NotFound)
+        ));
+
+        assert_eq!(repo.read("packages/app/index.toml"), before);
+    }
+
+    #[test]
+    fn arch_requires_platform() {
+        let repo = Repo::new();
+        let version = Version::new("1.0.0").unwrap();
+        let partial = PartialRequest::builder()
+            .repo_path(Some(repo.path()))
+            .id(Some("app"))
+            .version(Some(&version))
+            .channel(Some(""))
+            .arch(Some("arm64"))
+            .build();
+
+        assert!(matches!(
+            <Request as crate::Request>::new_from_user_input(partial, &Input::default()),
+            Err(RequestError::ArchWithoutPlatform)
+        ));
+    }
+}
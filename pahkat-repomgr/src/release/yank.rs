@@ -0,0 +1,268 @@
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use pahkat_types::package::Version;
+use typed_builder::TypedBuilder;
+
+use super::remove::{find_repo, FindRepoError};
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub repo_path: Cow<'a, Path>,
+    pub id: Cow<'a, str>,
+    pub version: Cow<'a, Version>,
+    pub channel: Option<Cow<'a, str>>,
+    /// Whether to yank the release, or undo a previous yank
+    pub yanked: bool,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub id: Option<&'a str>,
+    #[builder(default)]
+    pub version: Option<&'a Version>,
+    #[builder(default)]
+    pub channel: Option<&'a str>,
+    #[builder(default)]
+    pub yanked: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let _ = find_repo(&repo_path)?;
+
+        let id = match partial.id {
+            Some(id) => Cow::Borrowed(id),
+            None => Cow::Owned(
+                input
+                    .field("id", "Package identifier")
+                    .required::<String>()?,
+            ),
+        };
+
+        let version = match partial.version {
+            Some(version) => Cow::Borrowed(version),
+            None => Cow::Owned(
+                input
+                    .field("version", "Release version")
+                    .required::<Version>()?,
+            ),
+        };
+
+        let channel = match partial.channel {
+            Some(channel) => {
+                if channel == "" {
+                    None
+                } else {
+                    Some(Cow::Borrowed(channel))
+                }
+            }
+            None => input
+                .field("channel", "Channel (or none for stable)")
+                .optional::<String>()?
+                .map(Cow::Owned),
+        };
+
+        Ok(Request {
+            repo_path,
+            id,
+            version,
+            channel,
+            yanked: partial.yanked,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read descriptor index: `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Failed to write TOML file `{0}`")]
+    WriteToml(PathBuf, #[source] io::Error),
+
+    #[error("Failed to serialize TOML for `{0}`")]
+    SerializeToml(PathBuf, #[source] toml::ser::Error),
+
+    #[error("No matching release found to yank")]
+    NotFound,
+
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+}
+
+/// Marks a release as yanked, or clears the mark.
+///
+/// A yanked release stays in the index, so clients can still find what is
+/// installed, but is not chosen for new installs. The index must be rebuilt
+/// for clients to see the change.
+pub fn yank<'a>(request: Request<'a>) -> Result<(), Error> {
+    log::debug!("{:?}", request);
+
+    let pkg_path = find_repo(&request.repo_path)?
+        .join("packages")
+        .join(&*request.id)
+        .join("index.toml");
+
+    let pkg_file =
+        fs::read_to_string(&pkg_path).map_err(|e| Error::ReadFailed(pkg_path.clone(), e))?;
+    let mut descriptor: pahkat_types::package::Descriptor =
+        toml::from_str(&pkg_file).map_err(|e| Error::ReadToml(pkg_path.clone(), e))?;
+
+    let channel = request.channel.as_deref();
+    let release = descriptor
+        .release
+        .iter_mut()
+        .find(|x| &x.version == &*request.version && x.channel.as_deref() == channel)
+        .ok_or(Error::NotFound)?;
+
+    if release.yanked == request.yanked {
+        log::info!("Release is already in that state; nothing to do.");
+        return Ok(());
+    }
+
+    release.yanked = request.yanked;
+    match request.yanked {
+        true => log::info!("Yanked release."),
+        false => log::info!("Unyanked release."),
+    }
+
+    // Write the toml
+    let data = toml::to_string_pretty(&descriptor)
+        .map_err(|e| Error::SerializeToml(pkg_path.clone(), e))?;
+    fs::write(&pkg_path, data).map_err(|e| Error::WriteToml(pkg_path.to_path_buf(), e))?;
+    log::info!("Wrote descriptor to {}", pkg_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+    use pahkat_types::package::Descriptor;
+
+    fn request<'a>(
+        repo: &'a Repo,
+        version: &'a Version,
+        channel: Option<&'a str>,
+        yanked: bool,
+    ) -> Request<'a> {
+        Request::builder()
+            .repo_path(Cow::Borrowed(repo.path()))
+            .id(Cow::Borrowed("app"))
+            .version(Cow::Borrowed(version))
+            .channel(channel.map(Cow::Borrowed))
+            .yanked(yanked)
+            .build()
+    }
+
+    /// Each release as its version, channel and whether it is yanked.
+    fn releases(repo: &Repo) -> Vec<(String, Option<String>, bool)> {
+        let descriptor: Descriptor = toml::from_str(&repo.read("packages/app/index.toml")).unwrap();
+        descriptor
+            .release
+            .iter()
+            .map(|x| (x.version.to_string(), x.channel.clone(), x.yanked))
+            .collect()
+    }
+
+    #[test]
+    fn yanks_and_unyanks() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None), ("1.0.0", Some("beta"))]),
+        );
+
+        let version = Version::new("1.0.0").unwrap();
+        yank(request(&repo, &version, Some("beta"), true)).unwrap();
+        assert_eq!(
+            releases(&repo),
+            vec![
+                ("1.0.0".to_string(), None, false),
+                ("1.0.0".to_string(), Some("beta".to_string()), true),
+            ]
+        );
+
+        yank(request(&repo, &version, Some("beta"), false)).unwrap();
+        assert_eq!(
+            releases(&repo),
+            vec![
+                ("1.0.0".to_string(), None, false),
+                ("1.0.0".to_string(), Some("beta".to_string()), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn already_in_state_is_unchanged() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", None)]));
+        let version = Version::new("1.0.0").unwrap();
+
+        let before = repo.read("packages/app/index.toml");
+        yank(request(&repo, &version, None, false)).unwrap();
+        assert_eq!(repo.read("packages/app/index.toml"), before);
+
+        yank(request(&repo, &version, None, true)).unwrap();
+        let before = repo.read("packages/app/index.toml");
+        yank(request(&repo, &version, None, true)).unwrap();
+        assert_eq!(repo.read("packages/app/index.toml"), before);
+        assert_eq!(releases(&repo), vec![("1.0.0".to_string(), None, true)]);
+    }
+
+    #[test]
+    fn wrong_channel_is_not_found() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", Some("beta"))]));
+
+        let version = Version::new("1.0.0").unwrap();
+        for channel in &[None, Some("nightly")] {
+            let result = yank(request(&repo, &version, *channel, true));
+            assert!(matches!(result, Err(Error::NotFound)));
+        }
+
+        let missing = Version::new("2.0.0").unwrap();
+        let result = yank(request(&repo, &missing, Some("beta"), true));
+        assert!(matches!(result, Err(Error::NotFound)));
+
+        assert_eq!(
+            releases(&repo),
+            vec![("1.0.0".to_string(), Some("beta".to_string()), false)]
+        );
+    }
+}
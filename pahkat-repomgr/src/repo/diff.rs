@@ -0,0 +1,1001 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use pahkat_types::package::Package;
+use serde::Serialize;
+use typed_builder::TypedBuilder;
+
+use crate::fbs::pahkat as pahkat_fbs;
+use crate::input::{Input, InputError};
+
+/// Where one side of a diff is read from.
+#[derive(Debug, Clone)]
+pub enum Source<'a> {
+    /// A repository directory, read from its package descriptors
+    Dir(Cow<'a, Path>),
+    /// A built `index.bin`
+    Index(Cow<'a, Path>),
+    /// A git revision of the repository at `repo_path`
+    Git {
+        repo_path: Cow<'a, Path>,
+        rev: Cow<'a, str>,
+    },
+}
+
+impl<'a> Source<'a> {
+    /// Interprets user input as a directory, an `index.bin` or otherwise a git
+    /// revision of the repository at `repo_path`.
+    ///
+    /// Relative paths are resolved against `repo_path`.
+    pub fn from_input(input: &'a str, repo_path: &'a Path) -> Source<'a> {
+        Source::from_cow(Cow::Borrowed(input), Cow::Borrowed(repo_path))
+    }
+
+    fn from_cow(input: Cow<'a, str>, repo_path: Cow<'a, Path>) -> Source<'a> {
+        let path = repo_path.join(&*input);
+
+        if !path.exists() {
+            return Source::Git {
+                repo_path,
+                rev: input,
+            };
+        }
+
+        if path.is_dir() {
+            Source::Dir(Cow::Owned(path))
+        } else {
+            Source::Index(Cow::Owned(path))
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub old: Source<'a>,
+    pub new: Source<'a>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub old: Option<&'a str>,
+    #[builder(default)]
+    pub new: Option<&'a str>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Provided path was invalid")]
+    PathError(#[source] io::Error),
+
+    #[error("Invalid input")]
+    InvalidInput,
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .lookup("repo_path")?
+                    .unwrap_or_else(|| PathBuf::from(".")),
+            ),
+        };
+
+        let old = match partial.old {
+            Some(old) => Cow::Borrowed(old),
+            None => Cow::Owned(
+                input
+                    .field("old", "Old revision, directory or index.bin")
+                    .default("HEAD")
+                    .required::<String>()?,
+            ),
+        };
+        let old = Source::from_cow(old, repo_path.clone());
+
+        // Without a new side, compare against the working tree
+        let new = match partial.new {
+            Some(new) => Source::from_cow(Cow::Borrowed(new), repo_path),
+            None => match input.lookup::<String>("new")? {
+                Some(new) => Source::from_cow(Cow::Owned(new), repo_path),
+                None => Source::Dir(repo_path),
+            },
+        };
+
+        Ok(Request { old, new })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Invalid index `{0}`: {1}")]
+    InvalidIndex(PathBuf, String),
+
+    #[error("Failed to run git")]
+    GitSpawn(#[source] io::Error),
+
+    #[error("git failed for revision `{0}`: {1}")]
+    Git(String, String),
+}
+
+/// The output format of a changelog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(Format::Markdown),
+            "json" => Ok(Format::Json),
+            x => Err(format!("Unknown format `{}`; expected markdown or json", x)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ReleaseRef {
+    pub version: String,
+    pub channel: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TargetRef {
+    pub version: String,
+    pub channel: Option<String>,
+    pub platform: String,
+    pub arch: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UrlChange {
+    pub version: String,
+    pub channel: Option<String>,
+    pub platform: String,
+    pub arch: Option<String>,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyChange {
+    pub version: String,
+    pub channel: Option<String>,
+    pub platform: String,
+    pub arch: Option<String>,
+    pub added: BTreeMap<String, String>,
+    pub removed: BTreeMap<String, String>,
+    /// Dependencies whose version requirement changed, as `(old, new)`
+    pub changed: BTreeMap<String, (String, String)>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PackageChange {
+    pub id: String,
+    pub added_releases: Vec<ReleaseRef>,
+    pub removed_releases: Vec<ReleaseRef>,
+    /// Targets added to or removed from releases present on both sides
+    pub added_targets: Vec<TargetRef>,
+    pub removed_targets: Vec<TargetRef>,
+    pub changed_urls: Vec<UrlChange>,
+    pub changed_dependencies: Vec<DependencyChange>,
+}
+
+impl PackageChange {
+    fn is_empty(&self) -> bool {
+        self.added_releases.is_empty()
+            && self.removed_releases.is_empty()
+            && self.added_targets.is_empty()
+            && self.removed_targets.is_empty()
+            && self.changed_urls.is_empty()
+            && self.changed_dependencies.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Diff {
+    pub added: Vec<PackageChange>,
+    pub removed: Vec<String>,
+    pub updated: Vec<PackageChange>,
+}
+
+impl Diff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Markdown => self.to_markdown(),
+            Format::Json => serde_json::to_string_pretty(self).unwrap(),
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "# Repository changes").unwrap();
+
+        if self.is_empty() {
+            writeln!(out, "\nNo changes.").unwrap();
+            return out;
+        }
+
+        if !self.added.is_empty() {
+            writeln!(out, "\n## Added packages\n").unwrap();
+            for package in self.added.iter() {
+                writeln!(
+                    out,
+                    "- **{}**: {}",
+                    package.id,
+                    release_list(&package.added_releases)
+                )
+                .unwrap();
+            }
+        }
+
+        if !self.removed.is_empty() {
+            writeln!(out, "\n## Removed packages\n").unwrap();
+            for id in self.removed.iter() {
+                writeln!(out, "- **{}**", id).unwrap();
+            }
+        }
+
+        if !self.updated.is_empty() {
+            writeln!(out, "\n## Updated packages").unwrap();
+        }
+
+        for package in self.updated.iter() {
+            writeln!(out, "\n### {}\n", package.id).unwrap();
+
+            if !package.added_releases.is_empty() {
+                writeln!(out, "- New releases:").unwrap();
+                for (channel, versions) in by_channel(&package.added_releases) {
+                    writeln!(out, "  - {}: {}", channel, versions.join(", ")).unwrap();
+                }
+            }
+
+            if !package.removed_releases.is_empty() {
+                writeln!(
+                    out,
+                    "- Removed releases: {}",
+                    release_list(&package.removed_releases)
+                )
+                .unwrap();
+            }
+
+            if !package.added_targets.is_empty() {
+                writeln!(
+                    out,
+                    "- New targets: {}",
+                    target_list(&package.added_targets)
+                )
+                .unwrap();
+            }
+
+            if !package.removed_targets.is_empty() {
+                writeln!(
+                    out,
+                    "- Removed targets: {}",
+                    target_list(&package.removed_targets)
+                )
+                .unwrap();
+            }
+
+            for change in package.changed_urls.iter() {
+                writeln!(
+                    out,
+                    "- URL of {} changed: {} → {}",
+                    target_name(&change.version, &change.platform, change.arch.as_deref()),
+                    change.old.as_deref().unwrap_or("none"),
+                    change.new.as_deref().unwrap_or("none"),
+                )
+                .unwrap();
+            }
+
+            for change in package.changed_dependencies.iter() {
+                let mut parts = vec![];
+                parts.extend(
+                    change
+                        .added
+                        .iter()
+                        .map(|(k, v)| format!("added `{}` ({})", k, v)),
+                );
+                parts.extend(change.removed.keys().map(|k| format!("removed `{}`", k)));
+                parts.extend(
+                    change
+                        .changed
+                        .iter()
+                        .map(|(k, (old, new))| format!("`{}` {} → {}", k, old, new)),
+                );
+
+                writeln!(
+                    out,
+                    "- Dependencies of {}: {}",
+                    target_name(&change.version, &change.platform, change.arch.as_deref()),
+                    parts.join("; ")
+                )
+                .unwrap();
+            }
+        }
+
+        out
+    }
+}
+
+fn channel_name(channel: Option<&str>) -> &str {
+    channel.unwrap_or("stable")
+}
+
+fn release_list(releases: &[ReleaseRef]) -> String {
+    releases
+        .iter()
+        .map(|x| format!("{} ({})", x.version, channel_name(x.channel.as_deref())))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn by_channel(releases: &[ReleaseRef]) -> BTreeMap<&str, Vec<&str>> {
+    let mut out = BTreeMap::<_, Vec<_>>::new();
+    for release in releases.iter() {
+        out.entry(channel_name(release.channel.as_deref()))
+            .or_default()
+            .push(&*release.version);
+    }
+    out
+}
+
+fn target_list(targets: &[TargetRef]) -> String {
+    targets
+        .iter()
+        .map(|x| {
+            format!(
+                "{} ({})",
+                target_name(&x.version, &x.platform, x.arch.as_deref()),
+                channel_name(x.channel.as_deref())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn target_name(version: &str, platform: &str, arch: Option<&str>) -> String {
+    match arch {
+        Some(arch) => format!("{} {}/{}", version, platform, arch),
+        None => format!("{} {}", version, platform),
+    }
+}
+
+/// The parts of a target a changelog is concerned with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TargetEntry {
+    platform: String,
+    arch: Option<String>,
+    url: Option<String>,
+    dependencies: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ReleaseEntry {
+    version: String,
+    channel: Option<String>,
+    targets: Vec<TargetEntry>,
+}
+
+type Snapshot = BTreeMap<String, Vec<ReleaseEntry>>;
+
+/// Compares two versions of a repository.
+pub fn diff<'a>(request: Request<'a>) -> Result<Diff, Error> {
+    log::debug!("{:?}", request);
+
+    let old = load(&request.old)?;
+    let new = load(&request.new)?;
+
+    let mut diff = Diff::default();
+
+    for (id, releases) in new.iter() {
+        match old.get(id) {
+            None => diff.added.push(PackageChange {
+                id: id.clone(),
+                added_releases: releases.iter().map(release_ref).collect(),
+                ..Default::default()
+            }),
+            Some(old_releases) => {
+                let change = diff_package(id, old_releases, releases);
+                if !change.is_empty() {
+                    diff.updated.push(change);
+                }
+            }
+        }
+    }
+
+    diff.removed = old
+        .keys()
+        .filter(|id| !new.contains_key(*id))
+        .cloned()
+        .collect();
+
+    Ok(diff)
+}
+
+fn release_ref(release: &ReleaseEntry) -> ReleaseRef {
+    ReleaseRef {
+        version: release.version.clone(),
+        channel: release.channel.clone(),
+    }
+}
+
+fn target_ref(release: &ReleaseEntry, target: &TargetEntry) -> TargetRef {
+    TargetRef {
+        version: release.version.clone(),
+        channel: release.channel.clone(),
+        platform: target.platform.clone(),
+        arch: target.arch.clone(),
+    }
+}
+
+fn diff_package(id: &str, old: &[ReleaseEntry], new: &[ReleaseEntry]) -> PackageChange {
+    let mut change = PackageChange {
+        id: id.to_string(),
+        ..Default::default()
+    };
+
+    let find = |releases: &'_ [ReleaseEntry], r: &ReleaseEntry| {
+        releases
+            .iter()
+            .position(|x| x.version == r.version && x.channel == r.channel)
+    };
+
+    for release in new.iter() {
+        let old_release = match find(old, release) {
+            Some(i) => &old[i],
+            None => {
+                change.added_releases.push(release_ref(release));
+                continue;
+            }
+        };
+
+        let find_target = |targets: &'_ [TargetEntry], t: &TargetEntry| {
+            targets
+                .iter()
+                .find(|x| x.platform == t.platform && x.arch == t.arch)
+                .cloned()
+        };
+
+        for target in release.targets.iter() {
+            let old_target = match find_target(&old_release.targets, target) {
+                Some(v) => v,
+                None => {
+                    change.added_targets.push(target_ref(release, target));
+                    continue;
+                }
+            };
+
+            if old_target.url != target.url {
+                change.changed_urls.push(UrlChange {
+                    version: release.version.clone(),
+                    channel: release.channel.clone(),
+                    platform: target.platform.clone(),
+                    arch: target.arch.clone(),
+                    old: old_target.url,
+                    new: target.url.clone(),
+                });
+            }
+
+            if old_target.dependencies != target.dependencies {
+                change.changed_dependencies.push(diff_dependencies(
+                    release,
+                    target,
+                    &old_target.dependencies,
+                ));
+            }
+        }
+
+        change.removed_targets.extend(
+            old_release
+                .targets
+                .iter()
+                .filter(|x| find_target(&release.targets, x).is_none())
+                .map(|x| target_ref(release, x)),
+        );
+    }
+
+    change.removed_releases = old
+        .iter()
+        .filter(|x| find(new, x).is_none())
+        .map(release_ref)
+        .collect();
+
+    change
+}
+
+fn diff_dependencies(
+    release: &ReleaseEntry,
+    target: &TargetEntry,
+    old: &BTreeMap<String, String>,
+) -> DependencyChange {
+    let new = &target.dependencies;
+    let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+
+    let mut change = DependencyChange {
+        version: release.version.clone(),
+        channel: release.channel.clone(),
+        platform: target.platform.clone(),
+        arch: target.arch.clone(),
+        added: BTreeMap::new(),
+        removed: BTreeMap::new(),
+        changed: BTreeMap::new(),
+    };
+
+    for key in keys {
+        match (old.get(key), new.get(key)) {
+            (None, Some(v)) => {
+                change.added.insert(key.clone(), v.clone());
+            }
+            (Some(v), None) => {
+                change.removed.insert(key.clone(), v.clone());
+            }
+            (Some(a), Some(b)) if a != b => {
+                change.changed.insert(key.clone(), (a.clone(), b.clone()));
+            }
+            _ => {}
+        }
+    }
+
+    change
+}
+
+fn load(source: &Source<'_>) -> Result<Snapshot, Error> {
+    match source {
+        Source::Dir(path) => load_dir(path),
+        Source::Index(path) => load_index(path),
+        Source::Git { repo_path, rev } => load_git(repo_path, rev),
+    }
+}
+
+fn insert_package(snapshot: &mut Snapshot, package: Package) {
+    match package {
+        Package::Concrete(descriptor) => {
+            let releases = descriptor
+                .release
+                .into_iter()
+                .map(|release| ReleaseEntry {
+                    version: release.version.to_string(),
+                    channel: release.channel,
+                    targets: release
+                        .target
+                        .into_iter()
+                        .map(|t| TargetEntry {
+                            platform: t.platform,
+                            arch: t.arch,
+                            url: Some(t.payload.url().to_string()),
+                            dependencies: dependencies(&t.dependencies),
+                        })
+                        .collect(),
+                })
+                .collect();
+            snapshot.insert(descriptor.package.id, releases);
+        }
+        Package::Synthetic(descriptor) => {
+            let releases = descriptor
+                .releases
+                .into_iter()
+                .map(|release| ReleaseEntry {
+                    version: release.version,
+                    channel: Some(release.channel).filter(|x| !x.is_empty()),
+                    targets: release
+                        .targets
+                        .into_iter()
+                        .map(|t| TargetEntry {
+                            platform: t.platform,
+                            arch: t.arch,
+                            url: None,
+                            dependencies: dependencies(&t.dependencies),
+                        })
+                        .collect(),
+                })
+                .collect();
+            snapshot.insert(descriptor.synthetic.id, releases);
+        }
+        _ => {}
+    }
+}
+
+fn dependencies(map: &pahkat_types::DependencyMap) -> BTreeMap<String, String> {
+    map.iter()
+        .map(|(k, v)| (k.as_str().to_string(), v.clone()))
+        .collect()
+}
+
+fn load_dir(repo_path: &Path) -> Result<Snapshot, Error> {
+    let packages_path = repo_path.join("packages");
+    let dirs = fs::read_dir(&packages_path)
+        .map_err(|e| Error::ReadFailed(packages_path.clone(), e))?
+        .filter_map(Result::ok)
+        .map(|x| x.path().join("index.toml"))
+        .filter(|x| x.is_file());
+
+    let mut snapshot = Snapshot::new();
+    for path in dirs {
+        let file = fs::read_to_string(&path).map_err(|e| Error::ReadFailed(path.clone(), e))?;
+        let package = toml::from_str(&file).map_err(|e| Error::ReadToml(path, e))?;
+        insert_package(&mut snapshot, package);
+    }
+
+    Ok(snapshot)
+}
+
+fn git(repo_path: &Path, rev: &str, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(args)
+        .output()
+        .map_err(Error::GitSpawn)?;
+
+    if !output.status.success() {
+        return Err(Error::Git(
+            rev.to_string(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn load_git(repo_path: &Path, rev: &str) -> Result<Snapshot, Error> {
+    let files = git(
+        repo_path,
+        rev,
+        &["ls-tree", "-r", "--name-only", rev, "--", "packages"],
+    )?;
+
+    let mut snapshot = Snapshot::new();
+    for file in files.lines() {
+        // Only `packages/<id>/index.toml`
+        let parts = file.split('/').collect::<Vec<_>>();
+        if parts.len() != 3 || parts[2] != "index.toml" {
+            continue;
+        }
+
+        let data = git(repo_path, rev, &["show", &format!("{}:./{}", rev, file)])?;
+        let package = toml::from_str(&data)
+            .map_err(|e| Error::ReadToml(PathBuf::from(format!("{}:{}", rev, file)), e))?;
+        insert_package(&mut snapshot, package);
+    }
+
+    Ok(snapshot)
+}
+
+fn load_index(path: &Path) -> Result<Snapshot, Error> {
+    let data = fs::read(path).map_err(|e| Error::ReadFailed(path.to_path_buf(), e))?;
+    read_index(&data).map_err(|e| Error::InvalidIndex(path.to_path_buf(), format!("{:?}", e)))
+}
+
+fn read_index(data: &[u8]) -> Result<Snapshot, fbs::Error> {
+    let packages = pahkat_fbs::Packages::get_root(data)?;
+    let mut snapshot = Snapshot::new();
+
+    if let (Some(keys), Some(values)) = (packages.packages_keys()?, packages.packages_values()?) {
+        for (id, pkg) in keys.iter().zip(values.iter()) {
+            let pkg = pkg?;
+            let mut releases = vec![];
+
+            for release in pkg.release()?.into_iter().flat_map(|x| x.iter()) {
+                let release = release?;
+                let mut targets = vec![];
+
+                for target in release.target()?.into_iter().flat_map(|x| x.iter()) {
+                    let target = target?;
+                    let url = match target.payload()? {
+                        pahkat_fbs::Payload::WindowsExecutable(x) => x.url()?,
+                        pahkat_fbs::Payload::MacOSPackage(x) => x.url()?,
+                        pahkat_fbs::Payload::TarballPackage(x) => x.url()?,
+                    };
+
+                    targets.push(TargetEntry {
+                        platform: target.platform()?.to_string(),
+                        arch: target.arch()?.map(str::to_string),
+                        url: Some(url.to_string()),
+                        dependencies: read_map(
+                            target.dependencies_keys()?,
+                            target.dependencies_values()?,
+                        )?,
+                    });
+                }
+
+                releases.push(ReleaseEntry {
+                    version: release.version()?.to_string(),
+                    channel: release.channel()?.map(str::to_string),
+                    targets,
+                });
+            }
+
+            snapshot.insert(id?.to_string(), releases);
+        }
+    }
+
+    if let (Some(keys), Some(values)) = (packages.synthetics_keys()?, packages.synthetics_values()?)
+    {
+        for (id, pkg) in keys.iter().zip(values.iter()) {
+            let pkg = pkg?;
+            let mut releases = vec![];
+
+            for release in pkg.release()?.into_iter().flat_map(|x| x.iter()) {
+                let release = release?;
+                let mut targets = vec![];
+
+                for target in release.target()?.into_iter().flat_map(|x| x.iter()) {
+                    let target = target?;
+                    targets.push(TargetEntry {
+                        platform: target.platform()?.to_string(),
+                        arch: target.arch()?.map(str::to_string),
+                        url: None,
+                        dependencies: read_map(
+                            target.dependencies_keys()?,
+                            target.dependencies_values()?,
+                        )?,
+                    });
+                }
+
+                releases.push(ReleaseEntry {
+                    version: release.version()?.to_string(),
+                    channel: Some(release.channel()?.to_string()).filter(|x| !x.is_empty()),
+                    targets,
+                });
+            }
+
+            snapshot.insert(id?.to_string(), releases);
+        }
+    }
+
+    Ok(snapshot)
+}
+
+fn read_map<'a>(
+    keys: Option<fbs::Vector<'a, fbs::ForwardsUOffset<&'a str>>>,
+    values: Option<fbs::Vector<'a, fbs::ForwardsUOffset<&'a str>>>,
+) -> Result<BTreeMap<String, String>, fbs::Error> {
+    let mut out = BTreeMap::new();
+
+    if let (Some(keys), Some(values)) = (keys, values) {
+        for (k, v) in keys.iter().zip(values.iter()) {
+            out.insert(k?.to_string(), v?.to_string());
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+
+    fn target(platform: &str, url: &str, deps: &[(&str, &str)]) -> TargetEntry {
+        TargetEntry {
+            platform: platform.to_string(),
+            arch: None,
+            url: Some(url.to_string()),
+            dependencies: deps
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    fn release(version: &str, targets: Vec<TargetEntry>) -> ReleaseEntry {
+        ReleaseEntry {
+            version: version.to_string(),
+            channel: None,
+            targets,
+        }
+    }
+
+    fn platforms(targets: &[TargetRef]) -> Vec<(&str, &str)> {
+        targets
+            .iter()
+            .map(|x| (&*x.version, &*x.platform))
+            .collect()
+    }
+
+    #[test]
+    fn releases_added_and_removed() {
+        let old = vec![
+            release("1.0.0", vec![target("linux", "a", &[])]),
+            release("1.1.0", vec![target("linux", "b", &[])]),
+        ];
+        let new = vec![
+            release("1.1.0", vec![target("linux", "b", &[])]),
+            ReleaseEntry {
+                channel: Some("beta".into()),
+                ..release("1.2.0", vec![target("linux", "c", &[])])
+            },
+        ];
+
+        let change = diff_package("app", &old, &new);
+        assert_eq!(
+            change.added_releases,
+            vec![ReleaseRef {
+                version: "1.2.0".into(),
+                channel: Some("beta".into())
+            }]
+        );
+        assert_eq!(
+            change.removed_releases,
+            vec![ReleaseRef {
+                version: "1.0.0".into(),
+                channel: None
+            }]
+        );
+        assert!(change.added_targets.is_empty());
+        assert!(change.removed_targets.is_empty());
+        assert!(change.changed_urls.is_empty());
+    }
+
+    #[test]
+    fn targets_added_and_removed() {
+        let old = vec![release(
+            "1.0.0",
+            vec![target("linux", "a", &[]), target("macos", "b", &[])],
+        )];
+        let new = vec![release(
+            "1.0.0",
+            vec![target("linux", "a", &[]), target("windows", "c", &[])],
+        )];
+
+        let change = diff_package("app", &old, &new);
+        assert_eq!(platforms(&change.added_targets), vec![("1.0.0", "windows")]);
+        assert_eq!(platforms(&change.removed_targets), vec![("1.0.0", "macos")]);
+        assert!(change.changed_urls.is_empty());
+        assert!(change.changed_dependencies.is_empty());
+    }
+
+    #[test]
+    fn targets_differing_by_arch() {
+        let old = vec![release("1.0.0", vec![target("linux", "a", &[])])];
+        let new = vec![release(
+            "1.0.0",
+            vec![TargetEntry {
+                arch: Some("x86_64".into()),
+                ..target("linux", "a", &[])
+            }],
+        )];
+
+        let change = diff_package("app", &old, &new);
+        assert_eq!(change.added_targets[0].arch.as_deref(), Some("x86_64"));
+        assert_eq!(change.removed_targets[0].arch, None);
+    }
+
+    #[test]
+    fn urls_and_dependencies_changed() {
+        let old = vec![release(
+            "1.0.0",
+            vec![target("linux", "a", &[("lib", "^1"), ("gone", "*")])],
+        )];
+        let new = vec![release(
+            "1.0.0",
+            vec![target("linux", "b", &[("lib", "^2"), ("new", "*")])],
+        )];
+
+        let change = diff_package("app", &old, &new);
+        assert_eq!(change.changed_urls.len(), 1);
+        assert_eq!(change.changed_urls[0].old.as_deref(), Some("a"));
+        assert_eq!(change.changed_urls[0].new.as_deref(), Some("b"));
+
+        let deps = &change.changed_dependencies[0];
+        assert_eq!(deps.added.keys().collect::<Vec<_>>(), vec!["new"]);
+        assert_eq!(deps.removed.keys().collect::<Vec<_>>(), vec!["gone"]);
+        assert_eq!(deps.changed["lib"], ("^1".to_string(), "^2".to_string()));
+    }
+
+    #[test]
+    fn unchanged_package() {
+        let releases = vec![release("1.0.0", vec![target("linux", "a", &[("x", "*")])])];
+        assert!(diff_package("app", &releases, &releases).is_empty());
+    }
+
+    #[test]
+    fn sources_resolve_against_repo_path() {
+        let repo = Repo::new();
+        repo.write("old/index.toml", "");
+        repo.write("index.bin", "");
+
+        match Source::from_input("old", repo.path()) {
+            Source::Dir(path) => assert_eq!(path, repo.path().join("old")),
+            x => panic!("expected a directory, got {:?}", x),
+        }
+        match Source::from_input("index.bin", repo.path()) {
+            Source::Index(path) => assert_eq!(path, repo.path().join("index.bin")),
+            x => panic!("expected an index, got {:?}", x),
+        }
+        match Source::from_input("HEAD~1", repo.path()) {
+            Source::Git { repo_path, rev } => {
+                assert_eq!(repo_path, repo.path());
+                assert_eq!(rev, "HEAD~1");
+            }
+            x => panic!("expected a git revision, got {:?}", x),
+        }
+
+        let absolute = repo.path().join("old");
+        match Source::from_input(absolute.to_str().unwrap(), Path::new("/nonexistent")) {
+            Source::Dir(path) => assert_eq!(path, absolute),
+            x => panic!("expected a directory, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn diff_directories() {
+        let old = Repo::new();
+        old.package("app", &descriptor("app", &[("1.0.0", None)]));
+        old.package("gone", &descriptor("gone", &[("1.0.0", None)]));
+
+        let new = Repo::new();
+        new.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None), ("1.1.0", Some("beta"))]),
+        );
+        new.package("added", &descriptor("added", &[("0.1.0", None)]));
+
+        let request = Request::builder()
+            .old(Source::Dir(Cow::Borrowed(old.path())))
+            .new(Source::Dir(Cow::Borrowed(new.path())))
+            .build();
+        let diff = diff(request).unwrap();
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].id, "added");
+        assert_eq!(diff.removed, vec!["gone".to_string()]);
+        assert_eq!(diff.updated.len(), 1);
+
+        let markdown = diff.render(Format::Markdown);
+        assert!(markdown.contains("- **added**: 0.1.0 (stable)"));
+        assert!(markdown.contains("## Removed packages\n\n- **gone**"));
+        assert!(markdown.contains("### app\n\n- New releases:\n  - beta: 1.1.0"));
+
+        let json: serde_json::Value = serde_json::from_str(&diff.render(Format::Json)).unwrap();
+        assert_eq!(json["updated"][0]["added_releases"][0]["version"], "1.1.0");
+    }
+
+    #[test]
+    fn no_changes() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", None)]));
+
+        let request = Request::builder()
+            .old(Source::Dir(Cow::Borrowed(repo.path())))
+            .new(Source::Dir(Cow::Borrowed(repo.path())))
+            .build();
+        let diff = diff(request).unwrap();
+
+        assert!(diff.is_empty());
+        assert!(diff.to_markdown().ends_with("No changes.\n"));
+    }
+}
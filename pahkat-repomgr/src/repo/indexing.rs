@@ -1,63 +1,172 @@
 use fbs::FlatBufferBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use typed_builder::TypedBuilder;
 
-pub fn index(request: Request<'_>) -> anyhow::Result<()> {
+use crate::input::{self, Input, InputError};
+
+/// The file recording the hashes of the inputs `index.bin` was built from.
+pub const MANIFEST_FILE: &str = "index.manifest.toml";
+
+const GENERATOR: &str = concat!("pahkat-repomgr ", env!("CARGO_PKG_VERSION"));
+
+/// The outcome of an index run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// `index.bin` was written
+    Written,
+    /// The manifest matched the inputs, so nothing was rebuilt
+    Unchanged,
+    /// A check found `index.bin` identical to a fresh build
+    UpToDate,
+    /// A check found `index.bin` missing or different from a fresh build
+    Stale,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Manifest {
+    generator: String,
+    /// SHA-256 of the `index.bin` built from the inputs
+    index: String,
+    /// SHA-256 of each input file, keyed by its path relative to the repository
+    inputs: BTreeMap<String, String>,
+}
+
+pub fn index(request: Request<'_>) -> anyhow::Result<Status> {
     log::debug!("Attempting to load repo in path: {:?}", &request.path);
     let packages_path = request.path.join("packages");
-    std::fs::create_dir_all(&packages_path)?;
+    let index_path = packages_path.join("index.bin");
+    let manifest_path = packages_path.join(MANIFEST_FILE);
 
-    // Attempt to make strings directory if it doesn't exist
-    let strings_path = request.path.join("strings");
-    std::fs::create_dir_all(&strings_path)?;
+    if !request.check {
+        std::fs::create_dir_all(&packages_path)?;
 
-    // Find all package descriptor TOMLs
-    let packages = std::fs::read_dir(&*packages_path)?
-        .filter_map(Result::ok)
-        .filter(|x| {
-            let v = x.file_type().ok().map(|x| x.is_dir()).unwrap_or(false);
-            log::trace!("Attempting {:?} := {:?}", &x, &v);
-            v
-        })
-        .filter_map(|x| {
-            let path = x.path().join("index.toml");
-            log::trace!("Attempting read to string: {:?}", &path);
-            let file = match std::fs::read_to_string(&path) {
-                Ok(v) => v,
-                Err(e) => {
-                    log::error!("Could not handle path: {:?}", &path);
-                    log::error!("{}", e);
-                    log::error!("Continuing.");
-                    return None;
-                }
-            };
-            let package: pahkat_types::package::Package = match toml::from_str(&file) {
-                Ok(v) => v,
-                Err(e) => {
-                    log::error!("Could not parse: {:?}", &path);
-                    log::error!("{}", e);
-                    log::error!("Continuing.");
-                    return None;
-                }
-            };
-            Some(package)
-        })
-        .collect::<Vec<pahkat_types::package::Package>>();
+        // Attempt to make strings directory if it doesn't exist
+        let strings_path = request.path.join("strings");
+        std::fs::create_dir_all(&strings_path)?;
+    }
+
+    let inputs = if request.manifest && !request.check {
+        let inputs = hash_inputs(&request.path)?;
+        let index_hash = std::fs::read(&index_path)
+            .ok()
+            .map(|x| format!("{:x}", Sha256::digest(&x)));
+        let manifest = std::fs::read_to_string(&manifest_path)
+            .ok()
+            .and_then(|x| toml::from_str::<Manifest>(&x).ok());
+
+        if let Some(manifest) = manifest {
+            if manifest.generator == GENERATOR
+                && manifest.inputs == inputs
+                && Some(&manifest.index) == index_hash.as_ref()
+            {
+                log::info!("Inputs are unchanged; not rebuilding index");
+                return Ok(Status::Unchanged);
+            }
+        }
+
+        Some(inputs)
+    } else {
+        None
+    };
+
+    // Refuse to publish an index built from an invalid repository
+    let report = super::validate::validate(
+        super::validate::Request::builder()
+            .path(Cow::Borrowed(&*request.path))
+            .build(),
+    )?;
+
+    if !report.is_valid() {
+        for issue in report.issues.iter() {
+            log::error!("{}", issue);
+        }
+        anyhow::bail!(
+            "Repository failed validation with {} issue(s); not writing index",
+            report.issues.len()
+        );
+    }
+
+    let packages = report.packages;
 
     let mut builder = FlatBufferBuilder::new();
     let index = build_index(&mut builder, &packages)?;
 
-    std::fs::write(packages_path.join("index.bin"), index)?;
+    if request.check {
+        let existing = std::fs::read(&index_path).ok();
+        return Ok(if existing.as_deref() == Some(index) {
+            Status::UpToDate
+        } else {
+            Status::Stale
+        });
+    }
+
+    std::fs::write(&index_path, index)?;
     log::trace!("Finished writing index.bin");
 
-    Ok(())
+    if let Some(inputs) = inputs {
+        let manifest = Manifest {
+            generator: GENERATOR.to_string(),
+            index: format!("{:x}", Sha256::digest(index)),
+            inputs,
+        };
+        std::fs::write(&manifest_path, toml::to_string_pretty(&manifest)?)?;
+        log::trace!("Finished writing {}", MANIFEST_FILE);
+    }
+
+    Ok(Status::Written)
+}
+
+/// Hashes the files the index and its validation are built from: the
+/// repository index, every package descriptor and every strings file.
+fn hash_inputs(repo_path: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut paths = vec![PathBuf::from("index.toml")];
+
+    let mut dirs = std::fs::read_dir(repo_path.join("packages"))?
+        .filter_map(Result::ok)
+        .filter(|x| x.file_type().map(|x| x.is_dir()).unwrap_or(false))
+        .map(|x| Path::new("packages").join(x.file_name()).join("index.toml"))
+        .filter(|x| repo_path.join(x).is_file())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    paths.append(&mut dirs);
+
+    let mut strings = std::fs::read_dir(repo_path.join("strings"))?
+        .filter_map(Result::ok)
+        .map(|x| Path::new("strings").join(x.file_name()))
+        .filter(|x| x.extension().map(|x| x == "toml").unwrap_or(false))
+        .collect::<Vec<_>>();
+    strings.sort();
+    paths.append(&mut strings);
+
+    let mut inputs = BTreeMap::new();
+    for path in paths {
+        let data = std::fs::read(repo_path.join(&path))?;
+        let key = path
+            .iter()
+            .map(|x| x.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        inputs.insert(key, format!("{:x}", Sha256::digest(&data)));
+    }
+
+    Ok(inputs)
 }
 
 #[non_exhaustive]
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct Request<'a> {
     pub path: Cow<'a, Path>,
+    /// Skip the build if the inputs match the manifest, and write the manifest
+    /// after building
+    #[builder(default)]
+    pub manifest: bool,
+    /// Compare a fresh build against the existing `index.bin` without writing
+    #[builder(default)]
+    pub check: bool,
 }
 
 #[non_exhaustive]
@@ -65,18 +174,29 @@ pub struct Request<'a> {
 pub struct PartialRequest<'a> {
     #[builder(default)]
     pub path: Option<&'a Path>,
+    #[builder(default)]
+    pub manifest: bool,
+    #[builder(default)]
+    pub check: bool,
 }
 
 impl<'a> crate::Request for Request<'a> {
-    type Error = std::convert::Infallible;
+    type Error = InputError;
     type Partial = PartialRequest<'a>;
 
-    fn new_from_user_input(partial: Self::Partial) -> Result<Self, Self::Error> {
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let path = match partial.path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(match input.lookup("path")? {
+                Some(path) => path,
+                None => input::current_path()?,
+            }),
+        };
+
         Ok(Request {
-            path: partial
-                .path
-                .map(Cow::Borrowed)
-                .unwrap_or_else(|| Cow::Owned(std::env::current_dir().unwrap())),
+            path,
+            manifest: partial.manifest || input.lookup_flag("manifest")?,
+            check: partial.check || input.lookup_flag("check")?,
         })
     }
 }
@@ -124,6 +244,54 @@ fn vectorize_lang_map<'a, 'd>(
     (name_keys_ref, name_values_ref)
 }
 
+fn vectorize_dependencies<'a>(
+    dependencies: &pahkat_types::DependencyMap,
+    builder: &mut FlatBufferBuilder<'a>,
+) -> (
+    Option<fbs::WIPOffset<fbs::Vector<'a, fbs::ForwardsUOffset<&'a str>>>>,
+    Option<fbs::WIPOffset<fbs::Vector<'a, fbs::ForwardsUOffset<&'a str>>>>,
+) {
+    // TODO: cache keys
+    let (keys, values): (Vec<_>, Vec<_>) = dependencies
+        .iter()
+        .map(|(key, value)| {
+            (
+                builder.create_string(key.as_str()),
+                builder.create_string(&value),
+            )
+        })
+        .unzip();
+
+    if keys.is_empty() {
+        (None, None)
+    } else {
+        (
+            Some(vectorize_strings(keys, builder)),
+            Some(vectorize_strings(values, builder)),
+        )
+    }
+}
+
+fn vectorize_tags<'a, 'd>(
+    tags: &'d [String],
+    str_keys: &mut std::collections::HashMap<&'d str, fbs::WIPOffset<&'a str>>,
+    builder: &mut FlatBufferBuilder<'a>,
+) -> Option<fbs::WIPOffset<fbs::Vector<'a, fbs::ForwardsUOffset<&'a str>>>> {
+    if tags.is_empty() {
+        return None;
+    }
+
+    let tags = tags
+        .iter()
+        .map(|x| {
+            *str_keys
+                .entry(&**x)
+                .or_insert_with(|| builder.create_string(&*x))
+        })
+        .collect::<Vec<_>>();
+    Some(vectorize_strings(tags, builder))
+}
+
 fn create_payload_windows_exe<'a>(
     payload: &pahkat_types::payload::windows::Executable,
     builder: &mut FlatBufferBuilder<'a>,
@@ -245,25 +413,8 @@ fn create_targets<'d, 'a>(
         .map(|target| {
             let platform = builder.create_string(&target.platform);
 
-            // TODO: cache keys
-            let (dependencies_keys, dependencies_values): (Vec<_>, Vec<_>) = target
-                .dependencies
-                .iter()
-                .map(|(key, value)| {
-                    (
-                        builder.create_string(key.as_str()),
-                        builder.create_string(&value),
-                    )
-                })
-                .unzip();
-            let (dependencies_keys, dependencies_values) = if dependencies_keys.is_empty() {
-                (None, None)
-            } else {
-                (
-                    Some(vectorize_strings(dependencies_keys, builder)),
-                    Some(vectorize_strings(dependencies_values, builder)),
-                )
-            };
+            let (dependencies_keys, dependencies_values) =
+                vectorize_dependencies(&target.dependencies, builder);
 
             let arch = target.arch.as_ref().map(|x| builder.create_string(&x));
 
@@ -313,8 +464,16 @@ fn create_releases<'d, 'a>(
     str_keys: &mut std::collections::HashMap<&'d str, fbs::WIPOffset<&'a str>>,
     builder: &mut FlatBufferBuilder<'a>,
 ) -> fbs::WIPOffset<fbs::Vector<'a, fbs::ForwardsUOffset<crate::fbs::pahkat::Release<&'a [u8]>>>> {
+    // Releases are always stored greatest version first, regardless of descriptor order.
+    let mut releases = releases.iter().collect::<Vec<_>>();
+    releases.sort_by(|a, b| {
+        b.version
+            .partial_cmp(&a.version)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
     let releases = releases
-        .iter()
+        .into_iter()
         .map(|release| {
             // TODO: handle version type properly
             use pahkat_types::package::version::Version;
@@ -367,6 +526,7 @@ fn create_releases<'d, 'a>(
                 license,
                 license_url,
                 target,
+                yanked: release.yanked,
             };
 
             crate::fbs::pahkat::Release::create(builder, &args)
@@ -381,18 +541,202 @@ fn create_releases<'d, 'a>(
     builder.end_vector(len)
 }
 
+fn create_verifier<'a>(
+    verifier: &pahkat_types::synth::Verifier,
+    builder: &mut FlatBufferBuilder<'a>,
+) -> (
+    crate::fbs::pahkat::fbs_gen::VerifierType,
+    fbs::WIPOffset<fbs::UnionWIPOffset>,
+) {
+    use crate::fbs::pahkat::fbs_gen::VerifierType;
+    use pahkat_types::synth::Verifier;
+
+    match verifier {
+        Verifier::WindowsRegistryKey(v) => {
+            let args = crate::fbs::pahkat::WindowsRegistryKeyArgs {
+                path: builder.create_string(&v.path),
+                name: builder.create_string(&v.name),
+            };
+            (
+                VerifierType::WindowsRegistryKey,
+                crate::fbs::pahkat::WindowsRegistryKey::create(builder, &args).as_union_value(),
+            )
+        }
+        Verifier::MacOSPackageRef(v) => {
+            let pkg_id = builder.create_string(&v.pkg_id);
+            let min_version = v.min_version.as_ref().map(|x| builder.create_string(x));
+            let max_version = v.max_version.as_ref().map(|x| builder.create_string(x));
+            let min_build = v.min_build.as_ref().map(|x| builder.create_string(x));
+            let max_build = v.max_build.as_ref().map(|x| builder.create_string(x));
+
+            let args = crate::fbs::pahkat::MacOSPackageRefArgs {
+                pkg_id,
+                min_version,
+                max_version,
+                min_build,
+                max_build,
+            };
+            (
+                VerifierType::MacOSPackageRef,
+                crate::fbs::pahkat::MacOSPackageRef::create(builder, &args).as_union_value(),
+            )
+        }
+        Verifier::MacOSPathRef(v) => {
+            let app_paths = v
+                .app_paths
+                .iter()
+                .map(|x| builder.create_string(x))
+                .collect::<Vec<_>>();
+            let app_paths = if app_paths.is_empty() {
+                None
+            } else {
+                Some(vectorize_strings(app_paths, builder))
+            };
+            let min_version = v.min_version.as_ref().map(|x| builder.create_string(x));
+            let max_version = v.max_version.as_ref().map(|x| builder.create_string(x));
+            let min_build = v.min_build.as_ref().map(|x| builder.create_string(x));
+            let max_build = v.max_build.as_ref().map(|x| builder.create_string(x));
+
+            let args = crate::fbs::pahkat::MacOSPathRefArgs {
+                app_paths,
+                min_version,
+                max_version,
+                min_build,
+                max_build,
+            };
+            (
+                VerifierType::MacOSPathRef,
+                crate::fbs::pahkat::MacOSPathRef::create(builder, &args).as_union_value(),
+            )
+        }
+        _ => panic!("Verifier must exist"),
+    }
+}
+
+fn create_synthetic_targets<'a>(
+    targets: &[pahkat_types::synth::Target],
+    builder: &mut FlatBufferBuilder<'a>,
+) -> fbs::WIPOffset<
+    fbs::Vector<'a, fbs::ForwardsUOffset<crate::fbs::pahkat::SyntheticTarget<&'a [u8]>>>,
+> {
+    let targets = targets
+        .iter()
+        .map(|target| {
+            let platform = builder.create_string(&target.platform);
+            let arch = target.arch.as_ref().map(|x| builder.create_string(&x));
+            let (dependencies_keys, dependencies_values) =
+                vectorize_dependencies(&target.dependencies, builder);
+            let (verifier_type, verifier) = create_verifier(&target.verifier, builder);
+
+            let args = crate::fbs::pahkat::SyntheticTargetArgs {
+                platform,
+                arch,
+                dependencies_keys,
+                dependencies_values,
+                verifier_type,
+                verifier,
+            };
+
+            crate::fbs::pahkat::SyntheticTarget::create(builder, &args)
+        })
+        .collect::<Vec<_>>();
+
+    let len = targets.len();
+    builder
+        .start_vector::<fbs::ForwardsUOffset<crate::fbs::pahkat::SyntheticTarget<&'_ [u8]>>>(len);
+    for target in targets.into_iter().rev() {
+        builder.push(target);
+    }
+    builder.end_vector(len)
+}
+
+fn create_synthetic_releases<'d, 'a>(
+    releases: &'d [pahkat_types::synth::Release],
+    str_keys: &mut std::collections::HashMap<&'d str, fbs::WIPOffset<&'a str>>,
+    builder: &mut FlatBufferBuilder<'a>,
+) -> fbs::WIPOffset<
+    fbs::Vector<'a, fbs::ForwardsUOffset<crate::fbs::pahkat::SyntheticRelease<&'a [u8]>>>,
+> {
+    use pahkat_types::package::Version;
+
+    // Same ordering as concrete releases: greatest version first.
+    let mut releases = releases.iter().collect::<Vec<_>>();
+    releases.sort_by(|a, b| {
+        Version::new(&b.version)
+            .ok()
+            .partial_cmp(&Version::new(&a.version).ok())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let releases = releases
+        .into_iter()
+        .map(|release| {
+            let version = *str_keys
+                .entry(&*release.version)
+                .or_insert_with(|| builder.create_string(&*release.version));
+            let channel = *str_keys
+                .entry(&*release.channel)
+                .or_insert_with(|| builder.create_string(&*release.channel));
+            let target = Some(create_synthetic_targets(&release.targets, builder));
+
+            let args = crate::fbs::pahkat::SyntheticReleaseArgs {
+                version,
+                channel,
+                target,
+            };
+
+            crate::fbs::pahkat::SyntheticRelease::create(builder, &args)
+        })
+        .collect::<Vec<_>>();
+
+    let len = releases.len();
+    builder
+        .start_vector::<fbs::ForwardsUOffset<crate::fbs::pahkat::SyntheticRelease<&'_ [u8]>>>(len);
+    for release in releases.into_iter().rev() {
+        builder.push(release);
+    }
+    builder.end_vector(len)
+}
+
 fn build_index<'a>(
     builder: &'a mut FlatBufferBuilder<'a>,
     packages: &[pahkat_types::package::Package],
 ) -> anyhow::Result<&'a [u8]> {
+    use pahkat_types::package::Package;
+
     let mut owned_keys = std::collections::HashMap::new();
     let mut str_keys = std::collections::HashMap::new();
 
+    // Keys are sorted so identical inputs always produce an identical index
+    let mut descriptors = packages
+        .iter()
+        .filter_map(|x| match x {
+            Package::Concrete(v) => Some(v),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    descriptors.sort_by(|a, b| a.package.id.cmp(&b.package.id));
+    let mut synthetics = packages
+        .iter()
+        .filter_map(|x| match x {
+            Package::Synthetic(v) => Some(v),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    synthetics.sort_by(|a, b| a.synthetic.id.cmp(&b.synthetic.id));
+    let mut redirects = packages
+        .iter()
+        .filter_map(|x| match x {
+            Package::Redirect(v) => Some(v),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    redirects.sort_by(|a, b| a.redirect.id.cmp(&b.redirect.id));
+
     // Use the count to create the vectors we need
-    let id_refs = packages
+    let id_refs = descriptors
         .iter()
-        .map(pahkat_types::package::Package::id)
-        .map(|id| builder.create_string(id))
+        .map(|x| builder.create_string(&x.package.id))
         .collect::<Vec<_>>();
 
     builder.start_vector::<fbs::ForwardsUOffset<&'_ str>>(id_refs.len());
@@ -409,33 +753,9 @@ fn build_index<'a>(
 
     let packages_values = id_refs
         .iter()
-        .zip(packages.iter())
-        .map(|(id_ref, package)| {
-            let descriptor = match package {
-                pahkat_types::package::Package::Concrete(p) => p,
-                _ => panic!("Unsupported package type"),
-            };
-
-            let tags = if descriptor.package.tags.is_empty() {
-                None
-            } else {
-                let tags = descriptor
-                    .package
-                    .tags
-                    .iter()
-                    .map(|x| {
-                        *str_keys
-                            .entry(&**x)
-                            .or_insert_with(|| builder.create_string(&*x))
-                    })
-                    .collect::<Vec<_>>();
-                let len = tags.len();
-                builder.start_vector::<fbs::ForwardsUOffset<&'_ str>>(len);
-                for tag_ref in tags.into_iter().rev() {
-                    builder.push(tag_ref);
-                }
-                Some(builder.end_vector(len))
-            };
+        .zip(descriptors.iter())
+        .map(|(id_ref, descriptor)| {
+            let tags = vectorize_tags(&descriptor.package.tags, &mut str_keys, builder);
 
             let (name_keys, name_values) =
                 vectorize_lang_map(&descriptor.name, &mut str_keys, builder);
@@ -469,10 +789,85 @@ fn build_index<'a>(
         ),
     );
 
+    let (synthetics_keys, synthetics_values) = if synthetics.is_empty() {
+        (None, None)
+    } else {
+        let keys = synthetics
+            .iter()
+            .map(|x| builder.create_string(&x.synthetic.id))
+            .collect::<Vec<_>>();
+
+        let values = keys
+            .iter()
+            .zip(synthetics.iter())
+            .map(|(id_ref, synthetic)| {
+                let tags = vectorize_tags(&synthetic.synthetic.tags, &mut str_keys, builder);
+                let (name_keys, name_values) =
+                    vectorize_lang_map(&synthetic.name, &mut str_keys, builder);
+                let (description_keys, description_values) =
+                    vectorize_lang_map(&synthetic.description, &mut str_keys, builder);
+                let release =
+                    create_synthetic_releases(&synthetic.releases, &mut str_keys, builder);
+
+                let args = crate::fbs::pahkat::SyntheticArgs {
+                    id: id_ref.clone(),
+                    name_keys,
+                    name_values,
+                    description_keys,
+                    description_values,
+                    tags,
+                    release: Some(release),
+                };
+                crate::fbs::pahkat::Synthetic::create(builder, &args)
+            })
+            .collect::<Vec<_>>();
+
+        let len = values.len();
+        builder.start_vector::<fbs::ForwardsUOffset<crate::fbs::pahkat::Synthetic<&'_ [u8]>>>(len);
+        for value in values.into_iter().rev() {
+            builder.push(value);
+        }
+        let values = builder.end_vector(len);
+
+        (Some(vectorize_strings(keys, builder)), Some(values))
+    };
+
+    let (redirects_keys, redirects_values) = if redirects.is_empty() {
+        (None, None)
+    } else {
+        let keys = redirects
+            .iter()
+            .map(|x| builder.create_string(&x.redirect.id))
+            .collect::<Vec<_>>();
+
+        let values = redirects
+            .iter()
+            .map(|redirect| {
+                let args = crate::fbs::pahkat::RedirectArgs {
+                    url: builder.create_string(redirect.redirect.url.as_str()),
+                };
+                crate::fbs::pahkat::Redirect::create(builder, &args)
+            })
+            .collect::<Vec<_>>();
+
+        let len = values.len();
+        builder.start_vector::<fbs::ForwardsUOffset<crate::fbs::pahkat::Redirect<&'_ [u8]>>>(len);
+        for value in values.into_iter().rev() {
+            builder.push(value);
+        }
+        let values = builder.end_vector(len);
+
+        (Some(vectorize_strings(keys, builder)), Some(values))
+    };
+
     let args = crate::fbs::pahkat::PackagesArgs {
         packages_values_types,
         packages_keys,
         packages_values,
+        synthetics_keys,
+        synthetics_values,
+        redirects_keys,
+        redirects_values,
     };
 
     let root = crate::fbs::pahkat::Packages::create(builder, &args);
@@ -480,3 +875,115 @@ fn build_index<'a>(
     builder.finish_minimal(root);
     Ok(builder.finished_data())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+
+    fn request(repo: &Repo) -> Request<'_> {
+        Request::builder().path(Cow::Borrowed(repo.path())).build()
+    }
+
+    fn check(repo: &Repo) -> Status {
+        index(Request {
+            check: true,
+            ..request(repo)
+        })
+        .unwrap()
+    }
+
+    /// A repository whose descriptors carry several map fields, written in
+    /// the given package order.
+    fn repo(ids: &[&str]) -> Repo {
+        let repo = Repo::new();
+        for id in ids {
+            let pkg = descriptor(id, &[("1.0.0", None), ("1.1.0-beta.1", Some("beta"))])
+                .replace(
+                    "[name]\n",
+                    "[name]\nse = \"Sámi\"\nnb = \"Norsk\"\nfi = \"Suomi\"\n",
+                )
+                .replace(
+                    "platform = \"linux\"\n",
+                    "platform = \"linux\"\n\n[release.target.dependencies]\nzlib = \"*\"\nalpha = \"^1\"\nmid = \"1.0\"\n",
+                );
+            repo.package(id, &pkg);
+        }
+        for id in &["zlib", "alpha", "mid"] {
+            repo.package(id, &descriptor(id, &[("1.0.0", None)]));
+        }
+        repo
+    }
+
+    #[test]
+    fn builds_are_reproducible() {
+        let first = repo(&["one", "two", "three"]);
+        assert_eq!(index(request(&first)).unwrap(), Status::Written);
+        let built = std::fs::read(first.path().join("packages/index.bin")).unwrap();
+
+        assert_eq!(index(request(&first)).unwrap(), Status::Written);
+        assert_eq!(
+            std::fs::read(first.path().join("packages/index.bin")).unwrap(),
+            built
+        );
+
+        // Directory listing order must not leak into the index either
+        let second = repo(&["three", "one", "two"]);
+        assert_eq!(index(request(&second)).unwrap(), Status::Written);
+        assert_eq!(
+            std::fs::read(second.path().join("packages/index.bin")).unwrap(),
+            built
+        );
+    }
+
+    #[test]
+    fn check_detects_stale_index() {
+        let repo = repo(&["one"]);
+        assert_eq!(check(&repo), Status::Stale);
+        assert!(!repo.path().join("packages/index.bin").exists());
+
+        index(request(&repo)).unwrap();
+        assert_eq!(check(&repo), Status::UpToDate);
+
+        repo.package("one", &descriptor("one", &[("2.0.0", None)]));
+        let before = std::fs::read(repo.path().join("packages/index.bin")).unwrap();
+        assert_eq!(check(&repo), Status::Stale);
+        assert_eq!(
+            std::fs::read(repo.path().join("packages/index.bin")).unwrap(),
+            before
+        );
+
+        index(request(&repo)).unwrap();
+        assert_eq!(check(&repo), Status::UpToDate);
+    }
+
+    #[test]
+    fn manifest_skips_unchanged_inputs() {
+        let repo = repo(&["one"]);
+        let request = || Request {
+            manifest: true,
+            ..request(&repo)
+        };
+
+        assert_eq!(index(request()).unwrap(), Status::Written);
+        assert!(repo.path().join("packages").join(MANIFEST_FILE).is_file());
+        assert_eq!(index(request()).unwrap(), Status::Unchanged);
+
+        repo.write("strings/se.toml", "[tags]\n");
+        assert_eq!(index(request()).unwrap(), Status::Written);
+        assert_eq!(index(request()).unwrap(), Status::Unchanged);
+
+        // A tampered index is rebuilt even if the inputs are unchanged
+        repo.write("packages/index.bin", "");
+        assert_eq!(index(request()).unwrap(), Status::Written);
+    }
+
+    #[test]
+    fn refuses_invalid_repository() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("other", &[("1.0.0", None)]));
+
+        assert!(index(request(&repo)).is_err());
+        assert!(!repo.path().join("packages/index.bin").exists());
+    }
+}
@@ -12,6 +12,8 @@ use pahkat_types::{
     LangTagMap,
 };
 
+use crate::input::{self, Input, InputError};
+
 #[non_exhaustive]
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct Request<'a> {
@@ -47,28 +49,24 @@ pub enum RequestError {
 
     #[error("Repository URL was not valid")]
     InvalidRepoUrl(#[from] RepoUrlError),
+
+    #[error(transparent)]
+    Input(#[from] InputError),
 }
 
 impl<'a> crate::Request for Request<'a> {
     type Error = RequestError;
     type Partial = PartialRequest<'a>;
 
-    fn new_from_user_input(partial: Self::Partial) -> Result<Self, Self::Error> {
-        use dialoguer::Input;
-
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
         let path = match partial.path {
             Some(path) => Cow::Borrowed(path),
-            None => Input::<String>::new()
-                .default(
-                    std::env::current_dir()
-                        .ok()
-                        .and_then(|x| x.to_str().map(str::to_string))
-                        .unwrap_or_else(|| ".".into()),
-                )
-                .with_prompt("Path")
-                .interact()
-                .map(|p| Cow::Owned(PathBuf::from(p)))
-                .map_err(RequestError::PathError)?,
+            None => Cow::Owned(
+                input
+                    .field("path", "Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
         };
 
         let url = match partial.url {
@@ -77,12 +75,10 @@ impl<'a> crate::Request for Request<'a> {
                 Cow::Owned(url)
             }
             None => {
-                let url = Input::<String>::new()
-                    .with_prompt("Base URL")
-                    .with_initial_text("https://")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?;
-                let url = Url::parse(&url)?;
+                let url = input
+                    .field("url", "Base URL")
+                    .initial_text("https://")
+                    .required::<Url>()?;
                 let url = RepoUrl::new(url)?;
                 Cow::Owned(url)
             }
@@ -91,20 +87,18 @@ impl<'a> crate::Request for Request<'a> {
         let name = match partial.name {
             Some(name) => Cow::Borrowed(name),
             None => Cow::Owned(
-                Input::<String>::new()
-                    .with_prompt("Repo name (in English)")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?,
+                input
+                    .field("name", "Repo name (in English)")
+                    .required::<String>()?,
             ),
         };
 
         let description = match partial.description {
             Some(description) => Cow::Borrowed(description),
             None => Cow::Owned(
-                Input::<String>::new()
-                    .with_prompt("Repo description (in English)")
-                    .interact()
-                    .map_err(|_| RequestError::InvalidInput)?,
+                input
+                    .field("description", "Repo description (in English)")
+                    .required::<String>()?,
             ),
         };
 
@@ -1,3 +1,5 @@
+pub mod diff;
 pub mod indexing;
 pub mod init;
+pub mod render;
 pub mod validate;
@@ -0,0 +1,579 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use pahkat_types::package::{Descriptor, Package, Version};
+use pahkat_types::repo::{Index, Localisation, Repository};
+use pahkat_types::LangTagMap;
+use serde::Serialize;
+use typed_builder::TypedBuilder;
+
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub path: Cow<'a, Path>,
+    pub output_path: Cow<'a, Path>,
+    /// Language pages are rendered in, falling back to any available string
+    pub language: Cow<'a, str>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub path: Option<&'a Path>,
+    #[builder(default)]
+    pub output_path: Option<&'a Path>,
+    #[builder(default)]
+    pub language: Option<&'a str>,
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = InputError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let path = match partial.path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(match input.lookup("path")? {
+                Some(path) => path,
+                None => input::current_path()?,
+            }),
+        };
+        let output_path = match partial.output_path {
+            Some(output_path) => Cow::Borrowed(output_path),
+            None => Cow::Owned(match input.lookup("output_path")? {
+                Some(output_path) => output_path,
+                None => path.join("site"),
+            }),
+        };
+        let language = match partial.language {
+            Some(language) => Cow::Borrowed(language),
+            None => Cow::Owned(
+                input
+                    .lookup("language")?
+                    .unwrap_or_else(|| "en".to_string()),
+            ),
+        };
+
+        Ok(Request {
+            path,
+            output_path,
+            language,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Failed to validate repository")]
+    Validate(#[from] super::validate::Error),
+
+    #[error("Failed to create directory `{0}`")]
+    DirCreateFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to write `{0}`")]
+    WriteFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to serialize JSON")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The JSON dump written to `packages.json`.
+#[derive(Debug, Serialize)]
+struct Dump<'a> {
+    repository: &'a Index,
+    packages: &'a [Package],
+}
+
+/// Renders a static catalogue of the repository: an `index.html` listing every
+/// package, a page per package under `packages/`, and `packages.json` holding
+/// the repository index and every descriptor.
+pub fn render(request: Request<'_>) -> Result<(), Error> {
+    log::debug!("{:?}", request);
+
+    let index_path = request.path.join("index.toml");
+    let file =
+        fs::read_to_string(&index_path).map_err(|e| Error::ReadFailed(index_path.clone(), e))?;
+    let repo =
+        match toml::from_str::<Repository>(&file).map_err(|e| Error::ReadToml(index_path, e))? {
+            Repository::Index(v) => v,
+            _ => return Err(super::validate::Error::Redirect(request.path.to_path_buf()).into()),
+        };
+
+    // Render what can be read, as a catalogue is still useful for a repository
+    // with lint issues
+    let report = super::validate::validate(
+        super::validate::Request::builder()
+            .path(Cow::Borrowed(&*request.path))
+            .build(),
+    )?;
+    for issue in report.issues.iter() {
+        log::warn!("{}", issue);
+    }
+
+    let strings = load_strings(&request.path, &request.language);
+    let page = Page {
+        language: &request.language,
+        strings: &strings,
+    };
+
+    let packages_dir = request.output_path.join("packages");
+    fs::create_dir_all(&packages_dir)
+        .map_err(|e| Error::DirCreateFailed(packages_dir.clone(), e))?;
+
+    write(
+        &request.output_path.join("index.html"),
+        page.index(&repo, &report.packages),
+    )?;
+
+    for package in report.packages.iter() {
+        if !is_safe_id(package.id()) {
+            log::warn!(
+                "Not rendering a page for unsafe package id `{}`",
+                package.id()
+            );
+            continue;
+        }
+
+        let html = match package {
+            Package::Concrete(x) => page.concrete(x),
+            Package::Synthetic(x) => page.synthetic(x),
+            _ => continue,
+        };
+        write(&packages_dir.join(format!("{}.html", package.id())), html)?;
+    }
+
+    let json = serde_json::to_string_pretty(&Dump {
+        repository: &repo,
+        packages: &report.packages,
+    })?;
+    write(&request.output_path.join("packages.json"), json)?;
+
+    log::info!("Rendered site to {}", request.output_path.display());
+    Ok(())
+}
+
+/// Whether an id can be used as a file name under `packages/` without
+/// escaping it.
+fn is_safe_id(id: &str) -> bool {
+    !id.is_empty()
+        && id != "."
+        && id != ".."
+        && !id
+            .chars()
+            .any(|c| matches!(c, '/' | '\\') || c.is_control())
+}
+
+fn write(path: &Path, data: String) -> Result<(), Error> {
+    fs::write(path, data).map_err(|e| Error::WriteFailed(path.to_path_buf(), e))
+}
+
+/// Tag and channel names are a nicety; missing or broken strings fall back to raw names.
+fn load_strings(repo_path: &Path, language: &str) -> Localisation {
+    let path = repo_path.join("strings").join(format!("{}.toml", language));
+    fs::read_to_string(&path)
+        .ok()
+        .and_then(|x| toml::from_str(&x).ok())
+        .unwrap_or_default()
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:60em;margin:2em auto;padding:0 1em}\
+table{border-collapse:collapse;width:100%}\
+th,td{text-align:left;padding:.3em .6em;border-bottom:1px solid #ddd}\
+.tag{display:inline-block;background:#eee;border-radius:3px;padding:0 .4em;margin-right:.3em}";
+
+struct Page<'a> {
+    language: &'a str,
+    strings: &'a Localisation,
+}
+
+impl Page<'_> {
+    /// Picks the string for the page language, or any other if missing.
+    fn localized<'m>(&self, map: &'m LangTagMap<String>) -> Option<&'m str> {
+        map.get(self.language)
+            .or_else(|| map.values().next())
+            .map(|x| &**x)
+    }
+
+    fn tag(&self, tag: &str) -> String {
+        let name = self.strings.tags.get(tag).map(|x| &**x).unwrap_or(tag);
+        format!("<span class=\"tag\">{}</span>", escape(name))
+    }
+
+    fn channel<'c>(&'c self, channel: Option<&'c str>) -> &'c str {
+        match channel {
+            Some(channel) => self
+                .strings
+                .channels
+                .get(channel)
+                .map(|x| &**x)
+                .unwrap_or(channel),
+            None => "stable",
+        }
+    }
+
+    fn layout(&self, title: &str, body: &str) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            escape(self.language),
+            escape(title),
+            STYLE,
+            body
+        )
+    }
+
+    fn index(&self, repo: &Index, packages: &[Package]) -> String {
+        let title = self
+            .localized(&repo.name)
+            .unwrap_or_else(|| repo.repository.url.as_str());
+        let mut body = String::new();
+
+        writeln!(body, "<h1>{}</h1>", escape(title)).unwrap();
+        if let Some(description) = self.localized(&repo.description) {
+            writeln!(body, "<p>{}</p>", escape(description)).unwrap();
+        }
+        writeln!(
+            body,
+            "<p>Repository URL: <code>{}</code></p>",
+            escape(repo.repository.url.as_str())
+        )
+        .unwrap();
+
+        writeln!(
+            body,
+            "<table>\n<tr><th>Package</th><th>Latest</th><th>Tags</th></tr>"
+        )
+        .unwrap();
+        for package in packages.iter() {
+            let (name, latest, tags) = match package {
+                Package::Concrete(x) => (
+                    self.localized(&x.name),
+                    x.release.iter().max().map(|x| x.version.to_string()),
+                    &x.package.tags,
+                ),
+                Package::Synthetic(x) => (
+                    self.localized(&x.name),
+                    x.releases
+                        .iter()
+                        .filter_map(|x| Version::new(&x.version).ok())
+                        .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                        .map(|x| x.to_string()),
+                    &x.synthetic.tags,
+                ),
+                Package::Redirect(x) => {
+                    writeln!(
+                        body,
+                        "<tr><td>{}</td><td colspan=\"2\">Moved to <code>{}</code></td></tr>",
+                        escape(&x.redirect.id),
+                        escape(x.redirect.url.as_str())
+                    )
+                    .unwrap();
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let name = escape(name.unwrap_or(package.id()));
+            let name = if is_safe_id(package.id()) {
+                format!(
+                    "<a href=\"packages/{}.html\">{}</a>",
+                    escape(package.id()),
+                    name
+                )
+            } else {
+                name
+            };
+
+            writeln!(
+                body,
+                "<tr><td>{name}</td><td>{latest}</td><td>{tags}</td></tr>",
+                name = name,
+                latest = escape(latest.as_deref().unwrap_or("")),
+                tags = tags.iter().map(|x| self.tag(x)).collect::<String>(),
+            )
+            .unwrap();
+        }
+        writeln!(body, "</table>").unwrap();
+
+        self.layout(title, &body)
+    }
+
+    fn header(
+        &self,
+        body: &mut String,
+        id: &str,
+        name: &LangTagMap<String>,
+        description: &LangTagMap<String>,
+        tags: &[String],
+    ) -> String {
+        let title = self.localized(name).unwrap_or(id).to_string();
+
+        writeln!(
+            body,
+            "<p><a href=\"../index.html\">&larr; All packages</a></p>"
+        )
+        .unwrap();
+        writeln!(body, "<h1>{}</h1>", escape(&title)).unwrap();
+        writeln!(body, "<p><code>{}</code></p>", escape(id)).unwrap();
+        if let Some(description) = self.localized(description) {
+            writeln!(body, "<p>{}</p>", escape(description)).unwrap();
+        }
+        if !tags.is_empty() {
+            let tags = tags.iter().map(|x| self.tag(x)).collect::<String>();
+            writeln!(body, "<p>{}</p>", tags).unwrap();
+        }
+
+        // Every translation, so one page serves all languages
+        if name.len() > 1 || description.len() > 1 {
+            writeln!(body, "<h2>Translations</h2>\n<table>").unwrap();
+            writeln!(
+                body,
+                "<tr><th>Language</th><th>Name</th><th>Description</th></tr>"
+            )
+            .unwrap();
+            let languages = name
+                .keys()
+                .chain(description.keys())
+                .collect::<std::collections::BTreeSet<_>>();
+            for language in languages {
+                writeln!(
+                    body,
+                    "<tr><td>{}</td><td lang=\"{0}\">{}</td><td lang=\"{0}\">{}</td></tr>",
+                    escape(language),
+                    escape(name.get(language).map(|x| &**x).unwrap_or("")),
+                    escape(description.get(language).map(|x| &**x).unwrap_or("")),
+                )
+                .unwrap();
+            }
+            writeln!(body, "</table>").unwrap();
+        }
+
+        title
+    }
+
+    fn concrete(&self, descriptor: &Descriptor) -> String {
+        let mut body = String::new();
+        let title = self.header(
+            &mut body,
+            &descriptor.package.id,
+            &descriptor.name,
+            &descriptor.description,
+            &descriptor.package.tags,
+        );
+
+        writeln!(body, "<h2>Releases</h2>\n<table>").unwrap();
+        writeln!(
+            body,
+            "<tr><th>Version</th><th>Channel</th><th>Platform</th><th>Download</th><th>Size</th></tr>"
+        )
+        .unwrap();
+
+        let mut releases = descriptor.release.iter().collect::<Vec<_>>();
+        releases.sort_by(|a, b| b.cmp(a));
+
+        for release in releases {
+            for target in release.target.iter() {
+                let platform = match target.arch.as_deref() {
+                    Some(arch) => format!("{} ({})", target.platform, arch),
+                    None => target.platform.clone(),
+                };
+                let url = target.payload.url();
+                let file_name = url
+                    .path_segments()
+                    .and_then(|x| x.last())
+                    .filter(|x| !x.is_empty())
+                    .unwrap_or(url.as_str());
+
+                writeln!(
+                    body,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td><a href=\"{}\">{}</a></td><td>{}</td></tr>",
+                    escape(&release.version.to_string()),
+                    escape(self.channel(release.channel.as_deref())),
+                    escape(&platform),
+                    escape(url.as_str()),
+                    escape(file_name),
+                    format_size(target.payload.size()),
+                )
+                .unwrap();
+            }
+        }
+        writeln!(body, "</table>").unwrap();
+
+        self.layout(&title, &body)
+    }
+
+    fn synthetic(&self, descriptor: &pahkat_types::synth::Descriptor) -> String {
+        let mut body = String::new();
+        let title = self.header(
+            &mut body,
+            &descriptor.synthetic.id,
+            &descriptor.name,
+            &descriptor.description,
+            &descriptor.synthetic.tags,
+        );
+
+        writeln!(
+            body,
+            "<p>This package is installed by other means; the repository only detects it.</p>"
+        )
+        .unwrap();
+        writeln!(body, "<h2>Releases</h2>\n<table>").unwrap();
+        writeln!(
+            body,
+            "<tr><th>Version</th><th>Channel</th><th>Platform</th></tr>"
+        )
+        .unwrap();
+
+        for release in descriptor.releases.iter() {
+            let channel = Some(&*release.channel).filter(|x| !x.is_empty());
+            for target in release.targets.iter() {
+                let platform = match target.arch.as_deref() {
+                    Some(arch) => format!("{} ({})", target.platform, arch),
+                    None => target.platform.clone(),
+                };
+
+                writeln!(
+                    body,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape(&release.version),
+                    escape(self.channel(channel)),
+                    escape(&platform),
+                )
+                .unwrap();
+            }
+        }
+        writeln!(body, "</table>").unwrap();
+
+        self.layout(&title, &body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+
+    const SYNTHETIC: &str = r#"
+[synthetic]
+id = "syn"
+
+[[releases]]
+version = "1.0.0"
+channel = ""
+targets = []
+
+[[releases]]
+version = "2.0.0"
+channel = ""
+targets = []
+
+[[releases]]
+version = "1.5.0"
+channel = ""
+targets = []
+"#;
+
+    fn render_repo(repo: &Repo) -> PathBuf {
+        let output_path = repo.path().join("site");
+        let request = Request::builder()
+            .path(Cow::Borrowed(repo.path()))
+            .output_path(Cow::Borrowed(&output_path))
+            .language(Cow::Borrowed("en"))
+            .build();
+        render(request).unwrap();
+        output_path
+    }
+
+    #[test]
+    fn safe_ids() {
+        assert!(is_safe_id("app"));
+        assert!(is_safe_id("app.v2"));
+        assert!(!is_safe_id(""));
+        assert!(!is_safe_id("."));
+        assert!(!is_safe_id(".."));
+        assert!(!is_safe_id("../app"));
+        assert!(!is_safe_id("a\\b"));
+        assert!(!is_safe_id("a\0b"));
+    }
+
+    #[test]
+    fn renders_pages() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None), ("1.1.0", None)]),
+        );
+        repo.package("syn", SYNTHETIC);
+
+        let site = render_repo(&repo);
+        let index = fs::read_to_string(site.join("index.html")).unwrap();
+
+        assert!(index.contains("<a href=\"packages/app.html\">app</a></td><td>1.1.0</td>"));
+        assert!(index.contains("<a href=\"packages/syn.html\">syn</a></td><td>2.0.0</td>"));
+        assert!(site.join("packages/app.html").is_file());
+        assert!(site.join("packages/syn.html").is_file());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(site.join("packages.json")).unwrap()).unwrap();
+        assert_eq!(json["packages"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn skips_pages_for_unsafe_ids() {
+        let repo = Repo::new();
+        repo.package("evil", &descriptor("../evil", &[("1.0.0", None)]));
+
+        let site = render_repo(&repo);
+        let index = fs::read_to_string(site.join("index.html")).unwrap();
+
+        assert!(!site.join("evil.html").exists());
+        assert!(!site.join("packages").join("..").join("evil.html").exists());
+        assert!(index.contains("<tr><td>../evil</td><td>1.0.0</td>"));
+        assert!(!index.contains("href=\"packages/../evil.html\""));
+    }
+}
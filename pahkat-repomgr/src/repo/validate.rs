@@ -1 +1,732 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
+use typed_builder::TypedBuilder;
+
+use pahkat_types::package::{Package, Version};
+use pahkat_types::repo::{Localisation, Repository};
+use pahkat_types::{DependencyKey, DependencyMap, LangTagMap, PackageKey};
+
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub path: Cow<'a, Path>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub path: Option<&'a Path>,
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = InputError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let path = match partial.path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(match input.lookup("path")? {
+                Some(path) => path,
+                None => input::current_path()?,
+            }),
+        };
+
+        Ok(Request { path })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Repository at `{0}` is a redirect and cannot be validated")]
+    Redirect(PathBuf),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IssueKind {
+    #[error("Could not read file")]
+    ReadFailed(#[source] io::Error),
+
+    #[error("Could not parse descriptor")]
+    InvalidDescriptor(#[source] toml::de::Error),
+
+    #[error("Package id `{id}` does not match directory name `{dir}`")]
+    IdMismatch { id: String, dir: String },
+
+    #[error("Invalid version `{0}`")]
+    InvalidVersion(String),
+
+    #[error("Channel `{channel}` of version {version} is not listed in the repository channels")]
+    UnknownChannel { version: String, channel: String },
+
+    #[error("Dependency `{0}` does not match any package in this repository")]
+    UnresolvedDependency(String),
+
+    #[error("Dependency `{0}` is not a valid package key")]
+    InvalidDependency(String, #[source] pahkat_types::package_key::TryFromError),
+
+    #[error("Redirect `{0}` is not a valid package key")]
+    InvalidRedirect(String, #[source] pahkat_types::package_key::TryFromError),
+
+    #[error("`{0}` is not a valid BCP 47 language tag")]
+    InvalidLangTag(String),
+
+    #[error("Could not parse strings file")]
+    InvalidStrings(#[source] toml::de::Error),
+
+    #[error("Tag `{tag}` has no translation for language `{language}`")]
+    MissingTranslation { tag: String, language: String },
+
+    #[error("License `{license}` of version {version} is not a valid SPDX expression")]
+    InvalidLicense { version: String, license: String },
+
+    #[error("Version {version} in channel {channel} has no targets")]
+    NoTargets { version: String, channel: String },
+
+    #[error("Duplicate target for version {version}, channel {channel}, platform {platform}, arch {arch}")]
+    DuplicateTarget {
+        version: String,
+        channel: String,
+        platform: String,
+        arch: String,
+    },
+}
+
+/// A single problem found in a repository, and the file it was found in.
+#[derive(Debug)]
+pub struct Issue {
+    pub path: PathBuf,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.kind)?;
+
+        let mut source = std::error::Error::source(&self.kind);
+        while let Some(e) = source {
+            write!(f, ": {}", e)?;
+            source = e.source();
+        }
+
+        Ok(())
+    }
+}
+
+/// The outcome of validating a repository.
+///
+/// `packages` holds every descriptor that could be parsed, in directory name order.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub packages: Vec<Package>,
+    pub issues: Vec<Issue>,
+}
+
+impl Report {
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+pub fn validate(request: Request<'_>) -> Result<Report, Error> {
+    let index_path = request.path.join("index.toml");
+    let file =
+        fs::read_to_string(&index_path).map_err(|e| Error::ReadFailed(index_path.clone(), e))?;
+    let repo: Repository =
+        toml::from_str(&file).map_err(|e| Error::ReadToml(index_path.clone(), e))?;
+    let repo = match repo {
+        Repository::Index(v) => v,
+        _ => return Err(Error::Redirect(request.path.to_path_buf())),
+    };
+
+    let mut report = Report::default();
+    let mut issues = Issues {
+        path: &index_path,
+        issues: &mut report.issues,
+    };
+    check_lang_tags(&mut issues, &repo.name);
+    check_lang_tags(&mut issues, &repo.description);
+
+    let packages_path = request.path.join("packages");
+    let mut dirs = fs::read_dir(&packages_path)
+        .map_err(|e| Error::ReadFailed(packages_path.clone(), e))?
+        .filter_map(Result::ok)
+        .filter(|x| x.file_type().ok().map(|x| x.is_dir()).unwrap_or(false))
+        .map(|x| x.path())
+        .collect::<Vec<_>>();
+    dirs.sort();
+
+    let mut paths = vec![];
+    for dir in dirs {
+        let path = dir.join("index.toml");
+        let dir_name = dir
+            .file_name()
+            .map(|x| x.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let package = match parse_descriptor(&path) {
+            Ok(v) => v,
+            Err(kind) => {
+                report.issues.push(Issue { path, kind });
+                continue;
+            }
+        };
+
+        if package.id() != dir_name {
+            report.issues.push(Issue {
+                path: path.clone(),
+                kind: IssueKind::IdMismatch {
+                    id: package.id().to_string(),
+                    dir: dir_name,
+                },
+            });
+        }
+
+        paths.push(path);
+        report.packages.push(package);
+    }
+
+    let ids = report
+        .packages
+        .iter()
+        .map(Package::id)
+        .collect::<HashSet<_>>();
+    let channels = repo
+        .repository
+        .channels
+        .iter()
+        .map(|x| &**x)
+        .collect::<HashSet<_>>();
+
+    for (path, package) in paths.iter().zip(report.packages.iter()) {
+        let mut issues = Issues {
+            path,
+            issues: &mut report.issues,
+        };
+
+        match package {
+            Package::Concrete(descriptor) => {
+                check_lang_tags(&mut issues, &descriptor.name);
+                check_lang_tags(&mut issues, &descriptor.description);
+
+                let mut seen = HashSet::new();
+                for release in descriptor.release.iter() {
+                    let version = release.version.to_string();
+                    check_channel(&mut issues, &channels, &version, release.channel.as_deref());
+
+                    if let Some(license) = release.license.as_ref() {
+                        if spdx::Expression::parse(license).is_err() {
+                            issues.push(IssueKind::InvalidLicense {
+                                version: version.clone(),
+                                license: license.clone(),
+                            });
+                        }
+                    }
+
+                    if release.target.is_empty() {
+                        issues.push(IssueKind::NoTargets {
+                            version: version.clone(),
+                            channel: release.channel.as_deref().unwrap_or("stable").to_string(),
+                        });
+                    }
+
+                    for target in release.target.iter() {
+                        check_dependencies(&mut issues, &ids, &target.dependencies);
+                        check_duplicate(
+                            &mut issues,
+                            &mut seen,
+                            &version,
+                            release.channel.as_deref(),
+                            &target.platform,
+                            target.arch.as_deref(),
+                        );
+                    }
+                }
+            }
+            Package::Synthetic(descriptor) => {
+                check_lang_tags(&mut issues, &descriptor.name);
+                check_lang_tags(&mut issues, &descriptor.description);
+
+                let mut seen = HashSet::new();
+                for release in descriptor.releases.iter() {
+                    if Version::new(&release.version).is_err() {
+                        issues.push(IssueKind::InvalidVersion(release.version.clone()));
+                    }
+                    check_channel(
+                        &mut issues,
+                        &channels,
+                        &release.version,
+                        Some(&release.channel),
+                    );
+
+                    if release.targets.is_empty() {
+                        issues.push(IssueKind::NoTargets {
+                            version: release.version.clone(),
+                            channel: release.channel.clone(),
+                        });
+                    }
+
+                    for target in release.targets.iter() {
+                        check_dependencies(&mut issues, &ids, &target.dependencies);
+                        check_duplicate(
+                            &mut issues,
+                            &mut seen,
+                            &release.version,
+                            Some(&release.channel),
+                            &target.platform,
+                            target.arch.as_deref(),
+                        );
+                    }
+                }
+            }
+            Package::Redirect(redirect) => {
+                let url = &redirect.redirect.url;
+                if let Err(e) = PackageKey::try_from(url) {
+                    issues.push(IssueKind::InvalidRedirect(url.to_string(), e));
+                }
+            }
+        }
+    }
+
+    let tags = report
+        .packages
+        .iter()
+        .filter_map(|package| match package {
+            Package::Concrete(x) => Some(&x.package.tags),
+            Package::Synthetic(x) => Some(&x.synthetic.tags),
+            _ => None,
+        })
+        .flatten()
+        .map(|x| &**x)
+        .collect::<BTreeSet<_>>();
+
+    for language in repo.repository.languages.iter() {
+        check_translations(
+            &mut report.issues,
+            &request.path,
+            &index_path,
+            language,
+            &tags,
+        );
+    }
+
+    Ok(report)
+}
+
+struct Issues<'a> {
+    path: &'a Path,
+    issues: &'a mut Vec<Issue>,
+}
+
+impl Issues<'_> {
+    fn push(&mut self, kind: IssueKind) {
+        self.issues.push(Issue {
+            path: self.path.to_path_buf(),
+            kind,
+        });
+    }
+}
+
+fn parse_descriptor(path: &Path) -> Result<Package, IssueKind> {
+    let file = fs::read_to_string(path).map_err(IssueKind::ReadFailed)?;
+
+    // Check versions first, as an invalid version otherwise only surfaces as an
+    // opaque deserialization failure for the entire descriptor.
+    if let Ok(value) = toml::from_str::<toml::Value>(&file) {
+        let versions = value
+            .get("release")
+            .and_then(toml::Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.get("version").and_then(toml::Value::as_str));
+
+        for version in versions {
+            if Version::new(version).is_err() {
+                return Err(IssueKind::InvalidVersion(version.to_string()));
+            }
+        }
+    }
+
+    toml::from_str(&file).map_err(IssueKind::InvalidDescriptor)
+}
+
+fn check_lang_tags<T>(issues: &mut Issues<'_>, map: &LangTagMap<T>) {
+    for key in map.keys() {
+        if key.parse::<language_tags::LanguageTag>().is_err() {
+            issues.push(IssueKind::InvalidLangTag(key.to_string()));
+        }
+    }
+}
+
+/// Checks that every used tag is translated into a declared language.
+fn check_translations(
+    issues: &mut Vec<Issue>,
+    repo_path: &Path,
+    index_path: &Path,
+    language: &str,
+    tags: &BTreeSet<&str>,
+) {
+    if language.parse::<language_tags::LanguageTag>().is_err() {
+        issues.push(Issue {
+            path: index_path.to_path_buf(),
+            kind: IssueKind::InvalidLangTag(language.to_string()),
+        });
+        return;
+    }
+
+    let path = repo_path.join("strings").join(format!("{}.toml", language));
+    let mut issues = Issues {
+        path: &path,
+        issues,
+    };
+
+    let strings = match fs::read_to_string(&path) {
+        Ok(file) => match toml::from_str::<Localisation>(&file) {
+            Ok(v) => v,
+            Err(e) => {
+                issues.push(IssueKind::InvalidStrings(e));
+                return;
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Localisation::default(),
+        Err(e) => {
+            issues.push(IssueKind::ReadFailed(e));
+            return;
+        }
+    };
+
+    for tag in tags.iter().filter(|x| !strings.tags.contains_key(**x)) {
+        issues.push(IssueKind::MissingTranslation {
+            tag: tag.to_string(),
+            language: language.to_string(),
+        });
+    }
+}
+
+fn check_channel(
+    issues: &mut Issues<'_>,
+    channels: &HashSet<&str>,
+    version: &str,
+    channel: Option<&str>,
+) {
+    if let Some(channel) = channel {
+        if !channels.contains(channel) {
+            issues.push(IssueKind::UnknownChannel {
+                version: version.to_string(),
+                channel: channel.to_string(),
+            });
+        }
+    }
+}
+
+fn check_dependencies(issues: &mut Issues<'_>, ids: &HashSet<&str>, dependencies: &DependencyMap) {
+    for key in dependencies.keys() {
+        match key {
+            DependencyKey::Local(id) => {
+                if !ids.contains(&**id) {
+                    issues.push(IssueKind::UnresolvedDependency(id.to_string()));
+                }
+            }
+            DependencyKey::Remote(url) => {
+                if let Err(e) = PackageKey::try_from(url) {
+                    issues.push(IssueKind::InvalidDependency(url.to_string(), e));
+                }
+            }
+        }
+    }
+}
+
+type TargetKey = (String, Option<String>, String, Option<String>);
+
+fn check_duplicate(
+    issues: &mut Issues<'_>,
+    seen: &mut HashSet<TargetKey>,
+    version: &str,
+    channel: Option<&str>,
+    platform: &str,
+    arch: Option<&str>,
+) {
+    let key = (
+        version.to_string(),
+        channel.map(str::to_string),
+        platform.to_string(),
+        arch.map(str::to_string),
+    );
+
+    if !seen.insert(key) {
+        issues.push(IssueKind::DuplicateTarget {
+            version: version.to_string(),
+            channel: channel.unwrap_or("stable").to_string(),
+            platform: platform.to_string(),
+            arch: arch.unwrap_or("any").to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{descriptor, Repo};
+
+    fn issues(repo: &Repo) -> Vec<IssueKind> {
+        let request = Request::builder().path(Cow::Borrowed(repo.path())).build();
+        validate(request)
+            .unwrap()
+            .issues
+            .into_iter()
+            .map(|x| x.kind)
+            .collect()
+    }
+
+    /// A valid descriptor for `id`, with `extra` appended to its only target.
+    fn with_target(id: &str, extra: &str) -> String {
+        format!("{}{}", descriptor(id, &[("1.0.0", None)]), extra)
+    }
+
+    #[test]
+    fn valid_repository() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None), ("1.1.0", Some("beta"))]),
+        );
+        repo.package("lib", &descriptor("lib", &[("1.0.0", None)]));
+
+        let request = Request::builder().path(Cow::Borrowed(repo.path())).build();
+        let report = validate(request).unwrap();
+
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert_eq!(report.packages.len(), 2);
+    }
+
+    #[test]
+    fn redirect_repository() {
+        let repo = Repo::with_index("[redirect]\nurl = \"https://example.com/other/\"\n");
+        let request = Request::builder().path(Cow::Borrowed(repo.path())).build();
+
+        assert!(matches!(validate(request), Err(Error::Redirect(_))));
+    }
+
+    #[test]
+    fn id_mismatch() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("other", &[("1.0.0", None)]));
+
+        let issues = issues(&repo);
+        assert!(matches!(
+            &issues[..],
+            [IssueKind::IdMismatch { id, dir }] if id == "other" && dir == "app"
+        ));
+    }
+
+    #[test]
+    fn invalid_descriptor() {
+        let repo = Repo::new();
+        repo.package("app", "[package]\n");
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::InvalidDescriptor(_)]
+        ));
+    }
+
+    #[test]
+    fn invalid_version() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("not-a-version", None)]));
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::InvalidVersion(v)] if v == "not-a-version"
+        ));
+    }
+
+    #[test]
+    fn invalid_payload_url() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None)]).replace("https://example.com/", "not a url/"),
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::InvalidDescriptor(_)]
+        ));
+    }
+
+    #[test]
+    fn unknown_channel() {
+        let repo = Repo::new();
+        repo.package("app", &descriptor("app", &[("1.0.0", Some("alpha"))]));
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::UnknownChannel { version, channel }]
+                if version == "1.0.0" && channel == "alpha"
+        ));
+    }
+
+    #[test]
+    fn duplicate_release() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor(
+                "app",
+                &[("1.0.0", None), ("1.0.0", Some("beta")), ("1.0.0", None)],
+            ),
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::DuplicateTarget { version, channel, platform, arch }]
+                if version == "1.0.0" && channel == "stable" && platform == "linux" && arch == "any"
+        ));
+    }
+
+    #[test]
+    fn missing_targets() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            "[package]\nid = \"app\"\n\n[[release]]\nversion = \"1.0.0\"\nchannel = \"beta\"\n",
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::NoTargets { version, channel }] if version == "1.0.0" && channel == "beta"
+        ));
+    }
+
+    #[test]
+    fn unresolved_dependency() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &with_target("app", "\n[release.target.dependencies]\nlib = \"*\"\n"),
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::UnresolvedDependency(id)] if id == "lib"
+        ));
+
+        repo.package("lib", &descriptor("lib", &[("1.0.0", None)]));
+        assert!(issues(&repo).is_empty());
+    }
+
+    #[test]
+    fn invalid_dependency_url() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &with_target(
+                "app",
+                "\n[release.target.dependencies]\n\"https://example.com/repo/lib\" = \"*\"\n",
+            ),
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::InvalidDependency(url, _)] if url == "https://example.com/repo/lib"
+        ));
+    }
+
+    #[test]
+    fn invalid_redirect_url() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            "[redirect]\nid = \"app\"\nurl = \"https://example.com/repo/app\"\n",
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::InvalidRedirect(url, _)] if url == "https://example.com/repo/app"
+        ));
+    }
+
+    #[test]
+    fn invalid_lang_tag() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None)]).replace("[name]\nen", "[name]\n\"not a tag!\""),
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::InvalidLangTag(tag)] if tag == "not a tag!"
+        ));
+    }
+
+    #[test]
+    fn invalid_license() {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None)]).replace(
+                "version = \"1.0.0\"\n",
+                "version = \"1.0.0\"\nlicense = \"Nope OR\"\n",
+            ),
+        );
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::InvalidLicense { version, license }]
+                if version == "1.0.0" && license == "Nope OR"
+        ));
+    }
+
+    #[test]
+    fn missing_translation() {
+        let repo = Repo::with_index(INDEX_WITH_LANGUAGES);
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None)]).replace(
+                "id = \"app\"\n",
+                "id = \"app\"\ntags = [\"cat:a\", \"cat:b\"]\n",
+            ),
+        );
+        repo.write("strings/se.toml", "[tags]\n\"cat:a\" = \"A\"\n");
+
+        assert!(matches!(
+            &issues(&repo)[..],
+            [IssueKind::MissingTranslation { tag, language }] if tag == "cat:b" && language == "se"
+        ));
+    }
+
+    #[test]
+    fn invalid_strings() {
+        let repo = Repo::with_index(INDEX_WITH_LANGUAGES);
+        repo.write("strings/se.toml", "[tags\n");
+
+        assert!(matches!(&issues(&repo)[..], [IssueKind::InvalidStrings(_)]));
+    }
+
+    const INDEX_WITH_LANGUAGES: &str = r#"
+[repository]
+url = "https://example.com/repo/"
+languages = ["se"]
+
+[agent]
+name = "pahkat"
+version = "0.0.0"
+"#;
+}
@@ -0,0 +1,235 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use typed_builder::TypedBuilder;
+use url::Url;
+
+use crate::input::{Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    /// The repository's URL on pahkat-server, such as `https://example.com/main/`
+    pub url: Cow<'a, Url>,
+    #[builder(default)]
+    pub package: Option<Cow<'a, str>>,
+    #[builder(default)]
+    pub since: Option<NaiveDate>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub url: Option<&'a Url>,
+    #[builder(default)]
+    pub package: Option<&'a str>,
+    #[builder(default)]
+    pub since: Option<NaiveDate>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let url = match partial.url {
+            Some(url) => Cow::Borrowed(url),
+            None => Cow::Owned(
+                input
+                    .field("url", "Repository URL")
+                    .initial_text("https://")
+                    .required::<Url>()?,
+            ),
+        };
+
+        let package = match partial.package {
+            Some(package) => Some(Cow::Borrowed(package)),
+            None => input.lookup::<String>("package")?.map(Cow::Owned),
+        };
+
+        let since = match partial.since {
+            Some(since) => Some(since),
+            None => input.lookup::<NaiveDate>("since")?,
+        };
+
+        Ok(Request {
+            url,
+            package,
+            since,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid URL")]
+    InvalidUrl(#[from] url::ParseError),
+
+    #[error("Failed to fetch statistics")]
+    Http(#[from] reqwest::Error),
+}
+
+/// Downloads and installs of one package release on one platform.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stat {
+    pub package_id: String,
+    pub version: String,
+    pub platform: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Artifact downloads served by pahkat-server
+    pub downloads: u64,
+    /// Installs reported by clients that opted in
+    pub installs: u64,
+}
+
+/// Fetches aggregated download statistics from pahkat-server.
+pub fn stats(request: Request<'_>) -> Result<Vec<Stat>, Error> {
+    let url = stats_url(&request)?;
+
+    log::info!("Fetching {}", url);
+    let stats = reqwest::blocking::get(url)?
+        .error_for_status()?
+        .json::<Vec<Stat>>()?;
+
+    Ok(stats)
+}
+
+fn stats_url(request: &Request<'_>) -> Result<Url, url::ParseError> {
+    let mut url = if request.url.path().ends_with('/') {
+        request.url.join("stats")?
+    } else {
+        Url::parse(&format!("{}/stats", request.url))?
+    };
+
+    // Only touch the query when there is something to add, or the URL ends
+    // with an empty `?`
+    if let Some(package) = request.package.as_ref() {
+        url.query_pairs_mut().append_pair("package", package);
+    }
+    if let Some(since) = request.since {
+        url.query_pairs_mut()
+            .append_pair("since", &since.to_string());
+    }
+
+    Ok(url)
+}
+
+/// Renders statistics as an aligned table.
+pub fn render_table(stats: &[Stat]) -> String {
+    let headers = [
+        "PACKAGE",
+        "VERSION",
+        "PLATFORM",
+        "ARCH",
+        "DOWNLOADS",
+        "INSTALLS",
+    ];
+    let rows = stats
+        .iter()
+        .map(|x| {
+            vec![
+                x.package_id.clone(),
+                x.version.clone(),
+                x.platform.clone(),
+                x.arch.clone().unwrap_or_else(|| "-".into()),
+                x.downloads.to_string(),
+                x.installs.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let mut widths = headers.iter().map(|x| x.len()).collect::<Vec<_>>();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let mut out = String::new();
+    let header_row = headers.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    for row in std::iter::once(&header_row).chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(request: Request<'_>) -> String {
+        stats_url(&request).unwrap().to_string()
+    }
+
+    fn stat(package_id: &str, arch: Option<&str>, downloads: u64, installs: u64) -> Stat {
+        Stat {
+            package_id: package_id.into(),
+            version: "1.0.0".into(),
+            platform: "windows".into(),
+            arch: arch.map(str::to_string),
+            downloads,
+            installs,
+        }
+    }
+
+    #[test]
+    fn stats_urls() {
+        let repo = Url::parse("https://example.com/main/").unwrap();
+        let bare = Url::parse("https://example.com/main").unwrap();
+
+        assert_eq!(
+            url(Request::builder().url(Cow::Borrowed(&repo)).build()),
+            "https://example.com/main/stats"
+        );
+        assert_eq!(
+            url(Request::builder().url(Cow::Borrowed(&bare)).build()),
+            "https://example.com/main/stats"
+        );
+        assert_eq!(
+            url(Request::builder()
+                .url(Cow::Borrowed(&repo))
+                .package(Some("speller sme".into()))
+                .since(Some("2020-09-01".parse().unwrap()))
+                .build()),
+            "https://example.com/main/stats?package=speller+sme&since=2020-09-01"
+        );
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            render_table(&[]),
+            "PACKAGE  VERSION  PLATFORM  ARCH  DOWNLOADS  INSTALLS\n"
+        );
+
+        let stats = [
+            stat("keyboard-sme", None, 12, 3),
+            stat("speller", Some("x86_64"), 1200, 0),
+        ];
+        assert_eq!(
+            render_table(&stats),
+            "\
+PACKAGE       VERSION  PLATFORM  ARCH    DOWNLOADS  INSTALLS
+keyboard-sme  1.0.0    windows   -       12         3
+speller       1.0.0    windows   x86_64  1200       0
+"
+        );
+    }
+}
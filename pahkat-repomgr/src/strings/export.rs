@@ -0,0 +1,243 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use pahkat_types::repo::{Localisation, Repository};
+use typed_builder::TypedBuilder;
+
+use super::po::{self, Entry};
+use super::{find_repo, Error, FindRepoError, Format};
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub repo_path: Cow<'a, Path>,
+    pub language: Cow<'a, str>,
+    /// Language whose strings are shown to translators alongside each key
+    pub source_language: Option<Cow<'a, str>>,
+    pub format: Format,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub language: Option<&'a str>,
+    #[builder(default)]
+    pub source_language: Option<&'a str>,
+    #[builder(default)]
+    pub format: Option<Format>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let _ = find_repo(&repo_path)?;
+
+        let language = match partial.language {
+            Some(language) => Cow::Borrowed(language),
+            None => Cow::Owned(
+                input
+                    .field("language", "Language tag")
+                    .required::<String>()?,
+            ),
+        };
+
+        Ok(Request {
+            repo_path,
+            language,
+            source_language: match partial.source_language {
+                Some(source_language) => Some(Cow::Borrowed(source_language)),
+                None => input.lookup("source_language")?.map(Cow::Owned),
+            },
+            format: match partial.format {
+                Some(format) => format,
+                None => input.lookup("format")?.unwrap_or(Format::Po),
+            },
+        })
+    }
+}
+
+/// Exports the strings of a language for translation.
+///
+/// Every tag used by a package and every channel of the repository is
+/// included, with an empty translation where none exists yet.
+pub fn export<'a>(request: Request<'a>) -> Result<String, Error> {
+    log::debug!("{:?}", request);
+
+    let repo_path = find_repo(&request.repo_path)?;
+    let index_path = repo_path.join("index.toml");
+    let file = std::fs::read_to_string(&index_path)
+        .map_err(|e| Error::ReadFailed(index_path.clone(), e))?;
+    let channels =
+        match toml::from_str::<Repository>(&file).map_err(|e| Error::ReadToml(index_path, e))? {
+            Repository::Index(index) => index.repository.channels,
+            _ => vec![],
+        };
+
+    let mut strings = super::load(repo_path, &request.language)?;
+    for tag in super::used_tags(repo_path)? {
+        strings.tags.entry(tag).or_default();
+    }
+    for channel in channels {
+        strings.channels.entry(channel).or_default();
+    }
+
+    match request.format {
+        Format::Toml => toml::to_string_pretty(&strings).map_err(|e| {
+            Error::SerializeToml(super::strings_path(repo_path, &request.language), e)
+        }),
+        Format::Po => {
+            let source = match request.source_language.as_deref() {
+                Some(language) => super::load(repo_path, language)?,
+                None => Localisation::default(),
+            };
+
+            let mut entries = po_entries(super::TAG_CONTEXT, &strings.tags, &source.tags);
+            entries.extend(po_entries(
+                super::CHANNEL_CONTEXT,
+                &strings.channels,
+                &source.channels,
+            ));
+            Ok(po::write(&request.language, &entries))
+        }
+    }
+}
+
+fn po_entries(
+    context: &str,
+    strings: &BTreeMap<String, String>,
+    source: &BTreeMap<String, String>,
+) -> Vec<Entry> {
+    strings
+        .iter()
+        .map(|(key, value)| Entry {
+            context: Some(context.to_string()),
+            id: key.clone(),
+            translation: value.clone(),
+            comment: source.get(key).cloned(),
+            fuzzy: false,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strings::import;
+    use crate::testing::{descriptor, Repo};
+
+    /// A repository with one tagged package and a partial `nb` translation.
+    fn repo() -> Repo {
+        let repo = Repo::new();
+        repo.package(
+            "app",
+            &descriptor("app", &[("1.0.0", None)]).replace(
+                "id = \"app\"\n",
+                "id = \"app\"\ntags = [\"cat:keyboards\", \"cat:fonts\"]\n",
+            ),
+        );
+        repo.write(
+            "strings/nb.toml",
+            "[tags]\n\"cat:keyboards\" = \"Tastatur\"\n\n[channels]\nbeta = \"Beta\"\n",
+        );
+        repo
+    }
+
+    fn request(repo: &Repo, format: Format) -> Request<'_> {
+        Request::builder()
+            .repo_path(Cow::Borrowed(repo.path()))
+            .language(Cow::Borrowed("nb"))
+            .source_language(None)
+            .format(format)
+            .build()
+    }
+
+    /// Exports `nb` in the format and imports the result as `nn`.
+    fn round_trip(repo: &Repo, format: Format, extension: &str) -> Localisation {
+        let exported = export(request(repo, format)).unwrap();
+        let path = repo.write(format!("export.{}", extension), exported);
+
+        import::import(
+            import::Request::builder()
+                .repo_path(Cow::Borrowed(repo.path()))
+                .language(Cow::Borrowed("nn"))
+                .input_path(Cow::Borrowed(&*path))
+                .format(format)
+                .build(),
+        )
+        .unwrap();
+
+        crate::strings::load(repo.path(), "nn").unwrap()
+    }
+
+    #[test]
+    fn export_includes_untranslated_keys() {
+        let repo = repo();
+        let exported: Localisation =
+            toml::from_str(&export(request(&repo, Format::Toml)).unwrap()).unwrap();
+
+        assert_eq!(
+            exported.tags.iter().collect::<Vec<_>>(),
+            vec![
+                (&"cat:fonts".to_string(), &String::new()),
+                (&"cat:keyboards".to_string(), &"Tastatur".to_string()),
+            ]
+        );
+        assert_eq!(
+            exported.channels.keys().collect::<Vec<_>>(),
+            vec!["beta", "nightly"]
+        );
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let repo = repo();
+        let nb = crate::strings::load(repo.path(), "nb").unwrap();
+
+        assert_eq!(round_trip(&repo, Format::Toml, "toml"), nb);
+        repo.write("strings/nn.toml", "");
+        assert_eq!(round_trip(&repo, Format::Po, "po"), nb);
+    }
+
+    #[test]
+    fn po_export_shows_source_strings() {
+        let repo = repo();
+        repo.write("strings/en.toml", "[channels]\nbeta = \"Beta testing\"\n");
+
+        let exported = export(Request {
+            source_language: Some(Cow::Borrowed("en")),
+            ..request(&repo, Format::Po)
+        })
+        .unwrap();
+        let entries = po::parse(&exported).unwrap();
+        let beta = entries.iter().find(|x| x.id == "beta").unwrap();
+
+        assert_eq!(beta.comment.as_deref(), Some("Beta testing"));
+        assert_eq!(beta.translation, "Beta");
+    }
+}
@@ -0,0 +1,231 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pahkat_types::repo::Localisation;
+use typed_builder::TypedBuilder;
+
+use super::{find_repo, po, Error, FindRepoError, Format};
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub repo_path: Cow<'a, Path>,
+    pub language: Cow<'a, str>,
+    pub input_path: Cow<'a, Path>,
+    pub format: Format,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub language: Option<&'a str>,
+    #[builder(default)]
+    pub input_path: Option<&'a Path>,
+    #[builder(default)]
+    pub format: Option<Format>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+
+    #[error("`{0}` is not a valid BCP 47 language tag")]
+    InvalidLanguage(String),
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let _ = find_repo(&repo_path)?;
+
+        let language = match partial.language {
+            Some(language) => Cow::Borrowed(language),
+            None => Cow::Owned(
+                input
+                    .field("language", "Language tag")
+                    .required::<String>()?,
+            ),
+        };
+
+        if language.parse::<language_tags::LanguageTag>().is_err() {
+            return Err(RequestError::InvalidLanguage(language.to_string()));
+        }
+
+        let input_path = match partial.input_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("input_path", "Input file (.po or .toml)")
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let format = match partial.format {
+            Some(format) => format,
+            None => input
+                .lookup("format")?
+                .unwrap_or_else(|| Format::from_path(&input_path)),
+        };
+
+        Ok(Request {
+            repo_path,
+            language,
+            input_path,
+            format,
+        })
+    }
+}
+
+/// Merges translations from a file into `strings/<language>.toml`.
+///
+/// Empty translations, and fuzzy ones in gettext files, are skipped so that
+/// untranslated entries of an export never clear existing strings.
+pub fn import<'a>(request: Request<'a>) -> Result<(), Error> {
+    log::debug!("{:?}", request);
+
+    let repo_path = find_repo(&request.repo_path)?;
+    let input_path = &*request.input_path;
+    let file = fs::read_to_string(input_path)
+        .map_err(|e| Error::ReadFailed(input_path.to_path_buf(), e))?;
+
+    let incoming = match request.format {
+        Format::Toml => toml::from_str::<Localisation>(&file)
+            .map_err(|e| Error::ReadToml(input_path.to_path_buf(), e))?,
+        Format::Po => {
+            let entries =
+                po::parse(&file).map_err(|e| Error::ReadPo(input_path.to_path_buf(), e))?;
+            let mut strings = Localisation::default();
+
+            for entry in entries.into_iter().filter(|x| !x.fuzzy) {
+                let map = match entry.context.as_deref() {
+                    Some(super::TAG_CONTEXT) => &mut strings.tags,
+                    Some(super::CHANNEL_CONTEXT) => &mut strings.channels,
+                    _ => {
+                        log::warn!("Skipping `{}` without a tag or channel context", entry.id);
+                        continue;
+                    }
+                };
+                map.insert(entry.id, entry.translation);
+            }
+
+            strings
+        }
+    };
+
+    let mut strings = super::load(repo_path, &request.language)?;
+    let mut count = 0;
+
+    let sections = vec![
+        (&mut strings.tags, incoming.tags),
+        (&mut strings.channels, incoming.channels),
+    ];
+    for (existing, incoming) in sections {
+        for (key, value) in incoming.into_iter().filter(|(_, v)| !v.is_empty()) {
+            existing.insert(key, value);
+            count += 1;
+        }
+    }
+
+    log::info!("Imported {} translation(s)", count);
+    super::save(repo_path, &request.language, &strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strings::po::Entry;
+    use crate::testing::Repo;
+
+    fn request<'a>(repo: &'a Repo, input_path: &'a Path, format: Format) -> Request<'a> {
+        Request::builder()
+            .repo_path(Cow::Borrowed(repo.path()))
+            .language(Cow::Borrowed("nb"))
+            .input_path(Cow::Borrowed(input_path))
+            .format(format)
+            .build()
+    }
+
+    fn existing(repo: &Repo) {
+        repo.write(
+            "strings/nb.toml",
+            "[tags]\n\"cat:keyboards\" = \"Tastatur\"\n\"cat:fonts\" = \"Skrifter\"\n",
+        );
+    }
+
+    #[test]
+    fn import_toml_merges_translations() {
+        let repo = Repo::new();
+        existing(&repo);
+        let path = repo.write(
+            "nb.toml",
+            "[tags]\n\"cat:keyboards\" = \"\"\n\"cat:fonts\" = \"Skrifttypar\"\n\n[channels]\nbeta = \"Beta\"\n",
+        );
+
+        import(request(&repo, &path, Format::Toml)).unwrap();
+
+        let strings = crate::strings::load(repo.path(), "nb").unwrap();
+        // Empty translations never clear existing ones
+        assert_eq!(strings.tags["cat:keyboards"], "Tastatur");
+        assert_eq!(strings.tags["cat:fonts"], "Skrifttypar");
+        assert_eq!(strings.channels["beta"], "Beta");
+    }
+
+    #[test]
+    fn import_po_skips_fuzzy_and_unknown_entries() {
+        let repo = Repo::new();
+        existing(&repo);
+
+        let entry = |context: Option<&str>, id: &str, translation: &str, fuzzy: bool| Entry {
+            context: context.map(str::to_string),
+            id: id.to_string(),
+            translation: translation.to_string(),
+            comment: None,
+            fuzzy,
+        };
+        let entries = vec![
+            entry(
+                Some(crate::strings::TAG_CONTEXT),
+                "cat:fonts",
+                "Skrifttypar",
+                false,
+            ),
+            entry(
+                Some(crate::strings::TAG_CONTEXT),
+                "cat:keyboards",
+                "Tastaturar",
+                true,
+            ),
+            entry(Some(crate::strings::CHANNEL_CONTEXT), "beta", "Beta", false),
+            entry(None, "nightly", "Nattleg", false),
+        ];
+        let path = repo.write("nb.po", po::write("nb", &entries));
+
+        import(request(&repo, &path, Format::Po)).unwrap();
+
+        let strings = crate::strings::load(repo.path(), "nb").unwrap();
+        assert_eq!(strings.tags["cat:keyboards"], "Tastatur");
+        assert_eq!(strings.tags["cat:fonts"], "Skrifttypar");
+        assert_eq!(strings.channels.keys().collect::<Vec<_>>(), vec!["beta"]);
+    }
+}
@@ -0,0 +1,152 @@
+pub mod export;
+pub mod import;
+pub mod set;
+
+mod po;
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use pahkat_types::package::Package;
+use pahkat_types::repo::Localisation;
+
+use crate::release::remove::find_repo;
+pub use crate::release::remove::FindRepoError;
+
+pub use po::ParseError as PoParseError;
+
+/// The `msgctxt` of gettext entries for tags and channels respectively.
+const TAG_CONTEXT: &str = "tag";
+const CHANNEL_CONTEXT: &str = "channel";
+
+/// The part of a strings file a key belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Tags,
+    Channels,
+}
+
+impl FromStr for Section {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tags" | "tag" => Ok(Section::Tags),
+            "channels" | "channel" => Ok(Section::Channels),
+            x => Err(format!(
+                "Unknown section `{}`; expected tags or channels",
+                x
+            )),
+        }
+    }
+}
+
+/// The file format strings are exported to or imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Po,
+}
+
+impl Format {
+    /// Guesses the format from a file extension, defaulting to gettext.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => Format::Toml,
+            _ => Format::Po,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "toml" => Ok(Format::Toml),
+            "po" | "gettext" => Ok(Format::Po),
+            x => Err(format!("Unknown format `{}`; expected po or toml", x)),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to read `{0}`")]
+    ReadFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to read TOML file `{0}`")]
+    ReadToml(PathBuf, #[source] toml::de::Error),
+
+    #[error("Failed to read gettext file `{0}`")]
+    ReadPo(PathBuf, #[source] po::ParseError),
+
+    #[error("Failed to create directory `{0}`")]
+    DirCreateFailed(PathBuf, #[source] io::Error),
+
+    #[error("Failed to write TOML file `{0}`")]
+    WriteToml(PathBuf, #[source] io::Error),
+
+    #[error("Failed to serialize TOML for `{0}`")]
+    SerializeToml(PathBuf, #[source] toml::ser::Error),
+
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+}
+
+fn strings_path(repo_path: &Path, language: &str) -> PathBuf {
+    repo_path.join("strings").join(format!("{}.toml", language))
+}
+
+/// Loads the strings for a language, where a missing file has no strings.
+fn load(repo_path: &Path, language: &str) -> Result<Localisation, Error> {
+    let path = strings_path(repo_path, language);
+
+    let file = match fs::read_to_string(&path) {
+        Ok(v) => v,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Localisation::default()),
+        Err(e) => return Err(Error::ReadFailed(path, e)),
+    };
+
+    toml::from_str(&file).map_err(|e| Error::ReadToml(path, e))
+}
+
+fn save(repo_path: &Path, language: &str, strings: &Localisation) -> Result<(), Error> {
+    let path = strings_path(repo_path, language);
+    let dir = path.parent().unwrap();
+    fs::create_dir_all(dir).map_err(|e| Error::DirCreateFailed(dir.to_path_buf(), e))?;
+
+    let data =
+        toml::to_string_pretty(strings).map_err(|e| Error::SerializeToml(path.clone(), e))?;
+    fs::write(&path, data).map_err(|e| Error::WriteToml(path.clone(), e))?;
+    log::info!("Wrote strings to {}", path.display());
+
+    Ok(())
+}
+
+/// Collects every tag used by a package in the repository.
+fn used_tags(repo_path: &Path) -> Result<BTreeSet<String>, Error> {
+    let packages_path = repo_path.join("packages");
+    let dirs = fs::read_dir(&packages_path)
+        .map_err(|e| Error::ReadFailed(packages_path.clone(), e))?
+        .filter_map(Result::ok)
+        .filter(|x| x.file_type().ok().map(|x| x.is_dir()).unwrap_or(false));
+
+    let mut tags = BTreeSet::new();
+    for dir in dirs {
+        let path = dir.path().join("index.toml");
+        let file = fs::read_to_string(&path).map_err(|e| Error::ReadFailed(path.clone(), e))?;
+        let package: Package = toml::from_str(&file).map_err(|e| Error::ReadToml(path, e))?;
+
+        match package {
+            Package::Concrete(x) => tags.extend(x.package.tags),
+            Package::Synthetic(x) => tags.extend(x.synthetic.tags),
+            _ => {}
+        }
+    }
+
+    Ok(tags)
+}
@@ -0,0 +1,333 @@
+//! A minimal reader and writer for gettext `.po` files, covering the subset
+//! needed to round-trip repository strings through translation tools.
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Entry {
+    pub context: Option<String>,
+    pub id: String,
+    pub translation: String,
+    /// Shown to translators as an extracted comment (`#.`)
+    pub comment: Option<String>,
+    pub fuzzy: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("Line {0}: expected a quoted string")]
+    ExpectedString(usize),
+
+    #[error("Line {0}: invalid escape sequence")]
+    InvalidEscape(usize),
+
+    #[error("Line {0}: plural forms are not supported")]
+    Plural(usize),
+
+    #[error("Line {0}: unexpected `{1}`")]
+    Unexpected(usize, String),
+}
+
+pub(crate) fn write(language: &str, entries: &[Entry]) -> String {
+    let mut out = String::new();
+
+    writeln!(out, "msgid \"\"").unwrap();
+    writeln!(out, "msgstr \"\"").unwrap();
+    writeln!(out, "\"Language: {}\\n\"", escape(language)).unwrap();
+    writeln!(out, "\"MIME-Version: 1.0\\n\"").unwrap();
+    writeln!(out, "\"Content-Type: text/plain; charset=UTF-8\\n\"").unwrap();
+    writeln!(out, "\"Content-Transfer-Encoding: 8bit\\n\"").unwrap();
+
+    for entry in entries {
+        writeln!(out).unwrap();
+        if let Some(comment) = entry.comment.as_ref() {
+            for line in comment.lines() {
+                writeln!(out, "#. {}", line).unwrap();
+            }
+        }
+        if entry.fuzzy {
+            writeln!(out, "#, fuzzy").unwrap();
+        }
+        if let Some(context) = entry.context.as_ref() {
+            writeln!(out, "msgctxt \"{}\"", escape(context)).unwrap();
+        }
+        writeln!(out, "msgid \"{}\"", escape(&entry.id)).unwrap();
+        writeln!(out, "msgstr \"{}\"", escape(&entry.translation)).unwrap();
+    }
+
+    out
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Context,
+    Id,
+    Translation,
+}
+
+/// Parses the entries of a `.po` file, skipping the header and obsolete entries.
+pub(crate) fn parse(input: &str) -> Result<Vec<Entry>, ParseError> {
+    let mut entries = vec![];
+    let mut current = Entry::default();
+    let mut field = None;
+    let mut has_translation = false;
+
+    let mut flush = |current: &mut Entry, has_translation: &mut bool| {
+        let entry = std::mem::take(current);
+        if *has_translation && !entry.id.is_empty() {
+            entries.push(entry);
+        }
+        *has_translation = false;
+    };
+
+    for (i, line) in input.lines().enumerate() {
+        let n = i + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with("#~") {
+            continue;
+        }
+
+        if line.starts_with('#') {
+            if has_translation {
+                flush(&mut current, &mut has_translation);
+            }
+            field = None;
+
+            if let Some(flags) = line.strip_prefix("#,") {
+                current.fuzzy |= flags.split(',').any(|x| x.trim() == "fuzzy");
+            } else if let Some(comment) = line.strip_prefix("#.") {
+                let comment = comment.trim();
+                current.comment = Some(match current.comment.take() {
+                    Some(v) => format!("{}\n{}", v, comment),
+                    None => comment.to_string(),
+                });
+            }
+            continue;
+        }
+
+        if line.starts_with('"') {
+            let value = unquote(line, n)?;
+            match field {
+                Some(Field::Context) => current
+                    .context
+                    .get_or_insert_with(String::new)
+                    .push_str(&value),
+                Some(Field::Id) => current.id.push_str(&value),
+                Some(Field::Translation) => current.translation.push_str(&value),
+                None => return Err(ParseError::Unexpected(n, line.to_string())),
+            }
+            continue;
+        }
+
+        let (keyword, rest) = match line.find(char::is_whitespace) {
+            Some(idx) => (&line[..idx], line[idx..].trim_start()),
+            None => return Err(ParseError::Unexpected(n, line.to_string())),
+        };
+
+        match keyword {
+            "msgctxt" | "msgid" if has_translation => flush(&mut current, &mut has_translation),
+            _ => {}
+        }
+
+        let value = unquote(rest, n)?;
+        match keyword {
+            "msgctxt" => {
+                current.context = Some(value);
+                field = Some(Field::Context);
+            }
+            "msgid" => {
+                current.id = value;
+                field = Some(Field::Id);
+            }
+            "msgstr" => {
+                current.translation = value;
+                has_translation = true;
+                field = Some(Field::Translation);
+            }
+            "msgid_plural" => return Err(ParseError::Plural(n)),
+            x if x.starts_with("msgstr[") => return Err(ParseError::Plural(n)),
+            x => return Err(ParseError::Unexpected(n, x.to_string())),
+        }
+    }
+
+    flush(&mut current, &mut has_translation);
+    Ok(entries)
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unquote(value: &str, line: usize) -> Result<String, ParseError> {
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(ParseError::ExpectedString(line));
+    }
+
+    let mut out = String::new();
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('"') => out.push('"'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            _ => return Err(ParseError::InvalidEscape(line)),
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(context: Option<&str>, id: &str, translation: &str) -> Entry {
+        Entry {
+            context: context.map(str::to_string),
+            id: id.to_string(),
+            translation: translation.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn skips_header() {
+        let input = write("se", &[]);
+        assert!(input.contains("\"Language: se\\n\""));
+        assert_eq!(parse(&input).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn escapes() {
+        let entries = vec![entry(
+            None,
+            "tab\there \"quoted\"\nback\\slash\r",
+            "tab\tdiehke \"quoted\"\n\\",
+        )];
+        let output = write("se", &entries);
+
+        assert!(output.contains(r#"msgid "tab\there \"quoted\"\nback\\slash\r""#));
+        assert_eq!(parse(&output).unwrap(), entries);
+    }
+
+    #[test]
+    fn invalid_escape() {
+        assert!(matches!(
+            parse("msgid \"a\\qb\"\nmsgstr \"\""),
+            Err(ParseError::InvalidEscape(1))
+        ));
+        assert!(matches!(
+            parse("msgid \"a\"\nmsgstr \"b\\\"\n"),
+            Err(ParseError::InvalidEscape(2))
+        ));
+    }
+
+    #[test]
+    fn expected_string() {
+        assert!(matches!(
+            parse("msgid \"a\"\nmsgstr b\n"),
+            Err(ParseError::ExpectedString(2))
+        ));
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let input = r#"
+msgctxt ""
+"tag"
+msgid ""
+"first "
+"second"
+msgstr "eka "
+"toka"
+"#;
+        assert_eq!(
+            parse(input).unwrap(),
+            vec![entry(Some("tag"), "first second", "eka toka")]
+        );
+    }
+
+    #[test]
+    fn msgctxt() {
+        let entries = vec![
+            entry(Some("tag"), "Keyboards", "Boallobeavddit"),
+            entry(Some("channel"), "Keyboards", "Kanála"),
+            entry(None, "Keyboards", "Boallobeavdi"),
+        ];
+        let output = write("se", &entries);
+
+        assert!(output.contains("msgctxt \"tag\"\nmsgid \"Keyboards\""));
+        assert_eq!(parse(&output).unwrap(), entries);
+    }
+
+    #[test]
+    fn fuzzy_and_comments() {
+        let entries = vec![
+            Entry {
+                comment: Some("First line\nSecond line".into()),
+                fuzzy: true,
+                ..entry(None, "a", "b")
+            },
+            entry(None, "c", "d"),
+        ];
+        let output = write("se", &entries);
+
+        assert!(output.contains("#. First line\n#. Second line\n#, fuzzy\n"));
+        assert_eq!(parse(&output).unwrap(), entries);
+    }
+
+    #[test]
+    fn fuzzy_among_other_flags() {
+        let input = "#, c-format, fuzzy\nmsgid \"a\"\nmsgstr \"b\"\n\n#, c-format\nmsgid \"c\"\nmsgstr \"d\"\n";
+        let entries = parse(input).unwrap();
+
+        assert!(entries[0].fuzzy);
+        assert!(!entries[1].fuzzy);
+    }
+
+    #[test]
+    fn skips_obsolete_entries() {
+        let input = "#~ msgid \"old\"\n#~ msgstr \"vanha\"\n\nmsgid \"a\"\nmsgstr \"b\"\n";
+        assert_eq!(parse(input).unwrap(), vec![entry(None, "a", "b")]);
+    }
+
+    #[test]
+    fn plural_forms() {
+        let input = "msgid \"file\"\nmsgid_plural \"files\"\nmsgstr[0] \"fiila\"\n";
+        assert!(matches!(parse(input), Err(ParseError::Plural(2))));
+
+        let input = "msgid \"file\"\nmsgstr[0] \"fiila\"\n";
+        assert!(matches!(parse(input), Err(ParseError::Plural(2))));
+    }
+
+    #[test]
+    fn unexpected() {
+        assert!(matches!(
+            parse("msgid \"a\"\nmsgstr \"b\"\nmsgfoo \"c\"\n"),
+            Err(ParseError::Unexpected(3, x)) if x == "msgfoo"
+        ));
+        assert!(matches!(
+            parse("\"dangling\"\n"),
+            Err(ParseError::Unexpected(1, _))
+        ));
+    }
+}
@@ -0,0 +1,230 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use typed_builder::TypedBuilder;
+
+use super::{find_repo, Error, FindRepoError, Section};
+use crate::input::{self, Input, InputError};
+
+#[non_exhaustive]
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct Request<'a> {
+    pub repo_path: Cow<'a, Path>,
+    pub language: Cow<'a, str>,
+    pub section: Section,
+    pub key: Cow<'a, str>,
+    /// The translation to set, or `None` to remove it
+    pub value: Option<Cow<'a, str>>,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, TypedBuilder)]
+pub struct PartialRequest<'a> {
+    #[builder(default)]
+    pub repo_path: Option<&'a Path>,
+    #[builder(default)]
+    pub language: Option<&'a str>,
+    #[builder(default)]
+    pub section: Option<Section>,
+    #[builder(default)]
+    pub key: Option<&'a str>,
+    #[builder(default)]
+    pub value: Option<&'a str>,
+    #[builder(default)]
+    pub remove: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RequestError {
+    #[error("Could not find repository at provided path")]
+    NoRepo(#[from] FindRepoError),
+
+    #[error("`{0}` is not a valid BCP 47 language tag")]
+    InvalidLanguage(String),
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+impl<'a> crate::Request for Request<'a> {
+    type Error = RequestError;
+    type Partial = PartialRequest<'a>;
+
+    fn new_from_user_input(partial: Self::Partial, input: &Input) -> Result<Self, Self::Error> {
+        let repo_path = match partial.repo_path {
+            Some(path) => Cow::Borrowed(path),
+            None => Cow::Owned(
+                input
+                    .field("repo_path", "Repository Path")
+                    .default(input::current_dir())
+                    .required::<PathBuf>()?,
+            ),
+        };
+
+        let _ = find_repo(&repo_path)?;
+
+        let language = match partial.language {
+            Some(language) => Cow::Borrowed(language),
+            None => Cow::Owned(
+                input
+                    .field("language", "Language tag")
+                    .required::<String>()?,
+            ),
+        };
+
+        if language.parse::<language_tags::LanguageTag>().is_err() {
+            return Err(RequestError::InvalidLanguage(language.to_string()));
+        }
+
+        let section = match partial.section {
+            Some(section) => section,
+            None => input.lookup("section")?.unwrap_or(Section::Tags),
+        };
+
+        let key = match partial.key {
+            Some(key) => Cow::Borrowed(key),
+            None => Cow::Owned(
+                input
+                    .field(
+                        "key",
+                        match section {
+                            Section::Tags => "Tag",
+                            Section::Channels => "Channel",
+                        },
+                    )
+                    .required::<String>()?,
+            ),
+        };
+
+        let remove = partial.remove || input.lookup_flag("remove")?;
+        let value = match (remove, partial.value) {
+            (true, _) => None,
+            (false, Some(value)) => Some(Cow::Borrowed(value)),
+            (false, None) => Some(Cow::Owned(
+                input.field("value", "Translation").required::<String>()?,
+            )),
+        };
+
+        Ok(Request {
+            repo_path,
+            language,
+            section,
+            key,
+            value,
+        })
+    }
+}
+
+/// Sets or removes a single translation in `strings/<language>.toml`.
+pub fn set<'a>(request: Request<'a>) -> Result<(), Error> {
+    log::debug!("{:?}", request);
+
+    let repo_path = find_repo(&request.repo_path)?;
+    let mut strings = super::load(repo_path, &request.language)?;
+
+    let map = match request.section {
+        Section::Tags => &mut strings.tags,
+        Section::Channels => &mut strings.channels,
+    };
+
+    match request.value {
+        Some(value) => {
+            map.insert(request.key.to_string(), value.to_string());
+        }
+        None => {
+            if map.remove(&*request.key).is_none() {
+                log::warn!("No translation for `{}` to remove", &request.key);
+                return Ok(());
+            }
+        }
+    }
+
+    super::save(repo_path, &request.language, &strings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Repo;
+
+    fn request<'a>(
+        repo: &'a Repo,
+        section: Section,
+        key: &'a str,
+        value: Option<&'a str>,
+    ) -> Request<'a> {
+        Request::builder()
+            .repo_path(Cow::Borrowed(repo.path()))
+            .language(Cow::Borrowed("nb"))
+            .section(section)
+            .key(Cow::Borrowed(key))
+            .value(value.map(Cow::Borrowed))
+            .build()
+    }
+
+    #[test]
+    fn set_and_remove() {
+        let repo = Repo::new();
+
+        set(request(
+            &repo,
+            Section::Tags,
+            "cat:keyboards",
+            Some("Tastatur"),
+        ))
+        .unwrap();
+        set(request(&repo, Section::Channels, "beta", Some("Beta"))).unwrap();
+
+        let strings = crate::strings::load(repo.path(), "nb").unwrap();
+        assert_eq!(
+            strings.tags.get("cat:keyboards").map(|x| &**x),
+            Some("Tastatur")
+        );
+        assert_eq!(strings.channels.get("beta").map(|x| &**x), Some("Beta"));
+
+        set(request(
+            &repo,
+            Section::Tags,
+            "cat:keyboards",
+            Some("Tastaturar"),
+        ))
+        .unwrap();
+        let strings = crate::strings::load(repo.path(), "nb").unwrap();
+        assert_eq!(
+            strings.tags.get("cat:keyboards").map(|x| &**x),
+            Some("Tastaturar")
+        );
+
+        set(request(&repo, Section::Tags, "cat:keyboards", None)).unwrap();
+        let strings = crate::strings::load(repo.path(), "nb").unwrap();
+        assert!(strings.tags.is_empty());
+        assert_eq!(strings.channels.get("beta").map(|x| &**x), Some("Beta"));
+    }
+
+    #[test]
+    fn removing_a_missing_translation_changes_nothing() {
+        let repo = Repo::new();
+        set(request(&repo, Section::Channels, "beta", Some("Beta"))).unwrap();
+        let before = repo.read("strings/nb.toml");
+
+        set(request(&repo, Section::Tags, "cat:keyboards", None)).unwrap();
+
+        assert_eq!(repo.read("strings/nb.toml"), before);
+    }
+
+    #[test]
+    fn rejects_invalid_language() {
+        let repo = Repo::new();
+        let partial = PartialRequest::builder()
+            .repo_path(Some(repo.path()))
+            .language(Some("not a tag!"))
+            .key(Some("beta"))
+            .value(Some("Beta"))
+            .build();
+
+        assert!(matches!(
+            <Request as crate::Request>::new_from_user_input(partial, &Input::default()),
+            Err(RequestError::InvalidLanguage(language)) if language == "not a tag!"
+        ));
+    }
+}
@@ -0,0 +1,96 @@
+//! Helpers for building repositories on disk in tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub(crate) const INDEX: &str = r#"
+[repository]
+url = "https://example.com/repo/"
+channels = ["beta", "nightly"]
+
+[name]
+en = "Test Repository"
+
+[description]
+en = "A repository for testing."
+
+[agent]
+name = "pahkat"
+version = "0.0.0"
+"#;
+
+/// A repository in a temporary directory, removed when dropped.
+pub(crate) struct Repo {
+    dir: tempfile::TempDir,
+}
+
+impl Repo {
+    /// Creates a repository with the default `index.toml` and no packages.
+    pub(crate) fn new() -> Repo {
+        Repo::with_index(INDEX)
+    }
+
+    pub(crate) fn with_index(index: &str) -> Repo {
+        let repo = Repo {
+            dir: tempfile::tempdir().unwrap(),
+        };
+        repo.write("index.toml", index);
+        fs::create_dir_all(repo.path().join("packages")).unwrap();
+        repo
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Writes a file relative to the repository root, creating its parents.
+    pub(crate) fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Writes `packages/{dir}/index.toml`.
+    pub(crate) fn package(&self, dir: &str, descriptor: &str) -> PathBuf {
+        self.write(
+            Path::new("packages").join(dir).join("index.toml"),
+            descriptor,
+        )
+    }
+
+    pub(crate) fn read(&self, path: impl AsRef<Path>) -> String {
+        fs::read_to_string(self.path().join(path)).unwrap()
+    }
+}
+
+/// A concrete descriptor with one release per `(version, channel)`, each with
+/// a single Linux tarball target.
+pub(crate) fn descriptor(id: &str, releases: &[(&str, Option<&str>)]) -> String {
+    let mut out = format!(
+        "[package]\nid = \"{}\"\n\n[name]\nen = \"{}\"\n\n[description]\nen = \"{}\"\n",
+        id, id, id
+    );
+
+    for (version, channel) in releases {
+        out.push_str(&format!("\n[[release]]\nversion = \"{}\"\n", version));
+        if let Some(channel) = channel {
+            out.push_str(&format!("channel = \"{}\"\n", channel));
+        }
+        out.push_str(&format!(
+            r#"
+[[release.target]]
+platform = "linux"
+
+[release.target.payload]
+type = "TarballPackage"
+url = "https://example.com/{}-{}.txz"
+size = 1
+installed_size = 1
+"#,
+            id, version
+        ));
+    }
+
+    out
+}
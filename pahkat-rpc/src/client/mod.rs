@@ -146,7 +146,10 @@ pub async fn run() -> anyhow::Result<()> {
 
             let req = stream::iter(vec![pb::TransactionRequest {
                 value: Some(pb::transaction_request::Value::Transaction(
-                    pb::transaction_request::Transaction { actions },
+                    pb::transaction_request::Transaction {
+                        actions,
+                        allow_downgrade: false,
+                    },
                 )),
             }]);
 
@@ -413,6 +416,25 @@ pub extern "C" fn pahkat_rpc_resolve_package_query(
     serde_json::from_str(&response?.json).box_err()
 }
 
+#[cffi::marshal(return_marshaler = "JsonMarshaler")]
+pub extern "C" fn pahkat_rpc_transaction_plan(
+    #[marshal(cffi::ArcRefMarshaler::<RwLock<PahkatClient>>)] client: Arc<RwLock<PahkatClient>>,
+    #[marshal(JsonRefMarshaler)] actions: Vec<pb::PackageAction>,
+) -> Result<pb::TransactionPlanResponse, Box<dyn Error>> {
+    let request = Request::new(pb::TransactionPlanRequest {
+        actions,
+        allow_downgrade: false,
+    });
+
+    let response = block_on(async move {
+        let mut client = client.write().await;
+        let response = client.transaction_plan(request).await.box_err()?;
+        Ok(response.into_inner())
+    });
+
+    response
+}
+
 #[cffi::marshal(return_marshaler = "cffi::UnitMarshaler")]
 pub extern "C" fn pahkat_rpc_process_transaction(
     #[marshal(cffi::ArcRefMarshaler::<RwLock<PahkatClient>>)] client: Arc<RwLock<PahkatClient>>,
@@ -451,7 +473,10 @@ pub extern "C" fn pahkat_rpc_process_transaction(
 
     tx.send(pb::TransactionRequest {
         value: Some(pb::transaction_request::Value::Transaction(
-            pb::transaction_request::Transaction { actions },
+            pb::transaction_request::Transaction {
+                actions,
+                allow_downgrade: false,
+            },
         )),
     })?;
 
@@ -7,7 +7,7 @@ use futures::stream::{StreamExt, TryStreamExt};
 use log::{error, info, warn};
 use pahkat_client::{
     config::RepoRecord, package_store::InstallTarget, PackageAction, PackageActionType, PackageKey,
-    PackageStatus, PackageStore, PackageTransaction,
+    PackageStatus, PackageStore, PackageTransaction, TransactionOptions,
 };
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
@@ -277,7 +277,7 @@ impl pb::pahkat_server::Pahkat for Rpc {
                     pb::dependency_status_response::Status {
                         statuses: response.into_iter().map(|item| (item.0.to_string(), match item.1 {
                             PackageStatus::NotInstalled => 0,
-                            PackageStatus::UpToDate => 1,
+                            PackageStatus::UpToDate | PackageStatus::RequiresDowngrade => 1,
                             PackageStatus::RequiresUpdate => 2,
                         })).collect(),
                     }
@@ -373,7 +373,10 @@ impl pb::pahkat_server::Pahkat for Rpc {
                     .collect::<Vec<_>>();
                 println!("{:?}", &actions);
 
-                let transaction = match PackageTransaction::new(Arc::clone(&store) as _, actions) {
+                let options = TransactionOptions {
+                    allow_downgrade: request.allow_downgrade,
+                };
+                let transaction = match PackageTransaction::with_options(Arc::clone(&store) as _, actions, options) {
                     Ok(v) => v,
                     Err(e) => {
                         let response = pb::TransactionResponse {
@@ -585,6 +588,52 @@ impl pb::pahkat_server::Pahkat for Rpc {
         Ok(Response::new(Box::pin(rx) as Self::ProcessTransactionStream))
     }
 
+    async fn transaction_plan(
+        &self,
+        request: Request<pb::TransactionPlanRequest>,
+    ) -> Result<pb::TransactionPlanResponse> {
+        let request = request.into_inner();
+        let actions = request
+            .actions
+            .into_iter()
+            .map(|x| PackageAction::from(x))
+            .collect::<Vec<_>>();
+
+        let options = TransactionOptions {
+            allow_downgrade: request.allow_downgrade,
+        };
+        let transaction =
+            PackageTransaction::with_options(Arc::clone(&self.store) as _, actions, options)
+                .map_err(|e| Status::failed_precondition(format!("{}", e)))?;
+        let plan = transaction.plan();
+
+        let actions = transaction
+            .actions()
+            .iter()
+            .cloned()
+            .zip(plan.actions.into_iter())
+            .map(|(record, planned)| pb::transaction_plan_response::PlannedAction {
+                action: Some(record.into()),
+                status: match planned.status {
+                    PackageStatus::NotInstalled => 0,
+                    PackageStatus::UpToDate => 1,
+                    PackageStatus::RequiresUpdate => 2,
+                    PackageStatus::RequiresDowngrade => 3,
+                },
+                is_dependency: planned.is_dependency,
+                download_size: planned.download_size,
+                installed_size: planned.installed_size,
+            })
+            .collect();
+
+        Ok(Response::new(pb::TransactionPlanResponse {
+            actions,
+            download_size: plan.download_size,
+            installed_size_delta: plan.installed_size_delta,
+            is_reboot_required: plan.is_reboot_required,
+        }))
+    }
+
     async fn set_repo(
         &self,
         request: tonic::Request<pb::SetRepoRequest>,
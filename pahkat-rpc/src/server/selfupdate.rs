@@ -83,7 +83,7 @@ pub(crate) fn requires_update(store: &dyn PackageStore) -> bool {
                 PackageStatus::RequiresUpdate => {
                     is_requiring_update = true;
                 }
-                PackageStatus::UpToDate => {}
+                PackageStatus::UpToDate | PackageStatus::RequiresDowngrade => {}
             },
             Err(err) => {
                 log::error!("{:?}", err);
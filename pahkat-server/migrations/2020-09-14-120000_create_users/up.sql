@@ -0,0 +1,20 @@
+CREATE TABLE users (
+  id BLOB(16) NOT NULL PRIMARY KEY,
+  username TEXT NOT NULL,
+  token_hash BLOB(32) NOT NULL,
+  created_at DATETIME NOT NULL,
+  revoked_at DATETIME
+);
+
+CREATE INDEX idx_users_username ON users (username);
+
+CREATE TABLE user_scopes (
+  id BLOB(16) NOT NULL PRIMARY KEY DEFAULT (randomblob(16)),
+  user_id BLOB(16) NOT NULL,
+  repo TEXT NOT NULL,
+  package TEXT NOT NULL,
+  channels TEXT,
+  FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX idx_user_scopes_user_id ON user_scopes (user_id);
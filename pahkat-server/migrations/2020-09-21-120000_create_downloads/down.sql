@@ -0,0 +1,2 @@
+DROP TABLE downloads;
+DROP TABLE artifact_releases;
@@ -0,0 +1,25 @@
+CREATE TABLE artifact_releases (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  sha256 TEXT NOT NULL,
+  repo TEXT NOT NULL,
+  package_id TEXT NOT NULL,
+  version TEXT NOT NULL,
+  platform TEXT NOT NULL,
+  arch TEXT,
+  created_at DATETIME NOT NULL
+);
+
+CREATE INDEX idx_artifact_releases_sha256 ON artifact_releases (sha256);
+
+CREATE TABLE downloads (
+  id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+  repo TEXT NOT NULL,
+  package_id TEXT NOT NULL,
+  version TEXT NOT NULL,
+  platform TEXT NOT NULL,
+  arch TEXT,
+  source TEXT NOT NULL,
+  created_at DATETIME NOT NULL
+);
+
+CREATE INDEX idx_downloads_repo_package_id ON downloads (repo, package_id);
@@ -0,0 +1,346 @@
+use std::path::{Path, PathBuf};
+
+use bytes::Buf;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+use uuid::Uuid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid artifact filename")]
+    InvalidFilename,
+
+    #[error("Artifact is larger than {0} bytes")]
+    TooLarge(u64),
+
+    #[error("Failed to receive artifact")]
+    Body(#[from] warp::Error),
+
+    #[error("Failed to store artifact")]
+    Io(#[from] std::io::Error),
+}
+
+/// Where uploaded artifacts are stored, and the URL they are served from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactsConfig {
+    pub path: PathBuf,
+
+    pub url: Url,
+
+    /// Largest artifact accepted, in bytes
+    #[serde(default = "default_max_size")]
+    pub max_size: u64,
+}
+
+fn default_max_size() -> u64 {
+    1024 * 1024 * 1024
+}
+
+/// A stored artifact, as returned to the uploader.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Artifact {
+    pub url: Url,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Only plain names are accepted, so they can't escape the artifacts
+/// directory and need no escaping in URLs.
+fn is_valid_filename(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._+-".contains(c))
+}
+
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+impl ArtifactsConfig {
+    /// Streams an upload to disk and stores it as `<sha256>/<filename>`.
+    pub async fn store<S, B>(&self, filename: &str, body: S) -> Result<Artifact, Error>
+    where
+        S: Stream<Item = Result<B, warp::Error>>,
+        B: Buf,
+    {
+        if !is_valid_filename(filename) {
+            return Err(Error::InvalidFilename);
+        }
+
+        tokio::fs::create_dir_all(&self.path).await?;
+        let tmp_path = self
+            .path
+            .join(format!(".upload-{}", Uuid::new_v4().to_simple()));
+
+        let (size, sha256) = match self.receive(&tmp_path, body).await {
+            Ok(v) => v,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&tmp_path).await;
+                return Err(e);
+            }
+        };
+
+        let dir = self.path.join(&sha256);
+        tokio::fs::create_dir_all(&dir).await?;
+        tokio::fs::rename(&tmp_path, dir.join(filename)).await?;
+        log::info!("Stored artifact {}/{} ({} bytes)", sha256, filename, size);
+
+        Ok(Artifact {
+            url: self.url_for(&sha256, filename),
+            size,
+            sha256,
+        })
+    }
+
+    async fn receive<S, B>(&self, path: &Path, body: S) -> Result<(u64, String), Error>
+    where
+        S: Stream<Item = Result<B, warp::Error>>,
+        B: Buf,
+    {
+        futures::pin_mut!(body);
+
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+
+        while let Some(buf) = body.next().await {
+            let mut buf = buf?;
+            while buf.has_remaining() {
+                let chunk = buf.bytes();
+                let len = chunk.len();
+
+                size += len as u64;
+                if size > self.max_size {
+                    return Err(Error::TooLarge(self.max_size));
+                }
+
+                hasher.update(chunk);
+                file.write_all(chunk).await?;
+                buf.advance(len);
+            }
+        }
+
+        file.flush().await?;
+        Ok((size, format!("{:x}", hasher.finalize())))
+    }
+
+    fn url_for(&self, sha256: &str, filename: &str) -> Url {
+        let url = format!(
+            "{}/{}/{}",
+            self.url.as_str().trim_end_matches('/'),
+            sha256,
+            filename
+        );
+        Url::parse(&url).expect("artifact paths are valid in URLs")
+    }
+
+    /// Finds the stored artifact a payload URL refers to, if any, as its
+    /// checksum and path.
+    pub fn resolve(&self, url: &Url) -> Option<(String, PathBuf)> {
+        let base = self.url.as_str().trim_end_matches('/');
+        let rest = url.as_str().strip_prefix(base)?.strip_prefix('/')?;
+
+        let mut chunks = rest.splitn(2, '/');
+        let sha256 = chunks.next()?;
+        let path = self.path_of(sha256, chunks.next()?)?;
+        if path.is_file() {
+            Some((sha256.to_string(), path))
+        } else {
+            None
+        }
+    }
+
+    /// Where an artifact would be stored, if the name is one we could have
+    /// stored.
+    pub fn path_of(&self, sha256: &str, filename: &str) -> Option<PathBuf> {
+        if is_sha256(sha256) && is_valid_filename(filename) {
+            Some(self.path.join(sha256).join(filename))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://example.com/artifacts/";
+    /// SHA-256 of `hello world`
+    const HELLO: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    fn config(dir: &tempfile::TempDir, max_size: u64) -> ArtifactsConfig {
+        ArtifactsConfig {
+            path: dir.path().join("artifacts"),
+            url: Url::parse(BASE).unwrap(),
+            max_size,
+        }
+    }
+
+    fn body(chunks: &[&'static [u8]]) -> impl Stream<Item = Result<bytes::Bytes, warp::Error>> {
+        futures::stream::iter(
+            chunks
+                .iter()
+                .map(|x| Ok(bytes::Bytes::from_static(x)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Every file under the artifacts directory, relative to it.
+    fn stored(config: &ArtifactsConfig) -> Vec<String> {
+        let mut files = vec![];
+        for dir in std::fs::read_dir(&config.path).unwrap() {
+            let dir = dir.unwrap().path();
+            let name = dir.file_name().unwrap().to_string_lossy().to_string();
+            if dir.is_dir() {
+                for file in std::fs::read_dir(&dir).unwrap() {
+                    let file = file.unwrap().file_name();
+                    files.push(format!("{}/{}", name, file.to_string_lossy()));
+                }
+            } else {
+                files.push(name);
+            }
+        }
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn stores_by_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&dir, 1024);
+
+        let artifact = config
+            .store("app-1.0.0.txz", body(&[b"hello ", b"world"]))
+            .await
+            .unwrap();
+
+        assert_eq!(artifact.sha256, HELLO);
+        assert_eq!(artifact.size, 11);
+        assert_eq!(
+            artifact.url.as_str(),
+            format!("{}{}/app-1.0.0.txz", BASE, HELLO)
+        );
+        assert_eq!(stored(&config), vec![format!("{}/app-1.0.0.txz", HELLO)]);
+        assert_eq!(
+            std::fs::read(config.path.join(HELLO).join("app-1.0.0.txz")).unwrap(),
+            b"hello world"
+        );
+
+        // The same content under another name shares the checksum directory
+        let again = config
+            .store("app-1.0.1.txz", body(&[b"hello world"]))
+            .await
+            .unwrap();
+        assert_eq!(again.sha256, HELLO);
+        assert_eq!(
+            stored(&config),
+            vec![
+                format!("{}/app-1.0.0.txz", HELLO),
+                format!("{}/app-1.0.1.txz", HELLO),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_filenames() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&dir, 1024);
+
+        for name in &[
+            "",
+            ".hidden",
+            "..",
+            "../app.txz",
+            "a/b.txz",
+            "a\\b.txz",
+            "a b.txz",
+        ] {
+            assert!(
+                matches!(
+                    config.store(name, body(&[b"hello world"])).await,
+                    Err(Error::InvalidFilename)
+                ),
+                "{:?} was accepted",
+                name
+            );
+        }
+
+        assert!(!config.path.exists());
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&dir, 11);
+
+        assert!(matches!(
+            config.store("big.txz", body(&[b"hello ", b"world!"])).await,
+            Err(Error::TooLarge(11))
+        ));
+        // The partial upload is removed
+        assert!(stored(&config).is_empty());
+
+        // Exactly the limit is fine
+        let artifact = config
+            .store("app.txz", body(&[b"hello ", b"world"]))
+            .await
+            .unwrap();
+        assert_eq!(artifact.size, 11);
+    }
+
+    #[tokio::test]
+    async fn resolves_stored_urls() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&dir, 1024);
+        let artifact = config
+            .store("app.txz", body(&[b"hello world"]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            config.resolve(&artifact.url),
+            Some((HELLO.to_string(), config.path.join(HELLO).join("app.txz")))
+        );
+
+        let unresolved = [
+            // Not stored
+            format!("{}{}/other.txz", BASE, HELLO),
+            // Not under the artifacts URL
+            format!("https://example.com/elsewhere/{}/app.txz", HELLO),
+            // Not a checksum
+            format!("{}latest/app.txz", BASE),
+            // Escaping the checksum directory
+            format!("{}{}/..%2F..%2Fsecret", BASE, HELLO),
+            format!("{}{}/sub/app.txz", BASE, HELLO),
+        ];
+        for url in unresolved.iter() {
+            assert_eq!(
+                config.resolve(&Url::parse(url).unwrap()),
+                None,
+                "{} resolved",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn paths_stay_in_the_artifacts_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(&dir, 1024);
+
+        assert_eq!(
+            config.path_of(HELLO, "app.txz"),
+            Some(config.path.join(HELLO).join("app.txz"))
+        );
+        assert_eq!(config.path_of("..", "app.txz"), None);
+        assert_eq!(config.path_of(&HELLO[1..], "app.txz"), None);
+        assert_eq!(config.path_of(HELLO, "../app.txz"), None);
+        assert_eq!(config.path_of(HELLO, ".upload-1234"), None);
+    }
+}
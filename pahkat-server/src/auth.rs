@@ -0,0 +1,497 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::database::models::UserScope;
+use crate::database::{Database, DatabaseError};
+
+/// The channel name that stands for releases without a channel.
+pub const STABLE_CHANNEL: &str = "stable";
+
+/// What a token may publish: packages matching a glob in one repo, or every
+/// repo with `*`, optionally limited to some channels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scope {
+    pub repo: String,
+    pub package: String,
+    pub channels: Option<Vec<String>>,
+}
+
+impl Scope {
+    pub fn allows(&self, repo: &str, package: &str, channel: Option<&str>) -> bool {
+        if self.repo != "*" && self.repo != repo {
+            return false;
+        }
+
+        if !glob_match(&self.package, package) {
+            return false;
+        }
+
+        match self.channels.as_ref() {
+            Some(channels) => {
+                let channel = channel.unwrap_or(STABLE_CHANNEL);
+                channels.iter().any(|x| x == channel)
+            }
+            None => true,
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    /// Parses `<repo>:<package glob>[:<channel>,...]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chunks = s.splitn(3, ':');
+        match (chunks.next(), chunks.next(), chunks.next()) {
+            (Some(repo), Some(package), channels) if !repo.is_empty() && !package.is_empty() => {
+                Ok(Scope {
+                    repo: repo.to_string(),
+                    package: package.to_string(),
+                    channels: channels.map(|x| x.split(',').map(str::to_string).collect()),
+                })
+            }
+            _ => Err(format!(
+                "Expected `<repo>:<package>[:<channel>,...]`, got `{}`",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.repo, self.package)?;
+        if let Some(channels) = self.channels.as_ref() {
+            write!(f, ":{}", channels.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<UserScope> for Scope {
+    fn from(scope: UserScope) -> Self {
+        Scope {
+            repo: scope.repo,
+            package: scope.package,
+            channels: scope
+                .channels
+                .map(|x| x.split(',').map(str::to_string).collect()),
+        }
+    }
+}
+
+/// Matches `*` against any run of characters and `?` against any one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&x| x == '*')
+}
+
+/// An API token, presented as `<id>.<secret>`. Only the id and a hash of the
+/// secret are stored.
+pub struct Token {
+    pub id: Uuid,
+    secret: [u8; 32],
+}
+
+impl Token {
+    pub fn generate() -> Token {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Token {
+            id: Uuid::new_v4(),
+            secret,
+        }
+    }
+
+    pub fn hash(&self) -> Vec<u8> {
+        Sha256::digest(&self.secret).to_vec()
+    }
+
+    /// Compares against a stored hash in constant time.
+    pub fn verify(&self, hash: &[u8]) -> bool {
+        self.hash().ct_eq(hash).into()
+    }
+}
+
+impl FromStr for Token {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chunks = s.splitn(2, '.');
+        let id = chunks.next().ok_or(())?;
+        let secret = chunks.next().ok_or(())?;
+
+        let id = Uuid::parse_str(id).map_err(|_| ())?;
+        let bytes = hex::decode(secret).map_err(|_| ())?;
+        if bytes.len() != 32 {
+            return Err(());
+        }
+
+        let mut secret = [0u8; 32];
+        secret.copy_from_slice(&bytes);
+        Ok(Token { id, secret })
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.id.to_simple(), hex::encode(&self.secret))
+    }
+}
+
+/// Compares two secrets in constant time.
+pub fn secret_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Who a request was authorized as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Principal {
+    /// The legacy `api_token` from the config, which may publish anything
+    Admin,
+    User(String),
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Principal::Admin => f.write_str("api_token"),
+            Principal::User(name) => f.write_str(name),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Invalid API token")]
+    Unauthorized,
+
+    #[error("Token is not allowed to publish this package to this channel")]
+    Forbidden,
+
+    #[error("Could not verify API token")]
+    Database(#[from] DatabaseError),
+}
+
+/// Checks an `Authorization` header against the token store and the token's
+/// scopes.
+pub fn authorize(
+    db: &Database,
+    api_token: Option<&str>,
+    header: &str,
+    repo: &str,
+    package: &str,
+    channel: Option<&str>,
+) -> Result<Principal, AuthError> {
+    let candidate = match header.strip_prefix("Bearer ") {
+        Some(v) => v.trim(),
+        None => return Err(AuthError::Unauthorized),
+    };
+
+    if let Some(api_token) = api_token {
+        if secret_eq(candidate, api_token) {
+            return Ok(Principal::Admin);
+        }
+    }
+
+    let token = candidate
+        .parse::<Token>()
+        .map_err(|_| AuthError::Unauthorized)?;
+    let (user, scopes) = match db.find_user(token.id.as_bytes())? {
+        Some(v) => v,
+        None => return Err(AuthError::Unauthorized),
+    };
+
+    if !token.verify(&user.token_hash) || user.revoked_at.is_some() {
+        return Err(AuthError::Unauthorized);
+    }
+
+    if scopes
+        .into_iter()
+        .map(Scope::from)
+        .any(|x| x.allows(repo, package, channel))
+    {
+        Ok(Principal::User(user.username))
+    } else {
+        Err(AuthError::Forbidden)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::{NewUser, NewUserScope};
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("speller", "speller"));
+        assert!(!glob_match("speller", "spellers"));
+        assert!(!glob_match("speller", "spell"));
+
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "speller"));
+        assert!(glob_match("speller-*", "speller-sme"));
+        assert!(glob_match("speller-*", "speller-"));
+        assert!(!glob_match("speller-*", "keyboard-sme"));
+        assert!(glob_match("*-sme", "keyboard-sme"));
+        assert!(glob_match("*-*-*", "a-b-c"));
+        assert!(!glob_match("*-*-*", "a-b"));
+        assert!(glob_match("s*l*r", "speller"));
+        assert!(glob_match("**", "speller"));
+
+        assert!(glob_match("speller-???", "speller-sme"));
+        assert!(!glob_match("speller-???", "speller-se"));
+        assert!(!glob_match("?", ""));
+
+        assert!(glob_match("divvun-*-mobile", "divvun-sme-mobile"));
+        assert!(!glob_match("divvun-*-mobile", "divvun-sme-mobile-old"));
+        assert!(glob_match("ordbok-ø*", "ordbok-øst"));
+    }
+
+    #[test]
+    fn scope_from_str() {
+        assert_eq!(
+            "main:speller-*".parse(),
+            Ok(Scope {
+                repo: "main".into(),
+                package: "speller-*".into(),
+                channels: None,
+            })
+        );
+        assert_eq!(
+            "*:speller:nightly,stable".parse(),
+            Ok(Scope {
+                repo: "*".into(),
+                package: "speller".into(),
+                channels: Some(vec!["nightly".into(), "stable".into()]),
+            })
+        );
+
+        for scope in &["main:speller-*", "*:speller:nightly,stable"] {
+            assert_eq!(scope.parse::<Scope>().unwrap().to_string(), *scope);
+        }
+
+        assert!("main".parse::<Scope>().is_err());
+        assert!("main:".parse::<Scope>().is_err());
+        assert!(":speller".parse::<Scope>().is_err());
+        assert!("".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn scope_allows() {
+        let scope = "main:speller-*:nightly,stable".parse::<Scope>().unwrap();
+        assert!(scope.allows("main", "speller-sme", Some("nightly")));
+        assert!(scope.allows("main", "speller-sme", None));
+        assert!(!scope.allows("main", "speller-sme", Some("beta")));
+        assert!(!scope.allows("main", "keyboard-sme", Some("nightly")));
+        assert!(!scope.allows("other", "speller-sme", Some("nightly")));
+
+        let scope = "*:*".parse::<Scope>().unwrap();
+        assert!(scope.allows("main", "speller-sme", Some("beta")));
+        assert!(scope.allows("other", "keyboard", None));
+    }
+
+    struct Fixture {
+        db: Database,
+        _dir: tempfile::TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("pahkat.sqlite");
+            let db = Database::new(path.to_str().unwrap()).unwrap();
+            Fixture { db, _dir: dir }
+        }
+
+        fn mint(&self, name: &str, scopes: &[&str]) -> Token {
+            let token = Token::generate();
+            let user_id = token.id.as_bytes().to_vec();
+            let user = NewUser {
+                id: user_id.clone(),
+                username: name.to_string(),
+                token_hash: token.hash(),
+                created_at: chrono::Utc::now().naive_utc(),
+            };
+            let scopes = scopes
+                .iter()
+                .map(|x| x.parse::<Scope>().unwrap())
+                .map(|scope| NewUserScope {
+                    user_id: user_id.clone(),
+                    repo: scope.repo,
+                    package: scope.package,
+                    channels: scope.channels.map(|x| x.join(",")),
+                })
+                .collect();
+            self.db.create_user(user, scopes).unwrap();
+            token
+        }
+
+        fn authorize(
+            &self,
+            header: &str,
+            package: &str,
+            channel: Option<&str>,
+        ) -> Result<Principal, AuthError> {
+            authorize(&self.db, Some("legacy"), header, "main", package, channel)
+        }
+    }
+
+    fn bearer(token: &Token) -> String {
+        format!("Bearer {}", token)
+    }
+
+    #[test]
+    fn authorize_scoped_token() {
+        let fixture = Fixture::new();
+        let token = fixture.mint("ci", &["main:speller-*:nightly"]);
+
+        assert_eq!(
+            fixture
+                .authorize(&bearer(&token), "speller-sme", Some("nightly"))
+                .unwrap(),
+            Principal::User("ci".into())
+        );
+        assert!(matches!(
+            fixture.authorize(&bearer(&token), "speller-sme", None),
+            Err(AuthError::Forbidden)
+        ));
+        assert!(matches!(
+            fixture.authorize(&bearer(&token), "keyboard-sme", Some("nightly")),
+            Err(AuthError::Forbidden)
+        ));
+        assert!(matches!(
+            authorize(
+                &fixture.db,
+                None,
+                &bearer(&token),
+                "other",
+                "speller-sme",
+                Some("nightly")
+            ),
+            Err(AuthError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn authorize_legacy_token() {
+        let fixture = Fixture::new();
+
+        assert_eq!(
+            fixture
+                .authorize("Bearer legacy", "anything", None)
+                .unwrap(),
+            Principal::Admin
+        );
+        assert!(matches!(
+            authorize(&fixture.db, None, "Bearer legacy", "main", "anything", None),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn authorize_rejects_invalid_tokens() {
+        let fixture = Fixture::new();
+        let token = fixture.mint("ci", &["*:*"]);
+
+        for header in &[
+            "",
+            "legacy",
+            "Basic legacy",
+            "Bearer ",
+            "Bearer legacy2",
+            "Bearer not-a-token",
+            token.to_string().as_str(),
+        ] {
+            assert!(
+                matches!(
+                    fixture.authorize(header, "speller", None),
+                    Err(AuthError::Unauthorized)
+                ),
+                "{:?} was accepted",
+                header
+            );
+        }
+
+        // The right id with the wrong secret
+        let forged = format!("Bearer {}.{}", token.id.to_simple(), "00".repeat(32));
+        assert!(matches!(
+            fixture.authorize(&forged, "speller", None),
+            Err(AuthError::Unauthorized)
+        ));
+
+        // A well-formed token that was never minted
+        assert!(matches!(
+            fixture.authorize(&bearer(&Token::generate()), "speller", None),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn authorize_revoked_tokens() {
+        let fixture = Fixture::new();
+        let first = fixture.mint("ci", &["*:*"]);
+        let second = fixture.mint("ci", &["*:*"]);
+        let other = fixture.mint("release", &["*:*"]);
+
+        assert!(fixture.db.revoke_token(first.id.as_bytes()).unwrap());
+        assert!(!fixture.db.revoke_token(first.id.as_bytes()).unwrap());
+        assert!(matches!(
+            fixture.authorize(&bearer(&first), "speller", None),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(fixture.authorize(&bearer(&second), "speller", None).is_ok());
+
+        assert!(fixture.db.revoke_user("ci").unwrap());
+        assert!(!fixture.db.revoke_user("ci").unwrap());
+        assert!(matches!(
+            fixture.authorize(&bearer(&second), "speller", None),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(fixture.authorize(&bearer(&other), "speller", None).is_ok());
+    }
+
+    #[test]
+    fn token_round_trip() {
+        let token = Token::generate();
+        let parsed = token.to_string().parse::<Token>().unwrap();
+        assert_eq!(parsed.id, token.id);
+        assert!(parsed.verify(&token.hash()));
+        assert!(!Token::generate().verify(&token.hash()));
+
+        assert!("".parse::<Token>().is_err());
+        assert!(format!("{}.", token.id).parse::<Token>().is_err());
+        assert!(format!("{}.abcd", token.id).parse::<Token>().is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use chrono::offset::Utc;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError};
+use diesel::sqlite::SqliteConnection;
+use std::collections::HashSet;
+
+pub mod models;
+pub mod schema;
+
+use self::models::{
+    ArtifactRelease, DownloadStat, NewArtifactRelease, NewDownload, NewUser, NewUserScope, User,
+    UserScope,
+};
+use self::schema::{artifact_releases, downloads, user_scopes, users};
+
+embed_migrations!("migrations");
+
+#[derive(Debug, thiserror::Error)]
+pub enum DatabaseError {
+    #[error("Could not connect to database")]
+    Pool(#[from] PoolError),
+
+    #[error("Database query failed")]
+    Query(#[from] diesel::result::Error),
+
+    #[error("Failed to migrate database")]
+    Migration(#[from] diesel_migrations::RunMigrationsError),
+}
+
+#[derive(Clone)]
+pub struct Database {
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and applying any pending
+    /// migrations as needed.
+    pub fn new(path: &str) -> Result<Self, DatabaseError> {
+        let manager = ConnectionManager::<SqliteConnection>::new(path);
+        let pool = Pool::builder().build(manager)?;
+
+        embedded_migrations::run(&pool.get()?)?;
+
+        Ok(Database { pool })
+    }
+
+    pub fn create_user(
+        &self,
+        user: NewUser,
+        scopes: Vec<NewUserScope>,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.pool.get()?;
+
+        connection.transaction::<_, diesel::result::Error, _>(|| {
+            diesel::insert_into(users::table)
+                .values(&user)
+                .execute(&connection)?;
+
+            for scope in scopes.iter() {
+                diesel::insert_into(user_scopes::table)
+                    .values(scope)
+                    .execute(&connection)?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    /// Marks every active token with that owner name as revoked. Returns
+    /// false if there were none.
+    pub fn revoke_user(&self, name: &str) -> Result<bool, DatabaseError> {
+        use self::schema::users::dsl::*;
+
+        let connection = self.pool.get()?;
+
+        let count = diesel::update(users.filter(username.eq(name)).filter(revoked_at.is_null()))
+            .set(revoked_at.eq(Some(Utc::now().naive_utc())))
+            .execute(&connection)?;
+
+        Ok(count > 0)
+    }
+
+    /// Marks a single token as revoked. Returns false if there is no active
+    /// token with that id.
+    pub fn revoke_token(&self, token_id: &[u8]) -> Result<bool, DatabaseError> {
+        use self::schema::users::dsl::*;
+
+        let connection = self.pool.get()?;
+
+        let count = diesel::update(users.find(token_id.to_vec()).filter(revoked_at.is_null()))
+            .set(revoked_at.eq(Some(Utc::now().naive_utc())))
+            .execute(&connection)?;
+
+        Ok(count > 0)
+    }
+
+    pub fn find_user(
+        &self,
+        user_id: &[u8],
+    ) -> Result<Option<(User, Vec<UserScope>)>, DatabaseError> {
+        let connection = self.pool.get()?;
+
+        let user = users::table
+            .find(user_id.to_vec())
+            .first::<User>(&connection)
+            .optional()?;
+
+        match user {
+            Some(user) => {
+                let scopes = UserScope::belonging_to(&user).load::<UserScope>(&connection)?;
+                Ok(Some((user, scopes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn list_users(&self) -> Result<Vec<(User, Vec<UserScope>)>, DatabaseError> {
+        use self::schema::users::dsl::*;
+
+        let connection = self.pool.get()?;
+
+        let all_users = users.order(username.asc()).load::<User>(&connection)?;
+        let scopes = UserScope::belonging_to(&all_users)
+            .load::<UserScope>(&connection)?
+            .grouped_by(&all_users);
+
+        Ok(all_users.into_iter().zip(scopes).collect())
+    }
+
+    pub fn create_artifact_release(
+        &self,
+        release: NewArtifactRelease,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.pool.get()?;
+
+        diesel::insert_into(artifact_releases::table)
+            .values(&release)
+            .execute(&connection)?;
+
+        Ok(())
+    }
+
+    /// Every release an artifact was published as, most recent first. An
+    /// artifact is only listed once per repository, package, version and
+    /// target, however many channels it was published to.
+    pub fn find_artifact_releases(
+        &self,
+        sha256: &str,
+    ) -> Result<Vec<ArtifactRelease>, DatabaseError> {
+        let connection = self.pool.get()?;
+
+        let mut releases = artifact_releases::table
+            .filter(artifact_releases::sha256.eq(sha256))
+            .order(artifact_releases::id.desc())
+            .load::<ArtifactRelease>(&connection)?;
+
+        let mut seen = HashSet::new();
+        releases.retain(|x| {
+            seen.insert((
+                x.repo.clone(),
+                x.package_id.clone(),
+                x.version.clone(),
+                x.platform.clone(),
+                x.arch.clone(),
+            ))
+        });
+
+        Ok(releases)
+    }
+
+    pub fn create_download(&self, download: NewDownload) -> Result<(), DatabaseError> {
+        let connection = self.pool.get()?;
+
+        diesel::insert_into(downloads::table)
+            .values(&download)
+            .execute(&connection)?;
+
+        Ok(())
+    }
+
+    /// Download and install counts per package, version, platform and arch.
+    pub fn download_stats(
+        &self,
+        repo: &str,
+        package_id: Option<&str>,
+        since: Option<NaiveDateTime>,
+    ) -> Result<Vec<DownloadStat>, DatabaseError> {
+        use diesel::sql_types::{Nullable, Text, Timestamp};
+
+        let connection = self.pool.get()?;
+
+        let stats = diesel::sql_query(
+            "SELECT package_id, version, platform, arch, \
+                SUM(CASE WHEN source = 'artifact' THEN 1 ELSE 0 END) AS downloads, \
+                SUM(CASE WHEN source = 'install' THEN 1 ELSE 0 END) AS installs \
+            FROM downloads \
+            WHERE repo = ? \
+                AND (? IS NULL OR package_id = ?) \
+                AND (? IS NULL OR created_at >= ?) \
+            GROUP BY package_id, version, platform, arch \
+            ORDER BY package_id, version, platform, arch",
+        )
+        .bind::<Text, _>(repo)
+        .bind::<Nullable<Text>, _>(package_id)
+        .bind::<Nullable<Text>, _>(package_id)
+        .bind::<Nullable<Timestamp>, _>(since)
+        .bind::<Nullable<Timestamp>, _>(since)
+        .load::<DownloadStat>(&connection)?;
+
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn database(dir: &tempfile::TempDir) -> Database {
+        let path = dir.path().join("pahkat.sqlite");
+        Database::new(path.to_str().unwrap()).unwrap()
+    }
+
+    fn date(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2020, 9, day).and_hms(12, 0, 0)
+    }
+
+    fn download(
+        repo: &str,
+        package_id: &str,
+        version: &str,
+        arch: Option<&str>,
+        source: &str,
+        day: u32,
+    ) -> NewDownload {
+        NewDownload {
+            repo: repo.into(),
+            package_id: package_id.into(),
+            version: version.into(),
+            platform: "windows".into(),
+            arch: arch.map(str::to_string),
+            source: source.into(),
+            created_at: date(day),
+        }
+    }
+
+    fn artifact_release(sha256: &str, repo: &str, version: &str) -> NewArtifactRelease {
+        NewArtifactRelease {
+            sha256: sha256.into(),
+            repo: repo.into(),
+            package_id: "speller".into(),
+            version: version.into(),
+            platform: "windows".into(),
+            arch: None,
+            created_at: date(1),
+        }
+    }
+
+    fn counts(stats: &[DownloadStat]) -> Vec<(&str, &str, Option<&str>, i64, i64)> {
+        stats
+            .iter()
+            .map(|x| {
+                assert_eq!(x.platform, "windows");
+                (
+                    x.package_id.as_str(),
+                    x.version.as_str(),
+                    x.arch.as_deref(),
+                    x.downloads,
+                    x.installs,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn download_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = database(&dir);
+
+        let downloads = vec![
+            download("main", "speller", "1.0.0", None, "artifact", 1),
+            download("main", "speller", "1.0.0", None, "artifact", 2),
+            download("main", "speller", "1.0.0", None, "install", 3),
+            download("main", "speller", "1.1.0", None, "install", 20),
+            download("main", "speller", "1.1.0", Some("x86_64"), "artifact", 21),
+            download("main", "keyboard", "2.0.0", None, "artifact", 22),
+            download("other", "speller", "1.0.0", None, "artifact", 23),
+        ];
+        for x in downloads {
+            db.create_download(x).unwrap();
+        }
+
+        let all = db.download_stats("main", None, None).unwrap();
+        assert_eq!(
+            counts(&all),
+            vec![
+                ("keyboard", "2.0.0", None, 1, 0),
+                ("speller", "1.0.0", None, 2, 1),
+                ("speller", "1.1.0", None, 0, 1),
+                ("speller", "1.1.0", Some("x86_64"), 1, 0),
+            ]
+        );
+
+        let speller = db.download_stats("main", Some("speller"), None).unwrap();
+        assert_eq!(counts(&speller), counts(&all[1..]));
+
+        let since = db.download_stats("main", None, Some(date(20))).unwrap();
+        assert_eq!(
+            counts(&since),
+            vec![
+                ("keyboard", "2.0.0", None, 1, 0),
+                ("speller", "1.1.0", None, 0, 1),
+                ("speller", "1.1.0", Some("x86_64"), 1, 0),
+            ]
+        );
+
+        let both = db
+            .download_stats("main", Some("keyboard"), Some(date(23)))
+            .unwrap();
+        assert!(both.is_empty());
+
+        assert!(db.download_stats("missing", None, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn artifact_releases() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = database(&dir);
+
+        assert!(db.find_artifact_releases("abc").unwrap().is_empty());
+
+        // Published to two channels, then to another repository
+        db.create_artifact_release(artifact_release("abc", "main", "1.0.0"))
+            .unwrap();
+        db.create_artifact_release(artifact_release("abc", "main", "1.0.0"))
+            .unwrap();
+        db.create_artifact_release(artifact_release("abc", "other", "1.0.0"))
+            .unwrap();
+        db.create_artifact_release(artifact_release("def", "main", "1.1.0"))
+            .unwrap();
+
+        let releases = db.find_artifact_releases("abc").unwrap();
+        let releases = releases
+            .iter()
+            .map(|x| (x.repo.as_str(), x.version.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(releases, vec![("other", "1.0.0"), ("main", "1.0.0")]);
+    }
+}
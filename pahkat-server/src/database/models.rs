@@ -0,0 +1,140 @@
+use chrono::NaiveDateTime;
+use diesel::sql_types::{BigInt, Nullable, Text};
+use serde::Serialize;
+
+use super::schema::{artifact_releases, downloads, user_scopes, users};
+
+#[derive(Identifiable, Queryable, Debug)]
+#[table_name = "users"]
+pub struct User {
+    pub id: Vec<u8>,
+
+    pub username: String,
+
+    pub token_hash: Vec<u8>,
+
+    pub created_at: NaiveDateTime,
+
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "users"]
+pub struct NewUser {
+    pub id: Vec<u8>,
+
+    pub username: String,
+
+    pub token_hash: Vec<u8>,
+
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Debug, Associations)]
+#[belongs_to(User)]
+#[table_name = "user_scopes"]
+pub struct UserScope {
+    pub id: Vec<u8>,
+
+    pub user_id: Vec<u8>,
+
+    pub repo: String,
+
+    pub package: String,
+
+    /// Comma-separated channels, or any channel if unset
+    pub channels: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "user_scopes"]
+pub struct NewUserScope {
+    pub user_id: Vec<u8>,
+
+    pub repo: String,
+
+    pub package: String,
+
+    pub channels: Option<String>,
+}
+
+/// The release a stored artifact was published as, so downloads of it can be
+/// attributed to a package.
+#[derive(Identifiable, Queryable, Debug)]
+#[table_name = "artifact_releases"]
+pub struct ArtifactRelease {
+    pub id: i32,
+
+    pub sha256: String,
+
+    pub repo: String,
+
+    pub package_id: String,
+
+    pub version: String,
+
+    pub platform: String,
+
+    pub arch: Option<String>,
+
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "artifact_releases"]
+pub struct NewArtifactRelease {
+    pub sha256: String,
+
+    pub repo: String,
+
+    pub package_id: String,
+
+    pub version: String,
+
+    pub platform: String,
+
+    pub arch: Option<String>,
+
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "downloads"]
+pub struct NewDownload {
+    pub repo: String,
+
+    pub package_id: String,
+
+    pub version: String,
+
+    pub platform: String,
+
+    pub arch: Option<String>,
+
+    /// `artifact` for downloads served by this server, `install` for installs
+    /// reported by clients
+    pub source: String,
+
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct DownloadStat {
+    #[sql_type = "Text"]
+    pub package_id: String,
+
+    #[sql_type = "Text"]
+    pub version: String,
+
+    #[sql_type = "Text"]
+    pub platform: String,
+
+    #[sql_type = "Nullable<Text>"]
+    pub arch: Option<String>,
+
+    #[sql_type = "BigInt"]
+    pub downloads: i64,
+
+    #[sql_type = "BigInt"]
+    pub installs: i64,
+}
@@ -0,0 +1,49 @@
+table! {
+    artifact_releases (id) {
+        id -> Integer,
+        sha256 -> Text,
+        repo -> Text,
+        package_id -> Text,
+        version -> Text,
+        platform -> Text,
+        arch -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    downloads (id) {
+        id -> Integer,
+        repo -> Text,
+        package_id -> Text,
+        version -> Text,
+        platform -> Text,
+        arch -> Nullable<Text>,
+        source -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    user_scopes (id) {
+        id -> Binary,
+        user_id -> Binary,
+        repo -> Text,
+        package -> Text,
+        channels -> Nullable<Text>,
+    }
+}
+
+table! {
+    users (id) {
+        id -> Binary,
+        username -> Text,
+        token_hash -> Binary,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+joinable!(user_scopes -> users (user_id));
+
+allow_tables_to_appear_in_same_query!(artifact_releases, downloads, user_scopes, users,);
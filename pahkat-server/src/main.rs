@@ -1,90 +1,42 @@
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+
+use futures::Stream;
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
-use std::time::Duration;
 use std::{collections::HashMap, convert::Infallible, path::PathBuf, sync::Arc};
 use warp::http::StatusCode;
 use warp::Filter;
 
 use structopt::StructOpt;
 
-use std::process::{Command, ExitStatus, Output};
-
-pub struct Subversion {
-    path: PathBuf,
-}
-
-impl Subversion {
-    fn cleanup(&self) -> Result<Output, Output> {
-        let output = Command::new("svn")
-            .args(&["cleanup", "--remove-unversioned"])
-            .current_dir(&self.path)
-            .output()
-            .unwrap();
-
-        if output.status.success() {
-            Ok(output)
-        } else {
-            Err(output)
-        }
-    }
-
-    fn revert(&self) -> Result<Output, Output> {
-        let output = Command::new("svn")
-            .args(&["revert", "-R", "."])
-            .current_dir(&self.path)
-            .output()
-            .unwrap();
-
-        if output.status.success() {
-            Ok(output)
-        } else {
-            Err(output)
-        }
-    }
-
-    fn update(&self) -> Result<Output, Output> {
-        let output = Command::new("svn")
-            .arg("up")
-            .current_dir(&self.path)
-            .output()
-            .unwrap();
-
-        if output.status.success() {
-            Ok(output)
-        } else {
-            Err(output)
-        }
-    }
-
-    fn commit(
-        &self,
-        repo: &str,
-        id: &str,
-        username: &str,
-        password: &str,
-    ) -> Result<Output, Output> {
-        let output = Command::new("svn")
-            .args(&["commit", "-m"])
-            .arg(format!("[CD] Package: {}, Repo: {}", id, repo))
-            .args(&[
-                format!("--username={}", username),
-                format!("--password={}", password),
-            ])
-            .current_dir(&self.path)
-            .output()
-            .unwrap();
-
-        if output.status.success() {
-            Ok(output)
-        } else {
-            Err(output)
-        }
-    }
-}
+mod artifacts;
+mod auth;
+mod database;
+mod queue;
+mod serve;
+mod server;
+mod stats;
+mod vcs;
+mod webhooks;
+
+use artifacts::ArtifactsConfig;
+use auth::{AuthError, Principal};
+use database::models::{NewUser, NewUserScope};
+use database::{Database, DatabaseError};
+use queue::Queue;
+use server::{ServerConfig, Shutdown};
+use vcs::{Identity, VcsConfig};
+use webhooks::{WebhookConfig, Webhooks};
 
 #[derive(Serialize, Deserialize)]
 struct PackageUpdateRequest {
     release: Release,
+
+    /// Who the commit is attributed to, as `Name <email>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uploader: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
@@ -111,17 +63,60 @@ enum PackageUpdateError {
     #[error("Invalid API token")]
     Unauthorized,
 
+    #[error("Token is not allowed to publish this package to this channel")]
+    Forbidden,
+
+    #[error("Could not verify API token")]
+    DatabaseError(#[from] DatabaseError),
+
     #[error("Unsupported repository identifier.")]
     UnsupportedRepo,
 
     #[error("{0}")]
     RepoError(#[from] pahkat_repomgr::package::update::Error),
 
+    #[error("{0}")]
+    RemoveError(#[from] pahkat_repomgr::release::remove::Error),
+
+    #[error("{0}")]
+    YankError(#[from] pahkat_repomgr::release::yank::Error),
+
+    #[error("{0}")]
+    PromoteError(#[from] pahkat_repomgr::release::promote::Error),
+
+    #[error("An arch can only be given along with a platform")]
+    ArchWithoutPlatform,
+
     #[error("Invalid version provided")]
     VersionError(#[from] pahkat_types::package::version::Error),
 
+    #[error("Invalid uploader identity: {0}")]
+    InvalidUploader(String),
+
     #[error("Indexing error")]
     IndexError,
+
+    #[error("{0}")]
+    VcsError(#[from] vcs::Error),
+
+    #[error("Update task failed")]
+    TaskFailed,
+
+    #[error("Artifact uploads are not configured")]
+    ArtifactsDisabled,
+
+    #[error("{0}")]
+    ArtifactError(#[from] artifacts::Error),
+}
+
+impl From<AuthError> for PackageUpdateError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Unauthorized => PackageUpdateError::Unauthorized,
+            AuthError::Forbidden => PackageUpdateError::Forbidden,
+            AuthError::Database(e) => PackageUpdateError::DatabaseError(e),
+        }
+    }
 }
 
 impl warp::reject::Reject for PackageUpdateError {}
@@ -130,105 +125,388 @@ impl warp::reply::Reply for PackageUpdateError {
     fn into_response(self) -> warp::reply::Response {
         let msg = format!("{}", self);
         let code = match self {
-            PackageUpdateError::Unauthorized => StatusCode::from_u16(403).unwrap(),
+            PackageUpdateError::Unauthorized => StatusCode::from_u16(401).unwrap(),
+            PackageUpdateError::Forbidden => StatusCode::from_u16(403).unwrap(),
+            PackageUpdateError::DatabaseError(_) => StatusCode::from_u16(500).unwrap(),
             PackageUpdateError::UnsupportedRepo => StatusCode::from_u16(400).unwrap(),
             PackageUpdateError::RepoError(_) => StatusCode::from_u16(500).unwrap(),
+            PackageUpdateError::RemoveError(_) => StatusCode::from_u16(500).unwrap(),
+            PackageUpdateError::YankError(_) => StatusCode::from_u16(500).unwrap(),
+            PackageUpdateError::PromoteError(_) => StatusCode::from_u16(500).unwrap(),
+            PackageUpdateError::ArchWithoutPlatform => StatusCode::from_u16(400).unwrap(),
             PackageUpdateError::IndexError => StatusCode::from_u16(500).unwrap(),
             PackageUpdateError::VersionError(_) => StatusCode::from_u16(400).unwrap(),
+            PackageUpdateError::InvalidUploader(_) => StatusCode::from_u16(400).unwrap(),
+            PackageUpdateError::VcsError(_) => StatusCode::from_u16(500).unwrap(),
+            PackageUpdateError::TaskFailed => StatusCode::from_u16(500).unwrap(),
+            PackageUpdateError::ArtifactsDisabled => StatusCode::from_u16(404).unwrap(),
+            PackageUpdateError::ArtifactError(artifacts::Error::InvalidFilename) => {
+                StatusCode::from_u16(400).unwrap()
+            }
+            PackageUpdateError::ArtifactError(artifacts::Error::TooLarge(_)) => {
+                StatusCode::from_u16(413).unwrap()
+            }
+            PackageUpdateError::ArtifactError(_) => StatusCode::from_u16(500).unwrap(),
         };
         warp::reply::with_status(msg, code).into_response()
     }
 }
 
-async fn process_package_update_request(
+async fn authorize(
+    config: &Arc<Config>,
+    db: Database,
+    auth_token: String,
+    repo_id: &str,
+    package_id: &str,
+    channel: Option<&str>,
+) -> Result<Principal, PackageUpdateError> {
+    let config = Arc::clone(config);
+    let repo_id = repo_id.to_string();
+    let package_id = package_id.to_string();
+    let channel = channel.map(str::to_string);
+
+    // Token lookups hit SQLite, which blocks
+    let result = tokio::task::spawn_blocking(move || {
+        auth::authorize(
+            &db,
+            config.api_token.as_deref(),
+            &auth_token,
+            &repo_id,
+            &package_id,
+            channel.as_deref(),
+        )
+    })
+    .await;
+
+    match result {
+        Ok(Ok(v)) => {
+            log::info!("Authorized as {}", v);
+            Ok(v)
+        }
+        Ok(Err(e)) => Err(e.into()),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(PackageUpdateError::TaskFailed)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ArtifactQuery {
+    /// The channel the artifact will be released to, for checking scopes
+    #[serde(default)]
+    channel: Option<String>,
+}
+
+async fn process_artifact_upload(
     config: Arc<Config>,
-    svn: Arc<HashMap<String, Arc<Mutex<Subversion>>>>,
+    db: Database,
     repo_id: String,
     package_id: String,
-    req: PackageUpdateRequest,
+    filename: String,
+    query: ArtifactQuery,
     auth_token: String,
+    body: impl Stream<Item = Result<impl bytes::Buf, warp::Error>>,
 ) -> Result<Box<dyn warp::Reply>, Infallible> {
-    if !auth_token.starts_with("Bearer ") {
-        return Ok(Box::new(PackageUpdateError::Unauthorized));
+    let artifacts = match config.artifacts.as_ref() {
+        Some(v) => v,
+        None => return Ok(Box::new(PackageUpdateError::ArtifactsDisabled)),
+    };
+
+    if !config.repos.contains_key(&repo_id) {
+        return Ok(Box::new(PackageUpdateError::UnsupportedRepo));
     }
 
-    let candidate = auth_token.split(" ").skip(1).next().unwrap();
-    if candidate != config.api_token {
-        return Ok(Box::new(PackageUpdateError::Unauthorized));
+    if let Err(e) = authorize(
+        &config,
+        db,
+        auth_token,
+        &repo_id,
+        &package_id,
+        query.channel.as_deref(),
+    )
+    .await
+    {
+        return Ok(Box::new(e));
     }
 
-    if !config.repos.contains_key(&repo_id) {
-        return Ok(Box::new(PackageUpdateError::UnsupportedRepo));
+    match artifacts.store(&filename, body).await {
+        Ok(artifact) => Ok(Box::new(warp::reply::with_status(
+            warp::reply::json(&artifact),
+            StatusCode::CREATED,
+        ))),
+        Err(e) => {
+            log::error!("{:?}", e);
+            Ok(Box::new(PackageUpdateError::from(e)))
+        }
     }
+}
 
-    let repo_path = &config.repos[&repo_id];
+async fn process_package_update_request(
+    config: Arc<Config>,
+    queue: Queue,
+    db: Database,
+    repo_id: String,
+    package_id: String,
+    req: PackageUpdateRequest,
+    auth_token: String,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let principal = match authorize(
+        &config,
+        db,
+        auth_token,
+        &repo_id,
+        &package_id,
+        req.release.channel.as_deref(),
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => return Ok(Box::new(e)),
+    };
+
+    let version: pahkat_types::package::Version = match req.release.version.parse() {
+        Ok(v) => v,
+        Err(e) => return Ok(Box::new(PackageUpdateError::VersionError(e))),
+    };
+
+    let uploader = match req.uploader.as_ref().map(|x| x.parse::<Identity>()) {
+        Some(Ok(v)) => Some(v),
+        Some(Err(e)) => return Ok(Box::new(PackageUpdateError::InvalidUploader(e))),
+        None => None,
+    };
+
+    let update = queue::Update {
+        package: package_id,
+        version,
+        action: queue::Action::Publish(req.release),
+        uploader,
+        principal,
+    };
+
+    Ok(submit(&queue, &repo_id, update))
+}
+
+/// Queues an update, replying with the job and where to poll it.
+fn submit(queue: &Queue, repo_id: &str, update: queue::Update) -> Box<dyn warp::Reply> {
+    match queue.submit(repo_id, update) {
+        Ok(job) => {
+            let location = format!("/jobs/{}", job.id.to_simple());
+            Box::new(warp::reply::with_header(
+                warp::reply::with_status(warp::reply::json(&job), StatusCode::ACCEPTED),
+                "location",
+                location,
+            ))
+        }
+        Err(e) => Box::new(e),
+    }
+}
 
-    log::info!("Waiting for lock on repository...");
-    let svn = svn[&repo_id].lock().await;
+#[derive(Deserialize)]
+struct ReleaseQuery {
+    #[serde(default)]
+    channel: Option<String>,
 
-    log::info!("Got lock!");
+    /// Only remove the target for this platform, rather than the whole release
+    #[serde(default)]
+    platform: Option<String>,
 
-    loop {
-        log::info!("Updating repository...");
-        svn.revert().unwrap();
-        svn.cleanup().unwrap();
-        svn.update().unwrap();
+    #[serde(default)]
+    arch: Option<String>,
 
-        let version: pahkat_types::package::Version = match req.release.version.parse() {
-            Ok(v) => v,
-            Err(e) => return Ok(Box::new(PackageUpdateError::VersionError(e))),
-        };
+    /// The channel to promote the release into; stable if unset
+    #[serde(default)]
+    to: Option<String>,
 
-        let inner_req = pahkat_repomgr::package::update::Request::builder()
-            .repo_path(svn.path.clone().into())
-            .id(package_id.clone().into())
-            .version(Cow::Owned(version))
-            .channel(req.release.channel.as_ref().map(|x| Cow::Borrowed(&**x)))
-            .target(Cow::Borrowed(&req.release.target))
-            .url(None)
-            .build();
-
-        log::info!("Updating package...");
-        match pahkat_repomgr::package::update::update(inner_req) {
-            Ok(_) => {}
-            Err(e) => return Ok(Box::new(PackageUpdateError::RepoError(e))),
-        };
+    /// Drop prerelease tags from the promoted release's version
+    #[serde(default)]
+    strip_prerelease: bool,
 
-        log::info!("Updating index...");
-        match pahkat_repomgr::repo::indexing::index(
-            pahkat_repomgr::repo::indexing::Request::builder()
-                .path(svn.path.clone().into())
-                .build(),
-        ) {
-            Ok(_) => {}
-            Err(e) => return Ok(Box::new(PackageUpdateError::IndexError)),
-        };
+    /// Who the commit is attributed to, as `Name <email>`
+    #[serde(default)]
+    uploader: Option<String>,
+}
 
-        log::info!("Committing to repository...");
-        match svn.commit(
-            &repo_id,
-            &package_id,
-            &config.svn_username,
-            &config.svn_password,
-        ) {
-            Ok(_) => break,
-            Err(_) => {
-                log::error!("Blocked from committing; retrying in 5 seconds.");
-                tokio::time::delay_for(std::time::Duration::from_secs(5)).await;
-                continue;
+/// `stable` and an empty channel both name releases without a channel, as
+/// they are stored.
+fn normalize_channel(channel: Option<String>) -> Option<String> {
+    channel.filter(|x| !x.is_empty() && x != auth::STABLE_CHANNEL)
+}
+
+enum ReleaseChange {
+    Remove,
+    Yank(bool),
+    Promote,
+}
+
+async fn process_release_change(
+    change: ReleaseChange,
+    config: Arc<Config>,
+    queue: Queue,
+    db: Database,
+    repo_id: String,
+    package_id: String,
+    version: String,
+    mut query: ReleaseQuery,
+    auth_token: String,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    query.channel = normalize_channel(query.channel);
+    query.to = normalize_channel(query.to);
+
+    // A promotion publishes into its destination channel, but must also be
+    // allowed to change the channel the release comes from
+    let channel = match change {
+        ReleaseChange::Promote => {
+            let from = query.channel.as_deref();
+            let result = authorize(
+                &config,
+                db.clone(),
+                auth_token.clone(),
+                &repo_id,
+                &package_id,
+                from,
+            )
+            .await;
+            if let Err(e) = result {
+                return Ok(Box::new(e));
             }
+            query.to.as_deref()
         }
+        _ => query.channel.as_deref(),
+    };
+
+    let principal = match authorize(&config, db, auth_token, &repo_id, &package_id, channel).await {
+        Ok(v) => v,
+        Err(e) => return Ok(Box::new(e)),
+    };
+
+    let version: pahkat_types::package::Version = match version.parse() {
+        Ok(v) => v,
+        Err(e) => return Ok(Box::new(PackageUpdateError::VersionError(e))),
+    };
+
+    let uploader = match query.uploader.as_ref().map(|x| x.parse::<Identity>()) {
+        Some(Ok(v)) => Some(v),
+        Some(Err(e)) => return Ok(Box::new(PackageUpdateError::InvalidUploader(e))),
+        None => None,
+    };
+
+    let action = match change {
+        ReleaseChange::Remove => {
+            if query.platform.is_none() && query.arch.is_some() {
+                return Ok(Box::new(PackageUpdateError::ArchWithoutPlatform));
+            }
+            queue::Action::Remove {
+                channel: query.channel,
+                platform: query.platform,
+                arch: query.arch,
+            }
+        }
+        ReleaseChange::Yank(yanked) => queue::Action::Yank {
+            channel: query.channel,
+            yanked,
+        },
+        ReleaseChange::Promote => queue::Action::Promote {
+            from: query.channel,
+            to: query.to,
+            strip_prerelease: query.strip_prerelease,
+        },
+    };
+
+    let update = queue::Update {
+        package: package_id,
+        version,
+        action,
+        uploader,
+        principal,
+    };
+
+    Ok(submit(&queue, &repo_id, update))
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    pending_jobs: usize,
+}
+
+/// Reports 503 once shutdown has started. Connections are still accepted
+/// for `server.shutdown_grace` seconds, so load balancers can notice and stop
+/// sending requests before the server stops listening.
+async fn process_health(queue: Queue, shutdown: Shutdown) -> Result<impl warp::Reply, Infallible> {
+    let (status, code) = match shutdown.is_started() {
+        true => ("shutting_down", StatusCode::SERVICE_UNAVAILABLE),
+        false => ("ok", StatusCode::OK),
+    };
+
+    let health = Health {
+        status,
+        pending_jobs: queue.pending(),
+    };
+    Ok(warp::reply::with_status(warp::reply::json(&health), code))
+}
+
+/// Only tokens that may publish the job's package to its channel can see it.
+async fn process_job_status(
+    config: Arc<Config>,
+    queue: Queue,
+    db: Database,
+    id: String,
+    auth_token: String,
+) -> Result<Box<dyn warp::Reply>, warp::Rejection> {
+    let job = match id.parse::<uuid::Uuid>().ok().and_then(|id| queue.get(&id)) {
+        Some(v) => v,
+        None => return Err(warp::reject::not_found()),
+    };
+
+    let channel = job.channel.as_deref();
+    match authorize(&config, db, auth_token, &job.repo, &job.package, channel).await {
+        Ok(_) => Ok(Box::new(warp::reply::json(&job))),
+        Err(e) => Ok(Box::new(e)),
     }
+}
 
-    Ok(Box::new("Success"))
+#[derive(Serialize, Deserialize)]
+pub struct RepoConfig {
+    path: PathBuf,
+
+    #[serde(flatten)]
+    vcs: VcsConfig,
+
+    /// Endpoints notified when updates to this repository are published or fail
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    webhooks: Vec<WebhookConfig>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
-    svn_username: String,
-    svn_password: String,
-    api_token: String,
-    repos: HashMap<String, PathBuf>,
+    /// Deprecated: a shared secret that may publish to every repository. Use
+    /// `pahkat-server token mint` to create scoped tokens instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    api_token: Option<String>,
+
+    /// Where API tokens are stored
+    #[serde(default = "default_database_path")]
+    database_path: PathBuf,
+
+    /// Where uploaded artifacts are stored and served from. Uploads are
+    /// disabled if unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    artifacts: Option<ArtifactsConfig>,
+
+    /// Who commits made by the server are committed as
+    #[serde(default)]
+    committer: Identity,
+
+    /// Listen addresses, TLS and request limits
+    #[serde(default)]
+    server: ServerConfig,
+
+    repos: HashMap<String, RepoConfig>,
+}
+
+fn default_database_path() -> PathBuf {
+    "pahkat-server.sqlite".into()
 }
 
 mod filters {
@@ -236,7 +514,6 @@ mod filters {
     use std::collections::HashMap;
     use std::convert::Infallible;
     use std::sync::Arc;
-    use tokio::sync::Mutex;
     use warp::Filter;
 
     pub fn config(
@@ -246,12 +523,23 @@ mod filters {
         warp::any().map(move || Arc::clone(&config))
     }
 
-    pub fn svn(
-        svn: &Arc<HashMap<String, Arc<Mutex<Subversion>>>>,
-    ) -> impl Filter<Extract = (Arc<HashMap<String, Arc<Mutex<Subversion>>>>,), Error = Infallible> + Clone
-    {
-        let svn = Arc::clone(svn);
-        warp::any().map(move || Arc::clone(&svn))
+    pub fn queue(queue: &Queue) -> impl Filter<Extract = (Queue,), Error = Infallible> + Clone {
+        let queue = queue.clone();
+        warp::any().map(move || queue.clone())
+    }
+
+    pub fn shutdown(
+        shutdown: &Shutdown,
+    ) -> impl Filter<Extract = (Shutdown,), Error = Infallible> + Clone {
+        let shutdown = shutdown.clone();
+        warp::any().map(move || shutdown.clone())
+    }
+
+    pub fn database(
+        db: &Database,
+    ) -> impl Filter<Extract = (Database,), Error = Infallible> + Clone {
+        let db = db.clone();
+        warp::any().map(move || db.clone())
     }
 }
 
@@ -259,9 +547,114 @@ mod filters {
 struct Args {
     #[structopt(short, long)]
     config_path: PathBuf,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
 
-use tokio::sync::Mutex;
+#[derive(StructOpt)]
+enum Command {
+    /// Run the server (the default)
+    Serve,
+
+    /// Manage API tokens
+    Token(TokenCommand),
+}
+
+#[derive(StructOpt)]
+enum TokenCommand {
+    /// Create a token. It is only shown once.
+    Mint {
+        /// Name of the token's owner, used in commit trailers and to revoke it
+        name: String,
+
+        /// What the token may publish, as `<repo>:<package glob>[:<channel>,...]`.
+        /// `*` as the repo matches every repository; `stable` matches releases
+        /// without a channel.
+        #[structopt(short, long = "scope", required = true)]
+        scopes: Vec<auth::Scope>,
+    },
+
+    /// Revoke every active token belonging to an owner, or a single token
+    Revoke {
+        /// Owner whose active tokens are all revoked
+        #[structopt(required_unless = "id")]
+        name: Option<String>,
+
+        /// Revoke only the token with this id, as shown by `token list`
+        #[structopt(long, conflicts_with = "name")]
+        id: Option<uuid::Uuid>,
+    },
+
+    /// List tokens and their scopes
+    List,
+}
+
+fn token_command(db: &Database, command: TokenCommand) -> anyhow::Result<()> {
+    match command {
+        TokenCommand::Mint { name, scopes } => {
+            let token = auth::Token::generate();
+            let user_id = token.id.as_bytes().to_vec();
+
+            let user = NewUser {
+                id: user_id.clone(),
+                username: name,
+                token_hash: token.hash(),
+                created_at: chrono::Utc::now().naive_utc(),
+            };
+            let scopes = scopes
+                .into_iter()
+                .map(|scope| NewUserScope {
+                    user_id: user_id.clone(),
+                    repo: scope.repo,
+                    package: scope.package,
+                    channels: scope.channels.map(|x| x.join(",")),
+                })
+                .collect();
+
+            db.create_user(user, scopes)?;
+            println!("{}", token);
+        }
+        TokenCommand::Revoke { name, id } => match (id, name) {
+            (Some(id), _) => {
+                if !db.revoke_token(id.as_bytes())? {
+                    anyhow::bail!("No active token with id `{}`", id.to_simple());
+                }
+            }
+            (None, Some(name)) => {
+                if !db.revoke_user(&name)? {
+                    anyhow::bail!("No active token for `{}`", name);
+                }
+            }
+            (None, None) => anyhow::bail!("Expected an owner name or a token id"),
+        },
+        TokenCommand::List => {
+            for (user, scopes) in db.list_users()? {
+                let status = match user.revoked_at {
+                    Some(v) => format!("revoked {}", v),
+                    None => "active".to_string(),
+                };
+                let scopes = scopes
+                    .into_iter()
+                    .map(|x| auth::Scope::from(x).to_string())
+                    .collect::<Vec<_>>();
+                let id = uuid::Uuid::from_slice(&user.id)
+                    .map(|x| x.to_simple().to_string())
+                    .unwrap_or_else(|_| hex::encode(&user.id));
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    id,
+                    user.username,
+                    user.created_at,
+                    status,
+                    scopes.join(" ")
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -271,37 +664,157 @@ async fn main() -> anyhow::Result<()> {
     let config = std::fs::read_to_string(&args.config_path)?;
     let config: Arc<Config> = Arc::new(toml::from_str(&config)?);
 
-    let mut svn = HashMap::new();
-    for (key, value) in config.repos.iter() {
-        svn.insert(
-            key.to_string(),
-            Arc::new(Mutex::new(Subversion {
-                path: std::fs::canonicalize(value.clone()).unwrap(),
-            })),
+    let db = Database::new(&config.database_path.to_string_lossy())?;
+
+    match args.command {
+        Some(Command::Token(command)) => return token_command(&db, command),
+        Some(Command::Serve) | None => {}
+    }
+
+    if config.api_token.is_some() {
+        log::warn!(
+            "`api_token` is deprecated and may publish anything; mint scoped tokens instead"
         );
     }
-    let svn = Arc::new(svn);
 
-    log::info!("Repos: {:#?}", &config.repos);
+    let mut backends = HashMap::new();
+    for (key, value) in config.repos.iter() {
+        let path = std::fs::canonicalize(&value.path)?;
+        log::info!("Repo {}: {}", key, path.display());
+        backends.insert(key.to_string(), value.vcs.open(path, &config.committer));
+    }
+    let shutdown = Shutdown::listen(std::time::Duration::from_secs(config.server.shutdown_grace));
+    let webhooks = Webhooks::new();
+    let queue = Queue::new(&config, &db, &webhooks, backends);
 
     let package_update = warp::any()
         .and(warp::filters::method::patch())
         .and(filters::config(&config))
-        .and(filters::svn(&svn))
+        .and(filters::queue(&queue))
+        .and(filters::database(&db))
         .and(warp::path::param::<String>())
         .and(warp::path("packages"))
         .and(warp::path::param::<String>())
+        .and(warp::body::content_length_limit(
+            config.server.max_body_size,
+        ))
         .and(warp::body::json())
         .and(warp::header::<String>("authorization"))
         .and_then(process_package_update_request)
         .with(warp::log("pahkat_server::update_pkg"));
 
-    // let index = warp::any()
-    //     .map(|| "Hello!");
+    let max_artifact_size = config
+        .artifacts
+        .as_ref()
+        .map(|x| x.max_size)
+        .unwrap_or(u64::MAX);
 
-    warp::serve(package_update)
-        .run(([127, 0, 0, 1], 3030))
-        .await;
+    let artifact_upload = warp::any()
+        .and(warp::filters::method::post())
+        .and(filters::config(&config))
+        .and(filters::database(&db))
+        .and(warp::path::param::<String>())
+        .and(warp::path("packages"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("artifacts"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::query::<ArtifactQuery>())
+        .and(warp::header::<String>("authorization"))
+        .and(warp::body::content_length_limit(max_artifact_size))
+        .and(warp::body::stream())
+        .and_then(process_artifact_upload)
+        .with(warp::log("pahkat_server::upload_artifact"));
+
+    // `/<repo>/packages/<id>/releases/<version>`, for changes to a release
+    let release = warp::any()
+        .and(filters::config(&config))
+        .and(filters::queue(&queue))
+        .and(filters::database(&db))
+        .and(warp::path::param::<String>())
+        .and(warp::path("packages"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("releases"))
+        .and(warp::path::param::<String>());
+
+    let release_remove = warp::delete()
+        .map(|| ReleaseChange::Remove)
+        .and(release.clone())
+        .and(warp::path::end())
+        .and(warp::query::<ReleaseQuery>())
+        .and(warp::header::<String>("authorization"))
+        .and_then(process_release_change)
+        .with(warp::log("pahkat_server::remove_release"));
+
+    let release_yank = warp::post()
+        .map(|| ReleaseChange::Yank(true))
+        .and(release.clone())
+        .and(warp::path("yank"))
+        .and(warp::path::end())
+        .and(warp::query::<ReleaseQuery>())
+        .and(warp::header::<String>("authorization"))
+        .and_then(process_release_change)
+        .with(warp::log("pahkat_server::yank_release"));
+
+    let release_unyank = warp::post()
+        .map(|| ReleaseChange::Yank(false))
+        .and(release.clone())
+        .and(warp::path("unyank"))
+        .and(warp::path::end())
+        .and(warp::query::<ReleaseQuery>())
+        .and(warp::header::<String>("authorization"))
+        .and_then(process_release_change)
+        .with(warp::log("pahkat_server::unyank_release"));
+
+    let release_promote = warp::post()
+        .map(|| ReleaseChange::Promote)
+        .and(release)
+        .and(warp::path("promote"))
+        .and(warp::path::end())
+        .and(warp::query::<ReleaseQuery>())
+        .and(warp::header::<String>("authorization"))
+        .and_then(process_release_change)
+        .with(warp::log("pahkat_server::promote_release"));
+
+    let job_status = warp::get()
+        .and(filters::config(&config))
+        .and(filters::queue(&queue))
+        .and(filters::database(&db))
+        .and(warp::path("jobs"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::header::<String>("authorization"))
+        .and_then(process_job_status)
+        .with(warp::log("pahkat_server::job_status"));
+
+    let health = warp::get()
+        .and(filters::queue(&queue))
+        .and(filters::shutdown(&shutdown))
+        .and(warp::path("health"))
+        .and(warp::path::end())
+        .and_then(process_health);
+
+    let files = serve::routes(&config, &db).with(warp::log("pahkat_server::files"));
+
+    let routes = health
+        .or(package_update)
+        .or(artifact_upload)
+        .or(release_remove)
+        .or(release_yank)
+        .or(release_unyank)
+        .or(release_promote)
+        .or(job_status)
+        .or(stats::routes(&config, &db))
+        .or(files);
+
+    server::serve(&config.server, routes, &shutdown).await?;
+
+    // Connections are closed, so no more jobs can be queued
+    let timeout = std::time::Duration::from_secs(config.server.shutdown_timeout);
+    if !queue.drain(timeout).await {
+        anyhow::bail!("Shut down with publish jobs still pending");
+    }
+    log::info!("Shut down cleanly");
 
     Ok(())
 }
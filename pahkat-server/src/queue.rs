@@ -0,0 +1,723 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use pahkat_types::package::Version;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::auth::Principal;
+use crate::database::models::NewArtifactRelease;
+use crate::database::Database;
+use crate::vcs::{self, Identity, VcsBackend};
+use crate::webhooks::{Event, EventKind, PackageEvent, Webhooks};
+use crate::{Config, PackageUpdateError, Release};
+
+/// How many times a batch is redone after the remote changed underneath it.
+const MAX_ATTEMPTS: u32 = 5;
+
+/// How long a worker waits for more updates before starting a batch.
+const BATCH_DELAY: Duration = Duration::from_secs(2);
+
+/// How long finished jobs can still be looked up, in hours.
+const JOB_RETENTION_HOURS: i64 = 24;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Syncing,
+    Updating,
+    Indexing,
+    Committing,
+    /// Waiting to retry after the remote changed
+    Retrying,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobError {
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl From<&PackageUpdateError> for JobError {
+    fn from(e: &PackageUpdateError) -> Self {
+        let kind = match e {
+            PackageUpdateError::RepoError(_) => "package_update",
+            PackageUpdateError::RemoveError(_) => "release_remove",
+            PackageUpdateError::YankError(_) => "release_yank",
+            PackageUpdateError::PromoteError(_) => "release_promote",
+            PackageUpdateError::IndexError => "index",
+            PackageUpdateError::VcsError(vcs::Error::OutOfDate) => "out_of_date",
+            PackageUpdateError::VcsError(_) => "vcs",
+            _ => "internal",
+        };
+
+        JobError {
+            kind,
+            message: e.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running { stage: Stage, attempt: u32 },
+    Succeeded,
+    Failed { error: JobError },
+}
+
+impl JobStatus {
+    fn is_finished(&self) -> bool {
+        match self {
+            JobStatus::Succeeded | JobStatus::Failed { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// A queued package update, as reported by `GET /jobs/<id>`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: Uuid,
+    pub repo: String,
+    pub action: &'static str,
+    pub package: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(flatten)]
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What an update does to a package's release.
+pub enum Action {
+    Publish(Release),
+    /// Removes the release, or only its targets for a platform and arch
+    Remove {
+        channel: Option<String>,
+        platform: Option<String>,
+        arch: Option<String>,
+    },
+    Yank {
+        channel: Option<String>,
+        yanked: bool,
+    },
+    /// Copies the release in `from` into `to`, where `None` is stable
+    Promote {
+        from: Option<String>,
+        to: Option<String>,
+        strip_prerelease: bool,
+    },
+}
+
+impl Action {
+    pub fn channel(&self) -> Option<&str> {
+        match self {
+            Action::Publish(release) => release.channel.as_deref(),
+            Action::Remove { channel, .. } | Action::Yank { channel, .. } => channel.as_deref(),
+            Action::Promote { to, .. } => to.as_deref(),
+        }
+    }
+
+    fn kind(&self) -> vcs::ChangeKind {
+        match self {
+            Action::Publish(_) => vcs::ChangeKind::Update,
+            Action::Remove { .. } => vcs::ChangeKind::Remove,
+            Action::Yank { yanked: true, .. } => vcs::ChangeKind::Yank,
+            Action::Yank { yanked: false, .. } => vcs::ChangeKind::Unyank,
+            Action::Promote { .. } => vcs::ChangeKind::Promote,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.kind() {
+            vcs::ChangeKind::Update => "publish",
+            vcs::ChangeKind::Remove => "remove",
+            vcs::ChangeKind::Yank => "yank",
+            vcs::ChangeKind::Unyank => "unyank",
+            vcs::ChangeKind::Promote => "promote",
+        }
+    }
+
+    fn event(&self) -> EventKind {
+        match self.kind() {
+            vcs::ChangeKind::Update => EventKind::Published,
+            vcs::ChangeKind::Remove => EventKind::Removed,
+            vcs::ChangeKind::Yank => EventKind::Yanked,
+            vcs::ChangeKind::Unyank => EventKind::Unyanked,
+            vcs::ChangeKind::Promote => EventKind::Promoted,
+        }
+    }
+}
+
+/// A validated and authorized change waiting to be published.
+pub struct Update {
+    pub package: String,
+    pub version: Version,
+    pub action: Action,
+    pub uploader: Option<Identity>,
+    pub principal: Principal,
+}
+
+struct Queued {
+    job_id: Uuid,
+    update: Update,
+}
+
+impl From<&Queued> for PackageEvent {
+    fn from(queued: &Queued) -> Self {
+        let update = &queued.update;
+        PackageEvent {
+            id: update.package.clone(),
+            version: update.version.to_string(),
+            channel: update.action.channel().map(str::to_string),
+            targets: match &update.action {
+                Action::Publish(release) => vec![release.target.clone()],
+                _ => vec![],
+            },
+            job: queued.job_id,
+        }
+    }
+}
+
+type Jobs = Arc<Mutex<HashMap<Uuid, Job>>>;
+
+/// Publishes updates in the background, with one worker per repository so
+/// updates to a repository never race each other.
+#[derive(Clone)]
+pub struct Queue {
+    jobs: Jobs,
+    workers: Arc<HashMap<String, mpsc::UnboundedSender<Queued>>>,
+}
+
+impl Queue {
+    pub fn new(
+        config: &Arc<Config>,
+        db: &Database,
+        webhooks: &Webhooks,
+        backends: HashMap<String, Box<dyn VcsBackend>>,
+    ) -> Queue {
+        let jobs: Jobs = Default::default();
+
+        let workers = backends
+            .into_iter()
+            .map(|(repo_id, vcs)| {
+                let (tx, rx) = mpsc::unbounded_channel();
+                let worker = Worker {
+                    repo_id: repo_id.clone(),
+                    vcs: Arc::from(vcs),
+                    config: Arc::clone(config),
+                    db: db.clone(),
+                    webhooks: webhooks.clone(),
+                    jobs: Arc::clone(&jobs),
+                };
+                tokio::spawn(worker.run(rx));
+                (repo_id, tx)
+            })
+            .collect();
+
+        Queue {
+            jobs,
+            workers: Arc::new(workers),
+        }
+    }
+
+    pub fn submit(&self, repo_id: &str, update: Update) -> Result<Job, PackageUpdateError> {
+        let worker = match self.workers.get(repo_id) {
+            Some(v) => v,
+            None => return Err(PackageUpdateError::UnsupportedRepo),
+        };
+
+        let now = Utc::now();
+        let job = Job {
+            id: Uuid::new_v4(),
+            repo: repo_id.to_string(),
+            action: update.action.name(),
+            package: update.package.clone(),
+            version: update.version.to_string(),
+            channel: update.action.channel().map(str::to_string),
+            status: JobStatus::Queued,
+            created_at: now,
+            updated_at: now,
+        };
+
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            jobs.retain(|_, x| {
+                !x.status.is_finished()
+                    || now - x.updated_at < chrono::Duration::hours(JOB_RETENTION_HOURS)
+            });
+            jobs.insert(job.id, job.clone());
+        }
+
+        let queued = Queued {
+            job_id: job.id,
+            update,
+        };
+        if worker.send(queued).is_err() {
+            log::error!("Worker for {} has stopped", repo_id);
+            set_status(
+                &self.jobs,
+                &[job.id],
+                JobStatus::Failed {
+                    error: JobError::from(&PackageUpdateError::TaskFailed),
+                },
+            );
+            return Err(PackageUpdateError::TaskFailed);
+        }
+
+        log::info!("Queued job {} for {}/{}", job.id, repo_id, job.package);
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Job> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+
+    /// How many jobs are queued or running.
+    pub fn pending(&self) -> usize {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values().filter(|x| !x.status.is_finished()).count()
+    }
+
+    /// Waits for queued and running jobs to finish. Returns false if some
+    /// were still pending after `timeout`.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            let pending = self.pending();
+            if pending == 0 {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                log::warn!("Giving up on {} unfinished job(s)", pending);
+                return false;
+            }
+
+            log::info!("Waiting for {} job(s) to finish...", pending);
+            tokio::time::delay_for(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+fn set_status(jobs: &Jobs, ids: &[Uuid], status: JobStatus) {
+    let now = Utc::now();
+    let mut jobs = jobs.lock().unwrap();
+    for id in ids {
+        if let Some(job) = jobs.get_mut(id) {
+            job.status = status.clone();
+            job.updated_at = now;
+        }
+    }
+}
+
+/// A package's descriptor as it was before an update was applied.
+struct Snapshot {
+    path: PathBuf,
+    data: Option<Vec<u8>>,
+}
+
+impl Snapshot {
+    fn take(repo_path: &Path, package: &str) -> Snapshot {
+        let path = repo_path.join("packages").join(package).join("index.toml");
+        let data = fs::read(&path).ok();
+        Snapshot { path, data }
+    }
+
+    /// Puts the descriptor back, removing it if the update created it.
+    fn restore(self) -> io::Result<()> {
+        match self.data {
+            Some(data) => fs::write(&self.path, data),
+            None => match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => {
+                    // Only removes the package directory if it is now empty
+                    if let Some(dir) = self.path.parent() {
+                        let _ = fs::remove_dir(dir);
+                    }
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+struct Worker {
+    repo_id: String,
+    vcs: Arc<dyn VcsBackend>,
+    config: Arc<Config>,
+    db: Database,
+    webhooks: Webhooks,
+    jobs: Jobs,
+}
+
+impl Worker {
+    async fn run(self, mut rx: mpsc::UnboundedReceiver<Queued>) {
+        let worker = Arc::new(self);
+
+        while let Some(first) = rx.recv().await {
+            // Let a burst of uploads, such as one per platform, arrive so
+            // they share an index rebuild and commit
+            tokio::time::delay_for(BATCH_DELAY).await;
+
+            let mut batch = vec![first];
+            while let Some(Some(next)) = rx.recv().now_or_never() {
+                batch.push(next);
+            }
+
+            let ids = batch.iter().map(|x| x.job_id).collect::<Vec<_>>();
+            log::info!("Publishing {} update(s) to {}", batch.len(), worker.repo_id);
+
+            let task_worker = Arc::clone(&worker);
+            let result = tokio::task::spawn_blocking(move || task_worker.publish(batch)).await;
+
+            if let Err(e) = result {
+                log::error!("{}", e);
+                let error = JobError::from(&PackageUpdateError::TaskFailed);
+
+                // Only jobs the task didn't get to finish are failed
+                let mut failed = vec![];
+                {
+                    let mut jobs = worker.jobs.lock().unwrap();
+                    for id in ids.iter() {
+                        if let Some(job) = jobs.get_mut(id).filter(|x| !x.status.is_finished()) {
+                            job.status = JobStatus::Failed {
+                                error: error.clone(),
+                            };
+                            job.updated_at = Utc::now();
+
+                            // The updates went with the task, so targets are unknown
+                            failed.push(PackageEvent {
+                                id: job.package.clone(),
+                                version: job.version.clone(),
+                                channel: job.channel.clone(),
+                                targets: vec![],
+                                job: job.id,
+                            });
+                        }
+                    }
+                }
+
+                if !failed.is_empty() {
+                    worker.notify(EventKind::Failed, failed, Some(error));
+                }
+            }
+        }
+    }
+
+    fn set_status(&self, ids: &[Uuid], status: JobStatus) {
+        set_status(&self.jobs, ids, status);
+    }
+
+    fn fail(&self, failed: &[Queued], error: &PackageUpdateError) {
+        log::error!("{:?}", error);
+        let ids = failed.iter().map(|x| x.job_id).collect::<Vec<_>>();
+        let error = JobError::from(error);
+        self.set_status(
+            &ids,
+            JobStatus::Failed {
+                error: error.clone(),
+            },
+        );
+        self.notify(
+            EventKind::Failed,
+            failed.iter().map(PackageEvent::from).collect(),
+            Some(error),
+        );
+    }
+
+    fn notify(&self, kind: EventKind, packages: Vec<PackageEvent>, error: Option<JobError>) {
+        let hooks = match self.config.repos.get(&self.repo_id) {
+            Some(v) => &v.webhooks,
+            None => return,
+        };
+
+        self.webhooks.send(
+            hooks,
+            &Event {
+                event: kind,
+                repo: self.repo_id.clone(),
+                packages,
+                error,
+                timestamp: Utc::now(),
+            },
+        );
+    }
+
+    /// Sends an event such as `published` for each update and
+    /// `index_rebuilt` for the batch, once they have been committed.
+    fn notify_published(&self, published: &[Queued]) {
+        let packages = published.iter().map(PackageEvent::from).collect::<Vec<_>>();
+        for (queued, package) in published.iter().zip(packages.iter()) {
+            self.notify(queued.update.action.event(), vec![package.clone()], None);
+        }
+        self.notify(EventKind::IndexRebuilt, packages, None);
+    }
+
+    /// Applies a batch of updates, then indexes and commits once. An update
+    /// that can't be applied fails alone; failing to index or commit fails
+    /// the whole batch.
+    fn publish(&self, batch: Vec<Queued>) {
+        let mut pending = batch;
+
+        for attempt in 1..=MAX_ATTEMPTS {
+            let ids = pending.iter().map(|x| x.job_id).collect::<Vec<_>>();
+            let running = |stage| JobStatus::Running { stage, attempt };
+
+            log::info!("Updating repository...");
+            self.set_status(&ids, running(Stage::Syncing));
+            if let Err(e) = self.vcs.sync() {
+                return self.fail(&pending, &e.into());
+            }
+
+            self.set_status(&ids, running(Stage::Updating));
+            let mut applied = vec![];
+            let mut updates = pending.into_iter();
+            while let Some(queued) = updates.next() {
+                let snapshot = Snapshot::take(self.vcs.path(), &queued.update.package);
+                let e = match self.apply(&queued.update) {
+                    Ok(()) => {
+                        applied.push(queued);
+                        continue;
+                    }
+                    Err(e) => e,
+                };
+                self.fail(std::slice::from_ref(&queued), &e);
+
+                // Later updates and the commit must not pick up whatever the
+                // failed update left behind
+                if let Err(e) = snapshot.restore() {
+                    log::error!("Failed to restore {}: {}", queued.update.package, e);
+                    applied.extend(updates);
+                    return self.fail(&applied, &PackageUpdateError::TaskFailed);
+                }
+            }
+            pending = applied;
+
+            if pending.is_empty() {
+                return;
+            }
+            let ids = pending.iter().map(|x| x.job_id).collect::<Vec<_>>();
+
+            log::info!("Updating index...");
+            self.set_status(&ids, running(Stage::Indexing));
+            if let Err(e) = pahkat_repomgr::repo::indexing::index(
+                pahkat_repomgr::repo::indexing::Request::builder()
+                    .path(self.vcs.path().into())
+                    .build(),
+            ) {
+                log::error!("{:?}", e);
+                return self.fail(&pending, &PackageUpdateError::IndexError);
+            }
+
+            log::info!("Committing to repository...");
+            self.set_status(&ids, running(Stage::Committing));
+            let versions = pending
+                .iter()
+                .map(|x| x.update.version.to_string())
+                .collect::<Vec<_>>();
+            let authorized_by = pending
+                .iter()
+                .map(|x| x.update.principal.to_string())
+                .collect::<Vec<_>>();
+            let commit = vcs::Commit {
+                repo: &self.repo_id,
+                changes: pending
+                    .iter()
+                    .enumerate()
+                    .map(|(i, x)| vcs::Change {
+                        kind: x.update.action.kind(),
+                        package: &x.update.package,
+                        version: &versions[i],
+                        channel: x.update.action.channel(),
+                        uploader: x.update.uploader.as_ref(),
+                        authorized_by: Some(&authorized_by[i]),
+                    })
+                    .collect(),
+            };
+
+            match self.vcs.commit(&commit) {
+                Ok(()) => {
+                    self.record_artifacts(&pending);
+                    self.set_status(&ids, JobStatus::Succeeded);
+                    return self.notify_published(&pending);
+                }
+                Err(vcs::Error::OutOfDate) if attempt < MAX_ATTEMPTS => {
+                    log::warn!(
+                        "Repository changed during update; retrying ({}/{})",
+                        attempt,
+                        MAX_ATTEMPTS
+                    );
+                    self.set_status(&ids, running(Stage::Retrying));
+                    std::thread::sleep(Duration::from_secs(5 * attempt as u64));
+                }
+                Err(e) => return self.fail(&pending, &e.into()),
+            }
+        }
+    }
+
+    fn apply(&self, update: &Update) -> Result<(), PackageUpdateError> {
+        match &update.action {
+            Action::Publish(release) => self.publish_release(update, release),
+            Action::Remove {
+                channel,
+                platform,
+                arch,
+            } => {
+                let request = pahkat_repomgr::release::remove::Request::builder()
+                    .repo_path(self.vcs.path().into())
+                    .id(Cow::Borrowed(update.package.as_str()))
+                    .version(Cow::Borrowed(&update.version))
+                    .channel(channel.as_deref().map(Cow::Borrowed))
+                    .platform(platform.as_deref().map(Cow::Borrowed))
+                    .arch(arch.as_deref().map(Cow::Borrowed))
+                    .build();
+
+                log::info!("Removing release of {}...", update.package);
+                pahkat_repomgr::release::remove::remove(request)?;
+                Ok(())
+            }
+            Action::Yank { channel, yanked } => {
+                let request = pahkat_repomgr::release::yank::Request::builder()
+                    .repo_path(self.vcs.path().into())
+                    .id(Cow::Borrowed(update.package.as_str()))
+                    .version(Cow::Borrowed(&update.version))
+                    .channel(channel.as_deref().map(Cow::Borrowed))
+                    .yanked(*yanked)
+                    .build();
+
+                log::info!("Yanking release of {}...", update.package);
+                pahkat_repomgr::release::yank::yank(request)?;
+                Ok(())
+            }
+            Action::Promote {
+                from,
+                to,
+                strip_prerelease,
+            } => {
+                let request = pahkat_repomgr::release::promote::Request::builder()
+                    .repo_path(self.vcs.path().into())
+                    .id(Cow::Borrowed(update.package.as_str()))
+                    .version(Cow::Borrowed(&update.version))
+                    .from(from.as_deref().map(Cow::Borrowed))
+                    .to(to.as_deref().map(Cow::Borrowed))
+                    .strip_prerelease(*strip_prerelease)
+                    .build();
+
+                log::info!("Promoting release of {}...", update.package);
+                pahkat_repomgr::release::promote::promote(request)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn publish_release(
+        &self,
+        update: &Update,
+        release: &Release,
+    ) -> Result<(), PackageUpdateError> {
+        // Payloads pointing at an uploaded artifact get their metadata from it
+        let artifact = self
+            .config
+            .artifacts
+            .as_ref()
+            .and_then(|x| x.resolve(release.target.payload.url()));
+
+        let request = pahkat_repomgr::package::update::Request::builder()
+            .repo_path(self.vcs.path().into())
+            .id(Cow::Borrowed(update.package.as_str()))
+            .version(Cow::Borrowed(&update.version))
+            .channel(release.channel.as_deref().map(Cow::Borrowed))
+            .targets(Cow::Owned(vec![release.target.clone()]))
+            .url(None)
+            .artifact(artifact.map(|(_, path)| Cow::Owned(path)))
+            .build();
+
+        log::info!("Updating package {}...", update.package);
+        pahkat_repomgr::package::update::update(request)?;
+        Ok(())
+    }
+
+    /// Remembers which release each uploaded artifact was published as, so
+    /// downloads of it can be counted against the package.
+    fn record_artifacts(&self, published: &[Queued]) {
+        let artifacts = match self.config.artifacts.as_ref() {
+            Some(v) => v,
+            None => return,
+        };
+
+        for update in published.iter().map(|x| &x.update) {
+            let target = match &update.action {
+                Action::Publish(release) => &release.target,
+                _ => continue,
+            };
+            let sha256 = match artifacts.resolve(target.payload.url()) {
+                Some((sha256, _)) => sha256,
+                None => continue,
+            };
+
+            let result = self.db.create_artifact_release(NewArtifactRelease {
+                sha256,
+                repo: self.repo_id.clone(),
+                package_id: update.package.clone(),
+                version: update.version.to_string(),
+                platform: target.platform.clone(),
+                arch: target.arch.clone(),
+                created_at: Utc::now().naive_utc(),
+            });
+
+            if let Err(e) = result {
+                log::error!("Failed to record artifact for {}: {:?}", update.package, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_restores_descriptor() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_dir = dir.path().join("packages").join("speller");
+        fs::create_dir_all(&pkg_dir).unwrap();
+        fs::write(pkg_dir.join("index.toml"), "before").unwrap();
+
+        let snapshot = Snapshot::take(dir.path(), "speller");
+        fs::write(pkg_dir.join("index.toml"), "half writ").unwrap();
+        snapshot.restore().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(pkg_dir.join("index.toml")).unwrap(),
+            "before"
+        );
+    }
+
+    #[test]
+    fn snapshot_removes_created_descriptor() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_dir = dir.path().join("packages").join("speller");
+
+        let snapshot = Snapshot::take(dir.path(), "speller");
+        fs::create_dir_all(&pkg_dir).unwrap();
+        fs::write(pkg_dir.join("index.toml"), "half writ").unwrap();
+        snapshot.restore().unwrap();
+        assert!(!pkg_dir.exists());
+
+        // Nothing was written at all
+        Snapshot::take(dir.path(), "speller").restore().unwrap();
+    }
+}
@@ -0,0 +1,595 @@
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use headers::{
+    AcceptRanges, CacheControl, ContentLength, ContentRange, ContentType, ETag, HeaderMapExt,
+    IfModifiedSince, IfNoneMatch, IfRange, LastModified,
+};
+use hyper::Body;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use warp::http::{header, HeaderMap, Method, Response, StatusCode};
+use warp::{Filter, Rejection};
+
+use crate::database::Database;
+use crate::Config;
+
+/// How long clients may use repository metadata before revalidating it.
+const METADATA_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Artifacts are content-addressed, so a stored artifact never changes.
+const ARTIFACT_MAX_AGE: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+enum Kind {
+    Metadata,
+    Artifact { sha256: String },
+}
+
+/// Maps a request path to a file in a repository or the artifact store.
+/// Anything else, including other files in a repository, is not served.
+fn resolve(config: &Config, path: &str) -> Option<(PathBuf, Kind)> {
+    let segments = path.split('/').collect::<Vec<_>>();
+    match segments.as_slice() {
+        ["artifacts", sha256, filename] => {
+            let path = config.artifacts.as_ref()?.path_of(sha256, filename)?;
+            Some((
+                path,
+                Kind::Artifact {
+                    sha256: sha256.to_string(),
+                },
+            ))
+        }
+        [repo, "index.toml"] => {
+            let repo = config.repos.get(*repo)?;
+            Some((repo.path.join("index.toml"), Kind::Metadata))
+        }
+        [repo, "packages", "index.bin"] => {
+            let repo = config.repos.get(*repo)?;
+            Some((repo.path.join("packages").join("index.bin"), Kind::Metadata))
+        }
+        [repo, "strings", name] if is_strings_file(name) => {
+            let repo = config.repos.get(*repo)?;
+            Some((repo.path.join("strings").join(name), Kind::Metadata))
+        }
+        _ => None,
+    }
+}
+
+fn is_strings_file(name: &str) -> bool {
+    match name.strip_suffix(".toml") {
+        Some(lang) => {
+            !lang.is_empty()
+                && lang
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        }
+        None => false,
+    }
+}
+
+fn content_type(path: &Path) -> ContentType {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("toml") => "application/toml".parse().unwrap(),
+        _ => ContentType::octet_stream(),
+    }
+}
+
+/// Serves repository metadata and hosted artifacts with ETag and
+/// Last-Modified validation. Artifacts also support byte ranges.
+pub fn routes(
+    config: &Arc<Config>,
+    db: &Database,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    warp::get()
+        .or(warp::head())
+        .unify()
+        .and(crate::filters::config(config))
+        .and(crate::filters::database(db))
+        .and(warp::method())
+        .and(warp::path::tail())
+        .and(warp::header::headers_cloned())
+        .and_then(serve)
+}
+
+async fn serve(
+    config: Arc<Config>,
+    db: Database,
+    method: Method,
+    tail: warp::path::Tail,
+    headers: HeaderMap,
+) -> Result<Response<Body>, Rejection> {
+    let (path, kind) = match resolve(&config, tail.as_str()) {
+        Some(v) => v,
+        None => return Err(warp::reject::not_found()),
+    };
+
+    let result = match kind {
+        Kind::Metadata => serve_metadata(&path, &headers).await,
+        Kind::Artifact { sha256 } => {
+            let result = serve_artifact(&path, &sha256, &headers).await;
+            if let Ok(response) = result.as_ref() {
+                if method == Method::GET && is_download_start(response) {
+                    crate::stats::record_artifact_download(db, sha256);
+                }
+            }
+            result
+        }
+    };
+
+    match result {
+        Ok(v) => Ok(v),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(warp::reject::not_found()),
+        Err(e) => {
+            log::error!("Failed to serve {}: {}", path.display(), e);
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+            Ok(response)
+        }
+    }
+}
+
+/// Whether a response sends the start of an artifact, so resumed and
+/// segmented downloads are only counted once.
+fn is_download_start(response: &Response<Body>) -> bool {
+    match response.status() {
+        StatusCode::OK => true,
+        StatusCode::PARTIAL_CONTENT => response
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.starts_with("bytes 0-"))
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Builds a response with the validators and cache headers shared by every
+/// response for a file, including 304s.
+fn response(
+    status: StatusCode,
+    etag: &ETag,
+    last_modified: Option<&LastModified>,
+    max_age: Duration,
+    body: Body,
+) -> Response<Body> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+
+    let headers = response.headers_mut();
+    headers.typed_insert(etag.clone());
+    headers.typed_insert(CacheControl::new().with_public().with_max_age(max_age));
+    if let Some(last_modified) = last_modified {
+        headers.typed_insert(last_modified.clone());
+    }
+
+    response
+}
+
+/// Whether the client's cached copy is still current. `If-None-Match` takes
+/// precedence over `If-Modified-Since`, as per RFC 7232.
+fn is_not_modified(headers: &HeaderMap, etag: &ETag, modified: Option<&LastModified>) -> bool {
+    if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+        return !if_none_match.precondition_passes(etag);
+    }
+
+    match (headers.typed_get::<IfModifiedSince>(), modified) {
+        (Some(since), Some(modified)) => !since.is_modified(modified.clone().into()),
+        _ => false,
+    }
+}
+
+async fn serve_metadata(path: &Path, headers: &HeaderMap) -> io::Result<Response<Body>> {
+    let metadata = tokio::fs::metadata(path).await?;
+    let last_modified = metadata.modified().ok().map(LastModified::from);
+
+    // Metadata files are small, so they are hashed for a strong ETag
+    let data = tokio::fs::read(path).await?;
+    let hash = Sha256::digest(&data);
+    let etag = format!("\"{}\"", hex::encode(&hash[..16]))
+        .parse::<ETag>()
+        .unwrap();
+
+    if is_not_modified(headers, &etag, last_modified.as_ref()) {
+        return Ok(response(
+            StatusCode::NOT_MODIFIED,
+            &etag,
+            last_modified.as_ref(),
+            METADATA_MAX_AGE,
+            Body::empty(),
+        ));
+    }
+
+    let len = data.len() as u64;
+    let mut response = response(
+        StatusCode::OK,
+        &etag,
+        last_modified.as_ref(),
+        METADATA_MAX_AGE,
+        Body::from(data),
+    );
+    response.headers_mut().typed_insert(ContentLength(len));
+    response.headers_mut().typed_insert(content_type(path));
+    Ok(response)
+}
+
+async fn serve_artifact(
+    path: &Path,
+    sha256: &str,
+    headers: &HeaderMap,
+) -> io::Result<Response<Body>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    let last_modified = metadata.modified().ok().map(LastModified::from);
+    let len = metadata.len();
+
+    let etag = format!("\"{}\"", sha256).parse::<ETag>().unwrap();
+
+    if is_not_modified(headers, &etag, last_modified.as_ref()) {
+        return Ok(response(
+            StatusCode::NOT_MODIFIED,
+            &etag,
+            last_modified.as_ref(),
+            ARTIFACT_MAX_AGE,
+            Body::empty(),
+        ));
+    }
+
+    // A range only applies if the client's copy is the one we have
+    let range = match headers.typed_get::<IfRange>() {
+        Some(if_range) if if_range.is_modified(Some(&etag), last_modified.as_ref()) => None,
+        _ => headers.get(header::RANGE).and_then(|x| x.to_str().ok()),
+    };
+
+    let (status, start, end) = match range.map(|x| parse_range(x, len)) {
+        None | Some(Range::Ignored) => (StatusCode::OK, 0, len),
+        Some(Range::Satisfiable(start, end)) => (StatusCode::PARTIAL_CONTENT, start, end),
+        Some(Range::Unsatisfiable) => {
+            let mut response = response(
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                &etag,
+                last_modified.as_ref(),
+                ARTIFACT_MAX_AGE,
+                Body::empty(),
+            );
+            response
+                .headers_mut()
+                .typed_insert(ContentRange::unsatisfied_bytes(len));
+            return Ok(response);
+        }
+    };
+
+    if start > 0 {
+        tokio::io::AsyncSeekExt::seek(&mut file, SeekFrom::Start(start)).await?;
+    }
+
+    let mut response = response(
+        status,
+        &etag,
+        last_modified.as_ref(),
+        ARTIFACT_MAX_AGE,
+        Body::wrap_stream(read_stream(file, end - start)),
+    );
+
+    let response_headers = response.headers_mut();
+    response_headers.typed_insert(AcceptRanges::bytes());
+    response_headers.typed_insert(ContentLength(end - start));
+    response_headers.typed_insert(content_type(path));
+    if status == StatusCode::PARTIAL_CONTENT {
+        if let Ok(content_range) = ContentRange::bytes(start..end, len) {
+            response_headers.typed_insert(content_range);
+        }
+    }
+
+    Ok(response)
+}
+
+enum Range {
+    /// Not a single byte range we understand, so the whole file is sent
+    Ignored,
+    Unsatisfiable,
+    /// Start and exclusive end offsets
+    Satisfiable(u64, u64),
+}
+
+/// Parses a single `bytes=` range against a file of `len` bytes. Multiple
+/// ranges are not supported, and are answered with the whole file.
+fn parse_range(header: &str, len: u64) -> Range {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(v) if !v.contains(',') => v.trim(),
+        _ => return Range::Ignored,
+    };
+
+    let mut chunks = spec.splitn(2, '-');
+    let (start, end) = match (chunks.next(), chunks.next()) {
+        (Some(start), Some(end)) => (start.trim(), end.trim()),
+        _ => return Range::Ignored,
+    };
+
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // `bytes=-N` is the last N bytes
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return Range::Unsatisfiable;
+            }
+            (len.saturating_sub(suffix), len)
+        }
+        (Ok(start), Err(_)) if end.is_empty() => (start, len),
+        (Ok(start), Ok(end)) if start <= end => (start, (end + 1).min(len)),
+        _ => return Range::Ignored,
+    };
+
+    if start >= len {
+        Range::Unsatisfiable
+    } else {
+        Range::Satisfiable(start, end)
+    }
+}
+
+fn read_stream(
+    file: tokio::fs::File,
+    remaining: u64,
+) -> impl futures::Stream<Item = io::Result<Vec<u8>>> {
+    futures::stream::unfold((file, remaining), |(mut file, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+
+        let mut buf = vec![0u8; (CHUNK_SIZE as u64).min(remaining) as usize];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), (file, remaining - n as u64)))
+            }
+            Err(e) => Some((Err(e), (file, 0))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    /// A 100 byte artifact, where each byte is its own offset.
+    fn artifact() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.txz");
+        std::fs::write(&path, (0..100u8).collect::<Vec<_>>()).unwrap();
+        (dir, path)
+    }
+
+    fn headers(values: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.insert(name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    async fn get(path: &Path, headers: &HeaderMap) -> (StatusCode, HeaderMap, Vec<u8>) {
+        let response = serve_artifact(path, "abcd", headers).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, headers, body.to_vec())
+    }
+
+    fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+        headers.get(name).map(|x| x.to_str().unwrap())
+    }
+
+    fn satisfiable(range: Range) -> Option<(u64, u64)> {
+        match range {
+            Range::Satisfiable(start, end) => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(satisfiable(parse_range("bytes=0-9", 100)), Some((0, 10)));
+        assert_eq!(satisfiable(parse_range("bytes=10-19", 100)), Some((10, 20)));
+        assert_eq!(satisfiable(parse_range("bytes=90-", 100)), Some((90, 100)));
+        // The end is clamped to the file
+        assert_eq!(
+            satisfiable(parse_range("bytes=90-200", 100)),
+            Some((90, 100))
+        );
+        assert_eq!(
+            satisfiable(parse_range(" bytes= 5 - 5 ", 100)),
+            Some((5, 6))
+        );
+    }
+
+    #[test]
+    fn suffix_ranges() {
+        assert_eq!(satisfiable(parse_range("bytes=-10", 100)), Some((90, 100)));
+        assert_eq!(satisfiable(parse_range("bytes=-500", 100)), Some((0, 100)));
+        assert!(matches!(parse_range("bytes=-0", 100), Range::Unsatisfiable));
+    }
+
+    #[test]
+    fn unsatisfiable_ranges() {
+        assert!(matches!(
+            parse_range("bytes=100-", 100),
+            Range::Unsatisfiable
+        ));
+        assert!(matches!(
+            parse_range("bytes=200-300", 100),
+            Range::Unsatisfiable
+        ));
+        assert!(matches!(parse_range("bytes=0-", 0), Range::Unsatisfiable));
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        for header in &[
+            "bytes=0-9,20-29",
+            "items=0-9",
+            "bytes=9-0",
+            "bytes=a-b",
+            "bytes=-",
+            "bytes=10",
+        ] {
+            assert!(
+                matches!(parse_range(header, 100), Range::Ignored),
+                "{} was not ignored",
+                header
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn full_artifact() {
+        let (_dir, path) = artifact();
+
+        let (status, headers, body) = get(&path, &HeaderMap::new()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, (0..100u8).collect::<Vec<_>>());
+        assert_eq!(header_str(&headers, header::CONTENT_LENGTH), Some("100"));
+        assert_eq!(header_str(&headers, header::ACCEPT_RANGES), Some("bytes"));
+        assert_eq!(header_str(&headers, header::ETAG), Some("\"abcd\""));
+        assert!(headers.get(header::CONTENT_RANGE).is_none());
+    }
+
+    #[tokio::test]
+    async fn partial_artifact() {
+        let (_dir, path) = artifact();
+
+        let (status, headers, body) =
+            get(&path, &self::headers(&[(header::RANGE, "bytes=10-19")])).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, (10..20u8).collect::<Vec<_>>());
+        assert_eq!(header_str(&headers, header::CONTENT_LENGTH), Some("10"));
+        assert_eq!(
+            header_str(&headers, header::CONTENT_RANGE),
+            Some("bytes 10-19/100")
+        );
+
+        let (status, headers, body) =
+            get(&path, &self::headers(&[(header::RANGE, "bytes=-5")])).await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, (95..100u8).collect::<Vec<_>>());
+        assert_eq!(
+            header_str(&headers, header::CONTENT_RANGE),
+            Some("bytes 95-99/100")
+        );
+    }
+
+    #[tokio::test]
+    async fn unsatisfiable_artifact_range() {
+        let (_dir, path) = artifact();
+
+        let (status, headers, body) =
+            get(&path, &self::headers(&[(header::RANGE, "bytes=100-")])).await;
+
+        assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert!(body.is_empty());
+        assert_eq!(
+            header_str(&headers, header::CONTENT_RANGE),
+            Some("bytes */100")
+        );
+    }
+
+    #[tokio::test]
+    async fn if_range_mismatch_sends_whole_artifact() {
+        let (_dir, path) = artifact();
+
+        let (status, _, body) = get(
+            &path,
+            &headers(&[
+                (header::RANGE, "bytes=10-19"),
+                (header::IF_RANGE, "\"other\""),
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.len(), 100);
+
+        let (status, _, body) = get(
+            &path,
+            &headers(&[
+                (header::RANGE, "bytes=10-19"),
+                (header::IF_RANGE, "\"abcd\""),
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn if_none_match() {
+        let (_dir, path) = artifact();
+
+        let (status, headers, body) = get(
+            &path,
+            &self::headers(&[(header::IF_NONE_MATCH, "\"abcd\"")]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+        assert_eq!(header_str(&headers, header::ETAG), Some("\"abcd\""));
+
+        let (status, _, _) = get(
+            &path,
+            &self::headers(&[(header::IF_NONE_MATCH, "\"other\"")]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn if_modified_since() {
+        let (_dir, path) = artifact();
+        let since = |time: SystemTime| {
+            let mut headers = HeaderMap::new();
+            headers.typed_insert(IfModifiedSince::from(time));
+            headers
+        };
+        let hour = Duration::from_secs(60 * 60);
+
+        let (status, _, _) = get(&path, &since(SystemTime::now() + hour)).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+        let (status, _, _) = get(&path, &since(SystemTime::now() - hour)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // If-None-Match takes precedence
+        let mut headers = since(SystemTime::now() + hour);
+        headers.insert(header::IF_NONE_MATCH, "\"other\"".parse().unwrap());
+        let (status, _, _) = get(&path, &headers).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn metadata_revalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.toml");
+        std::fs::write(&path, "[repository]\n").unwrap();
+
+        let response = serve_metadata(&path, &HeaderMap::new()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+        assert_eq!(
+            header_str(response.headers(), header::CONTENT_TYPE),
+            Some("application/toml")
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, etag);
+        let response = serve_metadata(&path, &headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        std::fs::write(&path, "[repository]\nurl = \"https://example.com/\"\n").unwrap();
+        let response = serve_metadata(&path, &headers).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
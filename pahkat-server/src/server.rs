@@ -0,0 +1,528 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::sign::{self, CertifiedKey};
+use tokio_rustls::rustls::{
+    ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig as RustlsConfig,
+};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use warp::{Filter, Rejection};
+
+/// How long a client has to complete a TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connections accepted but not yet picked up by the server.
+const ACCEPT_BACKLOG: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to listen on {0}")]
+    Bind(Listen, #[source] io::Error),
+
+    #[error("Unix sockets are not supported on this platform")]
+    UnixUnsupported,
+
+    #[error("Failed to read `{0}`")]
+    ReadTls(PathBuf, #[source] io::Error),
+
+    #[error("No certificates found in `{0}`")]
+    InvalidCert(PathBuf),
+
+    #[error("No supported private key found in `{0}`")]
+    InvalidKey(PathBuf),
+}
+
+/// Where the server accepts connections, as `<ip>:<port>` or `unix:<path>`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(Listen::Unix(path.into())),
+            Some(_) => Err("Expected a path after `unix:`".into()),
+            None => s
+                .parse()
+                .map(Listen::Tcp)
+                .map_err(|_| format!("Expected `<ip>:<port>` or `unix:<path>`, got `{}`", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Listen {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Listen> for String {
+    fn from(listen: Listen) -> Self {
+        listen.to_string()
+    }
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listen::Tcp(addr) => write!(f, "{}", addr),
+            Listen::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A certificate and key in PEM format, reloaded when either file changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+
+    /// How often to check the files for changes, in seconds. They are also
+    /// reloaded on SIGHUP.
+    #[serde(default = "default_reload_interval")]
+    pub reload_interval: u64,
+}
+
+fn default_reload_interval() -> u64 {
+    60 * 60
+}
+
+/// Where and how the server listens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    #[serde(default = "default_listen")]
+    pub listen: Vec<Listen>,
+
+    /// Serves TCP listeners over TLS. Unix sockets are always plain HTTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+
+    /// Largest JSON request body accepted, in bytes. Artifact uploads are
+    /// limited by `artifacts.max_size` instead.
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: u64,
+
+    /// How long to keep accepting connections after a shutdown signal, in
+    /// seconds, so load balancers see `/health` fail before the server goes
+    #[serde(default = "default_shutdown_grace")]
+    pub shutdown_grace: u64,
+
+    /// How long to wait for queued publish jobs on shutdown, in seconds
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: default_listen(),
+            tls: None,
+            max_body_size: default_max_body_size(),
+            shutdown_grace: default_shutdown_grace(),
+            shutdown_timeout: default_shutdown_timeout(),
+        }
+    }
+}
+
+fn default_listen() -> Vec<Listen> {
+    vec![Listen::Tcp(([127, 0, 0, 1], 3030).into())]
+}
+
+fn default_max_body_size() -> u64 {
+    1024 * 1024
+}
+
+fn default_shutdown_grace() -> u64 {
+    5
+}
+
+fn default_shutdown_timeout() -> u64 {
+    5 * 60
+}
+
+/// Starts once SIGTERM or Ctrl-C is received, and resolves after the grace
+/// period. Clones share the signal.
+#[derive(Clone)]
+pub struct Shutdown {
+    started: watch::Receiver<bool>,
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// Starts listening for shutdown signals. Must be called from within the
+    /// runtime.
+    pub fn listen(grace: Duration) -> Shutdown {
+        let (started_tx, started) = watch::channel(false);
+        let (tx, rx) = watch::channel(false);
+
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            let _ = started_tx.broadcast(true);
+
+            if grace > Duration::from_secs(0) {
+                log::info!(
+                    "Shutting down in {}s; send the signal again to shut down now",
+                    grace.as_secs()
+                );
+                tokio::select! {
+                    _ = tokio::time::delay_for(grace) => {}
+                    _ = wait_for_signal() => {}
+                }
+            }
+
+            log::info!("Shutting down; no longer accepting connections");
+            let _ = tx.broadcast(true);
+        });
+
+        Shutdown { started, rx }
+    }
+
+    /// Whether a shutdown signal was received, even if connections are still
+    /// accepted during the grace period.
+    pub fn is_started(&self) -> bool {
+        *self.started.borrow()
+    }
+
+    pub async fn wait(mut self) {
+        while let Some(false) = self.rx.recv().await {}
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Serves `routes` on every configured listener until shutdown, letting
+/// in-flight requests finish.
+pub async fn serve<F, R>(config: &ServerConfig, routes: F, shutdown: &Shutdown) -> Result<(), Error>
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: warp::Reply + Send,
+{
+    let acceptor = match config.tls.as_ref() {
+        Some(tls) => Some(tls_acceptor(tls, shutdown)?),
+        None => None,
+    };
+
+    let mut servers: Vec<Pin<Box<dyn Future<Output = ()>>>> = vec![];
+    for listen in config.listen.iter() {
+        let server = warp::serve(routes.clone());
+        let signal = shutdown.clone().wait();
+
+        match listen {
+            Listen::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .map_err(|e| Error::Bind(listen.clone(), e))?;
+                let incoming = accept(listener, shutdown.clone());
+
+                match acceptor.as_ref() {
+                    Some(acceptor) => {
+                        log::info!("Listening on https://{}", addr);
+                        let incoming = handshake(incoming, acceptor.clone());
+                        servers.push(Box::pin(
+                            server.serve_incoming_with_graceful_shutdown(incoming, signal),
+                        ));
+                    }
+                    None => {
+                        log::info!("Listening on http://{}", addr);
+                        servers.push(Box::pin(
+                            server.serve_incoming_with_graceful_shutdown(incoming, signal),
+                        ));
+                    }
+                }
+            }
+            Listen::Unix(path) => {
+                let listener = bind_unix(path).map_err(|e| match e {
+                    Some(e) => Error::Bind(listen.clone(), e),
+                    None => Error::UnixUnsupported,
+                })?;
+                let incoming = accept(listener, shutdown.clone());
+
+                log::info!("Listening on {}", listen);
+                servers.push(Box::pin(
+                    server.serve_incoming_with_graceful_shutdown(incoming, signal),
+                ));
+            }
+        }
+    }
+
+    futures::future::join_all(servers).await;
+
+    for listen in config.listen.iter() {
+        if let Listen::Unix(path) = listen {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Connections for the server to serve. Only successful connections are
+/// sent, as an error would stop the server.
+type Incoming<S> = mpsc::Receiver<io::Result<S>>;
+
+/// Accepts connections until shutdown.
+fn accept<L, S>(mut listener: L, shutdown: Shutdown) -> Incoming<S>
+where
+    L: Stream<Item = io::Result<S>> + Send + Unpin + 'static,
+    S: Send + 'static,
+{
+    let (mut tx, rx) = mpsc::channel(ACCEPT_BACKLOG);
+
+    tokio::spawn(async move {
+        let signal = shutdown.wait();
+        futures::pin_mut!(signal);
+
+        loop {
+            let result = tokio::select! {
+                result = listener.next() => result,
+                _ = &mut signal => None,
+            };
+
+            match result {
+                Some(Ok(stream)) => {
+                    if tx.send(Ok(stream)).await.is_err() {
+                        return;
+                    }
+                }
+                Some(Err(e)) => {
+                    // Usually running out of file descriptors, so back off
+                    log::error!("Failed to accept connection: {}", e);
+                    tokio::time::delay_for(Duration::from_millis(100)).await;
+                }
+                None => return,
+            }
+        }
+    });
+
+    rx
+}
+
+/// Handshakes run in their own tasks, so a slow client can't hold up others.
+fn handshake<S>(mut incoming: Incoming<S>, acceptor: TlsAcceptor) -> Incoming<TlsStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel(ACCEPT_BACKLOG);
+
+    tokio::spawn(async move {
+        while let Some(Ok(stream)) = incoming.recv().await {
+            let acceptor = acceptor.clone();
+            let mut tx = tx.clone();
+
+            tokio::spawn(async move {
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Ok(Err(e)) => log::debug!("TLS handshake failed: {}", e),
+                    Err(_) => log::debug!("TLS handshake timed out"),
+                }
+            });
+        }
+    });
+
+    rx
+}
+
+/// Returns `None` as the error where Unix sockets aren't supported.
+#[cfg(unix)]
+fn bind_unix(path: &Path) -> Result<tokio::net::UnixListener, Option<io::Error>> {
+    // A socket left behind by an earlier run would make binding fail
+    if path.exists() {
+        let _ = std::fs::remove_file(path);
+    }
+    tokio::net::UnixListener::bind(path).map_err(Some)
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &Path) -> Result<TcpListener, Option<io::Error>> {
+    Err(None)
+}
+
+/// Hands out the most recently loaded certificate, so it can be replaced
+/// without restarting the server.
+struct CertResolver {
+    config: TlsConfig,
+    current: RwLock<CertifiedKey>,
+    modified: Mutex<Option<(SystemTime, SystemTime)>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+impl CertResolver {
+    fn new(config: &TlsConfig) -> Result<CertResolver, Error> {
+        let modified = modified_times(config);
+        let key = load_certified_key(config)?;
+
+        Ok(CertResolver {
+            config: config.clone(),
+            current: RwLock::new(key),
+            modified: Mutex::new(modified),
+        })
+    }
+
+    /// Loads the files again if they changed, or always when `force`d. A
+    /// bad certificate is logged and the current one kept.
+    fn reload(&self, force: bool) {
+        let modified = modified_times(&self.config);
+        if !force && modified == *self.modified.lock().unwrap() {
+            return;
+        }
+
+        match load_certified_key(&self.config) {
+            Ok(key) => {
+                *self.current.write().unwrap() = key;
+                *self.modified.lock().unwrap() = modified;
+                log::info!("Reloaded TLS certificate");
+            }
+            Err(e) => log::error!("Keeping current TLS certificate: {}", e),
+        }
+    }
+}
+
+fn modified_times(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|x| x.modified()).ok();
+    Some((modified(&config.cert_path)?, modified(&config.key_path)?))
+}
+
+fn load_certified_key(config: &TlsConfig) -> Result<CertifiedKey, Error> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| Error::ReadTls(path.to_path_buf(), e))
+    };
+
+    let certs = pemfile::certs(&mut open(&config.cert_path)?)
+        .ok()
+        .filter(|x| !x.is_empty())
+        .ok_or_else(|| Error::InvalidCert(config.cert_path.clone()))?;
+
+    let key = pemfile::pkcs8_private_keys(&mut open(&config.key_path)?)
+        .ok()
+        .and_then(|x| x.into_iter().next());
+    let key = match key {
+        Some(v) => Some(v),
+        None => pemfile::rsa_private_keys(&mut open(&config.key_path)?)
+            .ok()
+            .and_then(|x| x.into_iter().next()),
+    };
+    let key = key
+        .and_then(|x| sign::any_supported_type(&x).ok())
+        .ok_or_else(|| Error::InvalidKey(config.key_path.clone()))?;
+
+    Ok(CertifiedKey::new(certs, Arc::new(key)))
+}
+
+fn tls_acceptor(config: &TlsConfig, shutdown: &Shutdown) -> Result<TlsAcceptor, Error> {
+    let resolver = Arc::new(CertResolver::new(config)?);
+
+    let mut tls_config = RustlsConfig::new(NoClientAuth::new());
+    tls_config.cert_resolver = resolver.clone();
+    tls_config.set_protocols(&[b"http/1.1".to_vec()]);
+
+    let interval = Duration::from_secs(config.reload_interval.max(1));
+    let signal = shutdown.clone().wait();
+    tokio::spawn(async move {
+        futures::pin_mut!(signal);
+        let mut interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        let mut hangup = hangup();
+
+        loop {
+            let force = tokio::select! {
+                _ = interval.tick() => false,
+                _ = next_hangup(&mut hangup) => true,
+                _ = &mut signal => return,
+            };
+            resolver.reload(force);
+        }
+    });
+
+    Ok(TlsAcceptor::from(Arc::new(tls_config)))
+}
+
+#[cfg(unix)]
+type Hangup = Option<tokio::signal::unix::Signal>;
+
+#[cfg(unix)]
+fn hangup() -> Hangup {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::hangup()) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            log::warn!("Failed to listen for SIGHUP: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn next_hangup(hangup: &mut Hangup) {
+    match hangup.as_mut() {
+        Some(signal) => {
+            signal.recv().await;
+        }
+        None => futures::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+type Hangup = ();
+
+#[cfg(not(unix))]
+fn hangup() -> Hangup {}
+
+#[cfg(not(unix))]
+async fn next_hangup(_hangup: &mut Hangup) {
+    futures::future::pending().await
+}
@@ -0,0 +1,290 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection};
+
+use crate::database::models::NewDownload;
+use crate::database::{Database, DatabaseError};
+use crate::Config;
+
+#[derive(Debug, thiserror::Error)]
+enum StatsError {
+    #[error("Unsupported repository identifier.")]
+    UnsupportedRepo,
+
+    #[error("No such package")]
+    NoPackage,
+
+    #[error("Invalid {0}")]
+    Invalid(&'static str),
+
+    #[error("Could not access download statistics")]
+    DatabaseError(#[from] DatabaseError),
+
+    #[error("Statistics task failed")]
+    TaskFailed,
+}
+
+impl warp::reply::Reply for StatsError {
+    fn into_response(self) -> warp::reply::Response {
+        let msg = format!("{}", self);
+        let code = match self {
+            StatsError::UnsupportedRepo => StatusCode::from_u16(400).unwrap(),
+            StatsError::NoPackage => StatusCode::from_u16(404).unwrap(),
+            StatsError::Invalid(_) => StatusCode::from_u16(400).unwrap(),
+            StatsError::DatabaseError(_) => StatusCode::from_u16(500).unwrap(),
+            StatsError::TaskFailed => StatusCode::from_u16(500).unwrap(),
+        };
+        warp::reply::with_status(msg, code).into_response()
+    }
+}
+
+/// An install reported by a client that opted in to sending statistics.
+#[derive(Deserialize)]
+struct InstallPing {
+    version: String,
+    platform: String,
+    #[serde(default)]
+    arch: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    #[serde(default)]
+    package: Option<String>,
+
+    /// Only count downloads on or after this date, as `YYYY-MM-DD`
+    #[serde(default)]
+    since: Option<String>,
+}
+
+/// Identifiers are kept to what package ids, platforms and arches use, so
+/// clients can't fill the database with arbitrary strings.
+fn is_valid_ident(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 128
+        && !s.starts_with('.')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+/// `POST /<repo>/packages/<id>/downloads` records an install ping, and
+/// `GET /<repo>/stats` reports counts per package, version, platform and arch.
+pub fn routes(
+    config: &Arc<Config>,
+    db: &Database,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = Rejection> + Clone {
+    let ping = warp::post()
+        .and(crate::filters::config(config))
+        .and(crate::filters::database(db))
+        .and(warp::path::param::<String>())
+        .and(warp::path("packages"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("downloads"))
+        .and(warp::path::end())
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and_then(process_install_ping)
+        .with(warp::log("pahkat_server::install_ping"));
+
+    let stats = warp::get()
+        .and(crate::filters::config(config))
+        .and(crate::filters::database(db))
+        .and(warp::path::param::<String>())
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(warp::query::<StatsQuery>())
+        .and_then(process_stats)
+        .with(warp::log("pahkat_server::stats"));
+
+    ping.or(stats)
+}
+
+async fn process_install_ping(
+    config: Arc<Config>,
+    db: Database,
+    repo_id: String,
+    package_id: String,
+    ping: InstallPing,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    let repo = match config.repos.get(&repo_id) {
+        Some(v) => v,
+        None => return Ok(Box::new(StatsError::UnsupportedRepo)),
+    };
+
+    if !is_valid_ident(&package_id) {
+        return Ok(Box::new(StatsError::NoPackage));
+    }
+
+    let descriptor = repo
+        .path
+        .join("packages")
+        .join(&package_id)
+        .join("index.toml");
+    if !descriptor.is_file() {
+        return Ok(Box::new(StatsError::NoPackage));
+    }
+
+    let version = match ping.version.parse::<pahkat_types::package::Version>() {
+        Ok(v) => v,
+        Err(_) => return Ok(Box::new(StatsError::Invalid("version"))),
+    };
+
+    if !is_valid_ident(&ping.platform) {
+        return Ok(Box::new(StatsError::Invalid("platform")));
+    }
+
+    if !ping.arch.as_deref().map(is_valid_ident).unwrap_or(true) {
+        return Ok(Box::new(StatsError::Invalid("arch")));
+    }
+
+    let download = NewDownload {
+        repo: repo_id,
+        package_id,
+        version: version.to_string(),
+        platform: ping.platform,
+        arch: ping.arch,
+        source: "install".into(),
+        created_at: Utc::now().naive_utc(),
+    };
+
+    let result = tokio::task::spawn_blocking(move || db.create_download(download)).await;
+
+    match result {
+        Ok(Ok(())) => Ok(Box::new(StatusCode::NO_CONTENT)),
+        Ok(Err(e)) => {
+            log::error!("{:?}", e);
+            Ok(Box::new(StatsError::from(e)))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            Ok(Box::new(StatsError::TaskFailed))
+        }
+    }
+}
+
+async fn process_stats(
+    config: Arc<Config>,
+    db: Database,
+    repo_id: String,
+    query: StatsQuery,
+) -> Result<Box<dyn warp::Reply>, Infallible> {
+    if !config.repos.contains_key(&repo_id) {
+        return Ok(Box::new(StatsError::UnsupportedRepo));
+    }
+
+    let since = match query.since.as_deref().map(|x| x.parse::<NaiveDate>()) {
+        Some(Ok(date)) => Some(date.and_hms(0, 0, 0)),
+        Some(Err(_)) => return Ok(Box::new(StatsError::Invalid("date"))),
+        None => None,
+    };
+
+    let result = tokio::task::spawn_blocking(move || {
+        db.download_stats(&repo_id, query.package.as_deref(), since)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(stats)) => Ok(Box::new(warp::reply::json(&stats))),
+        Ok(Err(e)) => {
+            log::error!("{:?}", e);
+            Ok(Box::new(StatsError::from(e)))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            Ok(Box::new(StatsError::TaskFailed))
+        }
+    }
+}
+
+/// Counts a download of a stored artifact against each release it was
+/// published as. Artifacts that were never published aren't counted.
+pub fn record_artifact_download(db: Database, sha256: String) {
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = record_downloads(&db, &sha256) {
+            log::error!("{:?}", e);
+        }
+    });
+}
+
+/// Returns how many releases the download was counted against.
+fn record_downloads(db: &Database, sha256: &str) -> Result<usize, DatabaseError> {
+    let releases = db.find_artifact_releases(sha256)?;
+    let count = releases.len();
+    let created_at = Utc::now().naive_utc();
+
+    for release in releases {
+        db.create_download(NewDownload {
+            repo: release.repo,
+            package_id: release.package_id,
+            version: release.version,
+            platform: release.platform,
+            arch: release.arch,
+            source: "artifact".into(),
+            created_at,
+        })?;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::NewArtifactRelease;
+
+    #[test]
+    fn valid_idents() {
+        assert!(is_valid_ident("speller-sme"));
+        assert!(is_valid_ident("x86_64"));
+        assert!(is_valid_ident("macos.10"));
+        assert!(is_valid_ident(&"a".repeat(128)));
+
+        assert!(!is_valid_ident(""));
+        assert!(!is_valid_ident(&"a".repeat(129)));
+        assert!(!is_valid_ident(".hidden"));
+        assert!(!is_valid_ident(".."));
+        assert!(!is_valid_ident("../index.toml"));
+        assert!(!is_valid_ident("speller sme"));
+        assert!(!is_valid_ident("ordbok-øst"));
+    }
+
+    #[test]
+    fn artifact_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pahkat.sqlite");
+        let db = Database::new(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(record_downloads(&db, "abc").unwrap(), 0);
+
+        for repo in &["main", "other"] {
+            db.create_artifact_release(NewArtifactRelease {
+                sha256: "abc".into(),
+                repo: repo.to_string(),
+                package_id: "speller".into(),
+                version: "1.0.0".into(),
+                platform: "windows".into(),
+                arch: Some("x86_64".into()),
+                created_at: Utc::now().naive_utc(),
+            })
+            .unwrap();
+        }
+
+        assert_eq!(record_downloads(&db, "abc").unwrap(), 2);
+        assert_eq!(record_downloads(&db, "def").unwrap(), 0);
+
+        for repo in &["main", "other"] {
+            let stats = db.download_stats(repo, None, None).unwrap();
+            assert_eq!(stats.len(), 1);
+            assert_eq!(stats[0].package_id, "speller");
+            assert_eq!(stats[0].version, "1.0.0");
+            assert_eq!(stats[0].platform, "windows");
+            assert_eq!(stats[0].arch.as_deref(), Some("x86_64"));
+            assert_eq!(stats[0].downloads, 1);
+            assert_eq!(stats[0].installs, 0);
+        }
+    }
+}
@@ -0,0 +1,806 @@
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to run `{0}`")]
+    Spawn(&'static str, #[source] io::Error),
+
+    #[error("`{command}` failed: {stderr}")]
+    Command { command: String, stderr: String },
+
+    #[error("Working copy is out of date with the remote")]
+    OutOfDate,
+}
+
+/// The name and email a commit is attributed to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+}
+
+impl Default for Identity {
+    fn default() -> Self {
+        Identity {
+            name: "pahkat-server".into(),
+            email: "pahkat-server@localhost".into(),
+        }
+    }
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <{}>", self.name, self.email)
+    }
+}
+
+impl FromStr for Identity {
+    type Err = String;
+
+    /// Parses `Name <email>`, as used by git.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match (s.find('<'), s.ends_with('>')) {
+            (Some(idx), true) if idx > 0 => Ok(Identity {
+                name: s[..idx].trim().to_string(),
+                email: s[idx + 1..s.len() - 1].trim().to_string(),
+            }),
+            _ => Err(format!("Expected `Name <email>`, got `{}`", s)),
+        }
+    }
+}
+
+/// What a change did to a release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Update,
+    Remove,
+    Yank,
+    Unyank,
+    /// Copied into the change's channel from another one
+    Promote,
+}
+
+impl ChangeKind {
+    fn verb(&self) -> &'static str {
+        match self {
+            ChangeKind::Update => "Update",
+            ChangeKind::Remove => "Remove",
+            ChangeKind::Yank => "Yank",
+            ChangeKind::Unyank => "Unyank",
+            ChangeKind::Promote => "Promote",
+        }
+    }
+}
+
+/// One package release recorded by a commit.
+#[derive(Debug, Clone)]
+pub struct Change<'a> {
+    pub kind: ChangeKind,
+    pub package: &'a str,
+    pub version: &'a str,
+    pub channel: Option<&'a str>,
+    pub uploader: Option<&'a Identity>,
+    pub authorized_by: Option<&'a str>,
+}
+
+impl Change<'_> {
+    fn summary(&self) -> String {
+        let summary = match self.channel {
+            Some(channel) => format!("{} {} ({})", self.package, self.version, channel),
+            None => format!("{} {}", self.package, self.version),
+        };
+
+        match self.kind {
+            ChangeKind::Update => summary,
+            kind => format!("{} {}", kind.verb(), summary),
+        }
+    }
+
+    fn trailers(&self, msg: &mut String) {
+        msg.push_str(&format!(
+            "Package: {}\nVersion: {}\n",
+            self.package, self.version
+        ));
+        if let Some(channel) = self.channel {
+            msg.push_str(&format!("Channel: {}\n", channel));
+        }
+        if self.kind != ChangeKind::Update {
+            msg.push_str(&format!("Action: {}\n", self.kind.verb().to_lowercase()));
+        }
+        if let Some(uploader) = self.uploader {
+            msg.push_str(&format!("Uploaded-By: {}\n", uploader));
+        }
+        if let Some(authorized_by) = self.authorized_by {
+            msg.push_str(&format!("Authorized-By: {}\n", authorized_by));
+        }
+    }
+}
+
+/// Describes the changes a commit records; several queued updates to one
+/// repository are committed together.
+#[derive(Debug, Clone)]
+pub struct Commit<'a> {
+    pub repo: &'a str,
+    pub changes: Vec<Change<'a>>,
+}
+
+impl Commit<'_> {
+    /// A summary line followed by trailers that tools can parse.
+    pub fn message(&self) -> String {
+        let mut msg = match self.changes.as_slice() {
+            [change] if change.kind != ChangeKind::Update => change.summary(),
+            [change] => match change.channel {
+                Some(channel) => format!(
+                    "Update {} to {} ({})",
+                    change.package, change.version, channel
+                ),
+                None => format!("Update {} to {}", change.package, change.version),
+            },
+            changes => {
+                let mut msg = format!("Update {} packages\n", changes.len());
+                for change in changes {
+                    msg.push_str(&format!("\n- {}", change.summary()));
+                }
+                msg
+            }
+        };
+
+        msg.push_str(&format!("\n\nRepository: {}\n", self.repo));
+        for change in self.changes.iter() {
+            change.trailers(&mut msg);
+        }
+
+        msg
+    }
+
+    /// The uploader, if every change in the commit was made by the same one.
+    pub fn author(&self) -> Option<&Identity> {
+        let first = self.changes.first()?.uploader?;
+        if self.changes.iter().all(|x| x.uploader == Some(first)) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+}
+
+/// Keeps a repository working copy in sync with where it is published from.
+pub trait VcsBackend: Send + Sync {
+    /// The working copy package updates are written to.
+    fn path(&self) -> &Path;
+
+    /// Discards local changes and brings the working copy up to date.
+    fn sync(&self) -> Result<(), Error>;
+
+    /// Records and publishes all changes in the working copy.
+    ///
+    /// Returns [`Error::OutOfDate`] if the remote changed in a way that
+    /// requires the update to be redone on a fresh working copy.
+    fn commit(&self, commit: &Commit<'_>) -> Result<(), Error>;
+}
+
+/// Which backend a repository uses, as configured per repository.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum VcsConfig {
+    Git {
+        #[serde(default = "default_remote")]
+        remote: String,
+        #[serde(default = "default_branch")]
+        branch: String,
+        #[serde(default = "default_push_retries")]
+        push_retries: u32,
+    },
+    Svn {
+        username: String,
+        password: String,
+    },
+    /// A plain directory, published by whatever serves it
+    Fs,
+}
+
+fn default_remote() -> String {
+    "origin".into()
+}
+
+fn default_branch() -> String {
+    "main".into()
+}
+
+fn default_push_retries() -> u32 {
+    5
+}
+
+impl VcsConfig {
+    pub fn open(&self, path: PathBuf, committer: &Identity) -> Box<dyn VcsBackend> {
+        match self {
+            VcsConfig::Git {
+                remote,
+                branch,
+                push_retries,
+            } => Box::new(Git {
+                path,
+                remote: remote.clone(),
+                branch: branch.clone(),
+                push_retries: *push_retries,
+                committer: committer.clone(),
+            }),
+            VcsConfig::Svn { username, password } => Box::new(Subversion {
+                path,
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            VcsConfig::Fs => Box::new(Filesystem { path }),
+        }
+    }
+}
+
+fn run(
+    program: &'static str,
+    path: &Path,
+    args: &[&str],
+    env: &[(&str, &str)],
+    stdin: Option<&str>,
+) -> Result<Output, Error> {
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .envs(env.iter().cloned())
+        .current_dir(path)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    log::debug!("Running {} {:?}", program, args);
+    let mut child = command.spawn().map_err(|e| Error::Spawn(program, e))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input.as_bytes())
+            .map_err(|e| Error::Spawn(program, e))?;
+    }
+    child
+        .wait_with_output()
+        .map_err(|e| Error::Spawn(program, e))
+}
+
+fn check(program: &'static str, args: &[&str], output: Output) -> Result<Output, Error> {
+    if output.status.success() {
+        Ok(output)
+    } else {
+        Err(Error::Command {
+            command: format!("{} {}", program, args.join(" ")),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+pub struct Git {
+    path: PathBuf,
+    remote: String,
+    branch: String,
+    push_retries: u32,
+    committer: Identity,
+}
+
+impl Git {
+    fn git(&self, args: &[&str]) -> Result<Output, Error> {
+        self.git_with_env(args, &[])
+    }
+
+    fn git_with_env(&self, args: &[&str], env: &[(&str, &str)]) -> Result<Output, Error> {
+        let output = run("git", &self.path, args, env, None)?;
+        check("git", args, output)
+    }
+}
+
+impl VcsBackend for Git {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sync(&self) -> Result<(), Error> {
+        let upstream = format!("{}/{}", self.remote, self.branch);
+        self.git(&["fetch", &self.remote, &self.branch])?;
+        self.git(&["checkout", "--force", "-B", &self.branch, &upstream])?;
+        self.git(&["reset", "--hard", &upstream])?;
+        self.git(&["clean", "-fd"])?;
+        Ok(())
+    }
+
+    fn commit(&self, commit: &Commit<'_>) -> Result<(), Error> {
+        self.git(&["add", "--all"])?;
+
+        let staged = run(
+            "git",
+            &self.path,
+            &["diff", "--cached", "--quiet"],
+            &[],
+            None,
+        )?;
+        if staged.status.success() {
+            log::info!("No changes to commit");
+            return Ok(());
+        }
+
+        let author = commit.author().unwrap_or(&self.committer).to_string();
+        let message = commit.message();
+        self.git_with_env(
+            &["commit", "--author", &author, "-m", &message],
+            &[
+                ("GIT_COMMITTER_NAME", &self.committer.name),
+                ("GIT_COMMITTER_EMAIL", &self.committer.email),
+            ],
+        )?;
+
+        let refspec = format!("HEAD:refs/heads/{}", self.branch);
+        for attempt in 0..=self.push_retries {
+            let args = ["push", &self.remote, &refspec];
+            let output = run("git", &self.path, &args, &[], None)?;
+            if output.status.success() {
+                return Ok(());
+            }
+
+            if attempt == self.push_retries {
+                return check("git", &args, output).map(|_| ());
+            }
+
+            log::warn!(
+                "Push rejected; rebasing onto {}/{} ({}/{})",
+                self.remote,
+                self.branch,
+                attempt + 1,
+                self.push_retries
+            );
+            std::thread::sleep(Duration::from_secs(1 << attempt.min(5)));
+
+            // A conflicting rebase, usually on index.bin, means the update has
+            // to be redone against the new remote state.
+            if let Err(e) = self.git_with_env(
+                &["pull", "--rebase", &self.remote, &self.branch],
+                &[
+                    ("GIT_COMMITTER_NAME", &self.committer.name),
+                    ("GIT_COMMITTER_EMAIL", &self.committer.email),
+                ],
+            ) {
+                log::warn!("Rebase failed: {}", e);
+                let _ = self.git(&["rebase", "--abort"]);
+                return Err(Error::OutOfDate);
+            }
+        }
+
+        unreachable!()
+    }
+}
+
+pub struct Subversion {
+    path: PathBuf,
+    username: String,
+    password: String,
+}
+
+impl Subversion {
+    fn svn(&self, args: &[&str]) -> Result<Output, Error> {
+        let output = run("svn", &self.path, args, &[], None)?;
+        check("svn", args, output)
+    }
+
+    /// Runs an svn command against the server, passing the password on stdin
+    /// rather than the command line.
+    fn svn_auth(&self, args: &[&str]) -> Result<Output, Error> {
+        let mut args = args.to_vec();
+        args.extend_from_slice(&[
+            "--non-interactive",
+            "--no-auth-cache",
+            "--username",
+            self.username.as_str(),
+            "--password-from-stdin",
+        ]);
+        let output = run("svn", &self.path, &args, &[], Some(&self.password))?;
+        check("svn", &args, output)
+    }
+}
+
+impl VcsBackend for Subversion {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sync(&self) -> Result<(), Error> {
+        self.svn(&["revert", "-R", "."])?;
+        self.svn(&["cleanup", "--remove-unversioned"])?;
+        self.svn_auth(&["update"])?;
+        Ok(())
+    }
+
+    fn commit(&self, commit: &Commit<'_>) -> Result<(), Error> {
+        self.svn(&["add", "--force", "."])?;
+
+        // Files removed from the working copy, such as pruned releases, have
+        // to be scheduled for deletion or the commit would leave them behind.
+        let status = self.svn(&["status"])?;
+        let status = String::from_utf8_lossy(&status.stdout);
+        let missing = missing_paths(&status);
+        if !missing.is_empty() {
+            let mut args = vec!["rm", "--force", "--"];
+            args.extend(missing.iter().map(|x| x.as_str()));
+            self.svn(&args)?;
+        }
+
+        let message = commit.message();
+        match self.svn_auth(&["commit", "-m", &message]) {
+            Ok(_) => Ok(()),
+            Err(Error::Command { stderr, .. })
+                if stderr.contains("out of date") || stderr.contains("E155011") =>
+            {
+                Err(Error::OutOfDate)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Paths `svn status` reports as missing, escaped so that an `@` in them is
+/// not read as a peg revision.
+///
+/// Each line is seven status columns and a space, followed by the path.
+fn missing_paths(status: &str) -> Vec<String> {
+    status
+        .lines()
+        .filter(|line| line.starts_with('!'))
+        .filter_map(|line| line.get(8..))
+        .filter(|path| !path.is_empty())
+        .map(|path| {
+            if path.contains('@') {
+                format!("{}@", path)
+            } else {
+                path.to_string()
+            }
+        })
+        .collect()
+}
+
+/// A repository that is served directly from disk, with no version control.
+pub struct Filesystem {
+    path: PathBuf,
+}
+
+impl VcsBackend for Filesystem {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn commit(&self, commit: &Commit<'_>) -> Result<(), Error> {
+        log::info!("{}", commit.message().lines().next().unwrap_or_default());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jane() -> Identity {
+        Identity {
+            name: "Jane Doe".into(),
+            email: "jane@example.com".into(),
+        }
+    }
+
+    fn change<'a>(kind: ChangeKind, package: &'a str, channel: Option<&'a str>) -> Change<'a> {
+        Change {
+            kind,
+            package,
+            version: "1.0.0",
+            channel,
+            uploader: None,
+            authorized_by: None,
+        }
+    }
+
+    #[test]
+    fn identity_from_str() {
+        assert_eq!(
+            Identity::from_str("Jane Doe <jane@example.com>"),
+            Ok(jane())
+        );
+        assert_eq!(
+            Identity::from_str("  Jane Doe   < jane@example.com >  "),
+            Ok(jane())
+        );
+        assert_eq!(jane().to_string().parse::<Identity>(), Ok(jane()));
+
+        assert!(Identity::from_str("jane@example.com").is_err());
+        assert!(Identity::from_str("<jane@example.com>").is_err());
+        assert!(Identity::from_str("Jane Doe <jane@example.com").is_err());
+        assert!(Identity::from_str("").is_err());
+    }
+
+    #[test]
+    fn message_for_single_update() {
+        let commit = Commit {
+            repo: "main",
+            changes: vec![change(ChangeKind::Update, "speller", Some("nightly"))],
+        };
+        assert_eq!(
+            commit.message(),
+            "Update speller to 1.0.0 (nightly)\n\n\
+             Repository: main\n\
+             Package: speller\n\
+             Version: 1.0.0\n\
+             Channel: nightly\n"
+        );
+
+        let commit = Commit {
+            repo: "main",
+            changes: vec![change(ChangeKind::Update, "speller", None)],
+        };
+        assert!(commit.message().starts_with("Update speller to 1.0.0\n\n"));
+    }
+
+    #[test]
+    fn message_for_single_action() {
+        let uploader = jane();
+        let mut yank = change(ChangeKind::Yank, "speller", None);
+        yank.uploader = Some(&uploader);
+        yank.authorized_by = Some("ci");
+
+        let commit = Commit {
+            repo: "main",
+            changes: vec![yank],
+        };
+        assert_eq!(
+            commit.message(),
+            "Yank speller 1.0.0\n\n\
+             Repository: main\n\
+             Package: speller\n\
+             Version: 1.0.0\n\
+             Action: yank\n\
+             Uploaded-By: Jane Doe <jane@example.com>\n\
+             Authorized-By: ci\n"
+        );
+    }
+
+    #[test]
+    fn message_for_several_changes() {
+        let commit = Commit {
+            repo: "main",
+            changes: vec![
+                change(ChangeKind::Update, "speller", Some("nightly")),
+                change(ChangeKind::Promote, "keyboard", None),
+            ],
+        };
+        assert_eq!(
+            commit.message(),
+            "Update 2 packages\n\n\
+             - speller 1.0.0 (nightly)\n\
+             - Promote keyboard 1.0.0\n\n\
+             Repository: main\n\
+             Package: speller\n\
+             Version: 1.0.0\n\
+             Channel: nightly\n\
+             Package: keyboard\n\
+             Version: 1.0.0\n\
+             Action: promote\n"
+        );
+    }
+
+    #[test]
+    fn author_requires_a_single_uploader() {
+        let jane = jane();
+        let john: Identity = "John <john@example.com>".parse().unwrap();
+        let mut first = change(ChangeKind::Update, "speller", None);
+        let mut second = change(ChangeKind::Update, "keyboard", None);
+        first.uploader = Some(&jane);
+        second.uploader = Some(&jane);
+
+        let commit = Commit {
+            repo: "main",
+            changes: vec![first.clone(), second.clone()],
+        };
+        assert_eq!(commit.author(), Some(&jane));
+
+        second.uploader = Some(&john);
+        let commit = Commit {
+            repo: "main",
+            changes: vec![first.clone(), second],
+        };
+        assert_eq!(commit.author(), None);
+
+        first.uploader = None;
+        let commit = Commit {
+            repo: "main",
+            changes: vec![first],
+        };
+        assert_eq!(commit.author(), None);
+    }
+
+    #[test]
+    fn svn_missing_paths() {
+        let status = "\
+?       packages/new/index.toml
+!       packages/old/index.toml
+!     C packages/conflicted
+M       index.toml
+!       packages/user@host
+";
+        assert_eq!(
+            missing_paths(status),
+            vec![
+                "packages/old/index.toml",
+                "packages/conflicted",
+                "packages/user@host@"
+            ]
+        );
+        assert!(missing_paths("").is_empty());
+    }
+
+    fn git(path: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@localhost"])
+            .args(args)
+            .current_dir(path)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// A bare remote with one commit on `main`, which clones are made from.
+    struct Remote {
+        dir: tempfile::TempDir,
+    }
+
+    impl Remote {
+        fn new() -> Remote {
+            let dir = tempfile::tempdir().unwrap();
+            git(dir.path(), &["init", "--bare", "remote"]);
+
+            let seed = dir.path().join("seed");
+            std::fs::create_dir(&seed).unwrap();
+            git(&seed, &["init"]);
+            git(&seed, &["checkout", "-b", "main"]);
+            std::fs::write(seed.join("index.toml"), "version = 1\n").unwrap();
+            git(&seed, &["add", "--all"]);
+            git(&seed, &["commit", "-m", "Initial commit"]);
+            let remote = dir.path().join("remote");
+            git(&seed, &["push", remote.to_str().unwrap(), "main"]);
+
+            Remote { dir }
+        }
+
+        fn clone(&self, name: &str, push_retries: u32) -> Git {
+            git(self.dir.path(), &["clone", "remote", name]);
+            let backend = Git {
+                path: self.dir.path().join(name),
+                remote: "origin".into(),
+                branch: "main".into(),
+                push_retries,
+                committer: Identity::default(),
+            };
+            backend.sync().unwrap();
+            backend
+        }
+
+        fn log(&self) -> Vec<String> {
+            git(
+                &self.dir.path().join("remote"),
+                &["log", "--format=%s", "main"],
+            )
+            .lines()
+            .map(str::to_string)
+            .collect()
+        }
+    }
+
+    fn commit<'a>(package: &'a str) -> Commit<'a> {
+        Commit {
+            repo: "main",
+            changes: vec![change(ChangeKind::Update, package, None)],
+        }
+    }
+
+    #[test]
+    fn git_commit_pushes() {
+        let remote = Remote::new();
+        let repo = remote.clone("a", 0);
+
+        std::fs::write(repo.path().join("speller.toml"), "").unwrap();
+        repo.commit(&commit("speller")).unwrap();
+        assert_eq!(
+            remote.log(),
+            vec!["Update speller to 1.0.0", "Initial commit"]
+        );
+
+        let author = git(repo.path(), &["log", "-1", "--format=%an <%ae>"]);
+        assert_eq!(author.trim(), Identity::default().to_string());
+
+        // Nothing to commit is not an error, and does not push.
+        repo.commit(&commit("keyboard")).unwrap();
+        assert_eq!(remote.log().len(), 2);
+    }
+
+    #[test]
+    fn git_push_rebases_when_rejected() {
+        let remote = Remote::new();
+        let a = remote.clone("a", 1);
+        let b = remote.clone("b", 1);
+
+        std::fs::write(a.path().join("speller.toml"), "").unwrap();
+        a.commit(&commit("speller")).unwrap();
+
+        std::fs::write(b.path().join("keyboard.toml"), "").unwrap();
+        b.commit(&commit("keyboard")).unwrap();
+
+        assert_eq!(
+            remote.log(),
+            vec![
+                "Update keyboard to 1.0.0",
+                "Update speller to 1.0.0",
+                "Initial commit"
+            ]
+        );
+    }
+
+    #[test]
+    fn git_push_gives_up_after_retries() {
+        let remote = Remote::new();
+        let a = remote.clone("a", 0);
+        let b = remote.clone("b", 0);
+
+        std::fs::write(a.path().join("speller.toml"), "").unwrap();
+        a.commit(&commit("speller")).unwrap();
+
+        std::fs::write(b.path().join("keyboard.toml"), "").unwrap();
+        match b.commit(&commit("keyboard")) {
+            Err(Error::Command { command, .. }) => assert!(command.starts_with("git push")),
+            other => panic!("expected a failed push, got {:?}", other),
+        }
+        assert_eq!(remote.log().len(), 2);
+    }
+
+    #[test]
+    fn git_conflicting_rebase_is_out_of_date() {
+        let remote = Remote::new();
+        let a = remote.clone("a", 1);
+        let b = remote.clone("b", 1);
+
+        std::fs::write(a.path().join("index.toml"), "version = 2\n").unwrap();
+        a.commit(&commit("speller")).unwrap();
+
+        std::fs::write(b.path().join("index.toml"), "version = 3\n").unwrap();
+        match b.commit(&commit("keyboard")) {
+            Err(Error::OutOfDate) => {}
+            other => panic!("expected OutOfDate, got {:?}", other),
+        }
+
+        // The rebase was aborted, so the working copy can be synced again.
+        assert!(!b.path().join(".git/rebase-merge").exists());
+        assert!(!b.path().join(".git/rebase-apply").exists());
+        b.sync().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(b.path().join("index.toml")).unwrap(),
+            "version = 2\n"
+        );
+        assert_eq!(remote.log().len(), 2);
+    }
+}
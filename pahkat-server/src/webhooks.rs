@@ -0,0 +1,524 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use pahkat_types::payload::Target;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+use url::Url;
+use uuid::Uuid;
+
+use crate::queue::JobError;
+
+/// How long a receiver has to answer before a delivery is retried.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first retry waits this long, doubling for each attempt after it.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A package release was committed; sent once per package
+    Published,
+    /// A release, or some of its targets, was removed
+    Removed,
+    /// A release was yanked, so clients won't choose it for new installs
+    Yanked,
+    /// A yanked release was made available again
+    Unyanked,
+    /// A release was copied into another channel; `channel` is the destination
+    Promoted,
+    /// The repository index was rebuilt and committed; sent once per batch
+    IndexRebuilt,
+    /// An update could not be published
+    Failed,
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Published => "published",
+            EventKind::Removed => "removed",
+            EventKind::Yanked => "yanked",
+            EventKind::Unyanked => "unyanked",
+            EventKind::Promoted => "promoted",
+            EventKind::IndexRebuilt => "index_rebuilt",
+            EventKind::Failed => "failed",
+        }
+    }
+}
+
+/// An endpoint notified about a repository's updates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: Url,
+
+    /// Key for the `X-Pahkat-Signature` HMAC-SHA256 of each payload
+    pub secret: String,
+
+    /// Events to send; all of them if unset
+    #[serde(default = "all_events")]
+    pub events: Vec<EventKind>,
+
+    /// Attempts before a delivery is given up on
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn all_events() -> Vec<EventKind> {
+    vec![
+        EventKind::Published,
+        EventKind::Removed,
+        EventKind::Yanked,
+        EventKind::Unyanked,
+        EventKind::Promoted,
+        EventKind::IndexRebuilt,
+        EventKind::Failed,
+    ]
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+/// A package release an event is about.
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageEvent {
+    pub id: String,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    pub targets: Vec<Target>,
+    pub job: Uuid,
+}
+
+/// The JSON payload of a webhook delivery.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub event: EventKind,
+    pub repo: String,
+    pub packages: Vec<PackageEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JobError>,
+    pub timestamp: DateTime<Utc>,
+}
+
+struct Delivery {
+    id: Uuid,
+    hook: WebhookConfig,
+    event: EventKind,
+    body: Arc<Vec<u8>>,
+}
+
+/// Sends webhook deliveries in the background, so a slow or unreachable
+/// receiver never holds up publishing.
+#[derive(Clone)]
+pub struct Webhooks {
+    tx: mpsc::UnboundedSender<Delivery>,
+}
+
+impl Webhooks {
+    /// Starts the dispatcher. Must be called from within the runtime.
+    pub fn new() -> Webhooks {
+        let (tx, mut rx) = mpsc::unbounded_channel::<Delivery>();
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("pahkat-server/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("webhook client");
+
+        tokio::spawn(async move {
+            while let Some(delivery) = rx.recv().await {
+                tokio::spawn(deliver(client.clone(), delivery));
+            }
+        });
+
+        Webhooks { tx }
+    }
+
+    /// Queues an event for every hook subscribed to it.
+    pub fn send(&self, hooks: &[WebhookConfig], event: &Event) {
+        let hooks = hooks
+            .iter()
+            .filter(|x| x.events.contains(&event.event))
+            .collect::<Vec<_>>();
+        if hooks.is_empty() {
+            return;
+        }
+
+        let body = match serde_json::to_vec(event) {
+            Ok(v) => Arc::new(v),
+            Err(e) => {
+                log::error!("Failed to serialize webhook event: {}", e);
+                return;
+            }
+        };
+
+        for hook in hooks {
+            let delivery = Delivery {
+                id: Uuid::new_v4(),
+                hook: hook.clone(),
+                event: event.event,
+                body: Arc::clone(&body),
+            };
+            if self.tx.send(delivery).is_err() {
+                log::error!("Webhook dispatcher has stopped");
+                return;
+            }
+        }
+    }
+}
+
+/// `sha256=<hex>`, as GitHub signs its webhooks, so receivers can reuse
+/// existing verification code.
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC takes any key size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+enum Outcome {
+    Delivered,
+    Retry(String),
+    GiveUp(String),
+}
+
+async fn attempt(client: &reqwest::Client, delivery: &Delivery) -> Outcome {
+    let result = client
+        .post(delivery.hook.url.clone())
+        .header("content-type", "application/json")
+        .header("x-pahkat-event", delivery.event.as_str())
+        .header("x-pahkat-delivery", delivery.id.to_string())
+        .header(
+            "x-pahkat-signature",
+            signature(&delivery.hook.secret, &delivery.body),
+        )
+        .body(delivery.body.to_vec())
+        .send()
+        .await;
+
+    let status = match result {
+        Ok(response) => response.status(),
+        Err(e) => return Outcome::Retry(e.to_string()),
+    };
+
+    if status.is_success() {
+        Outcome::Delivered
+    } else if status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429 {
+        Outcome::Retry(status.to_string())
+    } else {
+        // The receiver rejected the payload itself; sending it again won't help
+        Outcome::GiveUp(status.to_string())
+    }
+}
+
+/// How long to wait after the `n`th failed attempt.
+fn backoff(n: u32) -> Duration {
+    2u32.checked_pow(n.saturating_sub(1))
+        .and_then(|x| INITIAL_BACKOFF.checked_mul(x))
+        .map_or(MAX_BACKOFF, |x| x.min(MAX_BACKOFF))
+}
+
+async fn deliver(client: reqwest::Client, delivery: Delivery) {
+    for n in 1..=delivery.hook.max_attempts.max(1) {
+        let reason = match attempt(&client, &delivery).await {
+            Outcome::Delivered => {
+                log::info!(
+                    "Delivered {} webhook {} to {}",
+                    delivery.event.as_str(),
+                    delivery.id,
+                    delivery.hook.url
+                );
+                return;
+            }
+            Outcome::GiveUp(reason) => {
+                log::error!(
+                    "Webhook {} to {} was rejected: {}",
+                    delivery.id,
+                    delivery.hook.url,
+                    reason
+                );
+                return;
+            }
+            Outcome::Retry(reason) => reason,
+        };
+
+        log::warn!(
+            "Webhook {} to {} failed ({}/{}): {}",
+            delivery.id,
+            delivery.hook.url,
+            n,
+            delivery.hook.max_attempts,
+            reason
+        );
+
+        if n < delivery.hook.max_attempts {
+            tokio::time::delay_for(backoff(n)).await;
+        }
+    }
+
+    log::error!(
+        "Giving up on webhook {} to {}",
+        delivery.id,
+        delivery.hook.url
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, HeaderMap, Response};
+
+    struct Received {
+        path: String,
+        headers: HeaderMap,
+        body: Vec<u8>,
+    }
+
+    /// A local HTTP server that records requests and answers them with the
+    /// given statuses in turn, then with 200.
+    struct Receiver {
+        url: Url,
+        received: Arc<Mutex<Vec<Received>>>,
+    }
+
+    impl Receiver {
+        fn start(statuses: &[u16]) -> Receiver {
+            let received = Arc::new(Mutex::new(vec![]));
+            let statuses = Arc::new(Mutex::new(
+                statuses.iter().cloned().collect::<VecDeque<_>>(),
+            ));
+
+            let service_received = Arc::clone(&received);
+            let make_service = make_service_fn(move |_| {
+                let received = Arc::clone(&service_received);
+                let statuses = Arc::clone(&statuses);
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| {
+                        let received = Arc::clone(&received);
+                        let statuses = Arc::clone(&statuses);
+                        async move {
+                            let (parts, body) = req.into_parts();
+                            let body = hyper::body::to_bytes(body).await.unwrap();
+                            received.lock().unwrap().push(Received {
+                                path: parts.uri.path().to_string(),
+                                headers: parts.headers,
+                                body: body.to_vec(),
+                            });
+
+                            let status = statuses.lock().unwrap().pop_front().unwrap_or(200);
+                            Ok::<_, Infallible>(
+                                Response::builder()
+                                    .status(status)
+                                    .body(Body::empty())
+                                    .unwrap(),
+                            )
+                        }
+                    }))
+                }
+            });
+
+            let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+            let url = format!("http://{}/", server.local_addr()).parse().unwrap();
+            tokio::spawn(server);
+
+            Receiver { url, received }
+        }
+
+        fn hook(&self, path: &str, events: Vec<EventKind>, max_attempts: u32) -> WebhookConfig {
+            WebhookConfig {
+                url: self.url.join(path).unwrap(),
+                secret: "hunter2".into(),
+                events,
+                max_attempts,
+            }
+        }
+
+        fn count(&self) -> usize {
+            self.received.lock().unwrap().len()
+        }
+
+        fn paths(&self) -> Vec<String> {
+            let received = self.received.lock().unwrap();
+            received.iter().map(|x| x.path.clone()).collect()
+        }
+
+        /// Waits up to a few seconds for `count` requests to arrive.
+        async fn wait_for(&self, count: usize) {
+            for _ in 0..100 {
+                if self.count() >= count {
+                    return;
+                }
+                tokio::time::delay_for(Duration::from_millis(50)).await;
+            }
+            panic!("expected {} request(s), got {}", count, self.count());
+        }
+    }
+
+    fn event(kind: EventKind) -> Event {
+        Event {
+            event: kind,
+            repo: "main".into(),
+            packages: vec![PackageEvent {
+                id: "speller".into(),
+                version: "1.0.0".into(),
+                channel: Some("nightly".into()),
+                targets: vec![],
+                job: Uuid::new_v4(),
+            }],
+            error: None,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn delivery(hook: WebhookConfig, kind: EventKind) -> Delivery {
+        Delivery {
+            id: Uuid::new_v4(),
+            hook,
+            event: kind,
+            body: Arc::new(serde_json::to_vec(&event(kind)).unwrap()),
+        }
+    }
+
+    #[test]
+    fn signature_is_hmac_sha256() {
+        assert_eq!(
+            signature("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_cap() {
+        assert_eq!(backoff(1), INITIAL_BACKOFF);
+        assert_eq!(backoff(2), INITIAL_BACKOFF * 2);
+        assert_eq!(backoff(3), INITIAL_BACKOFF * 4);
+        assert_eq!(backoff(8), Duration::from_secs(256));
+        assert_eq!(backoff(9), MAX_BACKOFF);
+        assert_eq!(backoff(40), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn delivery_is_signed() {
+        let receiver = Receiver::start(&[]);
+        let delivery = delivery(receiver.hook("hook", all_events(), 1), EventKind::Published);
+        let expected_body = delivery.body.to_vec();
+
+        deliver(reqwest::Client::new(), delivery).await;
+
+        let received = receiver.received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let request = &received[0];
+        assert_eq!(request.path, "/hook");
+        assert_eq!(request.body, expected_body);
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(request.headers["x-pahkat-event"], "published");
+        assert_eq!(
+            request.headers["x-pahkat-signature"],
+            signature("hunter2", &expected_body).as_str()
+        );
+        assert!(request.headers.contains_key("x-pahkat-delivery"));
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["event"], "published");
+        assert_eq!(body["repo"], "main");
+        assert_eq!(body["packages"][0]["id"], "speller");
+    }
+
+    #[tokio::test]
+    async fn send_filters_events() {
+        let receiver = Receiver::start(&[]);
+        let hooks = vec![
+            receiver.hook("published", vec![EventKind::Published], 1),
+            receiver.hook("failed", vec![EventKind::Failed], 1),
+            receiver.hook("all", all_events(), 1),
+        ];
+        let webhooks = Webhooks::new();
+
+        webhooks.send(&hooks, &event(EventKind::Published));
+        receiver.wait_for(2).await;
+        webhooks.send(&hooks, &event(EventKind::IndexRebuilt));
+        receiver.wait_for(3).await;
+
+        // Give a wrongly sent delivery time to arrive
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        let mut paths = receiver.paths();
+        paths.sort();
+        assert_eq!(paths, vec!["/all", "/all", "/published"]);
+    }
+
+    #[tokio::test]
+    async fn attempt_outcomes() {
+        let statuses = [200, 204, 500, 503, 408, 429, 400, 401, 404, 410];
+        let receiver = Receiver::start(&statuses);
+        let client = reqwest::Client::new();
+        let delivery = delivery(receiver.hook("hook", all_events(), 1), EventKind::Failed);
+
+        for status in statuses.iter() {
+            let outcome = attempt(&client, &delivery).await;
+            match (*status, outcome) {
+                (200, Outcome::Delivered) | (204, Outcome::Delivered) => {}
+                (500, Outcome::Retry(_))
+                | (503, Outcome::Retry(_))
+                | (408, Outcome::Retry(_))
+                | (429, Outcome::Retry(_)) => {}
+                (400, Outcome::GiveUp(_))
+                | (401, Outcome::GiveUp(_))
+                | (404, Outcome::GiveUp(_))
+                | (410, Outcome::GiveUp(_)) => {}
+                (status, _) => panic!("unexpected outcome for {}", status),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn attempt_retries_unreachable_receivers() {
+        let receiver = Receiver::start(&[]);
+        // Nothing listens on port 1
+        let mut hook = receiver.hook("hook", all_events(), 1);
+        hook.url.set_port(Some(1)).unwrap();
+
+        match attempt(&reqwest::Client::new(), &delivery(hook, EventKind::Failed)).await {
+            Outcome::Retry(_) => {}
+            _ => panic!("expected a retry"),
+        }
+    }
+
+    #[tokio::test]
+    async fn deliver_retries_until_delivered() {
+        let receiver = Receiver::start(&[503, 200]);
+        let hook = receiver.hook("hook", all_events(), 5);
+
+        deliver(reqwest::Client::new(), delivery(hook, EventKind::Published)).await;
+        assert_eq!(receiver.count(), 2);
+    }
+
+    #[tokio::test]
+    async fn deliver_stops_after_max_attempts() {
+        let receiver = Receiver::start(&[429, 500, 200]);
+        let hook = receiver.hook("hook", all_events(), 2);
+
+        deliver(reqwest::Client::new(), delivery(hook, EventKind::Published)).await;
+        assert_eq!(receiver.count(), 2);
+    }
+
+    #[tokio::test]
+    async fn deliver_gives_up_on_client_errors() {
+        let receiver = Receiver::start(&[422, 200]);
+        let hook = receiver.hook("hook", all_events(), 5);
+
+        deliver(reqwest::Client::new(), delivery(hook, EventKind::Published)).await;
+        assert_eq!(receiver.count(), 1);
+    }
+}
@@ -182,6 +182,12 @@ pub struct Release {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     #[builder(default)]
     pub target: Vec<crate::payload::Target>,
+
+    /// Yanked releases stay in the index so installed copies still resolve,
+    /// but clients won't choose them for new installs
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    #[builder(default)]
+    pub yanked: bool,
 }
 
 impl PartialOrd for Release {
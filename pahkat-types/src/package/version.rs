@@ -55,6 +55,19 @@ impl Version {
 
         Err(Error::UnhandledInput(version.to_string()))
     }
+
+    /// The version without its prerelease and build metadata, such as `1.0.0`
+    /// for `1.0.0-nightly.20190101T013059Z`.
+    pub fn without_prerelease(&self) -> Version {
+        match self {
+            Version::Semantic(semver) => {
+                let mut v = semver.0.clone();
+                v.pre.clear();
+                v.build.clear();
+                Version::Semantic(SemanticVersion(v))
+            }
+        }
+    }
 }
 
 impl Display for Version {
@@ -144,6 +157,14 @@ mod tests {
         assert_eq!(my == other, false);
     }
 
+    #[test]
+    fn test_without_prerelease() {
+        let my = Version::new("1.0.0-nightly.20180501T013059Z+abc123").unwrap();
+
+        assert_eq!(my.without_prerelease(), Version::new("1.0.0").unwrap());
+        assert_eq!(my.without_prerelease().to_string(), "1.0.0");
+    }
+
     #[test]
     fn test_equal_semver() {
         let my = Version::new("1.2.3").unwrap();
@@ -75,6 +75,12 @@ pub struct Package {
 
     #[cfg_attr(feature = "structopt", structopt(short, long))]
     pub installed_size: u64,
+
+    /// SHA-256 checksum of the payload file, as lowercase hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[builder(default)]
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    pub sha256: Option<String>,
 }
 
 impl super::AsDownloadUrl for Package {
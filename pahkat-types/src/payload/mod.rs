@@ -68,6 +68,14 @@ impl Payload {
         }
     }
 
+    pub fn sha256(&self) -> Option<&str> {
+        match self {
+            Payload::WindowsExecutable(x) => x.sha256.as_deref(),
+            Payload::MacOSPackage(x) => x.sha256.as_deref(),
+            Payload::TarballPackage(x) => x.sha256.as_deref(),
+        }
+    }
+
     pub fn set_url(&mut self, url: url::Url) {
         match self {
             Payload::WindowsExecutable(x) => {
@@ -41,6 +41,11 @@ pub struct RepositoryData {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     #[builder(default)]
     pub accepted_redirections: Vec<RepoUrl>,
+
+    /// Languages the repository provides strings for, as BCP 47 language tags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[builder(default)]
+    pub languages: Vec<String>,
 }
 
 #[derive(
@@ -75,13 +80,35 @@ pub struct Agent {
     pub url: Option<Url>,
 }
 
-/// This struct represents the strings for localising tags and is found in the
-/// `strings/` directory at the base of a Pahkat repository.
+/// This struct represents the strings for localising tags and channels and is found
+/// in the `strings/` directory at the base of a Pahkat repository.
 ///
-/// The TOML file this struct represents is named after the prefix of the given tag,
-/// such that a tag of `category:keyboards` would look up `strings/category.toml`.
+/// The TOML file this struct represents is named after the language tag it
+/// translates into, such that Northern Sami strings are found in `strings/se.toml`.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    TypedBuilder,
+)]
+#[non_exhaustive]
 pub struct Localisation {
-    // TODO
+    /// Translations keyed by the full tag, eg. `category:keyboards`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[builder(default)]
+    pub tags: BTreeMap<String, String>,
+
+    /// Translations keyed by channel name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    #[builder(default)]
+    pub channels: BTreeMap<String, String>,
 }
 
 #[derive(
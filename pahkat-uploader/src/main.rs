@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
+use tokio_util::codec::{BytesCodec, FramedRead};
 
 #[derive(StructOpt, Serialize, Deserialize)]
 struct Upload {
@@ -8,17 +9,116 @@ struct Upload {
     pub url: String,
     #[structopt(short = "P", long)]
     pub release_meta_path: PathBuf,
+    /// Who the release is attributed to, as `Name <email>`
+    #[structopt(long, env = "PAHKAT_UPLOADER")]
+    pub uploader: Option<String>,
+    /// Installer to upload to the server; the release's payload URL is set to
+    /// where it is stored
+    #[structopt(short, long)]
+    pub artifact: Option<PathBuf>,
+    /// Exit once the server has queued the release, rather than waiting for
+    /// it to be published
+    #[structopt(long)]
+    pub no_wait: bool,
+}
+
+/// Identifies a release already on the server.
+#[derive(StructOpt)]
+struct ReleaseRef {
+    /// The package's URL on the server, as given to `upload`
+    #[structopt(short, long)]
+    pub url: String,
+    #[structopt(short, long)]
+    pub version: String,
+    #[structopt(short, long)]
+    pub channel: Option<String>,
+    /// Who the change is attributed to, as `Name <email>`
+    #[structopt(long, env = "PAHKAT_UPLOADER")]
+    pub uploader: Option<String>,
+    /// Exit once the server has queued the change, rather than waiting for
+    /// it to be published
+    #[structopt(long)]
+    pub no_wait: bool,
+}
+
+impl ReleaseRef {
+    fn release_url(&self) -> String {
+        format!(
+            "{}/releases/{}",
+            self.url.trim_end_matches('/'),
+            self.version
+        )
+    }
+
+    fn query(&self) -> Vec<(&'static str, &str)> {
+        let mut query = vec![];
+        if let Some(channel) = self.channel.as_deref() {
+            query.push(("channel", channel));
+        }
+        if let Some(uploader) = self.uploader.as_deref() {
+            query.push(("uploader", uploader));
+        }
+        query
+    }
+}
+
+#[derive(StructOpt)]
+struct Delete {
+    #[structopt(flatten)]
+    pub release: ReleaseRef,
+    /// Only remove the target for this platform, rather than the whole release
+    #[structopt(short, long)]
+    pub platform: Option<String>,
+    #[structopt(short, long)]
+    pub arch: Option<String>,
+}
+
+#[derive(StructOpt)]
+struct Yank {
+    #[structopt(flatten)]
+    pub release: ReleaseRef,
+    /// Make a yanked release available for new installs again
+    #[structopt(long)]
+    pub undo: bool,
+}
+
+#[derive(StructOpt)]
+struct Promote {
+    /// The release to promote; `--channel` is the channel it is in
+    #[structopt(flatten)]
+    pub release: ReleaseRef,
+    /// The channel to promote the release into; stable if unset
+    #[structopt(long)]
+    pub to: Option<String>,
+    /// Drop prerelease tags such as `-nightly.<date>` from the promoted version
+    #[structopt(long)]
+    pub strip_prerelease: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Artifact {
+    url: url::Url,
+    size: u64,
+    sha256: String,
 }
 
 #[derive(Serialize, Deserialize)]
 struct PackageUpdateRequest {
     release: Release,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uploader: Option<String>,
 }
 
 #[derive(StructOpt)]
 enum Args {
     Release(Release),
     Upload(Upload),
+    /// Remove a release, or one of its targets, from the repository
+    Delete(Delete),
+    /// Stop a release being chosen for new installs, keeping it in the index
+    Yank(Yank),
+    /// Copy a release and its targets into another channel
+    Promote(Promote),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, structopt::StructOpt)]
@@ -46,6 +146,130 @@ pub struct Release {
     pub target: pahkat_types::payload::Target,
 }
 
+async fn upload_artifact(
+    client: &reqwest::Client,
+    package_url: &str,
+    auth: &str,
+    channel: Option<&str>,
+    path: &Path,
+) -> anyhow::Result<Artifact> {
+    let filename = match path.file_name().and_then(|x| x.to_str()) {
+        Some(v) => v,
+        None => anyhow::bail!("Invalid artifact path: {}", path.display()),
+    };
+    let url = format!(
+        "{}/artifacts/{}",
+        package_url.trim_end_matches('/'),
+        filename
+    );
+
+    // Stream the file rather than reading it into memory, giving its length
+    // up front as the server requires one
+    let file = tokio::fs::File::open(path).await?;
+    let len = file.metadata().await?.len();
+    let body = FramedRead::new(file, BytesCodec::new());
+
+    let mut request = client
+        .post(&url)
+        .body(reqwest::Body::wrap_stream(body))
+        .header("content-length", len)
+        .header("authorization", format!("Bearer {}", auth));
+    if let Some(channel) = channel {
+        request = request.query(&[("channel", channel)]);
+    }
+
+    let response = request.send().await?;
+    if let Err(err) = response.error_for_status_ref() {
+        let body = response.text().await.unwrap_or_default();
+        anyhow::bail!("Artifact upload failed: {}\n{}", err, body);
+    }
+
+    Ok(response.json().await?)
+}
+
+/// Looks up a publish job; the server requires the API token for this too.
+fn job_request(client: &reqwest::Client, job_url: url::Url, auth: &str) -> reqwest::RequestBuilder {
+    client
+        .get(job_url)
+        .header("authorization", format!("Bearer {}", auth))
+}
+
+/// Polls a publish job until it has succeeded or failed.
+async fn wait_for_job(
+    client: &reqwest::Client,
+    job_url: url::Url,
+    auth: &str,
+) -> anyhow::Result<()> {
+    let mut last_status = String::new();
+
+    loop {
+        let job: serde_json::Value = job_request(client, job_url.clone(), auth)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let status = match job["stage"].as_str() {
+            Some(stage) => format!("{} ({})", job["status"].as_str().unwrap_or_default(), stage),
+            None => job["status"].as_str().unwrap_or_default().to_string(),
+        };
+        if status != last_status {
+            println!("Job status: {}", status);
+            last_status = status;
+        }
+
+        match job["status"].as_str() {
+            Some("succeeded") => return Ok(()),
+            Some("failed") => {
+                anyhow::bail!(
+                    "Job failed ({}): {}",
+                    job["error"]["kind"].as_str().unwrap_or_default(),
+                    job["error"]["message"].as_str().unwrap_or_default()
+                );
+            }
+            _ => tokio::time::delay_for(std::time::Duration::from_secs(2)).await,
+        }
+    }
+}
+
+/// Reports the job a change was queued as, waiting for it unless `no_wait`,
+/// or exits with the server's error.
+async fn handle_job_response(
+    client: &reqwest::Client,
+    response: reqwest::Response,
+    auth: &str,
+    no_wait: bool,
+) -> anyhow::Result<()> {
+    match response.error_for_status_ref() {
+        Ok(_) if response.status() == reqwest::StatusCode::ACCEPTED => {
+            let job_url = match response.headers().get("location") {
+                Some(v) => response.url().join(v.to_str()?)?,
+                None => anyhow::bail!("Server did not return a job location"),
+            };
+            let job: serde_json::Value = response.json().await?;
+            println!("Queued job {}", job["id"].as_str().unwrap_or_default());
+
+            if !no_wait {
+                wait_for_job(client, job_url, auth).await?;
+            }
+        }
+        Ok(_) => {
+            println!("Response: {}", response.text().await?);
+        }
+        Err(err) => {
+            eprintln!("Errored with status {}", err.status().unwrap());
+            match response.text().await {
+                Ok(v) => eprintln!("{}", v),
+                Err(_) => {}
+            }
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::from_args();
@@ -58,12 +282,28 @@ async fn main() -> anyhow::Result<()> {
             let auth = std::env::var("PAHKAT_API_KEY")?;
 
             let release = std::fs::read_to_string(upload.release_meta_path)?;
-            let release: Release = toml::from_str(&release)?;
-
-            let json = PackageUpdateRequest { release };
+            let mut release: Release = toml::from_str(&release)?;
 
             let client = reqwest::Client::new();
 
+            if let Some(artifact_path) = upload.artifact.as_ref() {
+                let artifact = upload_artifact(
+                    &client,
+                    &upload.url,
+                    &auth,
+                    release.channel.as_deref(),
+                    artifact_path,
+                )
+                .await?;
+                println!("Uploaded artifact: {} ({})", artifact.url, artifact.sha256);
+                release.target.payload.set_url(artifact.url);
+            }
+
+            let json = PackageUpdateRequest {
+                release,
+                uploader: upload.uploader,
+            };
+
             let response = client
                 .patch(&upload.url)
                 .json(&json)
@@ -71,21 +311,84 @@ async fn main() -> anyhow::Result<()> {
                 .send()
                 .await?;
 
-            match response.error_for_status_ref() {
-                Ok(_) => {
-                    println!("Response: {}", response.text().await?);
-                }
-                Err(err) => {
-                    eprintln!("Errored with status {}", err.status().unwrap());
-                    match response.text().await {
-                        Ok(v) => eprintln!("{}", v),
-                        Err(_) => {}
-                    }
-                    std::process::exit(1);
-                }
+            handle_job_response(&client, response, &auth, upload.no_wait).await?;
+        }
+        Args::Delete(delete) => {
+            let auth = std::env::var("PAHKAT_API_KEY")?;
+            let client = reqwest::Client::new();
+
+            let mut query = delete.release.query();
+            if let Some(platform) = delete.platform.as_deref() {
+                query.push(("platform", platform));
+            }
+            if let Some(arch) = delete.arch.as_deref() {
+                query.push(("arch", arch));
+            }
+
+            let response = client
+                .delete(&delete.release.release_url())
+                .query(&query)
+                .header("authorization", format!("Bearer {}", auth))
+                .send()
+                .await?;
+
+            handle_job_response(&client, response, &auth, delete.release.no_wait).await?;
+        }
+        Args::Yank(yank) => {
+            let auth = std::env::var("PAHKAT_API_KEY")?;
+            let client = reqwest::Client::new();
+
+            let action = if yank.undo { "unyank" } else { "yank" };
+            let response = client
+                .post(&format!("{}/{}", yank.release.release_url(), action))
+                .query(&yank.release.query())
+                .header("authorization", format!("Bearer {}", auth))
+                .send()
+                .await?;
+
+            handle_job_response(&client, response, &auth, yank.release.no_wait).await?;
+        }
+        Args::Promote(promote) => {
+            let auth = std::env::var("PAHKAT_API_KEY")?;
+            let client = reqwest::Client::new();
+
+            let mut query = promote.release.query();
+            if let Some(to) = promote.to.as_deref().filter(|x| *x != "stable") {
+                query.push(("to", to));
+            }
+            if promote.strip_prerelease {
+                query.push(("strip_prerelease", "true"));
             }
+
+            let response = client
+                .post(&format!("{}/promote", promote.release.release_url()))
+                .query(&query)
+                .header("authorization", format!("Bearer {}", auth))
+                .send()
+                .await?;
+
+            handle_job_response(&client, response, &auth, promote.release.no_wait).await?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn job_requests_are_authorized() {
+        let client = reqwest::Client::new();
+        let job_url = url::Url::parse("https://example.com/jobs/abc").unwrap();
+
+        let request = job_request(&client, job_url.clone(), "secret")
+            .build()
+            .unwrap();
+
+        assert_eq!(request.method(), reqwest::Method::GET);
+        assert_eq!(request.url(), &job_url);
+        assert_eq!(request.headers()["authorization"], "Bearer secret");
+    }
+}